@@ -0,0 +1,226 @@
+//! Costs of each stage an event passes through before an opportunity can be
+//! acted on:
+//! - `parse`: price and quantity strings to integers
+//! - `decode`: each venue's decoder on a stream of its depth messages, and
+//!   its trade decoder on a single print
+//! - `book`: loading a snapshot, then replaying every venue's updates, which
+//!   sets levels and recomputes the venue's best bid and ask
+//! - `arbitrage`: the cross-venue check run after every book update
+//!
+//! The depth streams in `benches/data` are synthetic, not captured: generated
+//! messages in each venue's wire format, around a 65,000.00 mid with about a
+//! quarter of the levels being removals. Swap in a capture from the live
+//! feeds to profile a particular session.
+//!
+//! Run with `cargo bench --bench hot_path`.
+
+use std::time::Instant;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use pricelevel::Side;
+use security_flamegraph_lowlatency::{
+    api::{binance, coinbase, kraken, trades, Level, MarketEvent, MarketEventKind},
+    orderbook::{
+        book::{Exchange, OrderBook},
+        ladder::{ArrayLadder, PriceLadder, DEFAULT_WINDOW},
+    },
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const BINANCE_DEPTH: &str = include_str!("data/binance_depth.jsonl");
+const KRAKEN_BOOK: &str = include_str!("data/kraken_book.jsonl");
+const COINBASE_LEVEL2: &str = include_str!("data/coinbase_level2.jsonl");
+
+const BINANCE_TRADE: &str = r#"{"e":"trade","E":1718000000136,"s":"BTCUSDT","t":3601234567,"p":"65000.10","q":"0.02500000","T":1718000000134,"m":true}"#;
+const KRAKEN_TRADE: &str = r#"[337,[["65000.20000","0.15850568","1718000000.321597","s","l",""],["65000.30000","0.02455000","1718000000.324998","b","l",""]],"trade","XBT/USD"]"#;
+const COINBASE_TRADE: &str = r#"{"type":"match","trade_id":685204218,"sequence":80000000050,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","time":"2024-06-10T06:13:27.028Z","product_id":"BTC-USD","size":"0.23512","price":"65000.23","side":"sell"}"#;
+
+const MID: u64 = 6_500_000;
+/// Levels per side in each venue's snapshot
+const DEPTH: u64 = 500;
+
+type Decoder = fn(&str, Instant) -> anyhow::Result<Vec<MarketEvent>>;
+
+const VENUES: [(&str, Decoder, &str); 3] = [
+    ("binance", binance::decode_update, BINANCE_DEPTH),
+    ("kraken", kraken::decode, KRAKEN_BOOK),
+    ("coinbase", coinbase::decode, COINBASE_LEVEL2),
+];
+
+fn decode_all(decode: Decoder, messages: &str) -> Vec<MarketEvent> {
+    messages
+        .lines()
+        .flat_map(|message| decode(message, Instant::now()).unwrap())
+        .collect()
+}
+
+fn snapshot(exchange: Exchange) -> MarketEvent {
+    let mut event = MarketEvent::new(
+        exchange,
+        "BTC/USDT",
+        MarketEventKind::Snapshot,
+        Instant::now(),
+    );
+    for i in 1..=DEPTH {
+        event
+            .levels
+            .push(Level::new(Side::Buy, MID - i, i * 1_000_000));
+        event
+            .levels
+            .push(Level::new(Side::Sell, MID + i, i * 1_000_000));
+    }
+    event
+}
+
+/// Every venue's updates, interleaved as the aggregator would see them.
+fn update_mix() -> Vec<MarketEvent> {
+    let streams: Vec<Vec<MarketEvent>> = VENUES
+        .iter()
+        .map(|(_, decode, messages)| decode_all(*decode, messages))
+        .collect();
+    let longest = streams.iter().map(Vec::len).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|i| streams.iter().filter_map(move |stream| stream.get(i)))
+        .cloned()
+        .collect()
+}
+
+fn loaded<L: PriceLadder>(orderbook: OrderBook<L>) -> OrderBook<L> {
+    for exchange in Exchange::ALL {
+        orderbook.apply_market_event(&snapshot(exchange));
+    }
+    orderbook
+}
+
+fn map_book() -> OrderBook {
+    OrderBook::new("BTC/USDT".to_string())
+}
+
+fn array_book() -> OrderBook<ArrayLadder> {
+    OrderBook::with_ladders(
+        "BTC/USDT".to_string(),
+        ArrayLadder::new(Side::Buy, 1, DEFAULT_WINDOW),
+        ArrayLadder::new(Side::Sell, 1, DEFAULT_WINDOW),
+    )
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    let prices = ["65000.01", "64999.5", "65000", "65000.12345678"];
+    group.bench_function("price_cents", |b| {
+        b.iter(|| {
+            for price in prices {
+                black_box(parse_price_cents(black_box(price)));
+            }
+        })
+    });
+    let quantities = ["0.00000001", "1.25", "3", "0.21740162"];
+    group.bench_function("quantity_units", |b| {
+        b.iter(|| {
+            for quantity in quantities {
+                black_box(parse_quantity_units(black_box(quantity)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (venue, decode, messages) in VENUES {
+        group.bench_function(format!("{venue}_depth"), |b| {
+            b.iter(|| decode_all(decode, black_box(messages)))
+        });
+    }
+    group.bench_function("binance_trade", |b| {
+        b.iter(|| trades::decode_binance(black_box(BINANCE_TRADE), Instant::now()))
+    });
+    group.bench_function("kraken_trade", |b| {
+        let mut sequence = 0;
+        b.iter(|| trades::decode_kraken(black_box(KRAKEN_TRADE), Instant::now(), &mut sequence))
+    });
+    group.bench_function("coinbase_trade", |b| {
+        b.iter(|| trades::decode_coinbase(black_box(COINBASE_TRADE), Instant::now()))
+    });
+    group.finish();
+}
+
+fn bench_book(c: &mut Criterion) {
+    let updates = update_mix();
+    // Fixtures that trip validation would only measure quarantined venues
+    let orderbook = loaded(map_book());
+    for event in &updates {
+        orderbook.apply_market_event(event);
+    }
+    for exchange in Exchange::ALL {
+        assert!(
+            !orderbook.is_suppressed(exchange),
+            "{exchange:?} suppressed"
+        );
+    }
+
+    let snapshot = snapshot(Exchange::Binance);
+    let mut group = c.benchmark_group("book");
+    group.bench_function("snapshot/map", |b| {
+        let orderbook = map_book();
+        b.iter(|| orderbook.apply_market_event(black_box(&snapshot)))
+    });
+    group.bench_function("snapshot/array", |b| {
+        let orderbook = array_book();
+        b.iter(|| orderbook.apply_market_event(black_box(&snapshot)))
+    });
+    // A fresh book per run: replaying onto the same book would regress the
+    // venues' exchange timestamps and quarantine them
+    group.bench_function("updates/map", |b| {
+        b.iter_batched(
+            || loaded(map_book()),
+            |orderbook| {
+                for event in &updates {
+                    orderbook.apply_market_event(black_box(event));
+                }
+                orderbook
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("updates/array", |b| {
+        b.iter_batched(
+            || loaded(array_book()),
+            |orderbook| {
+                for event in &updates {
+                    orderbook.apply_market_event(black_box(event));
+                }
+                orderbook
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// Only the no-opportunity path, which is nearly every call.
+fn bench_arbitrage(c: &mut Criterion) {
+    let map = loaded(map_book());
+    let array = loaded(array_book());
+    let mut group = c.benchmark_group("arbitrage");
+    group.bench_function("check/map", |b| {
+        b.iter(|| {
+            map.check_for_immediate_purchase(black_box(MID - 1), Exchange::Binance, Side::Buy, 0)
+        })
+    });
+    group.bench_function("check/array", |b| {
+        b.iter(|| {
+            array.check_for_immediate_purchase(black_box(MID - 1), Exchange::Binance, Side::Buy, 0)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse,
+    bench_decode,
+    bench_book,
+    bench_arbitrage
+);
+criterion_main!(benches);
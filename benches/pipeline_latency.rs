@@ -0,0 +1,147 @@
+//! End-to-end latency of the feed → book pipelines: the time from an event
+//! being handed to the pipeline by a feed until the book has applied it.
+//!
+//! Three feeds each publish a paced stream of level updates through:
+//! - a plain tokio mpsc channel, as the aggregator used before the bus
+//! - the `MarketDataBus` with the `Block` policy (the tokio pipeline)
+//! - one SPSC ring per feed drained by a dedicated book thread
+//!
+//! Run with `cargo bench --bench pipeline_latency` on a machine with a core
+//! per feed plus one for the book: the ring's threads spin, and on fewer
+//! cores they mostly measure the scheduler.
+
+use std::time::{Duration, Instant};
+
+use pricelevel::Side;
+use security_flamegraph_lowlatency::{
+    api::{EventSink, Level, MarketDataBus, MarketEvent, MarketEventKind},
+    orderbook::book::{Exchange, OrderBook},
+    pipeline::{self, RingPipeline},
+};
+
+const EVENTS_PER_FEED: usize = 20_000;
+/// Gap between one feed's events, so the benchmark measures latency rather
+/// than how fast a backlog drains
+const PACE: Duration = Duration::from_micros(50);
+const CAPACITY: usize = 1000;
+
+fn event(exchange: Exchange, i: usize) -> MarketEvent {
+    let level = if i.is_multiple_of(2) {
+        Level::new(Side::Buy, 10_000 - (i % 10) as u64, (i % 5 + 1) as u64)
+    } else {
+        Level::new(Side::Sell, 10_010 + (i % 10) as u64, (i % 5 + 1) as u64)
+    };
+    MarketEvent {
+        levels: vec![level],
+        ..MarketEvent::new(
+            exchange,
+            "BTC/USDT",
+            MarketEventKind::LevelUpdate,
+            Instant::now(),
+        )
+    }
+}
+
+/// Publishes one feed's events at `PACE`, stamping each as it's sent.
+async fn publish(exchange: Exchange, sink: impl EventSink<MarketEvent>) {
+    let mut next = Instant::now();
+    for i in 0..EVENTS_PER_FEED {
+        while Instant::now() < next {
+            std::hint::spin_loop();
+        }
+        next += PACE;
+        sink.deliver(event(exchange, i)).await.unwrap();
+    }
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(Exchange::ALL.len() + 1)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn apply(orderbook: &OrderBook, latencies: &mut Vec<Duration>, event: MarketEvent) {
+    pipeline::process_event(orderbook, &event);
+    latencies.push(event.received_at.elapsed());
+}
+
+fn tokio_mpsc() -> Vec<Duration> {
+    runtime().block_on(async {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(CAPACITY);
+        for exchange in Exchange::ALL {
+            tokio::spawn(publish(exchange, tx.clone()));
+        }
+        drop(tx);
+
+        let orderbook = OrderBook::new("BTC/USDT".to_string());
+        let mut latencies = Vec::with_capacity(EVENTS_PER_FEED * Exchange::ALL.len());
+        while let Some(event) = rx.recv().await {
+            apply(&orderbook, &mut latencies, event);
+        }
+        latencies
+    })
+}
+
+fn market_data_bus() -> Vec<Duration> {
+    runtime().block_on(async {
+        let mut bus = MarketDataBus::new(CAPACITY);
+        for exchange in Exchange::ALL {
+            tokio::spawn(publish(exchange, bus.sender(exchange)));
+        }
+
+        let orderbook = OrderBook::new("BTC/USDT".to_string());
+        let mut latencies = Vec::with_capacity(EVENTS_PER_FEED * Exchange::ALL.len());
+        while let Some(event) = bus.recv().await {
+            apply(&orderbook, &mut latencies, event);
+        }
+        latencies
+    })
+}
+
+fn spsc_ring() -> Vec<Duration> {
+    let mut ring = RingPipeline::new(CAPACITY);
+    let feeds: Vec<_> = Exchange::ALL
+        .into_iter()
+        .map(|exchange| {
+            let producer = ring.add_feed();
+            pipeline::spawn_feed("bench-feed", None, move || publish(exchange, producer)).unwrap()
+        })
+        .collect();
+
+    let orderbook = OrderBook::new("BTC/USDT".to_string());
+    let mut latencies = Vec::with_capacity(EVENTS_PER_FEED * Exchange::ALL.len());
+    ring.run(|event| apply(&orderbook, &mut latencies, event));
+    for feed in feeds {
+        feed.join().unwrap();
+    }
+    latencies
+}
+
+fn report(name: &str, mut latencies: Vec<Duration>) {
+    latencies.sort_unstable();
+    let percentile = |p: f64| {
+        let index = ((latencies.len() - 1) as f64 * p).round() as usize;
+        latencies[index].as_secs_f64() * 1e6
+    };
+    println!(
+        "{:<16} {:>8} events  p50 {:>8.2}µs  p99 {:>8.2}µs  p99.9 {:>8.2}µs  max {:>9.2}µs",
+        name,
+        latencies.len(),
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+        percentile(1.0)
+    );
+}
+
+fn main() {
+    // `cargo bench` passes --bench; other builds of the target just exit
+    if !std::env::args().any(|arg| arg == "--bench") {
+        return;
+    }
+    report("tokio mpsc", tokio_mpsc());
+    report("market data bus", market_data_bus());
+    report("spsc ring", spsc_ring());
+}
@@ -0,0 +1,106 @@
+//! Update and best-level lookup costs of the two `PriceLadder`s.
+//!
+//! Each ladder starts with 500 levels per venue around a 65,000.00 mid, then
+//! replays a stream of updates clustered near the touch, about one in five
+//! removing its level, the mix a BTC depth feed produces.
+//!
+//! Run with `cargo bench --bench price_ladder`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use pricelevel::Side;
+use security_flamegraph_lowlatency::orderbook::{
+    book::Exchange,
+    ladder::{ArrayLadder, MapLadder, PriceLadder, DEFAULT_WINDOW},
+};
+
+const MID: u64 = 6_500_000;
+const DEPTH: u64 = 500;
+const UPDATES: usize = 10_000;
+
+/// Bids below the mid: level `i` ticks under it.
+fn populate(ladder: &impl PriceLadder) {
+    for exchange in Exchange::ALL {
+        for i in 1..=DEPTH {
+            ladder.set(exchange, MID - i, i * 1_000);
+        }
+    }
+}
+
+/// (venue, price, quantity) updates, mostly within 20 ticks of the touch.
+fn updates() -> Vec<(Exchange, u64, u64)> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        state >> 33
+    };
+    (0..UPDATES)
+        .map(|_| {
+            let exchange = Exchange::ALL[next() as usize % Exchange::ALL.len()];
+            let offset = if next().is_multiple_of(10) {
+                next() % DEPTH
+            } else {
+                next() % 20
+            };
+            let quantity = if next().is_multiple_of(5) {
+                0
+            } else {
+                next() % 100_000 + 1
+            };
+            (exchange, MID - 1 - offset, quantity)
+        })
+        .collect()
+}
+
+fn map_ladder() -> MapLadder {
+    let ladder = MapLadder::new(Side::Buy);
+    populate(&ladder);
+    ladder
+}
+
+fn array_ladder() -> ArrayLadder {
+    let ladder = ArrayLadder::new(Side::Buy, 1, DEFAULT_WINDOW);
+    populate(&ladder);
+    ladder
+}
+
+fn replay(ladder: &impl PriceLadder, updates: &[(Exchange, u64, u64)]) {
+    for &(exchange, price, quantity) in updates {
+        ladder.set(exchange, price, quantity);
+    }
+}
+
+fn bench_update(c: &mut Criterion) {
+    let updates = updates();
+    let mut group = c.benchmark_group("ladder_update");
+    group.bench_function("map", |b| {
+        b.iter_batched(
+            map_ladder,
+            |ladder| replay(&ladder, black_box(&updates)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("array", |b| {
+        b.iter_batched(
+            array_ladder,
+            |ladder| replay(&ladder, black_box(&updates)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_best(c: &mut Criterion) {
+    let map = map_ladder();
+    let array = array_ladder();
+    let mut group = c.benchmark_group("ladder_best");
+    group.bench_function("map", |b| b.iter(|| map.best(black_box(Exchange::Kraken))));
+    group.bench_function("array", |b| {
+        b.iter(|| array.best(black_box(Exchange::Kraken)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_update, bench_best);
+criterion_main!(benches);
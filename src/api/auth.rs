@@ -0,0 +1,571 @@
+//! # Authenticated Requests
+//!
+//! Private endpoints (orders, balances, user-data streams) need every request
+//! signed with the account's API secret. This module provides:
+//! - `Credentials` loaded from the environment or a JSON file, redacted in logs
+//! - `SignedRequest`, a venue-agnostic description of a ready-to-send request
+//! - One signer per venue implementing that venue's signing scheme:
+//!   - Binance: hex HMAC-SHA256 of the query string, appended as `signature`
+//!   - Kraken: `API-Sign` = HMAC-SHA512 over path + SHA256(nonce + body)
+//!   - Coinbase: `CB-ACCESS-SIGN` = HMAC-SHA256 over timestamp + method + path + body
+
+use std::{
+    fmt,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::orderbook::current_time_millis;
+
+const BINANCE_REST_URL: &str = "https://api.binance.com";
+const KRAKEN_REST_URL: &str = "https://api.kraken.com";
+const COINBASE_REST_URL: &str = "https://api.exchange.coinbase.com";
+
+/// How long Binance accepts a signed request after its timestamp, in ms.
+const BINANCE_RECV_WINDOW_MS: u64 = 5_000;
+
+/// API key and secret for one venue account.
+///
+/// `Debug` never prints the secret or passphrase, so credentials can sit in
+/// structs that get logged.
+#[derive(Clone, Deserialize)]
+pub struct Credentials {
+    pub api_key: String,
+    api_secret: String,
+    /// Only Coinbase issues a passphrase alongside the key
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+impl Credentials {
+    pub fn new(api_key: impl Into<String>, api_secret: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            api_secret: api_secret.into(),
+            passphrase: None,
+        }
+    }
+
+    pub fn with_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Reads `{PREFIX}_API_KEY`, `{PREFIX}_API_SECRET` and, if set,
+    /// `{PREFIX}_PASSPHRASE`, e.g. `from_env("BINANCE")`.
+    pub fn from_env(prefix: &str) -> anyhow::Result<Self> {
+        let var = |name: &str| {
+            let key = format!("{prefix}_{name}");
+            std::env::var(&key).with_context(|| format!("{key} is not set"))
+        };
+        Ok(Self {
+            api_key: var("API_KEY")?,
+            api_secret: var("API_SECRET")?,
+            passphrase: var("PASSPHRASE").ok(),
+        })
+    }
+
+    /// Reads a JSON file with `api_key`, `api_secret` and optional `passphrase`.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading credentials from {}", path.display()))?;
+        // serde_json errors can quote the input, so don't pass them through
+        serde_json::from_str(&contents)
+            .map_err(|_| anyhow!("{} is not a valid credentials file", path.display()))
+    }
+
+    pub fn passphrase(&self) -> Option<&str> {
+        self.passphrase.as_deref()
+    }
+
+    fn secret_bytes(&self) -> &[u8] {
+        self.api_secret.as_bytes()
+    }
+
+    /// Kraken and Coinbase hand out base64 secrets that are keyed as raw bytes.
+    fn decoded_secret(&self) -> anyhow::Result<Vec<u8>> {
+        BASE64
+            .decode(&self.api_secret)
+            .map_err(|_| anyhow!("API secret for {} is not valid base64", self.api_key))
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &self.api_key)
+            .field("api_secret", &"<redacted>")
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// HTTP method of a signed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// A fully signed request, ready to hand to any HTTP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRequest {
+    pub method: Method,
+    /// Full URL including any (signed) query string
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl SignedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sends the request and parses the JSON response. Non-2xx responses are
+    /// errors carrying the venue's response body.
+    pub async fn send(&self, client: &reqwest::Client) -> anyhow::Result<serde_json::Value> {
+        let method = match self.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+            Method::Delete => reqwest::Method::DELETE,
+        };
+        let mut builder = client.request(method, &self.url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        if !self.body.is_empty() {
+            builder = builder.body(self.body.clone());
+        }
+
+        let response = builder.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!(
+                "{} {} returned {status}: {text}",
+                self.method.as_str(),
+                self.url
+            ));
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+}
+
+/// Joins parameters as `k=v&k=v` in the given order. Callers pass values that
+/// are already URL-safe (symbols, numbers, enum strings).
+fn encode_params(params: &[(&str, &str)]) -> String {
+    params
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Signs Binance `SIGNED` endpoints.
+pub struct BinanceSigner {
+    credentials: Credentials,
+    base_url: String,
+}
+
+impl BinanceSigner {
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            base_url: BINANCE_REST_URL.to_string(),
+        }
+    }
+
+    /// Points the signer at another host, e.g. the spot testnet.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Request for `USER_STREAM` endpoints, which take the API key header but
+    /// no signature.
+    pub fn api_key_request(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> SignedRequest {
+        let mut url = format!("{}{path}", self.base_url);
+        if !params.is_empty() {
+            url = format!("{url}?{}", encode_params(params));
+        }
+        SignedRequest {
+            method,
+            url,
+            headers: vec![("X-MBX-APIKEY".to_string(), self.credentials.api_key.clone())],
+            body: String::new(),
+        }
+    }
+
+    /// Hex HMAC-SHA256 of the total query string.
+    pub fn signature(&self, query: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.credentials.secret_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(query.as_bytes());
+        to_hex(&mac.finalize().into_bytes())
+    }
+
+    /// Adds `recvWindow` and `timestamp` to `params` and signs the result.
+    pub fn sign(&self, method: Method, path: &str, params: &[(&str, &str)]) -> SignedRequest {
+        self.sign_at(method, path, params, current_time_millis())
+    }
+
+    pub fn sign_at(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+        timestamp_ms: u64,
+    ) -> SignedRequest {
+        let recv_window = BINANCE_RECV_WINDOW_MS.to_string();
+        let timestamp = timestamp_ms.to_string();
+        let mut params = params.to_vec();
+        params.push(("recvWindow", &recv_window));
+        params.push(("timestamp", &timestamp));
+
+        let query = encode_params(&params);
+        let signature = self.signature(&query);
+        SignedRequest {
+            method,
+            url: format!("{}{path}?{query}&signature={signature}", self.base_url),
+            headers: vec![("X-MBX-APIKEY".to_string(), self.credentials.api_key.clone())],
+            body: String::new(),
+        }
+    }
+}
+
+/// Signs Kraken private endpoints (`/0/private/*`).
+pub struct KrakenSigner {
+    credentials: Credentials,
+    base_url: String,
+    /// Kraken rejects any nonce not greater than the last one it saw
+    last_nonce: AtomicU64,
+}
+
+impl KrakenSigner {
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            base_url: KRAKEN_REST_URL.to_string(),
+            last_nonce: AtomicU64::new(0),
+        }
+    }
+
+    /// Current time in ms, bumped past the previous nonce if the clock hasn't moved.
+    pub fn next_nonce(&self) -> u64 {
+        let now = current_time_millis();
+        let previous = self
+            .last_nonce
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |last| {
+                Some(now.max(last + 1))
+            })
+            .unwrap_or_else(|last| last);
+        now.max(previous + 1)
+    }
+
+    /// Base64 HMAC-SHA512 of `path + SHA256(nonce + post_data)`, keyed with the
+    /// base64-decoded secret.
+    pub fn signature(&self, path: &str, nonce: u64, post_data: &str) -> anyhow::Result<String> {
+        let digest = Sha256::new()
+            .chain_update(nonce.to_string())
+            .chain_update(post_data)
+            .finalize();
+
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.credentials.decoded_secret()?)
+            .expect("HMAC accepts keys of any length");
+        mac.update(path.as_bytes());
+        mac.update(&digest);
+        Ok(BASE64.encode(mac.finalize().into_bytes()))
+    }
+
+    /// Builds a form-encoded POST with a fresh nonce as its first parameter.
+    pub fn sign(&self, path: &str, params: &[(&str, &str)]) -> anyhow::Result<SignedRequest> {
+        self.sign_with_nonce(path, params, self.next_nonce())
+    }
+
+    pub fn sign_with_nonce(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+        nonce: u64,
+    ) -> anyhow::Result<SignedRequest> {
+        let nonce_str = nonce.to_string();
+        let mut all_params = vec![("nonce", nonce_str.as_str())];
+        all_params.extend_from_slice(params);
+        let body = encode_params(&all_params);
+
+        let signature = self.signature(path, nonce, &body)?;
+        Ok(SignedRequest {
+            method: Method::Post,
+            url: format!("{}{path}", self.base_url),
+            headers: vec![
+                ("API-Key".to_string(), self.credentials.api_key.clone()),
+                ("API-Sign".to_string(), signature),
+                (
+                    "Content-Type".to_string(),
+                    "application/x-www-form-urlencoded".to_string(),
+                ),
+            ],
+            body,
+        })
+    }
+}
+
+/// Signs Coinbase Exchange REST requests.
+pub struct CoinbaseSigner {
+    credentials: Credentials,
+    base_url: String,
+}
+
+impl CoinbaseSigner {
+    pub fn new(credentials: Credentials) -> anyhow::Result<Self> {
+        if credentials.passphrase.is_none() {
+            return Err(anyhow!(
+                "Coinbase credentials for {} need a passphrase",
+                credentials.api_key
+            ));
+        }
+        Ok(Self {
+            credentials,
+            base_url: COINBASE_REST_URL.to_string(),
+        })
+    }
+
+    /// Points the signer at another host, e.g. the sandbox.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Base64 HMAC-SHA256 of `timestamp + method + request_path + body`, keyed
+    /// with the base64-decoded secret. `request_path` includes any query string.
+    pub fn signature(
+        &self,
+        timestamp: u64,
+        method: Method,
+        request_path: &str,
+        body: &str,
+    ) -> anyhow::Result<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.credentials.decoded_secret()?)
+            .expect("HMAC accepts keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(method.as_str().as_bytes());
+        mac.update(request_path.as_bytes());
+        mac.update(body.as_bytes());
+        Ok(BASE64.encode(mac.finalize().into_bytes()))
+    }
+
+    /// Signs a request timestamped now. `body` is the JSON payload, or empty.
+    pub fn sign(
+        &self,
+        method: Method,
+        request_path: &str,
+        body: &str,
+    ) -> anyhow::Result<SignedRequest> {
+        self.sign_at(method, request_path, body, current_time_millis() / 1_000)
+    }
+
+    pub fn sign_at(
+        &self,
+        method: Method,
+        request_path: &str,
+        body: &str,
+        timestamp: u64,
+    ) -> anyhow::Result<SignedRequest> {
+        let signature = self.signature(timestamp, method, request_path, body)?;
+        let mut headers = vec![
+            (
+                "CB-ACCESS-KEY".to_string(),
+                self.credentials.api_key.clone(),
+            ),
+            ("CB-ACCESS-SIGN".to_string(), signature),
+            ("CB-ACCESS-TIMESTAMP".to_string(), timestamp.to_string()),
+            (
+                "CB-ACCESS-PASSPHRASE".to_string(),
+                self.credentials
+                    .passphrase()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+        ];
+        if !body.is_empty() {
+            headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        }
+        Ok(SignedRequest {
+            method,
+            url: format!("{}{request_path}", self.base_url),
+            headers,
+            body: body.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BinanceSigner, CoinbaseSigner, Credentials, KrakenSigner, Method};
+
+    #[test]
+    fn test_binance_documented_signature() {
+        // Example from the Binance spot API docs ("SIGNED endpoint examples")
+        let signer = BinanceSigner::new(Credentials::new(
+            "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A",
+            "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j",
+        ));
+        let params = [
+            ("symbol", "LTCBTC"),
+            ("side", "BUY"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTC"),
+            ("quantity", "1"),
+            ("price", "0.1"),
+        ];
+
+        let request = signer.sign_at(Method::Post, "/api/v3/order", &params, 1499827319559);
+        assert_eq!(
+            request.url,
+            "https://api.binance.com/api/v3/order?symbol=LTCBTC&side=BUY&type=LIMIT\
+             &timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000&timestamp=1499827319559\
+             &signature=c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+        assert_eq!(
+            request.header("x-mbx-apikey"),
+            Some("vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A")
+        );
+    }
+
+    #[test]
+    fn test_kraken_documented_signature() {
+        // Example from the Kraken REST API docs ("Authentication")
+        let signer = KrakenSigner::new(Credentials::new(
+            "key",
+            "kQH5HW/8p1uGOVjbgWA7FunAmGO8lsSUXNsu3eow76sz84Q18fWxnyRzBHCd3pd5nE9qa99HAZtuZuj6F1huXg==",
+        ));
+        let params = [
+            ("ordertype", "limit"),
+            ("pair", "XBTUSD"),
+            ("price", "37500"),
+            ("type", "buy"),
+            ("volume", "1.25"),
+        ];
+
+        let request = signer
+            .sign_with_nonce("/0/private/AddOrder", &params, 1616492376594)
+            .unwrap();
+        assert_eq!(
+            request.body,
+            "nonce=1616492376594&ordertype=limit&pair=XBTUSD&price=37500&type=buy&volume=1.25"
+        );
+        assert_eq!(
+            request.header("API-Sign"),
+            Some("4/dpxb3iT4tp/ZCVEwSnEsLxx0bqyhLpdfOpc6fn7OR8+UClSV5n9E6aSS8MPtnRfp32bAb0nmbRn6H8ndwLUQ==")
+        );
+    }
+
+    #[test]
+    fn test_kraken_nonce_strictly_increases() {
+        let signer = KrakenSigner::new(Credentials::new("key", "c2VjcmV0"));
+        let first = signer.next_nonce();
+        let second = signer.next_nonce();
+        assert!(second > first);
+    }
+
+    #[test]
+    fn test_coinbase_signature() {
+        // Coinbase documents the scheme but not a fixed vector; these were
+        // computed independently of this code, with the secret base64-decoded
+        // to "coinbase-example-secret-key-0001":
+        //   printf '%s' '1700000000GET/accounts' \
+        //     | openssl dgst -sha256 -hmac coinbase-example-secret-key-0001 -binary \
+        //     | base64
+        let signer = CoinbaseSigner::new(
+            Credentials::new("key", "Y29pbmJhc2UtZXhhbXBsZS1zZWNyZXQta2V5LTAwMDE=")
+                .with_passphrase("phrase"),
+        )
+        .unwrap();
+
+        let get = signer
+            .sign_at(Method::Get, "/accounts", "", 1700000000)
+            .unwrap();
+        assert_eq!(
+            get.header("CB-ACCESS-SIGN"),
+            Some("7kA67KGpCKbOv2VzLPQ1ah8H1HL4pihaCAp10Q3vXbM=")
+        );
+        assert_eq!(get.header("CB-ACCESS-TIMESTAMP"), Some("1700000000"));
+        assert_eq!(get.header("Content-Type"), None);
+
+        let body = r#"{"product_id":"BTC-USD","side":"buy","size":"0.01","price":"30000.00"}"#;
+        let post = signer
+            .sign_at(Method::Post, "/orders", body, 1700000000)
+            .unwrap();
+        assert_eq!(
+            post.header("CB-ACCESS-SIGN"),
+            Some("HGPbo0PYLwbcnDW+2XTDhtJpMUCDQe/gTGqWrKUoEcg=")
+        );
+        assert_eq!(post.body, body);
+
+        // The query string is signed as part of the path; secret
+        // "sandbox-secret"
+        let signer = CoinbaseSigner::new(
+            Credentials::new("key", "c2FuZGJveC1zZWNyZXQ=").with_passphrase("phrase"),
+        )
+        .unwrap();
+        let cancel = signer
+            .sign_at(Method::Delete, "/orders?product_id=BTC-USD", "", 1700000123)
+            .unwrap();
+        assert_eq!(
+            cancel.header("CB-ACCESS-SIGN"),
+            Some("7hJ3PQ6DXSMvvDBtoLVZv43hpDpzTzRB4ymd35MucOw=")
+        );
+    }
+
+    #[test]
+    fn test_credentials_debug_is_redacted() {
+        let credentials = Credentials::new("public-key", "super-secret").with_passphrase("hunter2");
+        let printed = format!("{credentials:?}");
+        assert!(printed.contains("public-key"));
+        assert!(!printed.contains("super-secret"));
+        assert!(!printed.contains("hunter2"));
+
+        assert!(CoinbaseSigner::new(Credentials::new("key", "c2VjcmV0")).is_err());
+    }
+}
@@ -0,0 +1,598 @@
+//! # Feed Backpressure
+//!
+//! The channel between venue adapters and the aggregator. Each venue gets its
+//! own bounded queue with a policy for when the aggregator falls behind:
+//! - `Block`: the adapter waits for space, as a plain channel would
+//! - `DropOldest`: the oldest pending BBO or trade is discarded to make room
+//! - `Conflate`: pending book events are merged so only the latest quantity
+//!   per level is kept, and a snapshot replaces the book events it supersedes
+//!
+//! A BBO or trade can be lost without harm, a snapshot or level update can't.
+//! When only those are left to drop, `DropOldest` and `Conflate` discard the
+//! venue's pending book events and queue `FeedStatus::Resyncing` instead, so
+//! the venue stays suppressed until its next snapshot.
+//!
+//! Only `Block` can stall a websocket read loop. Per-venue counters record
+//! how many events were delivered, dropped, conflated or had to wait.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use super::event::{FeedStatus, MarketEvent, MarketEventKind};
+use crate::orderbook::book::Exchange;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackpressurePolicy {
+    Block,
+    DropOldest,
+    Conflate,
+}
+
+/// Counters for one venue's queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeedStats {
+    pub delivered: u64,
+    pub dropped: u64,
+    pub conflated: u64,
+    /// Sends that found the queue full and had to wait (`Block` only)
+    pub blocked: u64,
+}
+
+struct VenueQueue {
+    exchange: Exchange,
+    policy: BackpressurePolicy,
+    capacity: usize,
+    pending: Mutex<VecDeque<MarketEvent>>,
+    /// Signalled whenever the consumer takes an event
+    space: Notify,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    conflated: AtomicU64,
+    blocked: AtomicU64,
+}
+
+impl VenueQueue {
+    fn stats(&self) -> FeedStats {
+        FeedStats {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            conflated: self.conflated.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Queues `event` unless the queue is full under `Block`, in which case
+    /// it is handed back.
+    fn offer(&self, event: MarketEvent) -> Option<MarketEvent> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let event = match self.policy {
+            BackpressurePolicy::Block if pending.len() >= self.capacity => return Some(event),
+            BackpressurePolicy::Conflate => {
+                let (event, merged) = conflate(&mut pending, event);
+                self.conflated.fetch_add(merged, Ordering::Relaxed);
+                // None: merged into a pending event, nothing left to queue
+                event?
+            }
+            _ => event,
+        };
+        let event = if pending.len() >= self.capacity {
+            self.make_room(&mut pending, event)
+        } else {
+            Some(event)
+        };
+        pending.extend(event);
+        None
+    }
+
+    /// Drops the oldest pending BBO or trade, or the incoming one. Failing
+    /// that, drops every pending book event. An incoming snapshot or status
+    /// change resets the venue's book by itself; an incoming level update is
+    /// replaced by a resync. Returns what is left to queue.
+    fn make_room(
+        &self,
+        pending: &mut VecDeque<MarketEvent>,
+        event: MarketEvent,
+    ) -> Option<MarketEvent> {
+        if let Some(index) = pending.iter().position(is_droppable) {
+            pending.remove(index);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Some(event);
+        }
+        if is_droppable(&event) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let before = pending.len();
+        pending.retain(|e| !e.kind.updates_book());
+        let mut dropped = (before - pending.len()) as u64;
+        let event = if event.kind != MarketEventKind::LevelUpdate {
+            event
+        } else {
+            dropped += 1;
+            let resyncing = MarketEventKind::Status(FeedStatus::Resyncing);
+            if pending.back().is_some_and(|e| e.kind == resyncing) {
+                self.dropped.fetch_add(dropped, Ordering::Relaxed);
+                return None;
+            }
+            MarketEvent::status(
+                self.exchange,
+                &event.instrument,
+                FeedStatus::Resyncing,
+                event.received_at,
+            )
+        };
+        if pending.len() >= self.capacity {
+            // Nothing but status changes are pending
+            pending.pop_front();
+            dropped += 1;
+        }
+        self.dropped.fetch_add(dropped, Ordering::Relaxed);
+        Some(event)
+    }
+
+    fn take(&self) -> Option<MarketEvent> {
+        let event = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()?;
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.space.notify_one();
+        Some(event)
+    }
+}
+
+/// Whether losing the event leaves the venue's book as it should be.
+fn is_droppable(event: &MarketEvent) -> bool {
+    matches!(
+        event.kind,
+        MarketEventKind::Bbo | MarketEventKind::Trade { .. }
+    )
+}
+
+/// Merges `event` into the pending queue where possible. Returns the event
+/// if it still has to be queued, and how many events were absorbed.
+fn conflate(
+    pending: &mut VecDeque<MarketEvent>,
+    mut event: MarketEvent,
+) -> (Option<MarketEvent>, u64) {
+    // Status changes are barriers: nothing merges across a disconnect
+    let since_barrier = pending
+        .iter()
+        .rposition(|e| matches!(e.kind, MarketEventKind::Status(_)))
+        .map_or(0, |i| i + 1);
+
+    match event.kind {
+        MarketEventKind::Snapshot => {
+            let before = pending.len();
+            let mut index = 0;
+            pending.retain(|e| {
+                index += 1;
+                index <= since_barrier || !e.kind.updates_book()
+            });
+            (Some(event), (before - pending.len()) as u64)
+        }
+        MarketEventKind::LevelUpdate | MarketEventKind::Bbo => {
+            let target = pending
+                .iter_mut()
+                .skip(since_barrier)
+                .rev()
+                .find(|e| e.kind.updates_book());
+            match target {
+                Some(target) if event.kind == MarketEventKind::Bbo => {
+                    if target.kind != MarketEventKind::Bbo {
+                        return (Some(event), 0);
+                    }
+                    target.levels = std::mem::take(&mut event.levels);
+                    absorb_stamps(target, &event);
+                    (None, 1)
+                }
+                Some(target) if target.kind != MarketEventKind::Bbo => {
+                    let snapshot = target.kind == MarketEventKind::Snapshot;
+                    for level in std::mem::take(&mut event.levels) {
+                        let existing = target
+                            .levels
+                            .iter()
+                            .position(|l| l.side == level.side && l.price == level.price);
+                        match existing {
+                            // A snapshot lists only levels that exist
+                            Some(i) if snapshot && level.quantity == 0 => {
+                                target.levels.remove(i);
+                            }
+                            Some(i) => target.levels[i].quantity = level.quantity,
+                            None if snapshot && level.quantity == 0 => {}
+                            None => target.levels.push(level),
+                        }
+                    }
+                    absorb_stamps(target, &event);
+                    (None, 1)
+                }
+                _ => (Some(event), 0),
+            }
+        }
+        _ => (Some(event), 0),
+    }
+}
+
+/// The merged event carries the newest sequence and exchange time but keeps
+/// the oldest receive time, so latency still reflects its oldest data.
+fn absorb_stamps(target: &mut MarketEvent, newer: &MarketEvent) {
+    target.sequence = newer.sequence.or(target.sequence);
+    target.exchange_timestamp = target.exchange_timestamp.max(newer.exchange_timestamp);
+}
+
+struct Shared {
+    /// Signalled whenever an event is queued or the last sender goes away
+    ready: Notify,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+}
+
+/// Receiving end: the aggregator takes events from every venue's queue in turn.
+pub struct MarketDataBus {
+    queues: Vec<Arc<VenueQueue>>,
+    shared: Arc<Shared>,
+    next: usize,
+}
+
+impl MarketDataBus {
+    /// A bus whose venues all queue up to `capacity` events with `Block`.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queues: Exchange::ALL
+                .into_iter()
+                .map(|exchange| {
+                    Arc::new(VenueQueue {
+                        exchange,
+                        policy: BackpressurePolicy::Block,
+                        capacity,
+                        pending: Mutex::new(VecDeque::with_capacity(capacity)),
+                        space: Notify::new(),
+                        delivered: AtomicU64::new(0),
+                        dropped: AtomicU64::new(0),
+                        conflated: AtomicU64::new(0),
+                        blocked: AtomicU64::new(0),
+                    })
+                })
+                .collect(),
+            shared: Arc::new(Shared {
+                ready: Notify::new(),
+                senders: AtomicUsize::new(0),
+                closed: AtomicBool::new(false),
+            }),
+            next: 0,
+        }
+    }
+
+    /// Sets a venue's policy. Must be called before senders are created.
+    pub fn with_policy(mut self, exchange: Exchange, policy: BackpressurePolicy) -> Self {
+        for queue in &mut self.queues {
+            if queue.exchange == exchange {
+                if let Some(queue) = Arc::get_mut(queue) {
+                    queue.policy = policy;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn policy(&self, exchange: Exchange) -> BackpressurePolicy {
+        self.queue(exchange).policy
+    }
+
+    pub fn sender(&self, exchange: Exchange) -> FeedSender {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        FeedSender {
+            queue: Arc::clone(self.queue(exchange)),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    pub fn stats(&self, exchange: Exchange) -> FeedStats {
+        self.queue(exchange).stats()
+    }
+
+    /// Next event from any venue, or None once every sender is gone and the
+    /// queues are drained.
+    pub async fn recv(&mut self) -> Option<MarketEvent> {
+        loop {
+            for offset in 0..self.queues.len() {
+                let index = (self.next + offset) % self.queues.len();
+                if let Some(event) = self.queues[index].take() {
+                    self.next = index + 1;
+                    return Some(event);
+                }
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+
+    fn queue(&self, exchange: Exchange) -> &Arc<VenueQueue> {
+        self.queues
+            .iter()
+            .find(|q| q.exchange == exchange)
+            .expect("every exchange has a queue")
+    }
+}
+
+impl Drop for MarketDataBus {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        for queue in &self.queues {
+            queue.space.notify_waiters();
+        }
+    }
+}
+
+/// Sending end for one venue, shared by that venue's adapters.
+pub struct FeedSender {
+    queue: Arc<VenueQueue>,
+    shared: Arc<Shared>,
+}
+
+impl FeedSender {
+    pub fn exchange(&self) -> Exchange {
+        self.queue.exchange
+    }
+
+    /// Queues an event under the venue's policy. Only `Block` ever waits.
+    /// Fails once the bus has been dropped.
+    pub async fn send(&self, event: MarketEvent) -> anyhow::Result<()> {
+        let mut event = event;
+        let mut counted = false;
+        loop {
+            if self.shared.closed.load(Ordering::Acquire) {
+                return Err(anyhow!("market data bus closed"));
+            }
+            // Register for wakeups before checking, so a take in between isn't missed
+            let space = self.queue.space.notified();
+            match self.queue.offer(event) {
+                None => break,
+                Some(rejected) => {
+                    if !counted {
+                        self.queue.blocked.fetch_add(1, Ordering::Relaxed);
+                        counted = true;
+                    }
+                    event = rejected;
+                    space.await;
+                }
+            }
+        }
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for FeedSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            queue: Arc::clone(&self.queue),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for FeedSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.ready.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures_util::FutureExt;
+    use pricelevel::Side;
+
+    use super::{BackpressurePolicy, FeedStats, MarketDataBus};
+    use crate::{
+        api::event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        orderbook::book::{Exchange, OrderBook},
+    };
+
+    fn update(sequence: u64, levels: Vec<Level>) -> MarketEvent {
+        MarketEvent {
+            sequence: Some(sequence),
+            levels,
+            ..MarketEvent::new(
+                Exchange::Binance,
+                "BTC/USDT",
+                MarketEventKind::LevelUpdate,
+                Instant::now(),
+            )
+        }
+    }
+
+    fn of_kind(kind: MarketEventKind, event: MarketEvent) -> MarketEvent {
+        MarketEvent { kind, ..event }
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_never_blocks() {
+        let mut bus =
+            MarketDataBus::new(2).with_policy(Exchange::Binance, BackpressurePolicy::DropOldest);
+        let tx = bus.sender(Exchange::Binance);
+        tx.send(update(1, Vec::new())).await.unwrap();
+        for sequence in 2..=5 {
+            tx.send(of_kind(MarketEventKind::Bbo, update(sequence, Vec::new())))
+                .await
+                .unwrap();
+        }
+        // The level update is kept; the older BBOs made room
+        assert_eq!(bus.recv().await.unwrap().sequence, Some(1));
+        assert_eq!(bus.recv().await.unwrap().sequence, Some(5));
+        assert_eq!(
+            bus.stats(Exchange::Binance),
+            FeedStats {
+                delivered: 2,
+                dropped: 3,
+                ..FeedStats::default()
+            }
+        );
+        drop(tx);
+        assert!(bus.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_diff_leaves_venue_suppressed() {
+        let mut bus =
+            MarketDataBus::new(2).with_policy(Exchange::Binance, BackpressurePolicy::DropOldest);
+        let tx = bus.sender(Exchange::Binance);
+        let snapshot = |sequence| {
+            of_kind(
+                MarketEventKind::Snapshot,
+                update(
+                    sequence,
+                    vec![
+                        Level::new(Side::Buy, 100, 5),
+                        Level::new(Side::Sell, 101, 5),
+                    ],
+                ),
+            )
+        };
+        let book = OrderBook::new("BTC/USDT".to_string());
+        let drain = |bus: &mut MarketDataBus| {
+            while let Some(event) = bus.recv().now_or_never().flatten() {
+                book.apply_market_event(&event);
+            }
+        };
+
+        // Only book events are pending when the third arrives, so all three go
+        tx.send(snapshot(1)).await.unwrap();
+        tx.send(update(2, vec![Level::new(Side::Buy, 100, 7)]))
+            .await
+            .unwrap();
+        tx.send(update(3, vec![Level::new(Side::Buy, 99, 1)]))
+            .await
+            .unwrap();
+        tx.send(of_kind(MarketEventKind::Bbo, update(4, Vec::new())))
+            .await
+            .unwrap();
+        assert_eq!(bus.stats(Exchange::Binance).dropped, 3);
+        drain(&mut bus);
+        assert!(book.is_suppressed(Exchange::Binance));
+        assert_eq!(book.best_bid(Exchange::Binance), None);
+
+        // The next snapshot brings it back
+        tx.send(snapshot(5)).await.unwrap();
+        drain(&mut bus);
+        assert!(!book.is_suppressed(Exchange::Binance));
+        assert_eq!(book.best_bid(Exchange::Binance), Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_consumer() {
+        let mut bus = MarketDataBus::new(1);
+        let tx = bus.sender(Exchange::Binance);
+        tx.send(update(1, Vec::new())).await.unwrap();
+
+        let producer = tokio::spawn(async move { tx.send(update(2, Vec::new())).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(bus.recv().await.unwrap().sequence, Some(1));
+        producer.await.unwrap().unwrap();
+        assert_eq!(bus.recv().await.unwrap().sequence, Some(2));
+        assert_eq!(bus.stats(Exchange::Binance).blocked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_conflate_keeps_latest_per_level() {
+        let mut bus =
+            MarketDataBus::new(8).with_policy(Exchange::Binance, BackpressurePolicy::Conflate);
+        let tx = bus.sender(Exchange::Binance);
+        let snapshot = MarketEvent {
+            kind: MarketEventKind::Snapshot,
+            ..update(
+                1,
+                vec![
+                    Level::new(Side::Buy, 100, 5),
+                    Level::new(Side::Sell, 101, 5),
+                ],
+            )
+        };
+        tx.send(snapshot).await.unwrap();
+        tx.send(update(2, vec![Level::new(Side::Buy, 100, 7)]))
+            .await
+            .unwrap();
+        tx.send(update(
+            3,
+            vec![
+                Level::new(Side::Sell, 101, 0),
+                Level::new(Side::Sell, 102, 1),
+            ],
+        ))
+        .await
+        .unwrap();
+
+        // Both updates folded into the pending snapshot
+        let event = bus.recv().await.unwrap();
+        assert_eq!(event.kind, MarketEventKind::Snapshot);
+        assert_eq!(event.sequence, Some(3));
+        assert_eq!(
+            event.levels,
+            vec![
+                Level::new(Side::Buy, 100, 7),
+                Level::new(Side::Sell, 102, 1)
+            ]
+        );
+
+        // A disconnect is a barrier, and a later snapshot supersedes the updates before it
+        tx.send(update(4, vec![Level::new(Side::Buy, 99, 1)]))
+            .await
+            .unwrap();
+        tx.send(MarketEvent::status(
+            Exchange::Binance,
+            "BTC/USDT",
+            FeedStatus::Disconnected,
+            Instant::now(),
+        ))
+        .await
+        .unwrap();
+        tx.send(update(5, vec![Level::new(Side::Buy, 98, 1)]))
+            .await
+            .unwrap();
+        tx.send(MarketEvent {
+            kind: MarketEventKind::Snapshot,
+            ..update(6, vec![Level::new(Side::Buy, 97, 1)])
+        })
+        .await
+        .unwrap();
+
+        let sequences: Vec<_> = [
+            bus.recv().await.unwrap(),
+            bus.recv().await.unwrap(),
+            bus.recv().await.unwrap(),
+        ]
+        .iter()
+        .map(|e| (e.kind.name(), e.sequence))
+        .collect();
+        assert_eq!(
+            sequences,
+            vec![
+                ("update", Some(4)),
+                ("disconnected", None),
+                ("snapshot", Some(6))
+            ]
+        );
+        assert_eq!(bus.stats(Exchange::Binance).conflated, 3);
+    }
+}
@@ -1,91 +1,358 @@
-use crate::{api::ExchangePrice, util::parse_price_cents};
-use futures_util::{SinkExt, StreamExt};
-use std::time::Instant;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-use tracing::{error, info, warn};
+use std::time::{Duration, Instant};
 
-const BINANCE_WS_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@depth20@100ms";
+use anyhow::anyhow;
+use pricelevel::Side;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
 
-pub struct BinanceClient {
-    tx: tokio::sync::mpsc::Sender<ExchangePrice>,
+use crate::{
+    api::{
+        backpressure::FeedSender,
+        event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        stream::{forward_events, reconnect_after, EventSink},
+    },
+    orderbook::book::Exchange,
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const BINANCE_WS_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms";
+const BINANCE_DEPTH_SNAPSHOT_URL: &str =
+    "https://api.binance.com/api/v3/depth?symbol=BTCUSDT&limit=1000";
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+const INSTRUMENT: &str = "BTC/USDT";
+
+pub struct BinanceClient<S = FeedSender> {
+    tx: S,
+    url: String,
+    snapshot_url: String,
 }
 
-impl BinanceClient {
-    pub fn new(tx: tokio::sync::mpsc::Sender<ExchangePrice>) -> Self {
-        BinanceClient { tx }
+impl<S: EventSink<MarketEvent>> BinanceClient<S> {
+    pub fn new(tx: S) -> Self {
+        BinanceClient {
+            tx,
+            url: BINANCE_WS_URL.to_string(),
+            snapshot_url: BINANCE_DEPTH_SNAPSHOT_URL.to_string(),
+        }
+    }
+
+    /// Streams depth diffs from `url` instead of Binance's public endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Fetches depth snapshots from `url` instead of Binance's REST API.
+    pub fn with_snapshot_url(mut self, url: impl Into<String>) -> Self {
+        self.snapshot_url = url.into();
+        self
     }
+
+    /// Streams depth diffs, building the book from a REST snapshot and
+    /// refetching it whenever the diffs' update ids show a gap.
     pub async fn listen_btc_usdt(&self) {
         info!("[Binance] Connecting to BTC/USDT orderbook depth stream...");
+        let http = match reqwest::Client::builder().timeout(SNAPSHOT_TIMEOUT).build() {
+            Ok(http) => http,
+            Err(e) => {
+                warn!("[Binance] Failed to build HTTP client: {}", e);
+                return;
+            }
+        };
 
-        match connect_async(BINANCE_WS_URL).await {
-            Ok((ws_stream, _)) => {
-                info!("[Binance] Connected successfully");
-                let (_write, mut read) = ws_stream.split();
-
-                while let Some(msg) = read.next().await {
-                    match msg {
-                        Ok(Message::Text(text)) => {
-                            // Capture timestamp immediately when message received
-                            let received_at = Instant::now();
-                            if let Err(e) = self.handle_message(&text, received_at).await {
-                                warn!("[Binance] Error handling message: {}", e);
-                            }
-                        }
-                        Ok(Message::Ping(data)) => {
-                            info!("[Binance] Received ping");
-                        }
-                        Ok(Message::Close(_)) => {
-                            warn!("[Binance] Connection closed");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("[Binance] WebSocket error: {}", e);
-                            break;
-                        }
-                        _ => {}
-                    }
+        loop {
+            // Fresh sync state per connection: the snapshot is only fetched
+            // once diffs are flowing, so none are missed in between
+            let mut sync = DepthSync::default();
+            let (snapshot_tx, mut snapshot_rx) = mpsc::channel(1);
+            let http = http.clone();
+            let snapshot_url = self.snapshot_url.clone();
+            let decode = move |text: &str, received_at: Instant| {
+                let Some(diff) = decode_diff(text, received_at)? else {
+                    return Ok(Vec::new());
+                };
+                let mut events = Vec::new();
+                if let Ok(snapshot) = snapshot_rx.try_recv() {
+                    events.extend(sync.on_snapshot(snapshot?));
                 }
+                events.extend(sync.on_diff(diff));
+                if sync.take_snapshot_request() {
+                    let (http, snapshot_tx) = (http.clone(), snapshot_tx.clone());
+                    let snapshot_url = snapshot_url.clone();
+                    tokio::spawn(async move {
+                        let snapshot = fetch_snapshot(&http, &snapshot_url).await;
+                        snapshot_tx.send(snapshot).await.ok();
+                    });
+                }
+                anyhow::Ok(events)
+            };
+
+            let result = forward_events("Binance", &self.url, Vec::new(), decode, &self.tx).await;
+            let status = MarketEvent::status(
+                Exchange::Binance,
+                INSTRUMENT,
+                FeedStatus::Disconnected,
+                Instant::now(),
+            );
+            if self.tx.deliver(status).await.is_err() {
+                return;
             }
-            Err(e) => {
-                error!("[Binance] Failed to connect: {}", e);
-            }
+            reconnect_after("Binance", result).await;
         }
     }
+}
+
+async fn fetch_snapshot(http: &reqwest::Client, url: &str) -> anyhow::Result<MarketEvent> {
+    let text = http
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    decode_snapshot(&text, Instant::now())?
+        .pop()
+        .ok_or_else(|| anyhow!("depth snapshot without lastUpdateId"))
+}
+
+/// One `depthUpdate` and the range of update ids `U..=u` it covers.
+struct DepthDiff {
+    first_update_id: u64,
+    final_update_id: u64,
+    event: MarketEvent,
+}
+
+/// Orders depth diffs against the REST snapshot as Binance prescribes:
+/// diffs are buffered until the snapshot arrives, those already contained in
+/// it (`u <= lastUpdateId`) are dropped, and from then on each diff must
+/// start right after the previous one ends. A gap discards the book and asks
+/// for a new snapshot.
+#[derive(Default)]
+struct DepthSync {
+    /// `u` of the last diff applied, or the snapshot's `lastUpdateId`;
+    /// None while waiting for a snapshot
+    last_update_id: Option<u64>,
+    buffered: Vec<DepthDiff>,
+    snapshot_requested: bool,
+}
 
-    async fn handle_message(
-        &self,
-        text: &str,
-        received_at: Instant,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Basic validation - prevent injection attacks
-        if text.len() > 100_000 {
-            return Err("Message too large".into());
+impl DepthSync {
+    fn on_snapshot(&mut self, snapshot: MarketEvent) -> Vec<MarketEvent> {
+        self.snapshot_requested = false;
+        self.last_update_id = snapshot.sequence;
+        let mut events = vec![snapshot];
+        for diff in std::mem::take(&mut self.buffered) {
+            events.extend(self.on_diff(diff));
         }
+        events
+    }
 
-        // Parse ticker data
-        let ticker: serde_json::Value = serde_json::from_str(text)?;
-
-        if let (Some(symbol), Some(price_str)) = (
-            ticker.get("s").and_then(|s| s.as_str()),
-            ticker.get("c").and_then(|c| c.as_str()),
-        ) {
-            // Fast u64 parsing - avoids f64 overhead for low-latency
-            if let Some(price) = parse_price_cents(price_str) {
-                // Parse exchange timestamp (E field = event time in milliseconds)
-                let exchange_timestamp = ticker
-                    .get("E")
-                    .and_then(|e| e.as_u64());
-                
-                // Include both exchange timestamp (for ordering) and receive timestamp (for latency)
-                self.tx.send(ExchangePrice::Binance {
-                    price,
-                    exchange_timestamp,
-                    received_at,
-                }).await.ok();
-                info!("[Binance] {}: ${}", symbol, price_str);
-            }
+    fn on_diff(&mut self, diff: DepthDiff) -> Vec<MarketEvent> {
+        let Some(last_update_id) = self.last_update_id else {
+            self.buffered.push(diff);
+            return Vec::new();
+        };
+        if diff.final_update_id <= last_update_id {
+            return Vec::new();
+        }
+        if diff.first_update_id > last_update_id + 1 {
+            warn!(
+                "[Binance] Depth gap: expected update {}, got {}..={}",
+                last_update_id + 1,
+                diff.first_update_id,
+                diff.final_update_id
+            );
+            let received_at = diff.event.received_at;
+            self.last_update_id = None;
+            self.buffered.push(diff);
+            return vec![MarketEvent::status(
+                Exchange::Binance,
+                INSTRUMENT,
+                FeedStatus::Resyncing,
+                received_at,
+            )];
         }
+        self.last_update_id = Some(diff.final_update_id);
+        vec![diff.event]
+    }
+
+    /// True once each time a snapshot is needed and not yet being fetched.
+    fn take_snapshot_request(&mut self) -> bool {
+        let request = self.last_update_id.is_none() && !self.snapshot_requested;
+        self.snapshot_requested |= request;
+        request
+    }
+}
+
+/// Decodes a depth snapshot (REST `/api/v3/depth` or a partial depth
+/// stream), sequenced by `lastUpdateId`.
+pub fn decode_snapshot(text: &str, received_at: Instant) -> anyhow::Result<Vec<MarketEvent>> {
+    let depth: Value = serde_json::from_str(text)?;
+    let Some(last_update_id) = depth.get("lastUpdateId").and_then(|id| id.as_u64()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut event = MarketEvent::new(
+        Exchange::Binance,
+        INSTRUMENT,
+        MarketEventKind::Snapshot,
+        received_at,
+    );
+    event.sequence = Some(last_update_id);
+    parse_levels(&depth, "bids", Side::Buy, &mut event.levels)?;
+    parse_levels(&depth, "asks", Side::Sell, &mut event.levels)?;
+    Ok(vec![event])
+}
+
+/// Decodes a `depthUpdate` diff on its own, without ordering it against the
+/// snapshot.
+pub fn decode_update(text: &str, received_at: Instant) -> anyhow::Result<Vec<MarketEvent>> {
+    Ok(decode_diff(text, received_at)?
+        .map(|diff| diff.event)
+        .into_iter()
+        .collect())
+}
+
+/// Decodes a `depthUpdate` diff, sequenced by its final update id `u`.
+fn decode_diff(text: &str, received_at: Instant) -> anyhow::Result<Option<DepthDiff>> {
+    let update: Value = serde_json::from_str(text)?;
+    if update.get("e").and_then(|e| e.as_str()) != Some("depthUpdate") {
+        return Ok(None);
+    }
+    let update_id = |key: &str| {
+        update
+            .get(key)
+            .and_then(|id| id.as_u64())
+            .ok_or_else(|| anyhow!("missing field {key}"))
+    };
+
+    let mut event = MarketEvent::new(
+        Exchange::Binance,
+        INSTRUMENT,
+        MarketEventKind::LevelUpdate,
+        received_at,
+    );
+    event.sequence = Some(update_id("u")?);
+    event.exchange_timestamp = update.get("E").and_then(|e| e.as_u64());
+    parse_levels(&update, "b", Side::Buy, &mut event.levels)?;
+    parse_levels(&update, "a", Side::Sell, &mut event.levels)?;
+    Ok(Some(DepthDiff {
+        first_update_id: update_id("U")?,
+        final_update_id: update_id("u")?,
+        event,
+    }))
+}
+
+/// `[["price", "quantity"], ...]` under `key`, quantity 0 meaning the level was removed.
+fn parse_levels(
+    message: &Value,
+    key: &str,
+    side: Side,
+    levels: &mut Vec<Level>,
+) -> anyhow::Result<()> {
+    let entries = message
+        .get(key)
+        .and_then(|l| l.as_array())
+        .ok_or_else(|| anyhow!("missing field {key}"))?;
+    for level in entries {
+        let field = |i: usize| {
+            level
+                .get(i)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("malformed level"))
+        };
+        // Fast u64 parsing - avoids f64 overhead for low-latency
+        let price = parse_price_cents(field(0)?).ok_or_else(|| anyhow!("invalid price"))?;
+        let quantity =
+            parse_quantity_units(field(1)?).ok_or_else(|| anyhow!("invalid quantity"))?;
+        levels.push(Level::new(side, price, quantity));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{decode_diff, decode_snapshot, DepthSync};
+    use crate::api::event::{FeedStatus, Level, MarketEventKind};
+
+    #[test]
+    fn test_decode_partial_depth() {
+        let message = r#"{"lastUpdateId":160,"bids":[["16500.10","0.5"],["16500.00","1.25"]],"asks":[["16500.20","0.1"]]}"#;
+        let events = decode_snapshot(message, Instant::now()).unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.kind, MarketEventKind::Snapshot);
+        assert_eq!(event.sequence, Some(160));
+        assert_eq!(event.instrument, "BTC/USDT");
+        assert_eq!(
+            event.levels,
+            vec![
+                Level::new(Side::Buy, 1_650_010, 50_000_000),
+                Level::new(Side::Buy, 1_650_000, 125_000_000),
+                Level::new(Side::Sell, 1_650_020, 10_000_000),
+            ]
+        );
+
+        assert!(decode_snapshot(r#"{"result":null,"id":1}"#, Instant::now())
+            .unwrap()
+            .is_empty());
+    }
+
+    fn diff(first: u64, last: u64, price: u64) -> super::DepthDiff {
+        let message = format!(
+            r#"{{"e":"depthUpdate","E":1672515782136,"s":"BTCUSDT","U":{first},"u":{last},"b":[["{price}.00","1"]],"a":[]}}"#
+        );
+        decode_diff(&message, Instant::now()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_decode_depth_diff() {
+        let diff = diff(157, 160, 16_500);
+        assert_eq!((diff.first_update_id, diff.final_update_id), (157, 160));
+        assert_eq!(diff.event.kind, MarketEventKind::LevelUpdate);
+        assert_eq!(diff.event.sequence, Some(160));
+        assert_eq!(diff.event.exchange_timestamp, Some(1672515782136));
+        assert_eq!(
+            diff.event.levels,
+            vec![Level::new(Side::Buy, 1_650_000, 100_000_000)]
+        );
+    }
+
+    #[test]
+    fn test_depth_sync_orders_diffs_against_snapshot() {
+        let mut sync = DepthSync::default();
+
+        // Diffs wait for the snapshot, which is requested only once
+        assert!(sync.on_diff(diff(95, 99, 1)).is_empty());
+        assert!(sync.on_diff(diff(100, 104, 2)).is_empty());
+        assert!(sync.take_snapshot_request());
+        assert!(!sync.take_snapshot_request());
+
+        // 95..=99 is already in the snapshot; 100..=104 straddles it and applies
+        let snapshot = decode_snapshot(
+            r#"{"lastUpdateId":101,"bids":[],"asks":[]}"#,
+            Instant::now(),
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        let events = sync.on_snapshot(snapshot);
+        let sequences: Vec<_> = events.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![Some(101), Some(104)]);
+        assert_eq!(sync.on_diff(diff(105, 106, 3)).len(), 1);
 
-        Ok(())
+        // 107 went missing
+        let events = sync.on_diff(diff(108, 110, 4));
+        assert_eq!(
+            events[0].kind,
+            MarketEventKind::Status(FeedStatus::Resyncing)
+        );
+        assert!(sync.take_snapshot_request());
+        assert!(sync.on_diff(diff(111, 112, 5)).is_empty());
     }
 }
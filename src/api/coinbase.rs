@@ -1,123 +1,301 @@
-use crate::{api::ExchangePrice, util::parse_price_cents};
-use futures_util::{SinkExt, StreamExt};
+//! # Coinbase Depth Feed
+//!
+//! The public `level2_batch` and `ticker` channels for BTC-USD, decoded into
+//! `MarketEvent`s. The book is rebuilt from the snapshot sent on every
+//! (re)subscribe.
+//!
+//! Limitation: unlike Binance's update ids and Kraken's checksums, the
+//! unauthenticated level2 channel carries no sequence number, so a dropped
+//! or reordered level2 update is never detected and the book can drift until
+//! the next reconnect. Only `ticker` BBOs are sequenced, and only stale ones
+//! are caught (see `TickerSequence`). The authenticated `level2` channel or
+//! the sequenced `full` channel would be needed to detect gaps.
+
 use std::time::Instant;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-use tracing::{error, info, warn};
+
+use anyhow::anyhow;
+use pricelevel::Side;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        backpressure::FeedSender,
+        event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        stream::{
+            forward_events, parse_side, reconnect_after, rfc3339_millis, str_field, EventSink,
+        },
+    },
+    orderbook::book::Exchange,
+    util::{parse_price_cents, parse_quantity_units},
+};
 
 const COINBASE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+const INSTRUMENT: &str = "BTC/USD";
 
-pub struct CoinbaseClient {
-    tx: tokio::sync::mpsc::Sender<ExchangePrice>,
+pub struct CoinbaseClient<S = FeedSender> {
+    tx: S,
+    url: String,
 }
 
-impl CoinbaseClient {
-    pub fn new(tx: tokio::sync::mpsc::Sender<ExchangePrice>) -> Self {
-        CoinbaseClient { tx }
+impl<S: EventSink<MarketEvent>> CoinbaseClient<S> {
+    pub fn new(tx: S) -> Self {
+        CoinbaseClient {
+            tx,
+            url: COINBASE_WS_URL.to_string(),
+        }
+    }
+
+    /// Streams the book from `url` instead of Coinbase's public endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
     }
-    
+
     pub async fn listen_btc_usdt(&self) {
         info!("[Coinbase] Connecting to BTC/USDT orderbook depth stream...");
-        
-        match connect_async(COINBASE_WS_URL).await {
-            Ok((mut ws_stream, _)) => {
-                info!("[Coinbase] Connected successfully");
-                
-                // Subscribe to BTC-USD level2 orderbook (Coinbase uses BTC-USD, not BTC-USDT)
-                let subscribe_msg = serde_json::json!({
-                    "type": "subscribe",
-                    "product_ids": ["BTC-USD"],
-                    "channels": ["level2"]
-                });
-                
-                // Send subscription message
-                if let Err(e) = ws_stream.send(Message::Text(subscribe_msg.to_string())).await {
-                    error!("[Coinbase] Failed to send subscription: {}", e);
-                    return;
-                }
-                
-                let (_write, mut read) = ws_stream.split();
-                
-                while let Some(msg) = read.next().await {
-                    match msg {
-                        Ok(Message::Text(text)) => {
-                            // Capture timestamp immediately when message received
-                            let received_at = Instant::now();
-                            if let Err(e) = self.handle_message(&text, received_at).await {
-                                warn!("[Coinbase] Error handling message: {}", e);
-                            }
-                        }
-                        Ok(Message::Ping(data)) => {
-                            info!("[Coinbase] Received ping");
-                        }
-                        Ok(Message::Close(_)) => {
-                            warn!("[Coinbase] Connection closed");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("[Coinbase] WebSocket error: {}", e);
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Err(e) => {
-                error!("[Coinbase] Failed to connect: {}", e);
+
+        // Subscribe to BTC-USD level2 orderbook (Coinbase uses BTC-USD, not BTC-USDT).
+        // The unauthenticated feed only offers the batched level2 channel.
+        let subscribe_msg = serde_json::json!({
+            "type": "subscribe",
+            "product_ids": ["BTC-USD"],
+            "channels": ["level2_batch", "ticker"]
+        });
+
+        loop {
+            let mut sequence = TickerSequence::default();
+            let decode = move |text: &str, received_at: Instant| {
+                let mut events = decode(text, received_at)?;
+                events.retain(|event| sequence.admit(event));
+                anyhow::Ok(events)
+            };
+            let result = forward_events(
+                "Coinbase",
+                &self.url,
+                vec![subscribe_msg.to_string()],
+                decode,
+                &self.tx,
+            )
+            .await;
+            let status = MarketEvent::status(
+                Exchange::Coinbase,
+                INSTRUMENT,
+                FeedStatus::Disconnected,
+                Instant::now(),
+            );
+            if self.tx.deliver(status).await.is_err() {
+                return;
             }
+            reconnect_after("Coinbase", result).await;
         }
     }
-    
-    async fn handle_message(
-        &self,
-        text: &str,
-        received_at: Instant,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Basic validation - prevent injection attacks
-        if text.len() > 100_000 {
-            return Err("Message too large".into());
+}
+
+/// Orders `ticker` BBOs by their sequence number. Coinbase sequences are
+/// shared by every message for the product, so a jump is normal and only a
+/// sequence at or below the last one seen (a late or replayed message) is
+/// detectable; such a BBO is stale and dropped. Level2 gaps go undetected, see
+/// the module docs.
+#[derive(Default)]
+struct TickerSequence {
+    last: Option<u64>,
+}
+
+impl TickerSequence {
+    fn admit(&mut self, event: &MarketEvent) -> bool {
+        let (MarketEventKind::Bbo, Some(sequence)) = (&event.kind, event.sequence) else {
+            return true;
+        };
+        if self.last.is_some_and(|last| sequence <= last) {
+            warn!(
+                "[Coinbase] Dropping stale ticker #{} (last #{})",
+                sequence,
+                self.last.unwrap_or_default()
+            );
+            return false;
         }
-        
-        // Parse ticker data
-        let ticker: serde_json::Value = serde_json::from_str(text)?;
-        
-        // Handle subscription confirmation
-        if let Some(msg_type) = ticker.get("type").and_then(|t| t.as_str()) {
-            if msg_type == "subscriptions" {
-                info!("[Coinbase] Subscription confirmed");
-                return Ok(());
+        self.last = Some(sequence);
+        true
+    }
+}
+
+/// Decodes `snapshot` and `l2update` from the level2 channel and best
+/// bid/offer from `ticker`, the only one of the three that is sequenced.
+pub fn decode(text: &str, received_at: Instant) -> anyhow::Result<Vec<MarketEvent>> {
+    let message: Value = serde_json::from_str(text)?;
+    let timestamp = message
+        .get("time")
+        .and_then(|t| t.as_str())
+        .and_then(rfc3339_millis);
+
+    let event = match str_field(&message, "type")? {
+        "subscriptions" => MarketEvent::status(
+            Exchange::Coinbase,
+            INSTRUMENT,
+            FeedStatus::Subscribed,
+            received_at,
+        ),
+        "snapshot" => {
+            let mut event = MarketEvent::new(
+                Exchange::Coinbase,
+                INSTRUMENT,
+                MarketEventKind::Snapshot,
+                received_at,
+            );
+            for (key, side) in [("bids", Side::Buy), ("asks", Side::Sell)] {
+                let levels = message
+                    .get(key)
+                    .and_then(|l| l.as_array())
+                    .ok_or_else(|| anyhow!("missing field {key}"))?;
+                for level in levels {
+                    let field = |i: usize| {
+                        level
+                            .get(i)
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| anyhow!("malformed level"))
+                    };
+                    event.levels.push(parse_level(side, field(0)?, field(1)?)?);
+                }
             }
+            event
         }
-        
-        // Handle ticker updates
-        if let Some(msg_type) = ticker.get("type").and_then(|t| t.as_str()) {
-            if msg_type == "ticker" {
-                if let (Some(product_id), Some(price_str)) = (
-                    ticker.get("product_id").and_then(|p| p.as_str()),
-                    ticker.get("price").and_then(|p| p.as_str()),
-                ) {
-                    // Fast u64 parsing - avoids f64 overhead for low-latency
-                    if let Some(price) = parse_price_cents(price_str) {
-                        // Parse exchange timestamp (time field = ISO 8601, convert to ms)
-                        // Coinbase provides "time" field but it's ISO 8601 string, not ms
-                        // For now, we'll capture receive time and can parse exchange time later if needed
-                        // Coinbase provides "time" field as ISO 8601 string
-                        // For now, we'll use None (full implementation would parse ISO 8601 to Unix ms)
-                        // The received_at timestamp is sufficient for latency measurement
-                        let exchange_timestamp = None;
-                        
-                        // Include both exchange timestamp (for ordering) and receive timestamp (for latency)
-                        self.tx.send(ExchangePrice::Coinbase {
-                            price,
-                            exchange_timestamp, // Coinbase uses ISO 8601, would need parsing
-                            received_at,
-                        }).await.ok();
-                        info!("[Coinbase] {}: ${}", product_id, price_str);
-                    }
-                }
+        "l2update" => {
+            let mut event = MarketEvent::new(
+                Exchange::Coinbase,
+                INSTRUMENT,
+                MarketEventKind::LevelUpdate,
+                received_at,
+            );
+            let changes = message
+                .get("changes")
+                .and_then(|c| c.as_array())
+                .ok_or_else(|| anyhow!("missing field changes"))?;
+            // ["buy", "price", "size"], size 0 meaning the level was removed
+            for change in changes {
+                let field = |i: usize| {
+                    change
+                        .get(i)
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("malformed change"))
+                };
+                let side = parse_side(field(0)?).ok_or_else(|| anyhow!("invalid side"))?;
+                event.levels.push(parse_level(side, field(1)?, field(2)?)?);
             }
+            event
         }
-        
-        Ok(())
-    }
+        "ticker" => {
+            let mut event = MarketEvent::new(
+                Exchange::Coinbase,
+                INSTRUMENT,
+                MarketEventKind::Bbo,
+                received_at,
+            );
+            event.sequence = message.get("sequence").and_then(|s| s.as_u64());
+            event.levels = vec![
+                parse_level(
+                    Side::Buy,
+                    str_field(&message, "best_bid")?,
+                    str_field(&message, "best_bid_size")?,
+                )?,
+                parse_level(
+                    Side::Sell,
+                    str_field(&message, "best_ask")?,
+                    str_field(&message, "best_ask_size")?,
+                )?,
+            ];
+            event
+        }
+        "error" => {
+            let reason = message.get("message").and_then(|m| m.as_str());
+            return Err(anyhow!("feed error: {}", reason.unwrap_or("unknown")));
+        }
+        _ => return Ok(Vec::new()),
+    };
+    Ok(vec![MarketEvent {
+        exchange_timestamp: timestamp,
+        ..event
+    }])
+}
+
+fn parse_level(side: Side, price: &str, quantity: &str) -> anyhow::Result<Level> {
+    // Fast u64 parsing - avoids f64 overhead for low-latency
+    let price = parse_price_cents(price).ok_or_else(|| anyhow!("invalid price"))?;
+    let quantity = parse_quantity_units(quantity).ok_or_else(|| anyhow!("invalid quantity"))?;
+    Ok(Level::new(side, price, quantity))
 }
 
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{decode, TickerSequence};
+    use crate::api::event::{FeedStatus, Level, MarketEventKind};
+
+    #[test]
+    fn test_decode_level2() {
+        let snapshot = r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["10101.10","0.45054140"]],"asks":[["10102.55","0.57753524"]]}"#;
+        let events = decode(snapshot, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::Snapshot);
+        assert_eq!(
+            events[0].levels,
+            vec![
+                Level::new(Side::Buy, 1_010_110, 45_054_140),
+                Level::new(Side::Sell, 1_010_255, 57_753_524)
+            ]
+        );
+
+        let update = r#"{"type":"l2update","product_id":"BTC-USD","time":"2019-08-14T20:42:27.265Z","changes":[["buy","10101.80000000","0.162567"],["sell","10102.55","0"]]}"#;
+        let events = decode(update, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::LevelUpdate);
+        assert_eq!(events[0].exchange_timestamp, Some(1565815347265));
+        assert_eq!(
+            events[0].levels,
+            vec![
+                Level::new(Side::Buy, 1_010_180, 16_256_700),
+                Level::new(Side::Sell, 1_010_255, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_ticker_and_status() {
+        let ticker = r#"{"type":"ticker","sequence":37475248783,"product_id":"BTC-USD","price":"1285.22","best_bid":"1285.04","best_bid_size":"0.46688654","best_ask":"1285.27","best_ask_size":"1.56637040","side":"buy","time":"2022-10-19T23:28:22.061769Z","trade_id":370843401,"last_size":"0.00083428"}"#;
+        let events = decode(ticker, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::Bbo);
+        assert_eq!(events[0].sequence, Some(37475248783));
+        assert_eq!(events[0].best_bid(), Some(128_504));
+        assert_eq!(events[0].best_ask(), Some(128_527));
+
+        let subscriptions = r#"{"type":"subscriptions","channels":[{"name":"level2_batch","product_ids":["BTC-USD"]}]}"#;
+        let events = decode(subscriptions, Instant::now()).unwrap();
+        assert_eq!(
+            events[0].kind,
+            MarketEventKind::Status(FeedStatus::Subscribed)
+        );
+        assert!(decode(
+            r#"{"type":"error","message":"Failed to subscribe"}"#,
+            Instant::now()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_stale_ticker_dropped() {
+        let ticker = |sequence: u64| {
+            let message = format!(
+                r#"{{"type":"ticker","sequence":{sequence},"product_id":"BTC-USD","best_bid":"1285.04","best_bid_size":"1","best_ask":"1285.27","best_ask_size":"1"}}"#
+            );
+            decode(&message, Instant::now()).unwrap().pop().unwrap()
+        };
+        let mut sequence = TickerSequence::default();
+        assert!(sequence.admit(&ticker(100)));
+        // Other channels' messages share the sequence, so jumps are fine
+        assert!(sequence.admit(&ticker(250)));
+        assert!(!sequence.admit(&ticker(250)));
+        assert!(!sequence.admit(&ticker(180)));
+        assert!(sequence.admit(&ticker(251)));
+    }
+}
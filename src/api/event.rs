@@ -0,0 +1,271 @@
+//! # Market Data Events
+//!
+//! The one event type every venue adapter emits and the aggregator and
+//! `OrderBook` consume:
+//! - `Snapshot`: the venue's full (or top-N) book, replacing what we held
+//! - `LevelUpdate`: absolute quantities for changed levels, 0 removes a level
+//! - `Trade`: one public print, the aggressor side in its single level
+//! - `Bbo`: best bid and offer without the rest of the depth
+//! - `Status`: the feed itself changed state
+//!
+//! Prices are cents and quantities 1e-8 base units, as parsed by `util`.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use pricelevel::Side;
+use serde::{Deserialize, Serialize};
+
+use crate::orderbook::{book::Exchange, current_time_millis, Trade};
+
+/// One price level carried by an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Level {
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+}
+
+impl Level {
+    pub fn new(side: Side, price: u64, quantity: u64) -> Self {
+        Self {
+            side,
+            price,
+            quantity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedStatus {
+    /// The venue confirmed our subscription
+    Subscribed,
+    /// The connection dropped; the venue's book is stale until the next snapshot
+    Disconnected,
+    /// The adapter saw a sequence gap or checksum mismatch and is fetching a
+    /// fresh snapshot; the venue's book is corrupt until it arrives
+    Resyncing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketEventKind {
+    Snapshot,
+    LevelUpdate,
+    Trade { trade_id: String },
+    Bbo,
+    Status(FeedStatus),
+}
+
+impl MarketEventKind {
+    /// Whether the event changes the venue's book.
+    pub fn updates_book(&self) -> bool {
+        matches!(self, Self::Snapshot | Self::LevelUpdate | Self::Bbo)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Snapshot => "snapshot",
+            Self::LevelUpdate => "update",
+            Self::Trade { .. } => "trade",
+            Self::Bbo => "bbo",
+            Self::Status(FeedStatus::Subscribed) => "subscribed",
+            Self::Status(FeedStatus::Disconnected) => "disconnected",
+            Self::Status(FeedStatus::Resyncing) => "resyncing",
+        }
+    }
+}
+
+/// A normalized market-data event from one venue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketEvent {
+    pub exchange: Exchange,
+    pub instrument: String,
+    pub kind: MarketEventKind,
+    /// Venue sequence number, where the feed provides one
+    pub sequence: Option<u64>,
+    /// Exchange time in Unix milliseconds, where the feed provides one
+    pub exchange_timestamp: Option<u64>,
+    pub received_at: Instant,
+    pub levels: Vec<Level>,
+}
+
+impl MarketEvent {
+    pub fn new(
+        exchange: Exchange,
+        instrument: &str,
+        kind: MarketEventKind,
+        received_at: Instant,
+    ) -> Self {
+        Self {
+            exchange,
+            instrument: instrument.to_string(),
+            kind,
+            sequence: None,
+            exchange_timestamp: None,
+            received_at,
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn status(
+        exchange: Exchange,
+        instrument: &str,
+        status: FeedStatus,
+        received_at: Instant,
+    ) -> Self {
+        Self::new(
+            exchange,
+            instrument,
+            MarketEventKind::Status(status),
+            received_at,
+        )
+    }
+
+    pub fn from_trade(instrument: &str, trade: Trade) -> Self {
+        Self {
+            exchange: trade.exchange,
+            instrument: instrument.to_string(),
+            kind: MarketEventKind::Trade {
+                trade_id: trade.trade_id,
+            },
+            sequence: None,
+            exchange_timestamp: trade.exchange_timestamp,
+            received_at: trade.received_at,
+            levels: vec![Level::new(trade.aggressor, trade.price, trade.quantity)],
+        }
+    }
+
+    /// The trade carried by a `Trade` event.
+    pub fn trade(&self) -> Option<Trade> {
+        let MarketEventKind::Trade { trade_id } = &self.kind else {
+            return None;
+        };
+        let level = self.levels.first()?;
+        Some(Trade {
+            exchange: self.exchange,
+            price: level.price,
+            quantity: level.quantity,
+            aggressor: level.side,
+            trade_id: trade_id.clone(),
+            exchange_timestamp: self.exchange_timestamp,
+            received_at: self.received_at,
+        })
+    }
+
+    /// Best (highest) bid among the event's levels.
+    pub fn best_bid(&self) -> Option<u64> {
+        self.levels
+            .iter()
+            .filter(|l| l.side == Side::Buy && l.quantity > 0)
+            .map(|l| l.price)
+            .max()
+    }
+
+    /// Best (lowest) ask among the event's levels.
+    pub fn best_ask(&self) -> Option<u64> {
+        self.levels
+            .iter()
+            .filter(|l| l.side == Side::Sell && l.quantity > 0)
+            .map(|l| l.price)
+            .min()
+    }
+
+    /// Time from the exchange stamping the event to us receiving it, or
+    /// None if the feed sends no timestamp. Clock skew between us and the
+    /// venue is included.
+    pub fn network_latency_ms(&self) -> Option<u64> {
+        let exchange_timestamp = self.exchange_timestamp?;
+        let received_ms =
+            current_time_millis().saturating_sub(self.received_at.elapsed().as_millis() as u64);
+        Some(received_ms.saturating_sub(exchange_timestamp))
+    }
+
+    /// Time spent in our own pipeline since the event was received.
+    pub fn processing_latency(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+}
+
+impl fmt::Display for MarketEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {}",
+            self.exchange,
+            self.instrument,
+            self.kind.name()
+        )?;
+        if let Some(sequence) = self.sequence {
+            write!(f, " #{sequence}")?;
+        }
+        if !self.levels.is_empty() {
+            write!(f, " ({} levels", self.levels.len())?;
+            if let Some(bid) = self.best_bid() {
+                write!(f, ", bid {bid}")?;
+            }
+            if let Some(ask) = self.best_ask() {
+                write!(f, ", ask {ask}")?;
+            }
+            write!(f, ")")?;
+        }
+        if let Some(ts) = self.exchange_timestamp {
+            write!(f, " exchange_ts: {ts}ms,")?;
+        }
+        write!(f, " latency: {}μs", self.processing_latency().as_micros())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{Level, MarketEvent, MarketEventKind};
+    use crate::orderbook::{book::Exchange, Trade};
+
+    #[test]
+    fn test_trade_event_round_trip() {
+        let trade = Trade {
+            exchange: Exchange::Coinbase,
+            price: 40_023,
+            quantity: 5_000,
+            aggressor: Side::Sell,
+            trade_id: "10".to_string(),
+            exchange_timestamp: Some(1_000),
+            received_at: Instant::now(),
+        };
+        let event = MarketEvent::from_trade("BTC/USD", trade.clone());
+        assert_eq!(
+            event.kind,
+            MarketEventKind::Trade {
+                trade_id: "10".to_string()
+            }
+        );
+        assert!(!event.kind.updates_book());
+        assert_eq!(event.trade(), Some(trade));
+    }
+
+    #[test]
+    fn test_best_prices_skip_removed_levels() {
+        let mut event = MarketEvent::new(
+            Exchange::Kraken,
+            "BTC/USD",
+            MarketEventKind::LevelUpdate,
+            Instant::now(),
+        );
+        event.levels = vec![
+            Level::new(Side::Buy, 100, 5),
+            Level::new(Side::Buy, 101, 0),
+            Level::new(Side::Sell, 103, 2),
+            Level::new(Side::Sell, 102, 1),
+        ];
+        assert_eq!(event.best_bid(), Some(100));
+        assert_eq!(event.best_ask(), Some(102));
+        assert_eq!(event.trade(), None);
+    }
+}
@@ -1,123 +1,408 @@
-use crate::{api::ExchangePrice, util::parse_price_cents};
-use futures_util::{SinkExt, StreamExt};
-use std::time::Instant;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-use tracing::{error, info, warn};
+use std::{collections::BTreeMap, time::Instant};
+
+use anyhow::anyhow;
+use pricelevel::Side;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        backpressure::FeedSender,
+        event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        stream::{forward_events, reconnect_after, seconds_to_millis, EventSink},
+    },
+    orderbook::book::Exchange,
+    util::{parse_price_cents, parse_quantity_units},
+};
 
 const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
+const INSTRUMENT: &str = "BTC/USD";
+/// Levels per side we subscribe to, and keep when verifying checksums.
+const BOOK_DEPTH: usize = 10;
 
-pub struct KrakenClient {
-    tx: tokio::sync::mpsc::Sender<ExchangePrice>,
+pub struct KrakenClient<S = FeedSender> {
+    tx: S,
+    url: String,
 }
 
-impl KrakenClient {
-    pub fn new(tx: tokio::sync::mpsc::Sender<ExchangePrice>) -> Self {
-        KrakenClient { tx }
+impl<S: EventSink<MarketEvent>> KrakenClient<S> {
+    pub fn new(tx: S) -> Self {
+        KrakenClient {
+            tx,
+            url: KRAKEN_WS_URL.to_string(),
+        }
+    }
+
+    /// Streams the book from `url` instead of Kraken's public endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
     }
 
+    /// Streams the book, checking every update's checksum against a local
+    /// copy. A mismatch drops the connection, and resubscribing brings a
+    /// fresh snapshot.
     pub async fn listen_btc_usdt(&self) {
         info!("[Kraken] Connecting to BTC/USDT orderbook depth stream...");
 
-        match connect_async(KRAKEN_WS_URL).await {
-            Ok((mut ws_stream, _)) => {
-                info!("[Kraken] Connected successfully");
+        // Subscribe to XBT/USD orderbook (Kraken uses XBT for Bitcoin)
+        let subscribe_msg = serde_json::json!({
+            "event": "subscribe",
+            "pair": ["XBT/USD"],
+            "subscription": {
+                "name": "book",
+                "depth": BOOK_DEPTH
+            }
+        });
 
-                // Subscribe to XBT/USD orderbook (Kraken uses XBT for Bitcoin)
-                let subscribe_msg = serde_json::json!({
-                    "event": "subscribe",
-                    "pair": ["XBT/USD"],
-                    "subscription": {
-                        "name": "book"
-                    }
-                });
+        loop {
+            let mut book = ChecksumBook::default();
+            let decode = move |text: &str, received_at: Instant| book.decode(text, received_at);
+            let result = forward_events(
+                "Kraken",
+                &self.url,
+                vec![subscribe_msg.to_string()],
+                decode,
+                &self.tx,
+            )
+            .await;
+            let status = MarketEvent::status(
+                Exchange::Kraken,
+                INSTRUMENT,
+                FeedStatus::Disconnected,
+                Instant::now(),
+            );
+            if self.tx.deliver(status).await.is_err() {
+                return;
+            }
+            reconnect_after("Kraken", result).await;
+        }
+    }
+}
 
-                // Send subscription message
-                if let Err(e) = ws_stream
-                    .send(Message::Text(subscribe_msg.to_string()))
-                    .await
-                {
-                    error!("[Kraken] Failed to send subscription: {}", e);
-                    return;
-                }
+/// A level as sent: `["price", "volume", "timestamp", ...]`.
+struct RawLevel<'a> {
+    side: Side,
+    price: &'a str,
+    volume: &'a str,
+    timestamp: &'a str,
+}
+
+/// The levels and checksum carried by one `book` message.
+struct BookMessage<'a> {
+    snapshot: bool,
+    levels: Vec<RawLevel<'a>>,
+    checksum: Option<u32>,
+}
+
+/// Decodes a `book` channel message. Kraken format:
+/// `[channelID, {data}, ..., channelName, pair]`, where the first message
+/// carries the snapshot under `as`/`bs` and later ones carry updates under
+/// `a`/`b`, possibly split across two objects. The feed has no sequence
+/// numbers; the event time is the newest level timestamp.
+pub fn decode(text: &str, received_at: Instant) -> anyhow::Result<Vec<MarketEvent>> {
+    let value: Value = serde_json::from_str(text)?;
+    if let Some(status) = decode_status(&value, received_at)? {
+        return Ok(vec![status]);
+    }
+    match book_message(&value)? {
+        Some(message) => Ok(vec![book_event(&message, received_at)?]),
+        None => Ok(Vec::new()),
+    }
+}
 
-                let (_write, mut read) = ws_stream.split();
-
-                while let Some(msg) = read.next().await {
-                    match msg {
-                        Ok(Message::Text(text)) => {
-                            // Capture timestamp immediately when message received
-                            let received_at = Instant::now();
-                            if let Err(e) = self.handle_message(&text, received_at).await {
-                                warn!("[Kraken] Error handling message: {}", e);
-                            }
-                        }
-                        Ok(Message::Ping(data)) => {
-                            info!("[Kraken] Received ping");
-                        }
-                        Ok(Message::Close(_)) => {
-                            warn!("[Kraken] Connection closed");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("[Kraken] WebSocket error: {}", e);
-                            break;
-                        }
-                        _ => {}
+/// Handles subscription confirmation; other events (heartbeat, systemStatus) are skipped.
+fn decode_status(value: &Value, received_at: Instant) -> anyhow::Result<Option<MarketEvent>> {
+    if value.get("event").and_then(|e| e.as_str()) != Some("subscriptionStatus") {
+        return Ok(None);
+    }
+    match value.get("status").and_then(|s| s.as_str()) {
+        Some("subscribed") => Ok(Some(MarketEvent::status(
+            Exchange::Kraken,
+            INSTRUMENT,
+            FeedStatus::Subscribed,
+            received_at,
+        ))),
+        _ => Err(anyhow!(
+            "subscription failed: {}",
+            value.get("errorMessage").unwrap_or(&Value::Null)
+        )),
+    }
+}
+
+fn book_message(value: &Value) -> anyhow::Result<Option<BookMessage<'_>>> {
+    let Some(array) = value.as_array() else {
+        return Ok(None);
+    };
+    if array.len() < 4 {
+        return Ok(None);
+    }
+    let channel = array[array.len() - 2].as_str().unwrap_or_default();
+    if !channel.starts_with("book") {
+        return Ok(None);
+    }
+
+    let mut message = BookMessage {
+        snapshot: false,
+        levels: Vec::new(),
+        checksum: None,
+    };
+    for data in &array[1..array.len() - 2] {
+        let data = data
+            .as_object()
+            .ok_or_else(|| anyhow!("malformed book message"))?;
+        for (key, levels) in data {
+            let side = match key.as_str() {
+                "as" | "bs" => {
+                    message.snapshot = true;
+                    if key == "bs" {
+                        Side::Buy
+                    } else {
+                        Side::Sell
                     }
                 }
-            }
-            Err(e) => {
-                error!("[Kraken] Failed to connect: {}", e);
+                "b" => Side::Buy,
+                "a" => Side::Sell,
+                "c" => {
+                    let checksum = levels.as_str().and_then(|c| c.parse().ok());
+                    message.checksum = Some(checksum.ok_or_else(|| anyhow!("invalid checksum"))?);
+                    continue;
+                }
+                _ => continue,
+            };
+            for level in levels
+                .as_array()
+                .ok_or_else(|| anyhow!("malformed levels"))?
+            {
+                let field = |i: usize| {
+                    level
+                        .get(i)
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("malformed level"))
+                };
+                message.levels.push(RawLevel {
+                    side,
+                    price: field(0)?,
+                    volume: field(1)?,
+                    timestamp: field(2)?,
+                });
             }
         }
     }
+    Ok(Some(message))
+}
+
+fn book_event(message: &BookMessage<'_>, received_at: Instant) -> anyhow::Result<MarketEvent> {
+    let kind = if message.snapshot {
+        MarketEventKind::Snapshot
+    } else {
+        MarketEventKind::LevelUpdate
+    };
+    let mut event = MarketEvent::new(Exchange::Kraken, INSTRUMENT, kind, received_at);
+    for level in &message.levels {
+        // Fast u64 parsing - avoids f64 overhead for low-latency
+        let price = parse_price_cents(level.price).ok_or_else(|| anyhow!("invalid price"))?;
+        let quantity =
+            parse_quantity_units(level.volume).ok_or_else(|| anyhow!("invalid quantity"))?;
+        event.levels.push(Level::new(level.side, price, quantity));
+        event.exchange_timestamp = event
+            .exchange_timestamp
+            .max(seconds_to_millis(level.timestamp));
+    }
+    Ok(event)
+}
+
+/// Local copy of the subscribed depth, kept as the wire strings because
+/// Kraken's checksum is computed over them: CRC32 of the top 10 asks (best
+/// first) then the top 10 bids (best first), each as price then volume with
+/// the decimal point and leading zeros removed.
+#[derive(Default)]
+struct ChecksumBook {
+    /// price in cents -> (price, volume) as sent
+    asks: BTreeMap<u64, (String, String)>,
+    bids: BTreeMap<u64, (String, String)>,
+}
 
-    async fn handle_message(
-        &self,
-        text: &str,
-        received_at: Instant,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Basic validation - prevent injection attacks
-        if text.len() > 100_000 {
-            return Err("Message too large".into());
+impl ChecksumBook {
+    /// Decodes a message, applying book data to the local copy. A checksum
+    /// that doesn't match the copy means an update was missed.
+    fn decode(&mut self, text: &str, received_at: Instant) -> anyhow::Result<Vec<MarketEvent>> {
+        let value: Value = serde_json::from_str(text)?;
+        if let Some(status) = decode_status(&value, received_at)? {
+            return Ok(vec![status]);
         }
+        let Some(message) = book_message(&value)? else {
+            return Ok(Vec::new());
+        };
+        let mut event = book_event(&message, received_at)?;
 
-        // Parse Kraken message (can be array or object)
-        let value: serde_json::Value = serde_json::from_str(text)?;
+        if message.snapshot {
+            self.asks.clear();
+            self.bids.clear();
+        }
+        for (level, parsed) in message.levels.iter().zip(&event.levels) {
+            let side = match level.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            if parsed.quantity == 0 {
+                side.remove(&parsed.price);
+            } else {
+                side.insert(
+                    parsed.price,
+                    (level.price.to_string(), level.volume.to_string()),
+                );
+            }
+        }
+        // Levels pushed out of the subscribed depth are no longer updated, so
+        // the event removes them from the book too
+        let mut trimmed = Vec::new();
+        while self.asks.len() > BOOK_DEPTH {
+            if let Some((price, _)) = self.asks.pop_last() {
+                trimmed.push(Level::new(Side::Sell, price, 0));
+            }
+        }
+        while self.bids.len() > BOOK_DEPTH {
+            if let Some((price, _)) = self.bids.pop_first() {
+                trimmed.push(Level::new(Side::Buy, price, 0));
+            }
+        }
+        if message.snapshot {
+            // A snapshot only lists levels that exist
+            event.levels.retain(|level| {
+                !trimmed
+                    .iter()
+                    .any(|t| t.side == level.side && t.price == level.price)
+            });
+        } else {
+            event.levels.extend(trimmed);
+        }
 
-        // Handle subscription confirmation
-        if let Some(event) = value.get("event").and_then(|e| e.as_str()) {
-            info!("[Kraken] Event: {}", event);
-            return Ok(());
+        if let Some(expected) = message.checksum {
+            let actual = self.checksum();
+            if actual != expected {
+                warn!(
+                    "[Kraken] Book checksum mismatch: expected {}, computed {}",
+                    expected, actual
+                );
+                return Err(anyhow!("book checksum mismatch, resubscribing"));
+            }
         }
+        Ok(vec![event])
+    }
 
-        // Handle ticker data (array format)
-        // Kraken format: [channelID, {data}, channelName, pair]
-        if let Some(array) = value.as_array() {
-            if array.len() >= 4 {
-                if let Some(ticker_data) = array[1].as_object() {
-                    // Price is in ticker_data["c"][0]
-                    if let Some(price_str) = ticker_data
-                        .get("c")
-                        .and_then(|c| c.as_array())
-                        .and_then(|a| a.get(0))
-                        .and_then(|v| v.as_str())
-                    {
-                        // Fast u64 parsing - avoids f64 overhead for low-latency
-                        if let Some(price) = parse_price_cents(price_str) {
-                            // Kraken doesn't provide explicit timestamp in ticker, but we capture receive time
-                            self.tx.send(ExchangePrice::Kraken {
-                                price,
-                                exchange_timestamp: None, // Kraken ticker doesn't include timestamp
-                                received_at,
-                            }).await.ok();
-                            info!("[Kraken] XBT/USD: ${}", price_str);
-                        }
-                    }
-                }
+    fn checksum(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        let top = self
+            .asks
+            .values()
+            .take(BOOK_DEPTH)
+            .chain(self.bids.values().rev().take(BOOK_DEPTH));
+        for (price, volume) in top {
+            for field in [price, volume] {
+                let digits = field.replace('.', "");
+                hasher.update(digits.trim_start_matches('0').as_bytes());
             }
         }
+        hasher.finalize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{decode, ChecksumBook};
+    use crate::{
+        api::event::{FeedStatus, Level, MarketEventKind},
+        orderbook::book::OrderBook,
+    };
+
+    #[test]
+    fn test_decode_book_snapshot_and_update() {
+        let snapshot = r#"[0,{"as":[["5541.30000","2.50700000","1534614248.123678"]],"bs":[["5541.20000","1.52900000","1534614248.765567"]]},"book-10","XBT/USD"]"#;
+        let events = decode(snapshot, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::Snapshot);
+        assert_eq!(events[0].exchange_timestamp, Some(1534614248765));
+        assert_eq!(events[0].best_bid(), Some(554_120));
+        assert_eq!(events[0].best_ask(), Some(554_130));
+
+        // Ask and bid changes split across two objects; the zero volume removes a level
+        let update = r#"[1234,{"a":[["5541.30000","0.00000000","1534614335.345903"]]},{"b":[["5541.10000","0.40100000","1534614335.345904","r"]],"c":"974942666"},"book-10","XBT/USD"]"#;
+        let events = decode(update, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::LevelUpdate);
+        assert_eq!(
+            events[0].levels,
+            vec![
+                Level::new(Side::Sell, 554_130, 0),
+                Level::new(Side::Buy, 554_110, 40_100_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_subscription_status() {
+        let subscribed = r#"{"channelID":10001,"channelName":"book-10","event":"subscriptionStatus","pair":"XBT/USD","status":"subscribed","subscription":{"depth":10,"name":"book"}}"#;
+        let events = decode(subscribed, Instant::now()).unwrap();
+        assert_eq!(
+            events[0].kind,
+            MarketEventKind::Status(FeedStatus::Subscribed)
+        );
+
+        let failed = r#"{"errorMessage":"Currency pair not supported","event":"subscriptionStatus","status":"error"}"#;
+        assert!(decode(failed, Instant::now()).is_err());
+        assert!(decode(r#"{"event":"heartbeat"}"#, Instant::now())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_checksum_book_detects_missed_update() {
+        let mut book = ChecksumBook::default();
+        let snapshot = r#"[0,{"as":[["5541.30000","2.50700000","1534614248.123678"],["5541.80000","0.33000000","1534614098.345543"],["5542.70000","0.64700000","1534614244.654432"]],"bs":[["5541.20000","1.52900000","1534614248.765567"],["5539.90000","0.30000000","1534614241.769870"],["5539.50000","5.00000000","1534613831.243486"]]},"book-10","XBT/USD"]"#;
+        book.decode(snapshot, Instant::now()).unwrap();
+        assert_eq!(book.checksum(), 1710400350);
+
+        let update = r#"[0,{"a":[["5541.30000","0.00000000","1534614335.345903"]]},{"b":[["5539.90000","0.01000000","1534614335.345904"]],"c":"1650236179"},"book-10","XBT/USD"]"#;
+        let events = book.decode(update, Instant::now()).unwrap();
+        assert_eq!(events[0].kind, MarketEventKind::LevelUpdate);
+
+        // The venue's book moved on without us seeing the change
+        let update = r#"[0,{"b":[["5539.50000","4.00000000","1534614336.000000"]],"c":"1650236179"},"book-10","XBT/USD"]"#;
+        assert!(book.decode(update, Instant::now()).is_err());
+    }
+
+    #[test]
+    fn test_checksum_book_trims_to_depth() {
+        let mut book = ChecksumBook::default();
+        let asks: Vec<String> = (0..12)
+            .map(|i| format!(r#"["{}.00000","1.00000000","1534614248.123678"]"#, 5600 + i))
+            .collect();
+        let snapshot = format!(
+            r#"[0,{{"as":[{}],"bs":[]}},"book-10","XBT/USD"]"#,
+            asks.join(",")
+        );
+        let order_book = OrderBook::new("BTC/USD".to_string());
+        for event in book.decode(&snapshot, Instant::now()).unwrap() {
+            order_book.apply_market_event(&event);
+        }
+        assert_eq!(book.asks.len(), 10);
+        assert_eq!(book.asks.keys().last(), Some(&560_900));
+        let asks = order_book.consolidated_ladder(Side::Sell);
+        assert_eq!(asks.len(), 10);
+        assert_eq!(asks.keys().last(), Some(&560_900));
 
-        Ok(())
+        // A better ask pushes the worst one out of the subscribed depth
+        let update =
+            r#"[0,{"a":[["5599.00000","1.00000000","1534614249.123678"]]},"book-10","XBT/USD"]"#;
+        for event in book.decode(update, Instant::now()).unwrap() {
+            order_book.apply_market_event(&event);
+        }
+        let asks = order_book.consolidated_ladder(Side::Sell);
+        assert_eq!(asks.len(), 10);
+        assert_eq!(asks.keys().next(), Some(&559_900));
+        assert_eq!(asks.keys().last(), Some(&560_800));
     }
 }
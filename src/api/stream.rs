@@ -0,0 +1,124 @@
+//! # Websocket Stream Helpers
+//!
+//! Shared plumbing for the reconnecting streams (market data, private user
+//! data, public trades): connect and subscribe, decode each text frame into
+//! events and forward them to a sink, plus small field parsers used by every
+//! venue's decoder.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::DateTime;
+use futures_util::{SinkExt, StreamExt};
+use pricelevel::Side;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{info, warn};
+
+use super::{backpressure::FeedSender, event::MarketEvent};
+
+/// Delay before reconnecting a dropped stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Where a stream's decoded events go: a plain channel, the market data bus
+/// with its per-venue backpressure policy, or a feed's SPSC ring. Fails once
+/// the receiving side is gone.
+pub trait EventSink<T> {
+    fn deliver(&self, event: T) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+impl<T: Send> EventSink<T> for mpsc::Sender<T> {
+    async fn deliver(&self, event: T) -> anyhow::Result<()> {
+        self.send(event)
+            .await
+            .map_err(|_| anyhow!("event receiver dropped"))
+    }
+}
+
+impl EventSink<MarketEvent> for FeedSender {
+    async fn deliver(&self, event: MarketEvent) -> anyhow::Result<()> {
+        self.send(event).await
+    }
+}
+
+/// Connects to `url`, sends `subscriptions`, and forwards every decoded event
+/// until the connection closes or a message can't be decoded.
+pub(crate) async fn forward_events<T, F, S>(
+    venue: &str,
+    url: &str,
+    subscriptions: Vec<String>,
+    mut decode: F,
+    tx: &S,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&str, Instant) -> anyhow::Result<Vec<T>>,
+    S: EventSink<T>,
+{
+    let (mut ws_stream, _) = connect_async(url).await?;
+    info!("[{venue}] Stream connected to {url}");
+    for subscription in subscriptions {
+        ws_stream.send(Message::Text(subscription)).await?;
+    }
+
+    while let Some(msg) = ws_stream.next().await {
+        match msg? {
+            Message::Text(text) => {
+                // Capture timestamp immediately when message received
+                let received_at = Instant::now();
+                // Basic validation - prevent injection attacks
+                if text.len() > 100_000 {
+                    warn!("[{venue}] Dropping oversized message");
+                    continue;
+                }
+                for event in decode(&text, received_at)? {
+                    tx.deliver(event).await?;
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Waits before the next reconnect attempt, logging why the stream ended.
+pub(crate) async fn reconnect_after(venue: &str, result: anyhow::Result<()>) {
+    match result {
+        Ok(()) => warn!("[{venue}] Stream closed, reconnecting"),
+        Err(e) => warn!("[{venue}] Stream failed: {e}, reconnecting"),
+    }
+    tokio::time::sleep(RECONNECT_DELAY).await;
+}
+
+pub(crate) fn parse_side(side: &str) -> Option<Side> {
+    match side {
+        "BUY" | "buy" => Some(Side::Buy),
+        "SELL" | "sell" => Some(Side::Sell),
+        _ => None,
+    }
+}
+
+pub(crate) fn str_field<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a str> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("missing field {key}"))
+}
+
+/// RFC 3339 timestamp (as sent by Coinbase) to Unix milliseconds.
+pub(crate) fn rfc3339_millis(time: &str) -> Option<u64> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.timestamp_millis() as u64)
+}
+
+/// Kraken's "1560516023.070651" seconds to Unix milliseconds.
+pub(crate) fn seconds_to_millis(time: &str) -> Option<u64> {
+    let (seconds, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let millis = format!("{fraction:0<3}");
+    Some(seconds.parse::<u64>().ok()? * 1_000 + millis.get(..3)?.parse::<u64>().ok()?)
+}
@@ -0,0 +1,310 @@
+//! # Public Trade Streams
+//!
+//! Each venue's public trade channel decoded into the common `Trade`:
+//! - Binance: `@trade` (and `@aggTrade`, which decodes the same way)
+//! - Kraken: the `trade` channel
+//! - Coinbase: the `matches` channel
+//!
+//! Venues report the maker's side differently; every decoder converts it to
+//! the aggressor (taker) side so trade flow can be compared across venues.
+//! The client forwards each trade as a `MarketEvent` alongside the depth feeds.
+
+use std::time::Instant;
+
+use anyhow::anyhow;
+use pricelevel::Side;
+use serde_json::{json, Value};
+
+use crate::api::stream::{
+    forward_events, parse_side, reconnect_after, rfc3339_millis, seconds_to_millis, str_field,
+    EventSink,
+};
+use crate::{
+    api::{backpressure::FeedSender, event::MarketEvent},
+    orderbook::{book::Exchange, Trade},
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const BINANCE_TRADE_WS_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@trade";
+const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
+const COINBASE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+/// Streams one venue's public trades for BTC into `tx`.
+pub struct PublicTradeClient<S = FeedSender> {
+    exchange: Exchange,
+    tx: S,
+    /// Replaces the venue's public endpoint
+    url: Option<String>,
+}
+
+impl<S: EventSink<MarketEvent>> PublicTradeClient<S> {
+    pub fn new(exchange: Exchange, tx: S) -> Self {
+        Self {
+            exchange,
+            tx,
+            url: None,
+        }
+    }
+
+    /// Streams trades from `url` instead of the venue's public endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    fn instrument(&self) -> &'static str {
+        match self.exchange {
+            Exchange::Binance => "BTC/USDT",
+            Exchange::Kraken | Exchange::Coinbase => "BTC/USD",
+        }
+    }
+
+    fn endpoint(&self) -> (&str, Vec<String>) {
+        let (url, subscriptions) = match self.exchange {
+            Exchange::Binance => (BINANCE_TRADE_WS_URL, Vec::new()),
+            Exchange::Kraken => (
+                KRAKEN_WS_URL,
+                vec![json!({
+                    "event": "subscribe",
+                    "pair": ["XBT/USD"],
+                    "subscription": {"name": "trade"}
+                })
+                .to_string()],
+            ),
+            Exchange::Coinbase => (
+                COINBASE_WS_URL,
+                vec![json!({
+                    "type": "subscribe",
+                    "product_ids": ["BTC-USD"],
+                    "channels": ["matches"]
+                })
+                .to_string()],
+            ),
+        };
+        (self.url.as_deref().unwrap_or(url), subscriptions)
+    }
+
+    /// Streams trades forever, reconnecting whenever the connection drops.
+    pub async fn run(&self) {
+        let venue = format!("{:?}", self.exchange);
+        let instrument = self.instrument();
+        // Numbers Kraken's trades across reconnects, so their ids stay unique
+        let mut kraken_sequence = 0;
+        let mut decode = |text: &str, received_at: Instant| {
+            let trades = match self.exchange {
+                Exchange::Binance => decode_binance(text, received_at)?,
+                Exchange::Kraken => decode_kraken(text, received_at, &mut kraken_sequence)?,
+                Exchange::Coinbase => decode_coinbase(text, received_at)?,
+            };
+            Ok(trades
+                .into_iter()
+                .map(|trade| MarketEvent::from_trade(instrument, trade))
+                .collect())
+        };
+        loop {
+            let (url, subscriptions) = self.endpoint();
+            let result = forward_events(&venue, url, subscriptions, &mut decode, &self.tx).await;
+            reconnect_after(&venue, result).await;
+        }
+    }
+}
+
+/// Decodes a Binance `trade` or `aggTrade` event. `m` is set when the buyer
+/// was the maker, i.e. the seller took liquidity.
+pub fn decode_binance(text: &str, received_at: Instant) -> anyhow::Result<Vec<Trade>> {
+    let message: Value = serde_json::from_str(text)?;
+    let id_key = match message.get("e").and_then(|e| e.as_str()) {
+        Some("trade") => "t",
+        Some("aggTrade") => "a",
+        _ => return Ok(Vec::new()),
+    };
+    let trade_id = message
+        .get(id_key)
+        .and_then(|id| id.as_u64())
+        .ok_or_else(|| anyhow!("missing field {id_key}"))?;
+    let buyer_is_maker = message
+        .get("m")
+        .and_then(|m| m.as_bool())
+        .ok_or_else(|| anyhow!("missing field m"))?;
+
+    Ok(vec![Trade {
+        exchange: Exchange::Binance,
+        price: parse_price_cents(str_field(&message, "p")?)
+            .ok_or_else(|| anyhow!("invalid price"))?,
+        quantity: parse_quantity_units(str_field(&message, "q")?)
+            .ok_or_else(|| anyhow!("invalid quantity"))?,
+        aggressor: if buyer_is_maker {
+            Side::Sell
+        } else {
+            Side::Buy
+        },
+        trade_id: trade_id.to_string(),
+        exchange_timestamp: message.get("T").and_then(|t| t.as_u64()),
+        received_at,
+    }])
+}
+
+/// Decodes a Kraken `trade` message: `[channelID, [[price, volume, time,
+/// side, orderType, misc], ...], "trade", pair]`, where side is the taker's.
+/// Kraken sends no trade id, so one is built from the timestamp and
+/// `sequence`, which counts every Kraken trade decoded so far. Ids are unique
+/// for as long as the same counter is used, not across restarts.
+pub fn decode_kraken(
+    text: &str,
+    received_at: Instant,
+    sequence: &mut u64,
+) -> anyhow::Result<Vec<Trade>> {
+    let message: Value = serde_json::from_str(text)?;
+    // Events (heartbeat, subscriptionStatus) are objects
+    let Some(message) = message.as_array() else {
+        return Ok(Vec::new());
+    };
+    if message.get(2).and_then(|c| c.as_str()) != Some("trade") {
+        return Ok(Vec::new());
+    }
+    let trades = message
+        .get(1)
+        .and_then(|t| t.as_array())
+        .ok_or_else(|| anyhow!("missing trade list"))?;
+
+    trades
+        .iter()
+        .map(|trade| {
+            let field = |i: usize| {
+                trade
+                    .get(i)
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("missing trade field {i}"))
+            };
+            let time = field(2)?;
+            let aggressor = match field(3)? {
+                "b" => Side::Buy,
+                "s" => Side::Sell,
+                other => return Err(anyhow!("unknown trade side {other}")),
+            };
+            *sequence += 1;
+            Ok(Trade {
+                exchange: Exchange::Kraken,
+                price: parse_price_cents(field(0)?).ok_or_else(|| anyhow!("invalid price"))?,
+                quantity: parse_quantity_units(field(1)?)
+                    .ok_or_else(|| anyhow!("invalid quantity"))?,
+                aggressor,
+                trade_id: format!("{time}-{sequence}"),
+                exchange_timestamp: seconds_to_millis(time),
+                received_at,
+            })
+        })
+        .collect()
+}
+
+/// Decodes a Coinbase `match`. `side` is the maker order's side, so the
+/// aggressor is the opposite. The `last_match` sent on subscribe is skipped
+/// since it may be long stale.
+pub fn decode_coinbase(text: &str, received_at: Instant) -> anyhow::Result<Vec<Trade>> {
+    let message: Value = serde_json::from_str(text)?;
+    match str_field(&message, "type")? {
+        "match" => {}
+        "error" => {
+            let reason = message.get("message").and_then(|m| m.as_str());
+            return Err(anyhow!(
+                "matches channel error: {}",
+                reason.unwrap_or("unknown")
+            ));
+        }
+        _ => return Ok(Vec::new()),
+    }
+    let maker_side =
+        parse_side(str_field(&message, "side")?).ok_or_else(|| anyhow!("invalid side"))?;
+    let trade_id = message
+        .get("trade_id")
+        .and_then(|id| id.as_u64())
+        .ok_or_else(|| anyhow!("missing field trade_id"))?;
+
+    Ok(vec![Trade {
+        exchange: Exchange::Coinbase,
+        price: parse_price_cents(str_field(&message, "price")?)
+            .ok_or_else(|| anyhow!("invalid price"))?,
+        quantity: parse_quantity_units(str_field(&message, "size")?)
+            .ok_or_else(|| anyhow!("invalid quantity"))?,
+        aggressor: maker_side.opposite(),
+        trade_id: trade_id.to_string(),
+        exchange_timestamp: message
+            .get("time")
+            .and_then(|t| t.as_str())
+            .and_then(rfc3339_millis),
+        received_at,
+    }])
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{decode_binance, decode_coinbase, decode_kraken};
+
+    #[test]
+    fn test_decode_binance_trades() {
+        let now = Instant::now();
+        let trade = r#"{"e":"trade","E":1672515782136,"s":"BTCUSDT","t":12345,"p":"16500.10","q":"0.25","T":1672515782134,"m":true}"#;
+        let trades = decode_binance(trade, now).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, 1_650_010);
+        assert_eq!(trades[0].quantity, 25_000_000);
+        assert_eq!(trades[0].aggressor, Side::Sell);
+        assert_eq!(trades[0].trade_id, "12345");
+        assert_eq!(trades[0].exchange_timestamp, Some(1672515782134));
+
+        let agg = r#"{"e":"aggTrade","E":1672515782136,"s":"BTCUSDT","a":26129,"p":"16500.00","q":"1.5","f":100,"l":105,"T":1672515782136,"m":false}"#;
+        let trades = decode_binance(agg, now).unwrap();
+        assert_eq!(trades[0].aggressor, Side::Buy);
+        assert_eq!(trades[0].trade_id, "26129");
+
+        assert!(decode_binance(r#"{"result":null,"id":1}"#, now)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_decode_kraken_trades() {
+        let now = Instant::now();
+        let message = r#"[0,[["5541.20000","0.15850568","1534614057.321597","s","l",""],["6060.00000","0.02455000","1534614057.324998","b","l",""]],"trade","XBT/USD"]"#;
+        let mut sequence = 0;
+        let trades = decode_kraken(message, now, &mut sequence).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].price, 554_120);
+        assert_eq!(trades[0].quantity, 15_850_568);
+        assert_eq!(trades[0].aggressor, Side::Sell);
+        assert_eq!(trades[0].exchange_timestamp, Some(1534614057321));
+        assert_eq!(trades[1].aggressor, Side::Buy);
+        assert_eq!(trades[1].trade_id, "1534614057.324998-2");
+        // The same trades again, e.g. identical timestamps in a later message,
+        // still get new ids
+        let again = decode_kraken(message, now, &mut sequence).unwrap();
+        assert_eq!(again[1].trade_id, "1534614057.324998-4");
+
+        assert!(
+            decode_kraken(r#"{"event":"heartbeat"}"#, now, &mut sequence)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_decode_coinbase_match() {
+        let now = Instant::now();
+        let message = r#"{"type":"match","trade_id":10,"sequence":50,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","time":"2014-11-07T08:19:27.028Z","product_id":"BTC-USD","size":"5.23512","price":"400.23","side":"sell"}"#;
+        let trades = decode_coinbase(message, now).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, 40_023);
+        assert_eq!(trades[0].quantity, 523_512_000);
+        // Maker sold, so the taker bought
+        assert_eq!(trades[0].aggressor, Side::Buy);
+        assert_eq!(trades[0].trade_id, "10");
+        assert_eq!(trades[0].exchange_timestamp, Some(1415348367028));
+
+        let last_match = message.replace(r#""type":"match""#, r#""type":"last_match""#);
+        assert!(decode_coinbase(&last_match, now).unwrap().is_empty());
+    }
+}
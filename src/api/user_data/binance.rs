@@ -0,0 +1,206 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::api::stream::{forward_events, parse_side, reconnect_after, str_field};
+use crate::{
+    api::auth::{BinanceSigner, Method},
+    execution::{ExecType, ExecutionReport},
+    orderbook::book::Exchange,
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const BINANCE_USER_WS_URL: &str = "wss://stream.binance.com:9443/ws";
+const LISTEN_KEY_PATH: &str = "/api/v3/userDataStream";
+
+/// Listen keys expire after 60 minutes without a keepalive.
+const LISTEN_KEY_KEEPALIVE: Duration = Duration::from_secs(30 * 60);
+
+/// Binance user data stream: order updates as `executionReport` events.
+pub struct BinanceUserStream {
+    signer: Arc<BinanceSigner>,
+    http: reqwest::Client,
+    tx: mpsc::Sender<ExecutionReport>,
+}
+
+impl BinanceUserStream {
+    pub fn new(signer: BinanceSigner, tx: mpsc::Sender<ExecutionReport>) -> Self {
+        Self {
+            signer: Arc::new(signer),
+            http: reqwest::Client::new(),
+            tx,
+        }
+    }
+
+    async fn create_listen_key(&self) -> anyhow::Result<String> {
+        let response = self
+            .signer
+            .api_key_request(Method::Post, LISTEN_KEY_PATH, &[])
+            .send(&self.http)
+            .await?;
+        Ok(str_field(&response, "listenKey")?.to_string())
+    }
+
+    /// Streams execution reports forever, creating a fresh listen key on every
+    /// reconnect and keeping the current one alive in the background.
+    pub async fn run(&self) {
+        loop {
+            let listen_key = match self.create_listen_key().await {
+                Ok(key) => key,
+                Err(e) => {
+                    reconnect_after("Binance", Err(e)).await;
+                    continue;
+                }
+            };
+            let keepalive = spawn_listen_key_keepalive(
+                Arc::clone(&self.signer),
+                self.http.clone(),
+                listen_key.clone(),
+            );
+
+            let url = format!("{BINANCE_USER_WS_URL}/{listen_key}");
+            let result = forward_events("Binance", &url, Vec::new(), decode, &self.tx).await;
+            keepalive.abort();
+            reconnect_after("Binance", result).await;
+        }
+    }
+}
+
+fn spawn_listen_key_keepalive(
+    signer: Arc<BinanceSigner>,
+    http: reqwest::Client,
+    listen_key: String,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(LISTEN_KEY_KEEPALIVE);
+        // The first tick fires immediately and the key is brand new
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let request =
+                signer.api_key_request(Method::Put, LISTEN_KEY_PATH, &[("listenKey", &listen_key)]);
+            match request.send(&http).await {
+                Ok(_) => info!("[Binance] Listen key kept alive"),
+                Err(e) => warn!("[Binance] Listen key keepalive failed: {e}"),
+            }
+        }
+    })
+}
+
+/// Decodes one user data stream message. Balance and other account events
+/// produce no reports; an expired listen key is an error so the stream reconnects.
+pub fn decode(text: &str, received_at: Instant) -> anyhow::Result<Vec<ExecutionReport>> {
+    let event: Value = serde_json::from_str(text)?;
+    match event.get("e").and_then(|e| e.as_str()) {
+        Some("executionReport") => Ok(decode_execution_report(&event, received_at)?
+            .into_iter()
+            .collect()),
+        Some("listenKeyExpired") => Err(anyhow!("listen key expired")),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn decode_execution_report(
+    event: &Value,
+    received_at: Instant,
+) -> anyhow::Result<Option<ExecutionReport>> {
+    let exec_type = match str_field(event, "x")? {
+        "NEW" => ExecType::New,
+        "TRADE" => ExecType::Trade,
+        "CANCELED" => ExecType::Canceled,
+        "REJECTED" => ExecType::Rejected,
+        "EXPIRED" | "TRADE_PREVENTION" => ExecType::Expired,
+        // Amendments are not used by this system
+        _ => return Ok(None),
+    };
+    let venue_order_id = event
+        .get("i")
+        .and_then(|i| i.as_u64())
+        .ok_or_else(|| anyhow!("missing field i"))?;
+
+    // Cancels carry the new cancel request's id in `c` and ours in `C`
+    let client_order_id = match event.get("C").and_then(|c| c.as_str()) {
+        Some(original) if !original.is_empty() => original,
+        _ => str_field(event, "c")?,
+    };
+    let quantity = |key| {
+        str_field(event, key)
+            .and_then(|q| parse_quantity_units(q).ok_or_else(|| anyhow!("bad quantity {key}: {q}")))
+    };
+
+    let mut report = ExecutionReport::new(
+        Exchange::Binance,
+        venue_order_id.to_string(),
+        exec_type,
+        received_at,
+    );
+    report.client_order_id = Some(client_order_id.to_string());
+    report.side = parse_side(str_field(event, "S")?);
+    report.order_quantity = Some(quantity("q")?);
+    report.cumulative_quantity = Some(quantity("z")?);
+    report.exchange_timestamp = event.get("E").and_then(|e| e.as_u64());
+    if exec_type == ExecType::Trade {
+        report.trade_id = event
+            .get("t")
+            .and_then(|t| t.as_i64())
+            .map(|t| t.to_string());
+        report.last_quantity = quantity("l")?;
+        report.last_price =
+            parse_price_cents(str_field(event, "L")?).ok_or_else(|| anyhow!("bad last price"))?;
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::decode;
+    use crate::execution::ExecType;
+
+    #[test]
+    fn test_decode_trade_report() {
+        // Shape of the `executionReport` example in the Binance user data stream docs
+        let text = r#"{"e":"executionReport","E":1499405658658,"s":"BTCUSDT",
+            "c":"3f9e7a52-0d6b-4c53-9a1d-7b1f6d1c2e10","S":"BUY","o":"LIMIT","f":"GTC",
+            "q":"1.00000000","p":"95000.00","P":"0.00000000","F":"0.00000000","g":-1,"C":"",
+            "x":"TRADE","X":"PARTIALLY_FILLED","r":"NONE","i":4293153,"l":"0.25000000",
+            "z":"0.25000000","L":"94999.50","n":"0","N":null,"T":1499405658657,"t":42,
+            "w":false,"m":false,"M":true,"O":1499405658657}"#;
+
+        let reports = decode(text, Instant::now()).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.exec_type, ExecType::Trade);
+        assert_eq!(report.venue_order_id, "4293153");
+        assert!(report.order_id().is_some());
+        assert_eq!(report.side, Some(Side::Buy));
+        assert_eq!(report.trade_id.as_deref(), Some("42"));
+        assert_eq!(report.last_quantity, 25_000_000);
+        assert_eq!(report.last_price, 9_499_950);
+        assert_eq!(report.last_notional(), 2_374_987);
+        assert_eq!(report.order_quantity, Some(100_000_000));
+        assert_eq!(report.exchange_timestamp, Some(1499405658658));
+    }
+
+    #[test]
+    fn test_decode_cancel_uses_original_client_id() {
+        let text = r#"{"e":"executionReport","E":1,"c":"cancel-request","C":"original-order",
+            "S":"SELL","q":"1.0","z":"0.0","x":"CANCELED","i":7,"l":"0","L":"0"}"#;
+        let report = &decode(text, Instant::now()).unwrap()[0];
+        assert_eq!(report.exec_type, ExecType::Canceled);
+        assert_eq!(report.client_order_id.as_deref(), Some("original-order"));
+
+        let account = r#"{"e":"outboundAccountPosition","E":1,"B":[]}"#;
+        assert!(decode(account, Instant::now()).unwrap().is_empty());
+        assert!(decode(r#"{"e":"listenKeyExpired","E":1}"#, Instant::now()).is_err());
+    }
+}
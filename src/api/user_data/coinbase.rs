@@ -0,0 +1,234 @@
+use std::time::Instant;
+
+use anyhow::anyhow;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::api::stream::{forward_events, parse_side, reconnect_after, rfc3339_millis, str_field};
+use crate::{
+    api::auth::{CoinbaseSigner, Method},
+    execution::{ExecType, ExecutionReport},
+    orderbook::{book::Exchange, current_time_millis},
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const COINBASE_WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+/// Path the websocket subscription signature is computed over.
+const WS_AUTH_PATH: &str = "/users/self/verify";
+
+/// The Coinbase product trading `instrument`, e.g. `BTC-USD` for `BTC/USDT`:
+/// Coinbase lists USD books rather than USDT, as the depth feed subscribes to.
+pub fn product_id(instrument: &str) -> String {
+    match instrument.split_once('/') {
+        Some((base, "USDT")) => format!("{base}-USD"),
+        Some((base, quote)) => format!("{base}-{quote}"),
+        None => instrument.to_string(),
+    }
+}
+
+/// Coinbase `user` channel: the full-channel messages for our own orders.
+pub struct CoinbaseUserStream {
+    signer: CoinbaseSigner,
+    product_ids: Vec<String>,
+    tx: mpsc::Sender<ExecutionReport>,
+}
+
+impl CoinbaseUserStream {
+    pub fn new(
+        signer: CoinbaseSigner,
+        product_ids: Vec<String>,
+        tx: mpsc::Sender<ExecutionReport>,
+    ) -> Self {
+        Self {
+            signer,
+            product_ids,
+            tx,
+        }
+    }
+
+    /// Subscribe message signed like a `GET /users/self/verify` request.
+    fn subscription(&self) -> anyhow::Result<String> {
+        let timestamp = current_time_millis() / 1_000;
+        let signature = self
+            .signer
+            .signature(timestamp, Method::Get, WS_AUTH_PATH, "")?;
+        let credentials = self.signer.credentials();
+        Ok(json!({
+            "type": "subscribe",
+            "product_ids": self.product_ids,
+            "channels": ["user"],
+            "signature": signature,
+            "key": credentials.api_key,
+            "passphrase": credentials.passphrase(),
+            "timestamp": timestamp.to_string(),
+        })
+        .to_string())
+    }
+
+    /// Streams execution reports forever, re-signing the subscription on every reconnect.
+    pub async fn run(&self) {
+        loop {
+            let result = match self.subscription() {
+                Ok(subscription) => {
+                    forward_events(
+                        "Coinbase",
+                        COINBASE_WS_URL,
+                        vec![subscription],
+                        decode,
+                        &self.tx,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            reconnect_after("Coinbase", result).await;
+        }
+    }
+}
+
+/// Decodes one `user` channel message. `open` and `done`/`filled` add nothing
+/// beyond `received` and `match`; an `error` message fails the stream.
+pub fn decode(text: &str, received_at: Instant) -> anyhow::Result<Vec<ExecutionReport>> {
+    let message: Value = serde_json::from_str(text)?;
+    let report = match str_field(&message, "type")? {
+        "received" => Some(decode_received(&message, received_at)?),
+        "match" => Some(decode_match(&message, received_at)?),
+        "done" if message.get("reason") == Some(&json!("canceled")) => {
+            let mut report = ExecutionReport::new(
+                Exchange::Coinbase,
+                str_field(&message, "order_id")?,
+                ExecType::Canceled,
+                received_at,
+            );
+            report.side = parse_side(str_field(&message, "side")?);
+            report.exchange_timestamp = str_field(&message, "time").ok().and_then(rfc3339_millis);
+            Some(report)
+        }
+        "error" => {
+            return Err(anyhow!(
+                "{}",
+                message["reason"]
+                    .as_str()
+                    .or(message["message"].as_str())
+                    .unwrap_or("unknown error")
+            ))
+        }
+        _ => None,
+    };
+    Ok(report.into_iter().collect())
+}
+
+fn decode_received(message: &Value, received_at: Instant) -> anyhow::Result<ExecutionReport> {
+    let mut report = ExecutionReport::new(
+        Exchange::Coinbase,
+        str_field(message, "order_id")?,
+        ExecType::New,
+        received_at,
+    );
+    report.client_order_id = message
+        .get("client_oid")
+        .and_then(|c| c.as_str())
+        .filter(|c| !c.is_empty())
+        .map(str::to_string);
+    report.side = parse_side(str_field(message, "side")?);
+    // Market orders sized in quote currency carry `funds` instead of `size`
+    report.order_quantity = str_field(message, "size")
+        .ok()
+        .and_then(parse_quantity_units);
+    report.cumulative_quantity = Some(0);
+    report.exchange_timestamp = str_field(message, "time").ok().and_then(rfc3339_millis);
+    Ok(report)
+}
+
+/// A match names both orders; the authenticated fields say which one is ours.
+/// `side` is always the maker's side.
+fn decode_match(message: &Value, received_at: Instant) -> anyhow::Result<ExecutionReport> {
+    let maker_side =
+        parse_side(str_field(message, "side")?).ok_or_else(|| anyhow!("bad match side"))?;
+    let (order_id, side) = if message.get("taker_user_id").is_some() {
+        (str_field(message, "taker_order_id")?, maker_side.opposite())
+    } else {
+        (str_field(message, "maker_order_id")?, maker_side)
+    };
+
+    let mut report =
+        ExecutionReport::new(Exchange::Coinbase, order_id, ExecType::Trade, received_at);
+    report.side = Some(side);
+    report.trade_id = message.get("trade_id").map(|t| t.to_string());
+    report.last_quantity = parse_quantity_units(str_field(message, "size")?)
+        .ok_or_else(|| anyhow!("bad match size"))?;
+    report.last_price = parse_price_cents(str_field(message, "price")?)
+        .ok_or_else(|| anyhow!("bad match price"))?;
+    report.exchange_timestamp = str_field(message, "time").ok().and_then(rfc3339_millis);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{decode, product_id};
+    use crate::execution::ExecType;
+
+    #[test]
+    fn test_product_id() {
+        assert_eq!(product_id("BTC/USDT"), "BTC-USD");
+        assert_eq!(product_id("ETH/EUR"), "ETH-EUR");
+    }
+
+    #[test]
+    fn test_decode_taker_match() {
+        // Authenticated `match` example from the Coinbase Exchange websocket docs
+        let text = r#"{"type":"match","trade_id":10,"sequence":50,
+            "maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8",
+            "taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1",
+            "time":"2014-11-07T08:19:27.028459Z","product_id":"BTC-USD","size":"5.23512",
+            "price":"400.23","side":"sell","taker_user_id":"5844eceecf7e803e259d0365",
+            "user_id":"5844eceecf7e803e259d0365",
+            "taker_profile_id":"765d1549-9660-4be2-97d4-fa2d65fa3352",
+            "profile_id":"765d1549-9660-4be2-97d4-fa2d65fa3352","taker_fee_rate":"0.005"}"#;
+
+        let report = &decode(text, Instant::now()).unwrap()[0];
+        assert_eq!(report.exec_type, ExecType::Trade);
+        assert_eq!(
+            report.venue_order_id,
+            "132fb6ae-456b-4654-b4e0-d681ac05cea1"
+        );
+        // We took liquidity from a resting sell, so we bought
+        assert_eq!(report.side, Some(Side::Buy));
+        assert_eq!(report.trade_id.as_deref(), Some("10"));
+        assert_eq!(report.last_quantity, 523_512_000);
+        assert_eq!(report.last_price, 40_023);
+        assert_eq!(report.exchange_timestamp, Some(1415348367028));
+    }
+
+    #[test]
+    fn test_decode_order_lifecycle() {
+        let received = r#"{"type":"received","time":"2014-11-07T08:19:27.028459Z",
+            "product_id":"BTC-USD","sequence":10,"order_id":"d50ec984-77a8-460a-b958-66f114b0de9b",
+            "size":"1.34","price":"502.1","side":"buy","order_type":"limit",
+            "client_oid":"3f9e7a52-0d6b-4c53-9a1d-7b1f6d1c2e10"}"#;
+        let report = &decode(received, Instant::now()).unwrap()[0];
+        assert_eq!(report.exec_type, ExecType::New);
+        assert_eq!(report.order_quantity, Some(134_000_000));
+        assert!(report.order_id().is_some());
+
+        let canceled = r#"{"type":"done","time":"2014-11-07T08:19:27.028459Z",
+            "product_id":"BTC-USD","sequence":10,"price":"200.2","remaining_size":"1.0",
+            "order_id":"d50ec984-77a8-460a-b958-66f114b0de9b","reason":"canceled","side":"buy"}"#;
+        assert_eq!(
+            decode(canceled, Instant::now()).unwrap()[0].exec_type,
+            ExecType::Canceled
+        );
+
+        let filled = canceled.replace(r#""reason":"canceled""#, r#""reason":"filled""#);
+        assert!(decode(&filled, Instant::now()).unwrap().is_empty());
+        assert!(decode(
+            r#"{"type":"error","message":"Authentication Failed"}"#,
+            Instant::now()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::Instant;
+
+use anyhow::anyhow;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+
+use crate::api::stream::{
+    forward_events, parse_side, reconnect_after, seconds_to_millis, str_field,
+};
+use crate::{
+    api::auth::KrakenSigner,
+    execution::{ExecType, ExecutionReport},
+    orderbook::book::Exchange,
+    util::{parse_price_cents, parse_quantity_units},
+};
+
+const KRAKEN_AUTH_WS_URL: &str = "wss://ws-auth.kraken.com";
+const WEBSOCKETS_TOKEN_PATH: &str = "/0/private/GetWebSocketsToken";
+
+/// Kraken private feeds: fills from `ownTrades`, order lifecycle from `openOrders`.
+pub struct KrakenUserStream {
+    signer: KrakenSigner,
+    http: reqwest::Client,
+    tx: mpsc::Sender<ExecutionReport>,
+}
+
+impl KrakenUserStream {
+    pub fn new(signer: KrakenSigner, tx: mpsc::Sender<ExecutionReport>) -> Self {
+        Self {
+            signer,
+            http: reqwest::Client::new(),
+            tx,
+        }
+    }
+
+    /// Short-lived token that authenticates websocket subscriptions.
+    async fn websocket_token(&self) -> anyhow::Result<String> {
+        let response = self
+            .signer
+            .sign(WEBSOCKETS_TOKEN_PATH, &[])?
+            .send(&self.http)
+            .await?;
+        if let Some(error) = response
+            .get("error")
+            .and_then(|e| e.as_array())
+            .filter(|e| !e.is_empty())
+        {
+            return Err(anyhow!("GetWebSocketsToken failed: {error:?}"));
+        }
+        Ok(str_field(&response["result"], "token")?.to_string())
+    }
+
+    /// Streams execution reports forever, fetching a fresh token on every reconnect.
+    pub async fn run(&self) {
+        loop {
+            let token = match self.websocket_token().await {
+                Ok(token) => token,
+                Err(e) => {
+                    reconnect_after("Kraken", Err(e)).await;
+                    continue;
+                }
+            };
+            let result = forward_events(
+                "Kraken",
+                KRAKEN_AUTH_WS_URL,
+                subscriptions(&token),
+                decode,
+                &self.tx,
+            )
+            .await;
+            reconnect_after("Kraken", result).await;
+        }
+    }
+}
+
+fn subscriptions(token: &str) -> Vec<String> {
+    vec![
+        // Without `snapshot: false` the last 50 trades are replayed on every connect
+        json!({
+            "event": "subscribe",
+            "subscription": { "name": "ownTrades", "token": token, "snapshot": false }
+        })
+        .to_string(),
+        json!({
+            "event": "subscribe",
+            "subscription": { "name": "openOrders", "token": token }
+        })
+        .to_string(),
+    ]
+}
+
+/// Decodes one message from the authenticated endpoint. Heartbeats and status
+/// events produce no reports; a failed subscription is an error.
+pub fn decode(text: &str, received_at: Instant) -> anyhow::Result<Vec<ExecutionReport>> {
+    let value: Value = serde_json::from_str(text)?;
+
+    if let Some(event) = value.get("event").and_then(|e| e.as_str()) {
+        if event == "subscriptionStatus" && value.get("status") == Some(&json!("error")) {
+            return Err(anyhow!(
+                "subscription failed: {}",
+                value["errorMessage"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        return Ok(Vec::new());
+    }
+
+    // Private feeds are [[{id: {...}}, ...], channelName, {sequence}]
+    let Some(array) = value.as_array() else {
+        return Ok(Vec::new());
+    };
+    let (Some(entries), Some(channel)) = (
+        array.first().and_then(|e| e.as_array()),
+        array.get(1).and_then(|c| c.as_str()),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    let mut reports = Vec::new();
+    for (id, fields) in entries
+        .iter()
+        .filter_map(|e| e.as_object())
+        .flat_map(Map::iter)
+    {
+        let report = match channel {
+            "ownTrades" => Some(decode_trade(id, fields, received_at)?),
+            "openOrders" => decode_order_update(id, fields, received_at)?,
+            _ => None,
+        };
+        reports.extend(report);
+    }
+    Ok(reports)
+}
+
+fn decode_trade(
+    trade_id: &str,
+    trade: &Value,
+    received_at: Instant,
+) -> anyhow::Result<ExecutionReport> {
+    let mut report = ExecutionReport::new(
+        Exchange::Kraken,
+        str_field(trade, "ordertxid")?,
+        ExecType::Trade,
+        received_at,
+    );
+    report.trade_id = Some(trade_id.to_string());
+    report.side = parse_side(str_field(trade, "type")?);
+    report.last_quantity = parse_quantity_units(str_field(trade, "vol")?)
+        .ok_or_else(|| anyhow!("bad trade volume"))?;
+    report.last_price =
+        parse_price_cents(str_field(trade, "price")?).ok_or_else(|| anyhow!("bad trade price"))?;
+    report.exchange_timestamp = str_field(trade, "time").ok().and_then(seconds_to_millis);
+    Ok(report)
+}
+
+/// Order status changes. Fills arrive on `ownTrades`, so `closed` (fully
+/// filled) adds nothing here.
+fn decode_order_update(
+    order_id: &str,
+    order: &Value,
+    received_at: Instant,
+) -> anyhow::Result<Option<ExecutionReport>> {
+    let exec_type = match str_field(order, "status") {
+        // New orders arrive as `pending` with full details, then a bare `open`
+        Ok("pending") | Ok("open") => ExecType::New,
+        Ok("canceled") => ExecType::Canceled,
+        Ok("expired") => ExecType::Expired,
+        // `closed` or a volume-only update on an order we already know
+        _ => return Ok(None),
+    };
+
+    let mut report = ExecutionReport::new(Exchange::Kraken, order_id, exec_type, received_at);
+    report.client_order_id = order
+        .get("cl_ord_id")
+        .and_then(|c| c.as_str())
+        .map(str::to_string);
+    report.side = order
+        .get("descr")
+        .and_then(|d| d.get("type"))
+        .and_then(|t| t.as_str())
+        .and_then(parse_side);
+    report.order_quantity = str_field(order, "vol").ok().and_then(parse_quantity_units);
+    report.cumulative_quantity = str_field(order, "vol_exec")
+        .ok()
+        .and_then(parse_quantity_units);
+    report.exchange_timestamp = str_field(order, "lastupdated")
+        .or_else(|_| str_field(order, "opentm"))
+        .ok()
+        .and_then(seconds_to_millis);
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::decode;
+    use crate::execution::ExecType;
+
+    #[test]
+    fn test_decode_own_trades() {
+        // Payload from the Kraken websocket docs for `ownTrades`
+        let text = r#"[[{"TDLH43-DVQXD-2KHVYY":{"cost":"1000000.00000","fee":"1600.00000",
+            "margin":"0.00000","ordertxid":"TDLH43-DVQXD-2KHVYY","ordertype":"limit",
+            "pair":"XBT/EUR","postxid":"OGTT3Y-C6I3P-XRI6HX","price":"100000.00000",
+            "time":"1560516023.070651","type":"sell","vol":"1000000000.00000000"}}],
+            "ownTrades",{"sequence":2948}]"#;
+
+        let reports = decode(text, Instant::now()).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.exec_type, ExecType::Trade);
+        assert_eq!(report.venue_order_id, "TDLH43-DVQXD-2KHVYY");
+        assert_eq!(report.side, Some(Side::Sell));
+        assert_eq!(report.last_quantity, 100_000_000_000_000_000);
+        assert_eq!(report.last_price, 10_000_000);
+        assert_eq!(report.exchange_timestamp, Some(1560516023070));
+    }
+
+    #[test]
+    fn test_decode_open_orders() {
+        let text = r#"[[{"OGTT3Y-C6I3P-XRI6HX":{"status":"pending","vol":"1.25",
+            "vol_exec":"0.00000000","opentm":"1616492376.594","userref":0,
+            "cl_ord_id":"3f9e7a52-0d6b-4c53-9a1d-7b1f6d1c2e10",
+            "descr":{"pair":"XBT/USD","type":"buy","ordertype":"limit","price":"37500.0"}}},
+            {"OB5VMB-B4U2U-DK2WRW":{"status":"canceled","reason":"User requested"}},
+            {"OGTT3Y-C6I3P-XRI6HX":{"status":"closed","vol_exec":"1.25"}}],
+            "openOrders",{"sequence":59342}]"#;
+
+        let reports = decode(text, Instant::now()).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].exec_type, ExecType::New);
+        assert_eq!(reports[0].side, Some(Side::Buy));
+        assert_eq!(reports[0].order_quantity, Some(125_000_000));
+        assert!(reports[0].order_id().is_some());
+        assert_eq!(reports[1].exec_type, ExecType::Canceled);
+        assert_eq!(reports[1].venue_order_id, "OB5VMB-B4U2U-DK2WRW");
+
+        let failed = r#"{"event":"subscriptionStatus","status":"error",
+            "errorMessage":"EGeneral:Invalid arguments"}"#;
+        assert!(decode(failed, Instant::now()).is_err());
+        assert!(decode(r#"{"event":"heartbeat"}"#, Instant::now())
+            .unwrap()
+            .is_empty());
+    }
+}
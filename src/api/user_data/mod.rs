@@ -0,0 +1,18 @@
+//! # Private User-Data Streams
+//!
+//! Authenticated websocket streams that push our own order and fill updates,
+//! so executions are known within milliseconds instead of by polling. Each
+//! venue's messages are decoded into the common `ExecutionReport`:
+//! - Binance: user data stream behind a listen key that is kept alive over REST
+//! - Kraken: `ownTrades` and `openOrders` on the authenticated endpoint
+//! - Coinbase: the `user` channel, subscribed with a signed request
+//!
+//! Streams reconnect (and re-authenticate) whenever the connection drops.
+
+pub mod binance;
+pub mod coinbase;
+pub mod kraken;
+
+pub use binance::BinanceUserStream;
+pub use coinbase::CoinbaseUserStream;
+pub use kraken::KrakenUserStream;
@@ -0,0 +1,734 @@
+//! # Configuration
+//!
+//! Everything the aggregator used to hard-code, read from a TOML file (see
+//! `config.example.toml`):
+//! - `log_level` and the `instruments` to aggregate
+//! - `[pipeline]`: pipeline mode, price ladder, channel capacity and core layout
+//! - `[venues.<venue>]`: whether the venue is enabled, endpoint overrides, its
+//!   taker fee, backpressure policy and API credentials
+//! - `[thresholds]`: the minimum edge net of fees, the oldest quote an
+//!   opportunity may use, and the market-data checks
+//! - `[risk]`: pre-trade limits
+//! - `[inventory]`: the balances held on each venue, which cap opportunities
+//! - `[metrics]`: where the metrics endpoint listens
+//! - `[admin]`: where the admin endpoint listens, and the audit log
+//! - `[storage]`: the SQLite database opportunities and trades are written to
+//! - `[export]`: where and how often book snapshots and public trades are
+//!   exported for research
+//!
+//! Every key is optional and a missing key keeps its default. Unknown keys
+//! and invalid values are errors naming the key. API credentials can be left
+//! out of the file: `BINANCE_API_KEY`, `BINANCE_API_SECRET` and
+//! `BINANCE_PASSPHRASE` (likewise for the other venues) take precedence over
+//! it.
+//!
+//! The strategy parameters (see `reload`) can be reloaded from the file
+//! while running; everything else takes a restart.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Deserializer};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    api::{BackpressurePolicy, Credentials},
+    execution::RiskLimits,
+    orderbook::{book::Exchange, ValidationConfig},
+    pipeline::{PipelineMode, ThreadLayout},
+};
+
+pub mod reload;
+
+pub use reload::{ParamStore, StrategyParams};
+
+/// The only instrument the venue adapters subscribe to
+const SUPPORTED_INSTRUMENT: &str = "BTC/USDT";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// A `tracing` filter, e.g. `info` or `info,security_flamegraph_lowlatency=debug`
+    pub log_level: String,
+    pub instruments: Vec<String>,
+    pub pipeline: PipelineConfig,
+    /// Venues without a table keep their defaults
+    pub venues: HashMap<Exchange, VenueConfig>,
+    pub thresholds: Thresholds,
+    /// No limits unless the table is present, in which case every limit is required
+    pub risk: Option<RiskLimits>,
+    /// Opportunities aren't capped by balances unless the table is present
+    pub inventory: Option<InventoryConfig>,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub storage: StorageConfig,
+    pub export: ExportConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            instruments: vec![SUPPORTED_INSTRUMENT.to_string()],
+            pipeline: PipelineConfig::default(),
+            venues: HashMap::new(),
+            thresholds: Thresholds::default(),
+            risk: None,
+            inventory: None,
+            metrics: MetricsConfig::default(),
+            admin: AdminConfig::default(),
+            storage: StorageConfig::default(),
+            export: ExportConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LadderKind {
+    /// `MapLadder`
+    #[default]
+    Map,
+    /// `ArrayLadder` with a one cent tick
+    Array,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    #[serde(deserialize_with = "parse")]
+    pub mode: PipelineMode,
+    pub ladder: LadderKind,
+    /// Events each venue's queue (or ring) holds before backpressure applies
+    pub channel_capacity: usize,
+    /// Written as `binance=2,kraken=3,book=1,strategy=5-7,busy-poll`
+    #[serde(deserialize_with = "parse")]
+    pub cores: ThreadLayout,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            mode: PipelineMode::default(),
+            ladder: LadderKind::default(),
+            channel_capacity: 1000,
+            cores: ThreadLayout::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VenueConfig {
+    pub enabled: bool,
+    /// Replaces the venue's public depth websocket
+    pub depth_url: Option<String>,
+    /// Replaces the venue's public trade websocket
+    pub trades_url: Option<String>,
+    /// Replaces the REST depth snapshot endpoint (Binance only)
+    pub snapshot_url: Option<String>,
+    pub taker_fee_bps: u64,
+    pub backpressure: BackpressurePolicy,
+    pub api_key: Option<Secret>,
+    pub api_secret: Option<Secret>,
+    pub passphrase: Option<Secret>,
+}
+
+impl Default for VenueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_url: None,
+            trades_url: None,
+            snapshot_url: None,
+            taker_fee_bps: 10,
+            // A slow aggregator conflates pending book updates instead of
+            // stalling the websocket read loops
+            backpressure: BackpressurePolicy::Conflate,
+            api_key: None,
+            api_secret: None,
+            passphrase: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    /// Smallest edge worth acting on after both legs' taker fees, in basis points
+    pub min_edge_bps: u64,
+    /// Opportunities priced off a venue quote last updated longer ago than
+    /// this are ignored; 0 accepts quotes of any age
+    pub max_quote_age_ms: u64,
+    /// See `ValidationConfig`
+    pub price_band_bps: u64,
+    pub max_jump_bps: u64,
+    pub quarantine_secs: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        let validation = ValidationConfig::default();
+        Self {
+            min_edge_bps: 0,
+            max_quote_age_ms: 0,
+            price_band_bps: validation.price_band_bps,
+            max_jump_bps: validation.max_jump_bps,
+            quarantine_secs: validation.quarantine.as_secs(),
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn validation(&self) -> ValidationConfig {
+        ValidationConfig {
+            price_band_bps: self.price_band_bps,
+            max_jump_bps: self.max_jump_bps,
+            quarantine: Duration::from_secs(self.quarantine_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InventoryConfig {
+    /// Starting balances by venue and asset: the base asset in 1e-8 units,
+    /// the quote asset in cents. Missing ones are 0
+    pub balances: HashMap<Exchange, HashMap<String, i64>>,
+    /// Time between logged imbalance snapshots
+    pub report_secs: u64,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        Self {
+            balances: HashMap::new(),
+            report_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// The endpoint is off without a port
+    pub port: Option<u16>,
+    pub bind: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            bind: "127.0.0.1".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// The endpoint is off without a port
+    pub port: Option<u16>,
+    pub bind: String,
+    /// File every parameter reload is appended to, besides the log
+    pub audit_log: Option<PathBuf>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            bind: "127.0.0.1".to_string(),
+            audit_log: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Nothing is stored without a database
+    pub path: Option<PathBuf>,
+    /// Records waiting to be written before new ones are dropped
+    pub queue_capacity: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            queue_capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Nothing is exported without a directory
+    pub dir: Option<PathBuf>,
+    /// Time between book samples
+    pub interval_ms: u64,
+    /// Levels sampled per venue and side
+    pub depth: usize,
+    /// Also write CSV next to the Parquet
+    pub csv: bool,
+    /// How much of the day each file covers
+    pub roll_minutes: u64,
+    /// Samples and trades waiting to be written before new ones are dropped
+    pub queue_capacity: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            interval_ms: 1_000,
+            depth: 10,
+            csv: false,
+            roll_minutes: 60,
+            queue_capacity: 10_000,
+        }
+    }
+}
+
+/// A credential read from the config file. `Debug` never prints it.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Deserializes a string through the type's `FromStr`, so its parse errors
+/// are reported against the key.
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Each limit with its key under `[risk]`.
+fn risk_limits(risk: &RiskLimits) -> [(&'static str, u64); 6] {
+    [
+        ("max_order_notional", risk.max_order_notional),
+        ("max_position", risk.max_position),
+        ("max_open_orders", risk.max_open_orders as u64),
+        ("max_daily_loss", risk.max_daily_loss),
+        ("max_orders_per_second", risk.max_orders_per_second as u64),
+        ("price_band_bps", risk.price_band_bps),
+    ]
+}
+
+fn venue_key(exchange: Exchange) -> String {
+    format!("{exchange:?}").to_lowercase()
+}
+
+impl Config {
+    /// Reads and validates a config file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config from {}", path.display()))?;
+        Self::from_toml(&contents).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Parses and validates a config document.
+    pub fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        let config: Config = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the types alone can't, naming the offending key.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        EnvFilter::try_new(&self.log_level)
+            .map_err(|e| anyhow!("log_level: invalid filter {:?}: {e}", self.log_level))?;
+
+        if self.instruments.is_empty() {
+            return Err(anyhow!("instruments: at least one instrument is required"));
+        }
+        for (i, instrument) in self.instruments.iter().enumerate() {
+            if instrument != SUPPORTED_INSTRUMENT {
+                return Err(anyhow!(
+                    "instruments[{i}]: {instrument} is not supported, the venue adapters only stream {SUPPORTED_INSTRUMENT}"
+                ));
+            }
+        }
+
+        if self.pipeline.channel_capacity == 0 {
+            return Err(anyhow!("pipeline.channel_capacity: must be at least 1"));
+        }
+        self.pipeline
+            .cores
+            .validate()
+            .map_err(|e| anyhow!("pipeline.cores: {e}"))?;
+
+        if self.enabled_venues().is_empty() {
+            return Err(anyhow!("venues: every venue is disabled"));
+        }
+        for (exchange, venue) in &self.venues {
+            let key = format!("venues.{}", venue_key(*exchange));
+            for (name, url) in [
+                ("depth_url", &venue.depth_url),
+                ("trades_url", &venue.trades_url),
+            ] {
+                if let Some(url) = url {
+                    if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                        return Err(anyhow!("{key}.{name}: {url} is not a websocket URL"));
+                    }
+                }
+            }
+            if let Some(url) = &venue.snapshot_url {
+                if *exchange != Exchange::Binance {
+                    return Err(anyhow!(
+                        "{key}.snapshot_url: only Binance builds its book from a REST snapshot"
+                    ));
+                }
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(anyhow!("{key}.snapshot_url: {url} is not an HTTP URL"));
+                }
+            }
+            if venue.taker_fee_bps >= 10_000 {
+                return Err(anyhow!(
+                    "{key}.taker_fee_bps: {} is 100% or more",
+                    venue.taker_fee_bps
+                ));
+            }
+        }
+
+        if self.thresholds.price_band_bps == 0 || self.thresholds.max_jump_bps == 0 {
+            return Err(anyhow!(
+                "thresholds: price_band_bps and max_jump_bps must be positive"
+            ));
+        }
+
+        if let Some(risk) = &self.risk {
+            if let Some((name, _)) = risk_limits(risk).iter().find(|(_, limit)| *limit == 0) {
+                return Err(anyhow!("risk.{name}: must be positive"));
+            }
+        }
+
+        if let Some(inventory) = &self.inventory {
+            if inventory.report_secs == 0 {
+                return Err(anyhow!("inventory.report_secs: must be at least 1"));
+            }
+            let (base, quote) = self.assets();
+            for (exchange, balances) in &inventory.balances {
+                for (asset, balance) in balances {
+                    let key = format!("inventory.balances.{}.{asset}", venue_key(*exchange));
+                    if asset != base && asset != quote {
+                        return Err(anyhow!("{key}: not an asset of {}", self.instrument()));
+                    }
+                    if *balance < 0 {
+                        return Err(anyhow!("{key}: must not be negative"));
+                    }
+                }
+            }
+        }
+
+        for (key, port, bind) in [
+            ("metrics", self.metrics.port, &self.metrics.bind),
+            ("admin", self.admin.port, &self.admin.bind),
+        ] {
+            if port == Some(0) {
+                return Err(anyhow!("{key}.port: must not be 0"));
+            }
+            if bind.parse::<std::net::IpAddr>().is_err() {
+                return Err(anyhow!("{key}.bind: {bind} is not an IP address"));
+            }
+        }
+        if self.admin.port.is_some() && self.admin.port == self.metrics.port {
+            return Err(anyhow!("admin.port: already used by metrics.port"));
+        }
+        if self.storage.queue_capacity == 0 {
+            return Err(anyhow!("storage.queue_capacity: must be at least 1"));
+        }
+        for (name, value) in [
+            ("interval_ms", self.export.interval_ms),
+            ("depth", self.export.depth as u64),
+            ("roll_minutes", self.export.roll_minutes),
+            ("queue_capacity", self.export.queue_capacity as u64),
+        ] {
+            if value == 0 {
+                return Err(anyhow!("export.{name}: must be at least 1"));
+            }
+        }
+        if self.export.roll_minutes > 24 * 60 {
+            return Err(anyhow!(
+                "export.roll_minutes: files cannot span more than a day"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The venue's settings, or the defaults if it has no table.
+    pub fn venue(&self, exchange: Exchange) -> VenueConfig {
+        self.venues.get(&exchange).cloned().unwrap_or_default()
+    }
+
+    pub fn enabled_venues(&self) -> Vec<Exchange> {
+        Exchange::ALL
+            .into_iter()
+            .filter(|&exchange| self.venue(exchange).enabled)
+            .collect()
+    }
+
+    /// The book's symbol: the first instrument.
+    pub fn instrument(&self) -> &str {
+        self.instruments
+            .first()
+            .map(String::as_str)
+            .unwrap_or(SUPPORTED_INSTRUMENT)
+    }
+
+    /// The instrument's base and quote assets, e.g. `("BTC", "USDT")`.
+    pub fn assets(&self) -> (&str, &str) {
+        self.instrument()
+            .split_once('/')
+            .unwrap_or((self.instrument(), ""))
+    }
+
+    /// The venue's API credentials, each part taken from the environment
+    /// if set there and otherwise from the file. None if neither has a key.
+    pub fn credentials(&self, exchange: Exchange) -> anyhow::Result<Option<Credentials>> {
+        let venue = self.venue(exchange);
+        let key = venue_key(exchange);
+        let part = |name: &str, file: &Option<Secret>| {
+            std::env::var(format!("{}_{}", key.to_uppercase(), name.to_uppercase()))
+                .ok()
+                .or_else(|| file.as_ref().map(|secret| secret.0.clone()))
+        };
+        let api_key = part("api_key", &venue.api_key);
+        let api_secret = part("api_secret", &venue.api_secret);
+        let passphrase = part("passphrase", &venue.passphrase);
+        match (api_key, api_secret) {
+            (None, None) => Ok(None),
+            (Some(api_key), Some(api_secret)) => {
+                let credentials = Credentials::new(api_key, api_secret);
+                Ok(Some(match passphrase {
+                    Some(passphrase) => credentials.with_passphrase(passphrase),
+                    None => credentials,
+                }))
+            }
+            (Some(_), None) => Err(anyhow!("venues.{key}: api_key is set without api_secret")),
+            (None, Some(_)) => Err(anyhow!("venues.{key}: api_secret is set without api_key")),
+        }
+    }
+
+    /// The parameters `reload` can swap while running.
+    pub fn params(&self) -> StrategyParams {
+        StrategyParams {
+            min_edge_bps: self.thresholds.min_edge_bps,
+            taker_fee_bps: Exchange::ALL
+                .into_iter()
+                .map(|exchange| (exchange, self.venue(exchange).taker_fee_bps))
+                .collect(),
+            max_quote_age: (self.thresholds.max_quote_age_ms > 0)
+                .then(|| Duration::from_millis(self.thresholds.max_quote_age_ms)),
+            risk: self.risk,
+        }
+    }
+
+    /// The keys outside the strategy parameters that differ from `other`,
+    /// which only take effect after a restart.
+    pub fn restart_required(&self, other: &Config) -> Vec<String> {
+        let mut keys = Vec::new();
+        if self.log_level != other.log_level {
+            keys.push("log_level".to_string());
+        }
+        if self.instruments != other.instruments {
+            keys.push("instruments".to_string());
+        }
+        if self.pipeline != other.pipeline {
+            keys.push("pipeline".to_string());
+        }
+        for exchange in Exchange::ALL {
+            let ours = VenueConfig {
+                taker_fee_bps: 0,
+                ..self.venue(exchange)
+            };
+            let theirs = VenueConfig {
+                taker_fee_bps: 0,
+                ..other.venue(exchange)
+            };
+            if ours != theirs {
+                keys.push(format!("venues.{}", venue_key(exchange)));
+            }
+        }
+        let validation = |thresholds: &Thresholds| {
+            (
+                thresholds.price_band_bps,
+                thresholds.max_jump_bps,
+                thresholds.quarantine_secs,
+            )
+        };
+        if validation(&self.thresholds) != validation(&other.thresholds) {
+            keys.push("thresholds (market-data checks)".to_string());
+        }
+        if self.inventory != other.inventory {
+            keys.push("inventory".to_string());
+        }
+        if self.metrics != other.metrics {
+            keys.push("metrics".to_string());
+        }
+        if self.admin != other.admin {
+            keys.push("admin".to_string());
+        }
+        if self.storage != other.storage {
+            keys.push("storage".to_string());
+        }
+        if self.export != other.export {
+            keys.push("export".to_string());
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, LadderKind};
+    use crate::{
+        api::BackpressurePolicy,
+        orderbook::book::{ArbitrageOpportunity, Exchange},
+        pipeline::PipelineMode,
+    };
+
+    #[test]
+    fn test_example_config() {
+        let config = Config::from_toml(include_str!("../../config.example.toml")).unwrap();
+        assert_eq!(config.pipeline.mode, PipelineMode::Tokio);
+        assert_eq!(config.pipeline.ladder, LadderKind::Map);
+        assert_eq!(config.instrument(), "BTC/USDT");
+        assert!(config.risk.is_some());
+        assert_eq!(config.assets(), ("BTC", "USDT"));
+        let inventory = config.inventory.unwrap();
+        assert_eq!(inventory.balances[&Exchange::Kraken]["USDT"], 6_500_000);
+
+        // An empty file is the defaults
+        let defaults = Config::from_toml("").unwrap();
+        assert_eq!(defaults, Config::default());
+        assert_eq!(defaults.enabled_venues(), Exchange::ALL.to_vec());
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let config = Config::from_toml(
+            r#"
+            [pipeline]
+            mode = "ring"
+            ladder = "array"
+            channel_capacity = 64
+
+            [venues.kraken]
+            enabled = false
+
+            [venues.binance]
+            taker_fee_bps = 4
+            backpressure = "drop-oldest"
+            depth_url = "ws://127.0.0.1:9000"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.pipeline.mode, PipelineMode::Ring);
+        assert_eq!(config.pipeline.ladder, LadderKind::Array);
+        assert_eq!(config.pipeline.channel_capacity, 64);
+        assert_eq!(
+            config.enabled_venues(),
+            vec![Exchange::Binance, Exchange::Coinbase]
+        );
+        let binance = config.venue(Exchange::Binance);
+        assert_eq!(binance.backpressure, BackpressurePolicy::DropOldest);
+        assert_eq!(binance.depth_url.as_deref(), Some("ws://127.0.0.1:9000"));
+        assert_eq!(config.venue(Exchange::Coinbase).taker_fee_bps, 10);
+
+        // 20 bps gross, less 4 + 10 bps of fees
+        let opportunity = ArbitrageOpportunity {
+            buy_exchange: Exchange::Binance,
+            buy_price: 100_000,
+            sell_exchange: Exchange::Coinbase,
+            sell_price: 100_200,
+            quantity: 0,
+        };
+        let params = config.params();
+        assert_eq!(params.net_edge_bps(&opportunity), 6);
+        assert!(params.is_actionable(&opportunity));
+    }
+
+    #[test]
+    fn test_config_errors_name_the_key() {
+        let error = |toml: &str| format!("{:#}", Config::from_toml(toml).unwrap_err());
+
+        assert!(error("[venues.bitmex]\nenabled = true").contains("bitmex"));
+        assert!(error("[pipeline]\nmode = \"fast\"").contains("mode"));
+        assert!(error("[pipeline]\ncores = \"book=one\"").contains("cores"));
+        assert!(error("[thresholds]\nmin_edge = 5").contains("min_edge"));
+        assert!(error("[venues.kraken]\ntaker_fee_bps = -1").contains("taker_fee_bps"));
+        assert!(error("[venues.kraken]\nsnapshot_url = \"https://x\"")
+            .contains("venues.kraken.snapshot_url"));
+        assert!(error("[venues.coinbase]\ndepth_url = \"https://x\"")
+            .contains("venues.coinbase.depth_url"));
+        assert!(error("instruments = [\"BTC/USDT\", \"ETH/USDT\"]").contains("instruments[1]"));
+        assert!(error("log_level = \"info,=[\"").contains("log_level"));
+        assert!(error("[risk]\nmax_position = 1").contains("max_order_notional"));
+        assert!(error(
+            "[risk]\nmax_order_notional = 1\nmax_position = 0\nmax_open_orders = 1\n\
+             max_daily_loss = 1\nmax_orders_per_second = 1\nprice_band_bps = 1"
+        )
+        .contains("risk.max_position"));
+        assert!(error("[export]\ninterval_ms = 0").contains("export.interval_ms"));
+        assert!(
+            error("[inventory.balances.kraken]\nETH = 1").contains("inventory.balances.kraken.ETH")
+        );
+        assert!(error("[inventory.balances.binance]\nUSDT = -1")
+            .contains("inventory.balances.binance.USDT"));
+        assert!(error(
+            "[venues.binance]\nenabled = false\n[venues.kraken]\nenabled = false\n\
+             [venues.coinbase]\nenabled = false"
+        )
+        .contains("venues"));
+    }
+
+    #[test]
+    fn test_credentials_from_file_and_env() {
+        let config = Config::from_toml(
+            r#"
+            [venues.coinbase]
+            api_key = "file-key"
+            api_secret = "file-secret"
+            passphrase = "file-passphrase"
+
+            [venues.kraken]
+            api_key = "only-a-key"
+            "#,
+        )
+        .unwrap();
+        assert!(!format!("{config:?}").contains("file-secret"));
+
+        let coinbase = config.credentials(Exchange::Coinbase).unwrap().unwrap();
+        assert_eq!(coinbase.api_key, "file-key");
+        assert_eq!(coinbase.passphrase(), Some("file-passphrase"));
+        assert!(config.credentials(Exchange::Kraken).is_err());
+        assert!(config.credentials(Exchange::Binance).unwrap().is_none());
+
+        // The environment wins over the file
+        std::env::set_var("COINBASE_API_KEY", "env-key");
+        let coinbase = config.credentials(Exchange::Coinbase).unwrap().unwrap();
+        std::env::remove_var("COINBASE_API_KEY");
+        assert_eq!(coinbase.api_key, "env-key");
+    }
+}
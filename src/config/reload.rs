@@ -0,0 +1,586 @@
+//! # Parameter Reloads
+//!
+//! The strategy parameters can change while the aggregator runs:
+//! - `thresholds.min_edge_bps` and each venue's `taker_fee_bps`
+//! - `thresholds.max_quote_age_ms`
+//! - `[risk]`, pushed to every attached `RiskEngine`
+//!
+//! `ParamStore::reload` re-reads the config file, validates it and swaps the
+//! whole parameter set in at once, so a reader never sees half a reload. A
+//! file that fails validation changes nothing. Every attempt is logged, and
+//! appended to `admin.audit_log` if set, with each value that changed.
+//! Reloads are triggered by `SIGHUP` or `POST /reload` on the admin
+//! endpoint; other keys changed in the file are reported as needing a
+//! restart. The same endpoint trips the kill switch with `POST /kill` when a
+//! risk-checked gateway is attached.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use arc_swap::ArcSwap;
+use chrono::{SecondsFormat, Utc};
+use futures_util::future::BoxFuture;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{error, info, warn};
+
+use super::{risk_limits, venue_key, Config};
+use crate::{
+    execution::{ExecutionGateway, RiskCheckedGateway, RiskEngine, RiskLimits},
+    orderbook::book::{ArbitrageOpportunity, Exchange},
+};
+
+/// The parameters the strategy reads on every opportunity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrategyParams {
+    /// Smallest edge worth acting on after both legs' taker fees, in basis points
+    pub min_edge_bps: u64,
+    pub taker_fee_bps: HashMap<Exchange, u64>,
+    /// Oldest venue quote an opportunity may be priced off, if limited
+    pub max_quote_age: Option<Duration>,
+    pub risk: Option<RiskLimits>,
+}
+
+impl StrategyParams {
+    pub fn taker_fee_bps(&self, exchange: Exchange) -> u64 {
+        self.taker_fee_bps
+            .get(&exchange)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Gross edge less both legs' taker fees, in basis points of the buy price.
+    pub fn net_edge_bps(&self, opportunity: &ArbitrageOpportunity) -> i64 {
+        let gross = (opportunity.edge() * 10_000 / opportunity.buy_price.max(1)) as i64;
+        let fees = self.taker_fee_bps(opportunity.buy_exchange)
+            + self.taker_fee_bps(opportunity.sell_exchange);
+        gross - fees as i64
+    }
+
+    /// Whether the opportunity clears `min_edge_bps` after fees.
+    pub fn is_actionable(&self, opportunity: &ArbitrageOpportunity) -> bool {
+        self.net_edge_bps(opportunity) >= self.min_edge_bps as i64
+    }
+
+    /// Whether a quote last updated `age` ago is too old to trade on.
+    pub fn is_stale(&self, age: Duration) -> bool {
+        self.max_quote_age.is_some_and(|max| age > max)
+    }
+
+    /// Every value that differs in `new`, as `key: old -> new`.
+    pub fn changes(&self, new: &StrategyParams) -> Vec<String> {
+        let mut changes = Vec::new();
+        let mut compare = |key: String, old: u64, new: u64| {
+            if old != new {
+                changes.push(format!("{key}: {old} -> {new}"));
+            }
+        };
+        compare(
+            "thresholds.min_edge_bps".to_string(),
+            self.min_edge_bps,
+            new.min_edge_bps,
+        );
+        compare(
+            "thresholds.max_quote_age_ms".to_string(),
+            quote_age_ms(self.max_quote_age),
+            quote_age_ms(new.max_quote_age),
+        );
+        for exchange in Exchange::ALL {
+            compare(
+                format!("venues.{}.taker_fee_bps", venue_key(exchange)),
+                self.taker_fee_bps(exchange),
+                new.taker_fee_bps(exchange),
+            );
+        }
+        match (&self.risk, &new.risk) {
+            (Some(old), Some(new)) => {
+                for ((name, old), (_, new)) in risk_limits(old).into_iter().zip(risk_limits(new)) {
+                    compare(format!("risk.{name}"), old, new);
+                }
+            }
+            (None, Some(_)) => changes.push("risk: none -> set".to_string()),
+            (Some(_), None) => changes.push("risk: set -> none".to_string()),
+            (None, None) => {}
+        }
+        changes
+    }
+}
+
+/// How the admin endpoint shows the parameters, in the config file's keys.
+impl fmt::Display for StrategyParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "thresholds.min_edge_bps = {}", self.min_edge_bps)?;
+        writeln!(
+            f,
+            "thresholds.max_quote_age_ms = {}",
+            quote_age_ms(self.max_quote_age)
+        )?;
+        for exchange in Exchange::ALL {
+            writeln!(
+                f,
+                "venues.{}.taker_fee_bps = {}",
+                venue_key(exchange),
+                self.taker_fee_bps(exchange)
+            )?;
+        }
+        match &self.risk {
+            Some(risk) => {
+                for (name, limit) in risk_limits(risk) {
+                    writeln!(f, "risk.{name} = {limit}")?;
+                }
+            }
+            None => writeln!(f, "risk = none")?,
+        }
+        Ok(())
+    }
+}
+
+/// 0 for no limit, as in the config file.
+fn quote_age_ms(age: Option<Duration>) -> u64 {
+    age.map_or(0, |age| age.as_millis() as u64)
+}
+
+/// Trips a gateway's kill switch with the given reason, returning how many
+/// working orders were cancelled.
+type KillHook = Arc<dyn Fn(String) -> BoxFuture<'static, anyhow::Result<usize>> + Send + Sync>;
+
+/// The live parameter set, swapped whole on every reload.
+pub struct ParamStore {
+    params: ArcSwap<StrategyParams>,
+    /// The config the process started with; keys outside the parameters
+    /// are compared against it
+    startup: Config,
+    /// The file reloads read, if the process was started with one
+    path: Option<PathBuf>,
+    risk_engines: Vec<Arc<RiskEngine>>,
+    kill: Option<KillHook>,
+    /// Held for a whole reload, so two can't interleave
+    reloading: Mutex<()>,
+}
+
+impl ParamStore {
+    pub fn new(config: Config, path: Option<PathBuf>) -> Self {
+        Self {
+            params: ArcSwap::from_pointee(config.params()),
+            startup: config,
+            path,
+            risk_engines: Vec::new(),
+            kill: None,
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// Pushes reloaded risk limits to `engine`. Reloads that would remove
+    /// `[risk]` are then rejected.
+    pub fn with_risk_engine(mut self, engine: Arc<RiskEngine>) -> Self {
+        self.risk_engines.push(engine);
+        self
+    }
+
+    /// Lets `POST /kill` trip `gateway`'s kill switch and cancel everything it
+    /// still has working.
+    pub fn with_kill_switch<G: ExecutionGateway + 'static>(
+        mut self,
+        gateway: Arc<RiskCheckedGateway<G>>,
+    ) -> Self {
+        self.kill = Some(Arc::new(move |reason| {
+            let gateway = Arc::clone(&gateway);
+            Box::pin(async move { gateway.kill(reason).await })
+        }));
+        self
+    }
+
+    /// Trips the kill switch and cancels working orders, `None` if no
+    /// gateway is attached.
+    pub async fn kill(&self, reason: String) -> Option<anyhow::Result<usize>> {
+        let kill = self.kill.as_ref()?;
+        Some(kill(reason).await)
+    }
+
+    /// The current parameters. Cheap enough to call per opportunity; hold on
+    /// to the result to read one consistent set.
+    pub fn params(&self) -> Arc<StrategyParams> {
+        self.params.load_full()
+    }
+
+    /// Re-reads the config file and applies it. `source` names the trigger
+    /// in the audit log.
+    pub fn reload(&self, source: &str) -> anyhow::Result<Vec<String>> {
+        let config = match &self.path {
+            Some(path) => Config::load(path),
+            None => Err(anyhow!(
+                "no config file to reload, the process was started without --config"
+            )),
+        };
+        match config {
+            Ok(config) => self.apply(config, source),
+            Err(e) => {
+                self.audit(source, &format!("rejected: {e:#}"));
+                Err(e)
+            }
+        }
+    }
+
+    /// Validates `config` and swaps in its parameters, returning each value
+    /// that changed. Nothing changes if validation fails.
+    pub fn apply(&self, config: Config, source: &str) -> anyhow::Result<Vec<String>> {
+        let _reloading = self.reloading.lock().unwrap_or_else(|e| e.into_inner());
+        let checked = config.validate().and_then(|()| {
+            if config.risk.is_none() && !self.risk_engines.is_empty() {
+                return Err(anyhow!(
+                    "risk: the limits can't be removed while a risk engine is running"
+                ));
+            }
+            Ok(())
+        });
+        if let Err(e) = checked {
+            self.audit(source, &format!("rejected: {e:#}"));
+            return Err(e);
+        }
+
+        let params = config.params();
+        let changes = self.params.load().changes(&params);
+        if let Some(risk) = params.risk {
+            for engine in &self.risk_engines {
+                engine.set_limits(risk);
+            }
+        }
+        self.params.store(Arc::new(params));
+
+        if changes.is_empty() {
+            self.audit(source, "applied: no changes");
+        } else {
+            self.audit(source, &format!("applied: {}", changes.join(", ")));
+        }
+        let restart = self.startup.restart_required(&config);
+        if !restart.is_empty() {
+            warn!(
+                "[Params] Changes to {} take effect after a restart",
+                restart.join(", ")
+            );
+        }
+        Ok(changes)
+    }
+
+    fn audit(&self, source: &str, outcome: &str) {
+        if outcome.starts_with("rejected") {
+            warn!("[Params] Reload from {} {}", source, outcome);
+        } else {
+            info!("[Params] Reload from {} {}", source, outcome);
+        }
+        let Some(path) = &self.startup.admin.audit_log else {
+            return;
+        };
+        // One line per entry, though TOML errors span several
+        let outcome: Vec<_> = outcome
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let entry = format!(
+            "{} {source} {}\n",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            outcome.join(" ")
+        );
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(entry.as_bytes()));
+        if let Err(e) = written {
+            error!(
+                "[Params] Failed to write audit log {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Reloads the parameters when the process receives `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_reload_signal_listener(
+    store: Arc<ParamStore>,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            // Failures are already logged and audited
+            store.reload("SIGHUP").ok();
+        }
+    }))
+}
+
+/// Serves the admin endpoint on `addr`. Only returns if the listener can't
+/// be bound.
+/// - `GET /params`: the current parameters
+/// - `POST /reload`: reloads the config file, answering with each value that
+///   changed, or 422 and the reason if the file was rejected
+/// - `POST /kill`: trips the kill switch and cancels every working order,
+///   503 if no risk-checked gateway is attached
+pub async fn serve_admin(addr: SocketAddr, store: Arc<ParamStore>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("[Params] Admin endpoint on http://{}", addr);
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("[Params] Accept failed: {}", e);
+                continue;
+            }
+        };
+        let store = Arc::clone(&store);
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let read = match stream.read(&mut request).await {
+                Ok(read) => read,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&request[..read]);
+            let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+            let route = (request_line.next(), request_line.next());
+            let (status, body) = match route {
+                (Some("GET"), Some("/params")) => ("200 OK", store.params().to_string()),
+                (Some("POST"), Some("/reload")) => {
+                    match store.reload(&format!("admin endpoint ({peer})")) {
+                        Ok(changes) if changes.is_empty() => ("200 OK", "no changes\n".to_string()),
+                        Ok(changes) => ("200 OK", changes.join("\n") + "\n"),
+                        Err(e) => ("422 Unprocessable Entity", format!("{e:#}\n")),
+                    }
+                }
+                (Some("POST"), Some("/kill")) => {
+                    match store.kill(format!("admin endpoint ({peer})")).await {
+                        Some(Ok(cancelled)) => (
+                            "200 OK",
+                            format!("kill switch triggered, {cancelled} orders cancelled\n"),
+                        ),
+                        Some(Err(e)) => (
+                            "500 Internal Server Error",
+                            format!("kill switch triggered, cancelling failed: {e:#}\n"),
+                        ),
+                        None => (
+                            "503 Service Unavailable",
+                            "no risk-checked gateway attached\n".to_string(),
+                        ),
+                    }
+                }
+                _ => ("404 Not Found", "not found\n".to_string()),
+            };
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.ok();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use pricelevel::{Side, TimeInForce};
+
+    use super::ParamStore;
+    use crate::{
+        config::Config,
+        execution::{ExecutionGateway, OrderRequest, PaperGateway, RiskCheckedGateway, RiskEngine},
+        orderbook::{
+            book::{ArbitrageOpportunity, Exchange},
+            MatchingEngine,
+        },
+    };
+
+    const RISK: &str = "[risk]\nmax_order_notional = 1000\nmax_position = 10\n\
+        max_open_orders = 2\nmax_daily_loss = 500\nmax_orders_per_second = 5\n\
+        price_band_bps = 100\n";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn test_reload_swaps_validated_params() {
+        let path = temp_path("reload.toml");
+        let audit_log = temp_path("reload-audit.log");
+        let admin = format!(
+            "[admin]\naudit_log = {:?}\n",
+            audit_log.display().to_string()
+        );
+        std::fs::write(&path, format!("{admin}{RISK}")).unwrap();
+        let config = Config::load(&path).unwrap();
+        let engine = Arc::new(RiskEngine::new(config.risk.unwrap()));
+        let store =
+            ParamStore::new(config, Some(path.clone())).with_risk_engine(Arc::clone(&engine));
+
+        std::fs::write(
+            &path,
+            format!(
+                "{admin}[thresholds]\nmin_edge_bps = 5\nmax_quote_age_ms = 250\n\
+                 [venues.kraken]\ntaker_fee_bps = 20\n{}",
+                RISK.replace("max_position = 10", "max_position = 20")
+            ),
+        )
+        .unwrap();
+        let changes = store.reload("test").unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                "thresholds.min_edge_bps: 0 -> 5",
+                "thresholds.max_quote_age_ms: 0 -> 250",
+                "venues.kraken.taker_fee_bps: 10 -> 20",
+                "risk.max_position: 10 -> 20",
+            ]
+        );
+        let params = store.params();
+        assert_eq!(params.min_edge_bps, 5);
+        assert_eq!(params.taker_fee_bps(Exchange::Kraken), 20);
+        assert!(params.is_stale(Duration::from_millis(300)));
+        assert!(!params.is_stale(Duration::from_millis(200)));
+        assert_eq!(engine.limits().max_position, 20);
+
+        // 20 bps gross, less 20 + 10 bps of fees
+        let opportunity = ArbitrageOpportunity {
+            buy_exchange: Exchange::Kraken,
+            buy_price: 100_000,
+            sell_exchange: Exchange::Coinbase,
+            sell_price: 100_200,
+            quantity: 0,
+        };
+        assert_eq!(params.net_edge_bps(&opportunity), -10);
+        assert!(!params.is_actionable(&opportunity));
+
+        // An invalid file, or one dropping the running engine's limits,
+        // changes nothing
+        std::fs::write(&path, format!("{admin}[thresholds]\nmin_edge = 1\n{RISK}")).unwrap();
+        assert!(store.reload("test").is_err());
+        std::fs::write(&path, format!("{admin}[thresholds]\nmin_edge_bps = 1\n")).unwrap();
+        let error = store.reload("test").unwrap_err();
+        assert!(error.to_string().starts_with("risk:"));
+        assert_eq!(store.params(), params);
+        assert_eq!(engine.limits().max_position, 20);
+
+        let audit = std::fs::read_to_string(&audit_log).unwrap();
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&audit_log).ok();
+        let entries: Vec<_> = audit.lines().collect();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].contains("test applied: thresholds.min_edge_bps: 0 -> 5"));
+        assert!(entries[1].contains("test rejected:"));
+        assert!(entries[1].contains("min_edge"));
+        assert!(entries[2].contains("test rejected: risk:"));
+    }
+
+    async fn request(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = loop {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoint() {
+        let path = temp_path("admin.toml");
+        std::fs::write(&path, "").unwrap();
+        let store = Arc::new(ParamStore::new(Config::default(), Some(path.clone())));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(super::serve_admin(addr, Arc::clone(&store)));
+
+        let params = request(addr, "GET /params HTTP/1.1\r\n\r\n").await;
+        assert!(params.starts_with("HTTP/1.1 200 OK"));
+        assert!(params.contains("venues.binance.taker_fee_bps = 10"));
+        assert!(params.contains("risk = none"));
+
+        std::fs::write(&path, "[venues.binance]\ntaker_fee_bps = 4\n").unwrap();
+        let reloaded = request(addr, "POST /reload HTTP/1.1\r\n\r\n").await;
+        assert!(reloaded.starts_with("HTTP/1.1 200 OK"));
+        assert!(reloaded.ends_with("venues.binance.taker_fee_bps: 10 -> 4\n"));
+        assert_eq!(store.params().taker_fee_bps(Exchange::Binance), 4);
+
+        std::fs::write(&path, "[venues.binance]\ntaker_fee_bps = 10000\n").unwrap();
+        let rejected = request(addr, "POST /reload HTTP/1.1\r\n\r\n").await;
+        std::fs::remove_file(&path).ok();
+        assert!(rejected.starts_with("HTTP/1.1 422"));
+        assert!(rejected.contains("venues.binance.taker_fee_bps"));
+        assert_eq!(store.params().taker_fee_bps(Exchange::Binance), 4);
+
+        let missing = request(addr, "GET /reload HTTP/1.1\r\n\r\n").await;
+        assert!(missing.starts_with("HTTP/1.1 404"));
+
+        // Nothing to cancel through without a gateway
+        let unavailable = request(addr, "POST /kill HTTP/1.1\r\n\r\n").await;
+        assert!(unavailable.starts_with("HTTP/1.1 503"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_kill() {
+        let paper = PaperGateway::new();
+        let engine = Arc::new(MatchingEngine::new(Exchange::Binance, "BTC/USD"));
+        paper.add_engine(Arc::clone(&engine));
+        let path = temp_path("kill.toml");
+        std::fs::write(&path, RISK).unwrap();
+        let config = Config::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let risk = Arc::new(RiskEngine::new(config.risk.unwrap()));
+        risk.update_reference_mid(100);
+        let gateway = Arc::new(RiskCheckedGateway::new(Arc::new(paper), Arc::clone(&risk)));
+        let resting = OrderRequest {
+            time_in_force: TimeInForce::Gtc,
+            ..OrderRequest::ioc(Exchange::Binance, Side::Buy, 3, 99)
+        };
+        gateway.submit_order(resting).await.unwrap();
+        assert_eq!(engine.order_count(), 1);
+
+        let store = Arc::new(
+            ParamStore::new(config, None)
+                .with_risk_engine(Arc::clone(&risk))
+                .with_kill_switch(Arc::clone(&gateway)),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(super::serve_admin(addr, store));
+
+        let killed = request(addr, "POST /kill HTTP/1.1\r\n\r\n").await;
+        assert!(killed.starts_with("HTTP/1.1 200 OK"));
+        assert!(killed.ends_with("kill switch triggered, 1 orders cancelled\n"));
+        assert_eq!(engine.order_count(), 0);
+        assert_eq!(risk.open_orders(), 0);
+        assert!(risk
+            .kill_switch()
+            .reason()
+            .unwrap()
+            .starts_with("admin endpoint"));
+        assert!(gateway
+            .submit_order(OrderRequest::ioc(Exchange::Binance, Side::Buy, 1, 100))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! # Execution Algorithms
+//!
+//! Works a parent order over time instead of all at once:
+//! - TWAP: equal slices over the horizon, each routed through the smart order
+//!   router; slices that fall short are caught up in the next one
+//! - VWAP: each interval, trades a share of the volume observed on every venue
+//!   during that interval, sent straight to the venue it was observed on
+//!
+//! Both cap each slice at `max_participation_bps` of the observed volume,
+//! skip slices while the market is through the parent's limit price, and
+//! publish an `AlgoProgress` after every slice.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use futures_util::future::join_all;
+use pricelevel::Side;
+use tokio::{
+    sync::watch,
+    time::{interval_at, Instant},
+};
+use tracing::info;
+
+use super::{ExecutionGateway, OrderRequest, ParentOrder, SmartOrderRouter};
+pub use crate::orderbook::VolumeTracker;
+use crate::{
+    orderbook::book::{Exchange, OrderBook},
+    util,
+};
+
+/// Share of observed volume VWAP trades when no participation cap is set.
+const DEFAULT_VWAP_PARTICIPATION_BPS: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgoStyle {
+    Twap,
+    Vwap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlgoConfig {
+    pub style: AlgoStyle,
+    /// Total time to work the order over
+    pub horizon: Duration,
+    /// Time between slices, more than zero
+    pub slice_interval: Duration,
+    /// Most of the volume observed in an interval a slice may take, in bps.
+    /// For VWAP this is also the rate it trades at.
+    pub max_participation_bps: Option<u64>,
+}
+
+impl AlgoConfig {
+    /// Number of slices the horizon is cut into, at least one.
+    pub fn slice_count(&self) -> u64 {
+        let interval = self.slice_interval.as_nanos().max(1);
+        (self.horizon.as_nanos().div_ceil(interval) as u64).max(1)
+    }
+}
+
+/// How far an algo has got with its parent order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AlgoProgress {
+    pub target_quantity: u64,
+    pub filled_quantity: u64,
+    pub notional: u64,
+    pub slices_completed: u64,
+    pub slices_total: u64,
+    /// Slices skipped because the market was through the limit price
+    pub slices_skipped: u64,
+    pub finished: bool,
+}
+
+impl AlgoProgress {
+    pub fn remaining_quantity(&self) -> u64 {
+        self.target_quantity.saturating_sub(self.filled_quantity)
+    }
+
+    pub fn average_price(&self) -> Option<u64> {
+        util::unit_price(self.notional, self.filled_quantity)
+    }
+}
+
+pub struct ExecutionAlgo<G> {
+    router: Arc<SmartOrderRouter<G>>,
+    book: Arc<OrderBook>,
+    volumes: Arc<VolumeTracker>,
+    config: AlgoConfig,
+}
+
+impl<G: ExecutionGateway> ExecutionAlgo<G> {
+    /// Fails for a zero `slice_interval`, which can't be ticked.
+    pub fn new(
+        router: Arc<SmartOrderRouter<G>>,
+        book: Arc<OrderBook>,
+        volumes: Arc<VolumeTracker>,
+        config: AlgoConfig,
+    ) -> anyhow::Result<Self> {
+        if config.slice_interval.is_zero() {
+            return Err(anyhow!("slice_interval must be more than zero"));
+        }
+        Ok(Self {
+            router,
+            book,
+            volumes,
+            config,
+        })
+    }
+
+    /// TWAP quantity for slice `index`: whatever brings the filled total up to
+    /// an even share of the parent, so shortfalls roll into the next slice.
+    pub fn twap_slice(&self, parent_quantity: u64, index: u64, filled: u64) -> u64 {
+        let slices = self.config.slice_count();
+        let target = parent_quantity * (index + 1).min(slices) / slices;
+        target.saturating_sub(filled)
+    }
+
+    /// VWAP children for one interval: the participation rate applied to each
+    /// venue's observed volume, trimmed so the total never exceeds `remaining`.
+    pub fn vwap_children(
+        &self,
+        volumes: &[(Exchange, u64)],
+        remaining: u64,
+    ) -> Vec<(Exchange, u64)> {
+        let rate = self
+            .config
+            .max_participation_bps
+            .unwrap_or(DEFAULT_VWAP_PARTICIPATION_BPS);
+        let mut left = remaining;
+        volumes
+            .iter()
+            .filter_map(|&(exchange, volume)| {
+                let quantity = (volume * rate / 10_000).min(left);
+                left -= quantity;
+                (quantity > 0).then_some((exchange, quantity))
+            })
+            .collect()
+    }
+
+    fn participation_cap(&self, volumes: &[(Exchange, u64)]) -> u64 {
+        let total: u64 = volumes.iter().map(|(_, v)| v).sum();
+        self.config
+            .max_participation_bps
+            .map_or(u64::MAX, |bps| total * bps / 10_000)
+    }
+
+    /// Whether the best opposite price is within the parent's limit. An empty
+    /// book doesn't block the slice, routing simply finds nothing.
+    fn within_limit(&self, parent: &ParentOrder) -> bool {
+        let walk = self.book.walk_depth(parent.side, 1, None);
+        walk.filled_quantity == 0
+            || match parent.side {
+                Side::Buy => walk.worst_price <= parent.limit_price,
+                Side::Sell => walk.worst_price >= parent.limit_price,
+            }
+    }
+
+    /// Works `parent` over the configured horizon, publishing progress after
+    /// every slice. TWAP slices go out at the start of each interval, VWAP
+    /// slices at the end, once the interval's volume is known.
+    pub async fn run(
+        &self,
+        parent: ParentOrder,
+        progress_tx: Option<watch::Sender<AlgoProgress>>,
+    ) -> AlgoProgress {
+        let mut progress = AlgoProgress {
+            target_quantity: parent.quantity,
+            slices_total: self.config.slice_count(),
+            ..AlgoProgress::default()
+        };
+        let first_slice = match self.config.style {
+            AlgoStyle::Twap => Instant::now(),
+            AlgoStyle::Vwap => Instant::now() + self.config.slice_interval,
+        };
+        let mut ticker = interval_at(first_slice, self.config.slice_interval);
+        // Only volume traded while we are working counts
+        self.volumes.take();
+
+        for index in 0..progress.slices_total {
+            ticker.tick().await;
+            let volumes = self.volumes.take();
+            if progress.remaining_quantity() == 0 {
+                break;
+            }
+
+            if !self.within_limit(&parent) {
+                progress.slices_skipped += 1;
+            } else {
+                let (filled, notional) = match self.config.style {
+                    AlgoStyle::Twap => {
+                        self.send_twap_slice(&parent, index, &progress, &volumes)
+                            .await
+                    }
+                    AlgoStyle::Vwap => self.send_vwap_slice(&parent, &progress, &volumes).await,
+                };
+                progress.filled_quantity += filled;
+                progress.notional += notional;
+            }
+            progress.slices_completed += 1;
+
+            info!(
+                "[Algo] {:?} slice {}/{}: {} of {} filled",
+                self.config.style,
+                progress.slices_completed,
+                progress.slices_total,
+                progress.filled_quantity,
+                progress.target_quantity
+            );
+            if let Some(tx) = &progress_tx {
+                tx.send_replace(progress);
+            }
+        }
+
+        progress.finished = true;
+        if let Some(tx) = &progress_tx {
+            tx.send_replace(progress);
+        }
+        progress
+    }
+
+    async fn send_twap_slice(
+        &self,
+        parent: &ParentOrder,
+        index: u64,
+        progress: &AlgoProgress,
+        volumes: &[(Exchange, u64)],
+    ) -> (u64, u64) {
+        let quantity = self
+            .twap_slice(parent.quantity, index, progress.filled_quantity)
+            .min(self.participation_cap(volumes));
+        if quantity == 0 {
+            return (0, 0);
+        }
+        let result = self
+            .router
+            .execute(ParentOrder {
+                quantity,
+                ..*parent
+            })
+            .await;
+        (result.filled_quantity, result.notional)
+    }
+
+    async fn send_vwap_slice(
+        &self,
+        parent: &ParentOrder,
+        progress: &AlgoProgress,
+        volumes: &[(Exchange, u64)],
+    ) -> (u64, u64) {
+        let children = self.vwap_children(volumes, progress.remaining_quantity());
+        let fills = join_all(children.into_iter().map(|(exchange, quantity)| {
+            self.router.submit(OrderRequest::ioc(
+                exchange,
+                parent.side,
+                quantity,
+                parent.limit_price,
+            ))
+        }))
+        .await;
+        fills.iter().fold((0, 0), |(qty, notional), f| {
+            (qty + f.filled_quantity, notional + f.notional)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use pricelevel::{OrderId, Side};
+    use tokio::sync::watch;
+
+    use super::{AlgoConfig, AlgoProgress, AlgoStyle, ExecutionAlgo, VolumeTracker};
+    use crate::{
+        execution::{PaperGateway, ParentOrder, RouterConfig, SmartOrderRouter},
+        orderbook::{
+            book::{Exchange, OrderBook},
+            MatchingEngine, Trade,
+        },
+        util::QUANTITY_SCALE,
+    };
+
+    fn config(style: AlgoStyle) -> AlgoConfig {
+        AlgoConfig {
+            style,
+            horizon: Duration::from_millis(30),
+            slice_interval: Duration::from_millis(10),
+            max_participation_bps: None,
+        }
+    }
+
+    /// Binance and Kraken each offer 50 whole units @ 100, in both the book
+    /// and the engines.
+    /// Trades recorded in the returned book feed the algo's volume.
+    fn try_algo(
+        config: AlgoConfig,
+    ) -> (anyhow::Result<ExecutionAlgo<PaperGateway>>, Arc<OrderBook>) {
+        let volumes = Arc::new(VolumeTracker::new());
+        let book = Arc::new(
+            OrderBook::new("BTC/USD".to_string()).with_volume_tracker(Arc::clone(&volumes)),
+        );
+        let gateway = PaperGateway::new();
+        for exchange in [Exchange::Binance, Exchange::Kraken] {
+            book.set_exchange_price_level(100, exchange, Side::Sell, 50 * QUANTITY_SCALE);
+            let engine = Arc::new(MatchingEngine::new(exchange, "BTC/USD"));
+            engine
+                .add_to_limit_order(OrderId::default(), 100, 50 * QUANTITY_SCALE, Side::Sell)
+                .unwrap();
+            gateway.add_engine(engine);
+        }
+        let router = Arc::new(SmartOrderRouter::new(
+            Arc::new(gateway),
+            Arc::clone(&book),
+            RouterConfig::default(),
+        ));
+        (
+            ExecutionAlgo::new(router, Arc::clone(&book), volumes, config),
+            book,
+        )
+    }
+
+    fn algo(config: AlgoConfig) -> (ExecutionAlgo<PaperGateway>, Arc<OrderBook>) {
+        let (algo, book) = try_algo(config);
+        (algo.unwrap(), book)
+    }
+
+    fn parent(quantity: u64, limit_price: u64) -> ParentOrder {
+        ParentOrder {
+            side: Side::Buy,
+            quantity,
+            limit_price,
+        }
+    }
+
+    #[test]
+    fn test_slicing() {
+        let (twap, _) = algo(config(AlgoStyle::Twap));
+        assert_eq!(twap.config.slice_count(), 3);
+        assert_eq!(twap.twap_slice(10, 0, 0), 3);
+        // A slice that missed is caught up in the next one
+        assert_eq!(twap.twap_slice(10, 1, 0), 6);
+        assert_eq!(twap.twap_slice(10, 2, 6), 4);
+
+        let (vwap, _) = algo(AlgoConfig {
+            max_participation_bps: Some(2_000),
+            ..config(AlgoStyle::Vwap)
+        });
+        let volumes = [(Exchange::Binance, 100), (Exchange::Kraken, 50)];
+        assert_eq!(
+            vwap.vwap_children(&volumes, 100),
+            vec![(Exchange::Binance, 20), (Exchange::Kraken, 10)]
+        );
+        assert_eq!(
+            vwap.vwap_children(&volumes, 25),
+            vec![(Exchange::Binance, 20), (Exchange::Kraken, 5)]
+        );
+
+        // A zero interval can't be ticked
+        let (zero, _) = try_algo(AlgoConfig {
+            slice_interval: Duration::ZERO,
+            ..config(AlgoStyle::Twap)
+        });
+        assert!(zero.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_twap_run_reports_progress() {
+        let (twap, _) = algo(config(AlgoStyle::Twap));
+        let (tx, rx) = watch::channel(AlgoProgress::default());
+
+        let progress = twap.run(parent(9 * QUANTITY_SCALE, 100), Some(tx)).await;
+        assert_eq!(progress.filled_quantity, 9 * QUANTITY_SCALE);
+        assert_eq!(progress.notional, 900);
+        assert_eq!(progress.average_price(), Some(100));
+        assert_eq!(progress.slices_completed, 3);
+        assert!(progress.finished);
+        assert_eq!(*rx.borrow(), progress);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limit_guard_and_participation_cap() {
+        // Offers at 100 are through a 99 limit, so every slice is skipped
+        let (twap, _) = algo(config(AlgoStyle::Twap));
+        let progress = twap.run(parent(9, 99), None).await;
+        assert_eq!(progress.filled_quantity, 0);
+        assert_eq!(progress.slices_skipped, 3);
+
+        // With a participation cap and no observed volume, TWAP waits
+        let (capped, _) = algo(AlgoConfig {
+            max_participation_bps: Some(1_000),
+            ..config(AlgoStyle::Twap)
+        });
+        assert_eq!(capped.run(parent(9, 100), None).await.filled_quantity, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_vwap_follows_observed_volume() {
+        let (vwap, book) = algo(AlgoConfig {
+            horizon: Duration::from_millis(10),
+            max_participation_bps: Some(5_000),
+            ..config(AlgoStyle::Vwap)
+        });
+
+        let feed = async {
+            tokio::time::advance(Duration::from_millis(2)).await;
+            book.record_trade(Trade {
+                exchange: Exchange::Kraken,
+                price: 100,
+                quantity: 8,
+                aggressor: Side::Sell,
+                trade_id: "1".to_string(),
+                exchange_timestamp: None,
+                received_at: Instant::now(),
+            });
+        };
+        let (progress, ()) = tokio::join!(vwap.run(parent(20, 100), None), feed);
+
+        // Half of the 8 traded on Kraken during the interval
+        assert_eq!(progress.filled_quantity, 4);
+        assert_eq!(progress.remaining_quantity(), 16);
+    }
+}
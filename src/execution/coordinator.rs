@@ -0,0 +1,482 @@
+//! # Arbitrage Execution Coordinator
+//!
+//! Executes a detected `ArbitrageOpportunity` as two concurrent IOC legs and
+//! deals with the case where only one of them fills. A hanging leg is handled
+//! according to the configured `LegRiskPolicy`:
+//! - Retry the short leg at its original limit
+//! - Hedge by completing the short leg at market
+//! - Unwind by flattening the excess on the filled leg's venue
+//!
+//! Every execution produces a `TradeRecord` with realised PnL and slippage
+//! measured against the prices seen at detection time, written to storage if
+//! the coordinator has a `StorageWriter`.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use pricelevel::Side;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{ExecutionGateway, Fill, OrderRequest, RiskEngine};
+use crate::{
+    inventory::InventoryTracker, orderbook::book::ArbitrageOpportunity, storage::StorageWriter,
+    util,
+};
+
+/// How to resolve a trade where one leg filled more than the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegRiskPolicy {
+    /// Re-send the missing quantity as IOC at the leg's original limit, up to `attempts` times
+    Retry { attempts: u32 },
+    /// Complete the short leg with a market order on its own venue
+    HedgeAtMarket,
+    /// Flatten the excess with a market order on the venue that over-filled
+    Unwind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoordinatorConfig {
+    pub leg_risk_policy: LegRiskPolicy,
+}
+
+impl Default for CoordinatorConfig {
+    fn default() -> Self {
+        Self {
+            leg_risk_policy: LegRiskPolicy::Unwind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeOutcome {
+    /// Both legs filled the same quantity, possibly after retries
+    Completed,
+    /// Neither leg executed anything
+    Missed,
+    /// The short leg was completed at market
+    Hedged,
+    /// The excess on the long leg was flattened at market
+    Unwound,
+    /// The legs are still unbalanced, see `TradeRecord::net_position`
+    Exposed,
+}
+
+/// Everything sent for one opportunity and what came back.
+#[derive(Debug, Clone)]
+pub struct TradeRecord {
+    pub trade_id: Uuid,
+    pub opportunity: ArbitrageOpportunity,
+    /// Both legs first, followed by any retry, hedge or unwind orders
+    pub orders: Vec<OrderRequest>,
+    /// What each of `orders` filled, in the same order
+    pub fills: Vec<Fill>,
+    pub outcome: TradeOutcome,
+    pub started_at: Instant,
+    pub completed_at: Instant,
+}
+
+impl TradeRecord {
+    fn side_totals(&self, side: Side) -> (u64, u64) {
+        self.fills
+            .iter()
+            .filter(|f| f.side == side)
+            .fold((0, 0), |(qty, notional), f| {
+                (qty + f.filled_quantity, notional + f.notional)
+            })
+    }
+
+    pub fn bought_quantity(&self) -> u64 {
+        self.side_totals(Side::Buy).0
+    }
+
+    pub fn sold_quantity(&self) -> u64 {
+        self.side_totals(Side::Sell).0
+    }
+
+    /// Bought minus sold quantity; non-zero means the trade left a position open.
+    pub fn net_position(&self) -> i64 {
+        self.bought_quantity() as i64 - self.sold_quantity() as i64
+    }
+
+    /// PnL on the matched quantity at average buy and sell prices, in cents.
+    pub fn realized_pnl(&self) -> i64 {
+        let (bought, buy_notional) = self.side_totals(Side::Buy);
+        let (sold, sell_notional) = self.side_totals(Side::Sell);
+        let matched = bought.min(sold) as i128;
+        if matched == 0 {
+            return 0;
+        }
+        let proceeds = sell_notional as i128 * matched / sold as i128;
+        let cost = buy_notional as i128 * matched / bought as i128;
+        (proceeds - cost) as i64
+    }
+
+    /// Extra paid on buys versus the detected ask, in cents. Positive is worse.
+    pub fn buy_slippage(&self) -> i64 {
+        let (bought, buy_notional) = self.side_totals(Side::Buy);
+        buy_notional as i64 - util::notional(bought, self.opportunity.buy_price) as i64
+    }
+
+    /// Proceeds given up on sells versus the detected bid, in cents. Positive is worse.
+    pub fn sell_slippage(&self) -> i64 {
+        let (sold, sell_notional) = self.side_totals(Side::Sell);
+        util::notional(sold, self.opportunity.sell_price) as i64 - sell_notional as i64
+    }
+
+    /// Time from sending the legs to the trade being resolved.
+    pub fn duration(&self) -> Duration {
+        self.completed_at.duration_since(self.started_at)
+    }
+}
+
+/// Fires both legs of an opportunity through a gateway and resolves hanging legs.
+pub struct ArbitrageCoordinator<G> {
+    gateway: Arc<G>,
+    config: CoordinatorConfig,
+    inventory: Option<Arc<InventoryTracker>>,
+    risk: Option<Arc<RiskEngine>>,
+    storage: Option<StorageWriter>,
+}
+
+impl<G: ExecutionGateway> ArbitrageCoordinator<G> {
+    pub fn new(gateway: Arc<G>, config: CoordinatorConfig) -> Self {
+        Self {
+            gateway,
+            config,
+            inventory: None,
+            risk: None,
+            storage: None,
+        }
+    }
+
+    /// Books every fill into `inventory` as soon as it comes back.
+    pub fn with_inventory(mut self, inventory: Arc<InventoryTracker>) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Books each trade's realised PnL into `risk`, so its daily loss limit
+    /// sees what the coordinator made or lost.
+    pub fn with_risk_engine(mut self, risk: Arc<RiskEngine>) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    /// Writes every `TradeRecord` to storage once the trade is resolved.
+    pub fn with_storage(mut self, storage: StorageWriter) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub async fn execute(&self, opportunity: ArbitrageOpportunity) -> TradeRecord {
+        let started_at = Instant::now();
+        let buy = OrderRequest::ioc(
+            opportunity.buy_exchange,
+            Side::Buy,
+            opportunity.quantity,
+            opportunity.buy_price,
+        );
+        let sell = OrderRequest::ioc(
+            opportunity.sell_exchange,
+            Side::Sell,
+            opportunity.quantity,
+            opportunity.sell_price,
+        );
+
+        let (buy_fill, sell_fill) = tokio::join!(self.submit(buy), self.submit(sell));
+
+        let mut record = TradeRecord {
+            trade_id: Uuid::new_v4(),
+            opportunity,
+            orders: vec![buy, sell],
+            fills: vec![buy_fill, sell_fill],
+            outcome: TradeOutcome::Completed,
+            started_at,
+            completed_at: started_at,
+        };
+
+        record.outcome = if record.bought_quantity() == 0 && record.sold_quantity() == 0 {
+            TradeOutcome::Missed
+        } else if record.net_position() == 0 {
+            TradeOutcome::Completed
+        } else {
+            self.resolve_hanging_leg(&mut record).await
+        };
+        record.completed_at = Instant::now();
+
+        info!(
+            "[Arbitrage] {:?} buy {:?} / sell {:?}: {:?}, pnl {}, net position {}",
+            record.trade_id,
+            opportunity.buy_exchange,
+            opportunity.sell_exchange,
+            record.outcome,
+            record.realized_pnl(),
+            record.net_position()
+        );
+        if let Some(risk) = &self.risk {
+            risk.record_realized_pnl(record.realized_pnl());
+        }
+        if let Some(storage) = &self.storage {
+            storage.record_trade(&record, self.gateway.is_simulated());
+        }
+        record
+    }
+
+    async fn resolve_hanging_leg(&self, record: &mut TradeRecord) -> TradeOutcome {
+        let opportunity = record.opportunity;
+        warn!(
+            "[Arbitrage] {:?} hanging leg, net position {}, applying {:?}",
+            record.trade_id,
+            record.net_position(),
+            self.config.leg_risk_policy
+        );
+
+        let resolved = match self.config.leg_risk_policy {
+            LegRiskPolicy::Retry { attempts } => {
+                for _ in 0..attempts {
+                    let net = record.net_position();
+                    if net == 0 {
+                        break;
+                    }
+                    let retry = if net > 0 {
+                        OrderRequest::ioc(
+                            opportunity.sell_exchange,
+                            Side::Sell,
+                            net.unsigned_abs(),
+                            opportunity.sell_price,
+                        )
+                    } else {
+                        OrderRequest::ioc(
+                            opportunity.buy_exchange,
+                            Side::Buy,
+                            net.unsigned_abs(),
+                            opportunity.buy_price,
+                        )
+                    };
+                    record.orders.push(retry);
+                    record.fills.push(self.submit(retry).await);
+                }
+                TradeOutcome::Completed
+            }
+            LegRiskPolicy::HedgeAtMarket => {
+                let net = record.net_position();
+                let hedge = if net > 0 {
+                    OrderRequest::market(opportunity.sell_exchange, Side::Sell, net.unsigned_abs())
+                } else {
+                    OrderRequest::market(opportunity.buy_exchange, Side::Buy, net.unsigned_abs())
+                };
+                record.orders.push(hedge);
+                record.fills.push(self.submit(hedge).await);
+                TradeOutcome::Hedged
+            }
+            LegRiskPolicy::Unwind => {
+                let net = record.net_position();
+                let unwind = if net > 0 {
+                    OrderRequest::market(opportunity.buy_exchange, Side::Sell, net.unsigned_abs())
+                } else {
+                    OrderRequest::market(opportunity.sell_exchange, Side::Buy, net.unsigned_abs())
+                };
+                record.orders.push(unwind);
+                record.fills.push(self.submit(unwind).await);
+                TradeOutcome::Unwound
+            }
+        };
+
+        if record.net_position() == 0 {
+            resolved
+        } else {
+            TradeOutcome::Exposed
+        }
+    }
+
+    /// Sends an order, treating a gateway error as nothing filled.
+    async fn submit(&self, order: OrderRequest) -> Fill {
+        match self.gateway.submit_order(order).await {
+            Ok(fill) => {
+                if let Some(inventory) = &self.inventory {
+                    inventory.apply_fill(&fill);
+                }
+                fill
+            }
+            Err(e) => {
+                warn!("[Arbitrage] {:?} order rejected: {}", order.exchange, e);
+                Fill::empty(&order)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use pricelevel::{OrderId, Side};
+
+    use super::{ArbitrageCoordinator, CoordinatorConfig, LegRiskPolicy, TradeOutcome};
+    use crate::{
+        execution::{OrderRequest, PaperGateway, RiskEngine, RiskLimits, RiskViolation},
+        inventory::InventoryTracker,
+        orderbook::{
+            book::{ArbitrageOpportunity, Exchange},
+            MatchingEngine,
+        },
+        util::QUANTITY_SCALE,
+    };
+
+    /// Binance offers 5 @ 100, Kraken bids 3 @ 105 with more size further down,
+    /// in whole units.
+    fn gateway() -> Arc<PaperGateway> {
+        let gateway = PaperGateway::new();
+        let binance = Arc::new(MatchingEngine::new(Exchange::Binance, "BTC/USD"));
+        let kraken = Arc::new(MatchingEngine::new(Exchange::Kraken, "BTC/USD"));
+
+        binance
+            .add_to_limit_order(OrderId::default(), 100, 5 * QUANTITY_SCALE, Side::Sell)
+            .unwrap();
+        binance
+            .add_to_limit_order(OrderId::default(), 99, 10 * QUANTITY_SCALE, Side::Buy)
+            .unwrap();
+        kraken
+            .add_to_limit_order(OrderId::default(), 105, 3 * QUANTITY_SCALE, Side::Buy)
+            .unwrap();
+        kraken
+            .add_to_limit_order(OrderId::default(), 101, 10 * QUANTITY_SCALE, Side::Buy)
+            .unwrap();
+
+        gateway.add_engine(binance);
+        gateway.add_engine(kraken);
+        Arc::new(gateway)
+    }
+
+    fn opportunity(quantity: u64) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            buy_exchange: Exchange::Binance,
+            buy_price: 100,
+            sell_exchange: Exchange::Kraken,
+            sell_price: 105,
+            quantity: quantity * QUANTITY_SCALE,
+        }
+    }
+
+    fn coordinator(policy: LegRiskPolicy) -> ArbitrageCoordinator<PaperGateway> {
+        ArbitrageCoordinator::new(
+            gateway(),
+            CoordinatorConfig {
+                leg_risk_policy: policy,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_both_legs_fill() {
+        let inventory = Arc::new(InventoryTracker::new("BTC", "USD"));
+        let record = coordinator(LegRiskPolicy::Unwind)
+            .with_inventory(Arc::clone(&inventory))
+            .execute(opportunity(3))
+            .await;
+
+        assert_eq!(record.outcome, TradeOutcome::Completed);
+        assert_eq!(
+            inventory.base_balance(Exchange::Binance),
+            3 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(inventory.quote_balance(Exchange::Kraken), 3 * 105);
+        assert_eq!(record.fills.len(), 2);
+        assert_eq!(record.realized_pnl(), 3 * 5);
+        assert_eq!(record.buy_slippage(), 0);
+        assert_eq!(record.sell_slippage(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hanging_leg_hedged_at_market() {
+        let record = coordinator(LegRiskPolicy::HedgeAtMarket)
+            .execute(opportunity(5))
+            .await;
+
+        // 3 sold at 105, the missing 2 hedged into the 101 bid
+        assert_eq!(record.outcome, TradeOutcome::Hedged);
+        assert_eq!(record.net_position(), 0);
+        assert_eq!(record.realized_pnl(), 3 * 105 + 2 * 101 - 5 * 100);
+        assert_eq!(record.sell_slippage(), 2 * 4);
+    }
+
+    #[tokio::test]
+    async fn test_hanging_leg_unwound() {
+        let record = coordinator(LegRiskPolicy::Unwind)
+            .execute(opportunity(5))
+            .await;
+
+        // The 2 excess bought on Binance are sold back into its 99 bid
+        assert_eq!(record.outcome, TradeOutcome::Unwound);
+        assert_eq!(record.net_position(), 0);
+        assert_eq!(record.fills[2].exchange, Exchange::Binance);
+        assert_eq!(record.realized_pnl(), 3 * 105 + 2 * 99 - 5 * 100);
+    }
+
+    #[tokio::test]
+    async fn test_hanging_leg_retry_leaves_exposure() {
+        let record = coordinator(LegRiskPolicy::Retry { attempts: 2 })
+            .execute(opportunity(5))
+            .await;
+
+        // Nothing more trades at 105, so the retries miss and the position stays open
+        assert_eq!(record.outcome, TradeOutcome::Exposed);
+        assert_eq!(record.fills.len(), 4);
+        assert_eq!(record.net_position(), 2 * QUANTITY_SCALE as i64);
+        assert_eq!(record.realized_pnl(), 3 * 5);
+    }
+
+    #[tokio::test]
+    async fn test_losing_trade_trips_daily_loss() {
+        // Only 3 of 5 sell at 105 and Binance bids just 90 for the rest
+        let gateway = PaperGateway::new();
+        let binance = Arc::new(MatchingEngine::new(Exchange::Binance, "BTC/USD"));
+        let kraken = Arc::new(MatchingEngine::new(Exchange::Kraken, "BTC/USD"));
+        binance
+            .add_to_limit_order(OrderId::default(), 100, 5 * QUANTITY_SCALE, Side::Sell)
+            .unwrap();
+        binance
+            .add_to_limit_order(OrderId::default(), 90, 10 * QUANTITY_SCALE, Side::Buy)
+            .unwrap();
+        kraken
+            .add_to_limit_order(OrderId::default(), 105, 3 * QUANTITY_SCALE, Side::Buy)
+            .unwrap();
+        gateway.add_engine(binance);
+        gateway.add_engine(kraken);
+
+        let risk = Arc::new(RiskEngine::new(RiskLimits {
+            max_order_notional: 10_000,
+            max_position: 10 * QUANTITY_SCALE,
+            max_open_orders: 10,
+            max_daily_loss: 5,
+            max_orders_per_second: 100,
+            price_band_bps: 500,
+        }));
+        risk.update_reference_mid(100);
+        let record = ArbitrageCoordinator::new(
+            Arc::new(gateway),
+            CoordinatorConfig {
+                leg_risk_policy: LegRiskPolicy::Unwind,
+            },
+        )
+        .with_risk_engine(Arc::clone(&risk))
+        .execute(opportunity(5))
+        .await;
+
+        assert_eq!(record.outcome, TradeOutcome::Unwound);
+        assert_eq!(record.realized_pnl(), 3 * 105 + 2 * 90 - 5 * 100);
+        assert_eq!(risk.daily_pnl(), -5);
+        assert!(matches!(
+            risk.check_order(&OrderRequest::ioc(
+                Exchange::Binance,
+                Side::Buy,
+                QUANTITY_SCALE,
+                100
+            )),
+            Err(RiskViolation::DailyLoss { loss: 5, limit: 5 })
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+//! # Execution Module
+//!
+//! This module turns detected opportunities into orders. It includes:
+//! - The `ExecutionGateway` abstraction over anything that can fill an order
+//! - A paper gateway backed by the simulated per-venue matching engines
+//! - The two-legged arbitrage coordinator and its leg-risk handling
+//! - Pre-trade risk checks and the global kill switch
+//! - A smart order router splitting parent orders across venues
+//! - TWAP and VWAP algorithms working parent orders over time
+//! - `ExecutionReport`, the common order update decoded from private streams
+//! - The order manager tracking each order's lifecycle from those reports
+//!
+//! Prices are in cents per whole unit and quantities in order units (1e-8 of
+//! the base asset), as in the order book and the matching engine. Notionals
+//! are in cents, see `util::notional`.
+
+use std::future::Future;
+
+use pricelevel::{OrderId, Side, TimeInForce};
+
+use crate::{orderbook::book::Exchange, util};
+
+pub mod algo;
+pub mod coordinator;
+pub mod orders;
+pub mod paper;
+pub mod report;
+pub mod risk;
+pub mod router;
+
+pub use algo::{AlgoConfig, AlgoProgress, AlgoStyle, ExecutionAlgo, VolumeTracker};
+pub use coordinator::{ArbitrageCoordinator, CoordinatorConfig, LegRiskPolicy, TradeRecord};
+pub use orders::{OrderManager, OrderState};
+pub use paper::PaperGateway;
+pub use report::{ExecType, ExecutionReport};
+pub use risk::{KillSwitch, RiskCheckedGateway, RiskEngine, RiskLimits, RiskViolation};
+pub use router::{ParentOrder, RouterConfig, SmartOrderRouter, VenueRules};
+
+/// A single order sent to a venue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderRequest {
+    pub client_order_id: OrderId,
+    pub exchange: Exchange,
+    pub side: Side,
+    pub quantity: u64,
+    /// Limit price in cents, `None` for a market order
+    pub limit_price: Option<u64>,
+    pub time_in_force: TimeInForce,
+}
+
+impl OrderRequest {
+    /// Immediate-or-cancel limit order with a fresh client order id.
+    pub fn ioc(exchange: Exchange, side: Side, quantity: u64, limit_price: u64) -> Self {
+        Self {
+            client_order_id: OrderId::new_uuid(),
+            exchange,
+            side,
+            quantity,
+            limit_price: Some(limit_price),
+            time_in_force: TimeInForce::Ioc,
+        }
+    }
+
+    /// Market order with a fresh client order id.
+    pub fn market(exchange: Exchange, side: Side, quantity: u64) -> Self {
+        Self {
+            client_order_id: OrderId::new_uuid(),
+            exchange,
+            side,
+            quantity,
+            limit_price: None,
+            time_in_force: TimeInForce::Ioc,
+        }
+    }
+}
+
+/// What a venue executed for one `OrderRequest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub client_order_id: OrderId,
+    pub exchange: Exchange,
+    pub side: Side,
+    pub requested_quantity: u64,
+    pub filled_quantity: u64,
+    /// Cents paid or received over all executions
+    pub notional: u64,
+}
+
+impl Fill {
+    /// Fill for an order that executed nothing.
+    pub fn empty(order: &OrderRequest) -> Self {
+        Self {
+            client_order_id: order.client_order_id,
+            exchange: order.exchange,
+            side: order.side,
+            requested_quantity: order.quantity,
+            filled_quantity: 0,
+            notional: 0,
+        }
+    }
+
+    /// Volume-weighted execution price in cents, `None` if nothing filled.
+    pub fn average_price(&self) -> Option<u64> {
+        util::unit_price(self.notional, self.filled_quantity)
+    }
+}
+
+/// Anything that can take an order and report what was executed: the paper
+/// gateway in simulation, a venue's private API when trading live.
+pub trait ExecutionGateway: Send + Sync {
+    fn submit_order(
+        &self,
+        order: OrderRequest,
+    ) -> impl Future<Output = anyhow::Result<Fill>> + Send;
+
+    /// Cancels every order this gateway still has working, returning how many were cancelled.
+    fn cancel_all_orders(&self) -> impl Future<Output = anyhow::Result<usize>> + Send;
+
+    /// Whether fills are simulated rather than executed at a venue.
+    fn is_simulated(&self) -> bool {
+        false
+    }
+}
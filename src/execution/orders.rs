@@ -0,0 +1,760 @@
+//! # Order Manager
+//!
+//! Tracks every order we send through its lifecycle:
+//! - Client order ids are idempotent: registering the same id twice is a no-op,
+//!   so a retried submission can't create a second order
+//! - Our client ids are mapped to venue ids as acknowledgements arrive
+//! - Execution reports are applied in whatever order they arrive: duplicate
+//!   trades are dropped, terminal states are never left, fills that race a
+//!   cancel still count, and reports for orders not yet mapped are held back
+//!   until the mapping shows up, for a bounded time and number
+//! - Orders reaching a terminal state release their open-order slot in the
+//!   risk engine
+//! - After a reconnect, venue open-orders queries are reconciled against the
+//!   orders we believe are live
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use pricelevel::OrderId;
+use tokio::sync::{mpsc, Notify};
+use tracing::{info, warn};
+
+use super::{ExecType, ExecutionReport, OrderRequest, RiskEngine};
+use crate::{inventory::InventoryTracker, orderbook::book::Exchange};
+
+/// Where an order is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrderState {
+    /// Sent, not yet acknowledged by the venue
+    PendingNew,
+    New,
+    PartiallyFilled,
+    Filled,
+    /// Cancel sent, not yet confirmed
+    PendingCancel,
+    /// Cancelled by us or expired at the venue
+    Cancelled,
+    Rejected,
+}
+
+impl OrderState {
+    /// Terminal states never change again, though late fills still update quantities.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderState::Filled | OrderState::Cancelled | OrderState::Rejected
+        )
+    }
+}
+
+/// One order and everything the venue has told us about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedOrder {
+    pub request: OrderRequest,
+    pub venue_order_id: Option<String>,
+    pub state: OrderState,
+    pub filled_quantity: u64,
+    /// Cents paid or received over all applied fills
+    pub notional: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Trade ids already applied, so replays and duplicates are ignored
+    applied_trades: HashSet<String>,
+}
+
+impl ManagedOrder {
+    fn new(request: OrderRequest) -> Self {
+        let now = Utc::now();
+        Self {
+            request,
+            venue_order_id: None,
+            state: OrderState::PendingNew,
+            filled_quantity: 0,
+            notional: 0,
+            created_at: now,
+            updated_at: now,
+            applied_trades: HashSet::new(),
+        }
+    }
+
+    pub fn remaining_quantity(&self) -> u64 {
+        self.request.quantity.saturating_sub(self.filled_quantity)
+    }
+
+    /// Applies one report, returning whether it changed anything.
+    fn apply(&mut self, report: &ExecutionReport) -> bool {
+        let previous = (self.state, self.filled_quantity);
+        match report.exec_type {
+            ExecType::New => {
+                if self.state == OrderState::PendingNew {
+                    self.state = OrderState::New;
+                }
+            }
+            ExecType::Trade => {
+                if let Some(trade_id) = &report.trade_id {
+                    if !self.applied_trades.insert(trade_id.clone()) {
+                        return false;
+                    }
+                }
+                self.filled_quantity += report.last_quantity;
+                self.notional += report.last_notional();
+                // A fill arriving after the order finished only counts towards
+                // its quantities
+                if !self.state.is_terminal() {
+                    if self.filled_quantity >= self.request.quantity {
+                        self.state = OrderState::Filled;
+                    } else if self.state != OrderState::PendingCancel {
+                        self.state = OrderState::PartiallyFilled;
+                    }
+                }
+            }
+            ExecType::Canceled | ExecType::Expired => {
+                if !self.state.is_terminal() {
+                    self.state = OrderState::Cancelled;
+                }
+            }
+            ExecType::Rejected => {
+                if matches!(self.state, OrderState::PendingNew | OrderState::New) {
+                    self.state = OrderState::Rejected;
+                }
+            }
+        }
+
+        let changed = previous != (self.state, self.filled_quantity);
+        if changed {
+            self.updated_at = Utc::now();
+        }
+        changed
+    }
+}
+
+/// An order as returned by a venue's open-orders query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueOpenOrder {
+    pub venue_order_id: String,
+    pub client_order_id: Option<String>,
+    pub filled_quantity: u64,
+}
+
+/// What a reconciliation found that the streams had not told us.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Live locally but no longer open at the venue: finished while we were
+    /// disconnected, so their final status needs querying
+    pub closed_at_venue: Vec<OrderId>,
+    /// Open at the venue but unknown to us, e.g. placed before a restart
+    pub unknown_at_venue: Vec<VenueOpenOrder>,
+    /// Open at the venue with more filled than we have applied
+    pub missed_fills: Vec<OrderId>,
+}
+
+/// Reports held for unidentified orders beyond this many evict the oldest
+const MAX_UNMATCHED_REPORTS: usize = 1_000;
+/// How long a report is held for its order to be identified. Reports for
+/// orders we never sent, e.g. placed by hand on the same account, are
+/// otherwise held forever
+const UNMATCHED_TTL: Duration = Duration::from_secs(60);
+
+/// Reports for one venue order id we can't identify yet.
+struct HeldReports {
+    /// When the first of them arrived
+    since: Instant,
+    reports: Vec<ExecutionReport>,
+}
+
+/// Lifecycle tracking for every order sent to any venue.
+pub struct OrderManager {
+    orders: DashMap<OrderId, ManagedOrder>,
+    venue_ids: DashMap<(Exchange, String), OrderId>,
+    /// Reports that arrived before we could tell which order they belong to
+    unmatched: Mutex<HashMap<(Exchange, String), HeldReports>>,
+    max_unmatched: usize,
+    unmatched_ttl: Duration,
+    /// Woken by the first registered order
+    registered: Notify,
+    inventory: Option<Arc<InventoryTracker>>,
+    risk: Option<Arc<RiskEngine>>,
+}
+
+impl Default for OrderManager {
+    fn default() -> Self {
+        Self {
+            orders: DashMap::new(),
+            venue_ids: DashMap::new(),
+            unmatched: Mutex::default(),
+            max_unmatched: MAX_UNMATCHED_REPORTS,
+            unmatched_ttl: UNMATCHED_TTL,
+            registered: Notify::new(),
+            inventory: None,
+            risk: None,
+        }
+    }
+}
+
+impl OrderManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds at most `max_reports` reports for unidentified orders, each for
+    /// at most `ttl`.
+    pub fn with_unmatched_limits(mut self, max_reports: usize, ttl: Duration) -> Self {
+        self.max_unmatched = max_reports;
+        self.unmatched_ttl = ttl;
+        self
+    }
+
+    /// Applies every newly seen fill to `inventory`.
+    pub fn with_inventory(mut self, inventory: Arc<InventoryTracker>) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Tells `risk` when each order is finished so its open-order slot is freed.
+    pub fn with_risk_engine(mut self, risk: Arc<RiskEngine>) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    /// Starts tracking an order about to be sent. Returns `false` if the client
+    /// order id is already known, in which case the order must not be re-sent.
+    pub fn register(&self, request: OrderRequest) -> bool {
+        let mut inserted = false;
+        self.orders
+            .entry(request.client_order_id)
+            .or_insert_with(|| {
+                inserted = true;
+                ManagedOrder::new(request)
+            });
+        if inserted {
+            self.registered.notify_one();
+        }
+        inserted
+    }
+
+    /// Resolves once an order has been registered, e.g. to connect the
+    /// private streams only when there is something to follow.
+    pub async fn wait_for_orders(&self) {
+        if self.orders.is_empty() {
+            self.registered.notified().await;
+        }
+    }
+
+    /// Records the venue id returned by an order-entry response.
+    pub fn acknowledge(
+        &self,
+        client_order_id: OrderId,
+        venue_order_id: &str,
+    ) -> anyhow::Result<()> {
+        let exchange = self
+            .orders
+            .get(&client_order_id)
+            .map(|order| order.request.exchange)
+            .ok_or_else(|| anyhow!("unknown order {client_order_id}"))?;
+        self.link(client_order_id, exchange, venue_order_id);
+        Ok(())
+    }
+
+    /// Marks a cancel as sent. Fails for orders that are already finished.
+    pub fn request_cancel(&self, client_order_id: OrderId) -> anyhow::Result<()> {
+        let mut order = self
+            .orders
+            .get_mut(&client_order_id)
+            .ok_or_else(|| anyhow!("unknown order {client_order_id}"))?;
+        if order.state.is_terminal() {
+            return Err(anyhow!(
+                "order {client_order_id} is already {:?}",
+                order.state
+            ));
+        }
+        order.state = OrderState::PendingCancel;
+        order.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// The venue refused an order outright, e.g. an order-entry error response.
+    pub fn reject(&self, client_order_id: OrderId) {
+        if let Some(mut order) = self.orders.get_mut(&client_order_id) {
+            if !order.state.is_terminal() {
+                order.state = OrderState::Rejected;
+                order.updated_at = Utc::now();
+                self.on_closed(client_order_id);
+            }
+        }
+    }
+
+    /// Applies a report from a private stream. Reports that can't yet be tied
+    /// to an order are held until `acknowledge` or a later report links the
+    /// venue id. Returns whether any order changed.
+    pub fn apply_report(&self, report: ExecutionReport) -> bool {
+        let client_order_id = report
+            .order_id()
+            .filter(|id| self.orders.contains_key(id))
+            .or_else(|| {
+                self.venue_ids
+                    .get(&(report.exchange, report.venue_order_id.clone()))
+                    .map(|id| *id)
+            });
+
+        let Some(client_order_id) = client_order_id else {
+            self.hold(report);
+            return false;
+        };
+
+        let exchange = report.exchange;
+        let venue_order_id = report.venue_order_id.clone();
+        let changed = self.apply_to(client_order_id, &report);
+        // Linking replays anything held back for this venue id
+        self.link(client_order_id, exchange, &venue_order_id) || changed
+    }
+
+    /// Holds a report for an order we can't identify yet, first dropping
+    /// held reports past their TTL and then the oldest while over the limit.
+    fn hold(&self, report: ExecutionReport) {
+        let mut unmatched = self.unmatched.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        unmatched.retain(|(exchange, venue_order_id), held| {
+            let expired = now.duration_since(held.since) >= self.unmatched_ttl;
+            if expired {
+                warn!(
+                    "[Orders] {exchange:?} {venue_order_id}: dropping {} reports, order unknown after {:?}",
+                    held.reports.len(),
+                    self.unmatched_ttl
+                );
+            }
+            !expired
+        });
+
+        let mut total: usize = unmatched.values().map(|held| held.reports.len()).sum();
+        while total >= self.max_unmatched {
+            let Some(oldest) = unmatched
+                .iter()
+                .min_by_key(|(_, held)| held.since)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(held) = unmatched.remove(&oldest) {
+                warn!(
+                    "[Orders] {:?} {}: dropping {} reports, over {} held for unknown orders",
+                    oldest.0,
+                    oldest.1,
+                    held.reports.len(),
+                    self.max_unmatched
+                );
+                total -= held.reports.len();
+            }
+        }
+
+        if self.max_unmatched > 0 {
+            unmatched
+                .entry((report.exchange, report.venue_order_id.clone()))
+                .or_insert_with(|| HeldReports {
+                    since: now,
+                    reports: Vec::new(),
+                })
+                .reports
+                .push(report);
+        }
+    }
+
+    fn apply_to(&self, client_order_id: OrderId, report: &ExecutionReport) -> bool {
+        let Some(mut order) = self.orders.get_mut(&client_order_id) else {
+            return false;
+        };
+        let (filled_before, was_terminal) = (order.filled_quantity, order.state.is_terminal());
+        let changed = order.apply(report);
+        if order.filled_quantity > filled_before {
+            if let Some(inventory) = &self.inventory {
+                inventory.apply_report(report);
+            }
+        }
+        if !was_terminal && order.state.is_terminal() {
+            self.on_closed(client_order_id);
+        }
+        changed
+    }
+
+    fn on_closed(&self, client_order_id: OrderId) {
+        if let Some(risk) = &self.risk {
+            risk.on_order_closed(client_order_id);
+        }
+    }
+
+    /// Maps a venue id to our order and replays reports held back for it.
+    fn link(&self, client_order_id: OrderId, exchange: Exchange, venue_order_id: &str) -> bool {
+        let key = (exchange, venue_order_id.to_string());
+        self.venue_ids.insert(key.clone(), client_order_id);
+        if let Some(mut order) = self.orders.get_mut(&client_order_id) {
+            order.venue_order_id = Some(venue_order_id.to_string());
+        }
+
+        let held = self
+            .unmatched
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key)
+            .map(|held| held.reports)
+            .unwrap_or_default();
+        let mut changed = false;
+        for report in &held {
+            changed |= self.apply_to(client_order_id, report);
+        }
+        changed
+    }
+
+    /// Compares a venue's open orders with what we believe is live there.
+    pub fn reconcile(&self, exchange: Exchange, open_orders: &[VenueOpenOrder]) -> Reconciliation {
+        let mut result = Reconciliation::default();
+        let mut open_locally: HashSet<OrderId> = self
+            .orders
+            .iter()
+            .filter(|order| order.request.exchange == exchange && !order.state.is_terminal())
+            .map(|order| *order.key())
+            .collect();
+
+        for venue_order in open_orders {
+            let known = venue_order
+                .client_order_id
+                .as_deref()
+                .and_then(|id| id.parse::<OrderId>().ok())
+                .filter(|id| self.orders.contains_key(id))
+                .or_else(|| {
+                    self.venue_ids
+                        .get(&(exchange, venue_order.venue_order_id.clone()))
+                        .map(|id| *id)
+                });
+            let Some(client_order_id) = known else {
+                result.unknown_at_venue.push(venue_order.clone());
+                continue;
+            };
+
+            open_locally.remove(&client_order_id);
+            self.link(client_order_id, exchange, &venue_order.venue_order_id);
+            if let Some(mut order) = self.orders.get_mut(&client_order_id) {
+                if order.state == OrderState::PendingNew {
+                    order.state = OrderState::New;
+                    order.updated_at = Utc::now();
+                }
+                if venue_order.filled_quantity > order.filled_quantity {
+                    result.missed_fills.push(client_order_id);
+                }
+            }
+        }
+
+        result.closed_at_venue = open_locally.into_iter().collect();
+        if !result.closed_at_venue.is_empty()
+            || !result.unknown_at_venue.is_empty()
+            || !result.missed_fills.is_empty()
+        {
+            warn!(
+                "[Orders] {:?} reconciliation: {} closed while away, {} unknown, {} with missed fills",
+                exchange,
+                result.closed_at_venue.len(),
+                result.unknown_at_venue.len(),
+                result.missed_fills.len()
+            );
+        }
+        result
+    }
+
+    pub fn order(&self, client_order_id: OrderId) -> Option<ManagedOrder> {
+        self.orders.get(&client_order_id).map(|o| o.clone())
+    }
+
+    pub fn state(&self, client_order_id: OrderId) -> Option<OrderState> {
+        self.orders.get(&client_order_id).map(|o| o.state)
+    }
+
+    pub fn by_venue_id(&self, exchange: Exchange, venue_order_id: &str) -> Option<ManagedOrder> {
+        let client_order_id = *self
+            .venue_ids
+            .get(&(exchange, venue_order_id.to_string()))?;
+        self.order(client_order_id)
+    }
+
+    /// Orders not yet in a terminal state.
+    pub fn open_orders(&self) -> Vec<ManagedOrder> {
+        self.orders
+            .iter()
+            .filter(|o| !o.state.is_terminal())
+            .map(|o| o.clone())
+            .collect()
+    }
+
+    /// Number of reports still waiting for their order to be identified.
+    pub fn unmatched_reports(&self) -> usize {
+        self.unmatched
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|held| held.reports.len())
+            .sum()
+    }
+}
+
+/// Feeds execution reports from the private streams into the order manager.
+pub fn spawn_report_handler(
+    manager: Arc<OrderManager>,
+    mut rx: mpsc::Receiver<ExecutionReport>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(report) = rx.recv().await {
+            let (exchange, exec_type) = (report.exchange, report.exec_type);
+            let venue_order_id = report.venue_order_id.clone();
+            if manager.apply_report(report) {
+                info!("[Orders] {exchange:?} {venue_order_id}: {exec_type:?}");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use pricelevel::{Side, TimeInForce};
+
+    use super::{OrderManager, OrderState, VenueOpenOrder};
+    use crate::{
+        execution::{ExecType, ExecutionReport, Fill, OrderRequest, RiskEngine, RiskLimits},
+        inventory::InventoryTracker,
+        orderbook::book::Exchange,
+        util::QUANTITY_SCALE,
+    };
+
+    fn report(request: &OrderRequest, exec_type: ExecType) -> ExecutionReport {
+        let mut report = ExecutionReport::new(request.exchange, "V1", exec_type, Instant::now());
+        report.client_order_id = Some(request.client_order_id.to_string());
+        report.side = Some(request.side);
+        report
+    }
+
+    fn trade(request: &OrderRequest, trade_id: &str, quantity: u64) -> ExecutionReport {
+        // Venues like Kraken only carry the venue id on trades
+        let mut report = report(request, ExecType::Trade);
+        report.client_order_id = None;
+        report.trade_id = Some(trade_id.to_string());
+        report.last_quantity = quantity;
+        report.last_price = 100;
+        report
+    }
+
+    #[test]
+    fn test_register_is_idempotent() {
+        let manager = OrderManager::new();
+        let request = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 10, 100);
+        assert!(manager.register(request));
+        assert!(!manager.register(request));
+        assert_eq!(manager.open_orders().len(), 1);
+        assert_eq!(
+            manager.state(request.client_order_id),
+            Some(OrderState::PendingNew)
+        );
+    }
+
+    #[test]
+    fn test_lifecycle_with_duplicate_and_late_reports() {
+        let inventory = Arc::new(InventoryTracker::new("BTC", "USD"));
+        let manager = OrderManager::new().with_inventory(Arc::clone(&inventory));
+        let request = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 3 * QUANTITY_SCALE, 100);
+        manager.register(request);
+        let id = request.client_order_id;
+
+        // A trade ahead of the acknowledgement is held until the venue id is known
+        assert!(!manager.apply_report(trade(&request, "T1", QUANTITY_SCALE)));
+        assert_eq!(manager.unmatched_reports(), 1);
+
+        manager.apply_report(report(&request, ExecType::New));
+        assert_eq!(manager.unmatched_reports(), 0);
+        assert_eq!(manager.state(id), Some(OrderState::PartiallyFilled));
+        assert_eq!(
+            manager
+                .by_venue_id(Exchange::Kraken, "V1")
+                .unwrap()
+                .filled_quantity,
+            QUANTITY_SCALE
+        );
+
+        // The same trade delivered twice only counts once
+        assert!(!manager.apply_report(trade(&request, "T1", QUANTITY_SCALE)));
+        assert_eq!(
+            inventory.base_balance(Exchange::Kraken),
+            QUANTITY_SCALE as i64
+        );
+
+        // Cancel confirmed before a fill that happened first still keeps the fill
+        manager.request_cancel(id).unwrap();
+        assert_eq!(manager.state(id), Some(OrderState::PendingCancel));
+        manager.apply_report(report(&request, ExecType::Canceled));
+        manager.apply_report(trade(&request, "T2", QUANTITY_SCALE));
+        let order = manager.order(id).unwrap();
+        assert_eq!(order.state, OrderState::Cancelled);
+        assert_eq!(order.filled_quantity, 2 * QUANTITY_SCALE);
+        assert_eq!(order.notional, 200);
+        assert_eq!(inventory.quote_balance(Exchange::Kraken), -200);
+
+        // Terminal states don't regress on stale reports
+        manager.apply_report(report(&request, ExecType::New));
+        assert_eq!(manager.state(id), Some(OrderState::Cancelled));
+        assert!(manager.request_cancel(id).is_err());
+    }
+
+    #[test]
+    fn test_late_fill_keeps_terminal_state() {
+        let manager = OrderManager::new();
+        let request = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 2 * QUANTITY_SCALE, 100);
+        manager.register(request);
+        let id = request.client_order_id;
+        manager.apply_report(report(&request, ExecType::New));
+        manager.apply_report(report(&request, ExecType::Canceled));
+
+        // Completes the quantity, but the cancel already finished the order
+        assert!(manager.apply_report(trade(&request, "T1", 2 * QUANTITY_SCALE)));
+        let order = manager.order(id).unwrap();
+        assert_eq!(order.state, OrderState::Cancelled);
+        assert_eq!(order.filled_quantity, 2 * QUANTITY_SCALE);
+        assert_eq!(order.remaining_quantity(), 0);
+    }
+
+    #[test]
+    fn test_reconcile_open_orders() {
+        let manager = OrderManager::new();
+        let resting = OrderRequest::ioc(Exchange::Binance, Side::Sell, 5, 100);
+        let gone = OrderRequest::ioc(Exchange::Binance, Side::Buy, 5, 100);
+        manager.register(resting);
+        manager.register(gone);
+
+        let open_orders = [
+            VenueOpenOrder {
+                venue_order_id: "123".to_string(),
+                client_order_id: Some(resting.client_order_id.to_string()),
+                filled_quantity: 2,
+            },
+            VenueOpenOrder {
+                venue_order_id: "999".to_string(),
+                client_order_id: None,
+                filled_quantity: 0,
+            },
+        ];
+        let result = manager.reconcile(Exchange::Binance, &open_orders);
+
+        assert_eq!(result.closed_at_venue, vec![gone.client_order_id]);
+        assert_eq!(result.unknown_at_venue.len(), 1);
+        assert_eq!(result.missed_fills, vec![resting.client_order_id]);
+        assert_eq!(
+            manager.state(resting.client_order_id),
+            Some(OrderState::New)
+        );
+        assert_eq!(
+            manager
+                .by_venue_id(Exchange::Binance, "123")
+                .unwrap()
+                .request,
+            resting
+        );
+    }
+
+    #[test]
+    fn test_terminal_reports_release_risk_slots() {
+        let risk = Arc::new(RiskEngine::new(RiskLimits {
+            max_order_notional: 1_000_000,
+            max_position: 100,
+            max_open_orders: 1,
+            max_daily_loss: 1_000,
+            max_orders_per_second: 100,
+            price_band_bps: 500,
+        }));
+        risk.update_reference_mid(100);
+        let manager = OrderManager::new().with_risk_engine(Arc::clone(&risk));
+
+        let resting = OrderRequest {
+            time_in_force: TimeInForce::Gtc,
+            ..OrderRequest::ioc(Exchange::Kraken, Side::Buy, 10, 100)
+        };
+        manager.register(resting);
+        risk.check_order(&resting).unwrap();
+        risk.on_order_done(
+            &resting,
+            Some(&Fill {
+                filled_quantity: 4,
+                ..Fill::empty(&resting)
+            }),
+        );
+        // The remainder rests, holding the only slot
+        assert_eq!(risk.open_orders(), 1);
+        let next = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 1, 100);
+        assert!(risk.check_order(&next).is_err());
+
+        manager.apply_report(report(&resting, ExecType::New));
+        assert_eq!(risk.open_orders(), 1);
+        manager.apply_report(report(&resting, ExecType::Canceled));
+        assert_eq!(risk.open_orders(), 0);
+
+        // A slot taken by another order survives replays of the old terminal report
+        risk.check_order(&next).unwrap();
+        manager.apply_report(report(&resting, ExecType::Canceled));
+        manager.reject(resting.client_order_id);
+        assert_eq!(risk.open_orders(), 1);
+    }
+
+    #[test]
+    fn test_unmatched_reports_are_bounded() {
+        let manager = OrderManager::new().with_unmatched_limits(2, Duration::from_secs(60));
+        let stray = |venue_order_id: &str| {
+            ExecutionReport::new(
+                Exchange::Binance,
+                venue_order_id,
+                ExecType::New,
+                Instant::now(),
+            )
+        };
+        manager.apply_report(stray("A"));
+        manager.apply_report(stray("B"));
+        // At the limit, the oldest order's reports make room
+        manager.apply_report(stray("B"));
+        assert_eq!(manager.unmatched_reports(), 2);
+        manager.apply_report(stray("C"));
+        assert_eq!(manager.unmatched_reports(), 1);
+
+        let request = OrderRequest::ioc(Exchange::Binance, Side::Buy, 5, 100);
+        manager.register(request);
+        assert!(manager.acknowledge(request.client_order_id, "A").is_ok());
+        assert_eq!(
+            manager.state(request.client_order_id),
+            Some(OrderState::PendingNew)
+        );
+
+        // Reports held past their TTL are dropped
+        let manager = OrderManager::new().with_unmatched_limits(10, Duration::ZERO);
+        manager.apply_report(stray("A"));
+        manager.apply_report(stray("B"));
+        assert_eq!(manager.unmatched_reports(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_orders() {
+        let manager = Arc::new(OrderManager::new());
+        let waiting = tokio::spawn({
+            let manager = Arc::clone(&manager);
+            async move { manager.wait_for_orders().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        manager.register(OrderRequest::ioc(Exchange::Kraken, Side::Buy, 1, 100));
+        waiting.await.unwrap();
+        // Already registered, so nothing to wait for
+        manager.wait_for_orders().await;
+    }
+}
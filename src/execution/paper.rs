@@ -0,0 +1,105 @@
+//! # Paper Gateway
+//!
+//! Routes orders to the simulated `MatchingEngine` for each venue so strategies
+//! can be exercised end to end without touching a real exchange.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use dashmap::DashMap;
+use pricelevel::OrderId;
+
+use super::{ExecutionGateway, Fill, OrderRequest};
+use crate::{
+    orderbook::{book::Exchange, FillResponse, MatchingEngine},
+    util::QUANTITY_SCALE,
+};
+
+/// Execution gateway backed by one simulated matching engine per venue.
+#[derive(Default)]
+pub struct PaperGateway {
+    engines: DashMap<Exchange, Arc<MatchingEngine>>,
+    /// Our orders that rested on a simulated book, so they can be told apart
+    /// from seeded liquidity when cancelling
+    working_orders: DashMap<OrderId, Exchange>,
+}
+
+impl PaperGateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers (or replaces) the simulated engine for its venue.
+    pub fn add_engine(&self, engine: Arc<MatchingEngine>) {
+        self.engines.insert(engine.exchange, engine);
+    }
+
+    /// The simulated engine for `exchange`, e.g. to seed resting liquidity.
+    pub fn engine(&self, exchange: Exchange) -> Option<Arc<MatchingEngine>> {
+        self.engines.get(&exchange).map(|e| Arc::clone(e.value()))
+    }
+}
+
+impl ExecutionGateway for PaperGateway {
+    fn is_simulated(&self) -> bool {
+        true
+    }
+
+    async fn submit_order(&self, order: OrderRequest) -> anyhow::Result<Fill> {
+        let engine = self
+            .engine(order.exchange)
+            .ok_or_else(|| anyhow!("no simulated engine for {:?}", order.exchange))?;
+
+        let result = match order.limit_price {
+            Some(price) => engine.submit_limit_order(
+                order.client_order_id,
+                price,
+                order.quantity,
+                order.side,
+                order.time_in_force,
+            ),
+            None => engine.submit_market_order(order.client_order_id, order.quantity, order.side),
+        };
+
+        let rested = result.as_ref().is_ok_and(|r| {
+            r.remaining_quantity > 0
+                && order.limit_price.is_some()
+                && !order.time_in_force.is_immediate()
+        });
+        if rested {
+            self.working_orders
+                .insert(order.client_order_id, order.exchange);
+        }
+
+        match FillResponse::from(result) {
+            FillResponse::Fill(result) | FillResponse::PartialFill(result) => Ok(Fill {
+                filled_quantity: result.executed_quantity(),
+                // The engine sums price × quantity in order units
+                notional: result.executed_value() / QUANTITY_SCALE,
+                ..Fill::empty(&order)
+            }),
+            FillResponse::Error(e) => Err(e),
+        }
+    }
+
+    async fn cancel_all_orders(&self) -> anyhow::Result<usize> {
+        let working: Vec<(OrderId, Exchange)> = self
+            .working_orders
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+
+        let mut cancelled = 0;
+        for (order_id, exchange) in working {
+            self.working_orders.remove(&order_id);
+            let Some(engine) = self.engine(exchange) else {
+                continue;
+            };
+            // Orders that filled since resting are already gone from the book
+            if engine.cancel_order(order_id).is_ok() {
+                cancelled += 1;
+            }
+        }
+        Ok(cancelled)
+    }
+}
@@ -0,0 +1,93 @@
+//! # Execution Reports
+//!
+//! Every venue's private stream describes order updates differently. They are
+//! all decoded into `ExecutionReport`, modelled loosely on FIX: what happened
+//! (`ExecType`), which order it happened to, and for trades the size and price
+//! of the execution.
+//!
+//! Quantities from live venues are in order units of 1e-8 of the base asset
+//! (see `util::parse_quantity_units`), prices in cents.
+
+use std::{str::FromStr, time::Instant};
+
+use pricelevel::{OrderId, Side};
+
+use crate::{orderbook::book::Exchange, util};
+
+/// What a single execution report tells us about an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExecType {
+    /// The venue accepted the order
+    New,
+    /// Some or all of the order executed
+    Trade,
+    Canceled,
+    Rejected,
+    /// Removed by the venue, e.g. an IOC remainder or self-trade prevention
+    Expired,
+}
+
+/// One order update from a venue's private stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReport {
+    pub exchange: Exchange,
+    pub venue_order_id: String,
+    /// Our client order id, when the venue echoes it back on this update
+    pub client_order_id: Option<String>,
+    pub exec_type: ExecType,
+    /// Not every venue repeats the side on every update
+    pub side: Option<Side>,
+    /// Venue's id for this execution, used to drop duplicate trade reports
+    pub trade_id: Option<String>,
+    /// Quantity executed by this report, 0 unless `exec_type` is `Trade`
+    pub last_quantity: u64,
+    /// Price of this execution in cents, 0 unless `exec_type` is `Trade`
+    pub last_price: u64,
+    /// Total executed so far, when the venue reports it
+    pub cumulative_quantity: Option<u64>,
+    /// Original order size, when the venue reports it
+    pub order_quantity: Option<u64>,
+    pub exchange_timestamp: Option<u64>,
+    pub received_at: Instant,
+}
+
+impl ExecutionReport {
+    /// Report with no fill or order details, to be filled in by the decoder.
+    pub fn new(
+        exchange: Exchange,
+        venue_order_id: impl Into<String>,
+        exec_type: ExecType,
+        received_at: Instant,
+    ) -> Self {
+        Self {
+            exchange,
+            venue_order_id: venue_order_id.into(),
+            client_order_id: None,
+            exec_type,
+            side: None,
+            trade_id: None,
+            last_quantity: 0,
+            last_price: 0,
+            cumulative_quantity: None,
+            order_quantity: None,
+            exchange_timestamp: None,
+            received_at,
+        }
+    }
+
+    /// Our client order id parsed back into an `OrderId`, if it is one of ours.
+    pub fn order_id(&self) -> Option<OrderId> {
+        self.client_order_id
+            .as_deref()
+            .and_then(|id| OrderId::from_str(id).ok())
+    }
+
+    /// Cents paid or received for this execution.
+    pub fn last_notional(&self) -> u64 {
+        util::notional(self.last_quantity, self.last_price)
+    }
+
+    pub fn is_fill(&self) -> bool {
+        self.exec_type == ExecType::Trade && self.last_quantity > 0
+    }
+}
@@ -0,0 +1,574 @@
+//! # Pre-Trade Risk
+//!
+//! Every order passes through the `RiskEngine` before it reaches a venue. The
+//! engine enforces:
+//! - Maximum order notional
+//! - Maximum position per venue (orders that reduce a position are always allowed)
+//! - Maximum open orders and maximum orders per second
+//! - Maximum daily realised loss
+//! - Fat-finger price bands around the cross-exchange mid
+//!
+//! A global `KillSwitch` blocks all new orders once triggered, either through
+//! `RiskCheckedGateway::kill` or by sending the process `SIGUSR1`, and cancels
+//! everything still working.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{NaiveDate, Utc};
+use dashmap::{DashMap, DashSet};
+use pricelevel::{OrderId, Side};
+use serde::Deserialize;
+use tracing::{error, warn};
+
+use super::{ExecutionGateway, Fill, OrderRequest};
+use crate::{orderbook::book::Exchange, util};
+
+/// Limits for one instrument. Notionals and losses are in cents, positions
+/// in order units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_order_notional: u64,
+    /// Largest absolute position allowed on any single venue
+    pub max_position: u64,
+    pub max_open_orders: usize,
+    pub max_daily_loss: u64,
+    pub max_orders_per_second: usize,
+    /// Furthest a limit price may be from the cross-exchange mid, in basis points
+    pub price_band_bps: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskViolation {
+    KillSwitchActive {
+        reason: String,
+    },
+    OrderNotional {
+        notional: u64,
+        limit: u64,
+    },
+    Position {
+        exchange: Exchange,
+        projected: i64,
+        limit: u64,
+    },
+    OpenOrders {
+        limit: usize,
+    },
+    DailyLoss {
+        loss: u64,
+        limit: u64,
+    },
+    OrderRate {
+        limit: usize,
+    },
+    PriceBand {
+        price: u64,
+        mid: u64,
+        band_bps: u64,
+    },
+    NoReferencePrice,
+}
+
+impl fmt::Display for RiskViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskViolation::KillSwitchActive { reason } => {
+                write!(f, "kill switch active: {}", reason)
+            }
+            RiskViolation::OrderNotional { notional, limit } => {
+                write!(f, "order notional {} exceeds limit {}", notional, limit)
+            }
+            RiskViolation::Position {
+                exchange,
+                projected,
+                limit,
+            } => write!(
+                f,
+                "{:?} position would be {}, limit is {}",
+                exchange, projected, limit
+            ),
+            RiskViolation::OpenOrders { limit } => {
+                write!(f, "open order limit of {} reached", limit)
+            }
+            RiskViolation::DailyLoss { loss, limit } => {
+                write!(f, "daily loss {} has reached limit {}", loss, limit)
+            }
+            RiskViolation::OrderRate { limit } => {
+                write!(f, "order rate limit of {}/s reached", limit)
+            }
+            RiskViolation::PriceBand {
+                price,
+                mid,
+                band_bps,
+            } => write!(
+                f,
+                "price {} is more than {}bps from mid {}",
+                price, band_bps, mid
+            ),
+            RiskViolation::NoReferencePrice => write!(f, "no cross-exchange mid to check against"),
+        }
+    }
+}
+
+impl std::error::Error for RiskViolation {}
+
+/// Global trading halt. Cheap to check on every order and shareable across tasks.
+#[derive(Debug, Clone, Default)]
+pub struct KillSwitch {
+    triggered: Arc<AtomicBool>,
+    reason: Arc<Mutex<Option<String>>>,
+}
+
+impl KillSwitch {
+    pub fn trigger(&self, reason: impl Into<String>) {
+        let reason = reason.into();
+        error!("[Risk] Kill switch triggered: {}", reason);
+        *self.reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+        self.triggered.store(true, Ordering::Release);
+    }
+
+    pub fn reset(&self) {
+        warn!("[Risk] Kill switch reset");
+        self.triggered.store(false, Ordering::Release);
+        *self.reason.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::Acquire)
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Tracks exposure for one instrument and decides whether an order may be sent.
+pub struct RiskEngine {
+    /// Swapped whole by `set_limits`; each check reads one copy
+    limits: Mutex<RiskLimits>,
+    kill_switch: KillSwitch,
+    positions: DashMap<Exchange, i64>,
+    /// Client ids of accepted orders that may still be working at a venue
+    open_orders: DashSet<OrderId>,
+    /// Send times of orders accepted within the last second
+    recent_orders: Mutex<VecDeque<Instant>>,
+    /// Realised PnL for the current UTC day
+    daily_pnl: Mutex<(NaiveDate, i64)>,
+    /// Cross-exchange mid in cents, 0 if unknown
+    reference_mid: AtomicU64,
+}
+
+impl RiskEngine {
+    pub fn new(limits: RiskLimits) -> Self {
+        Self {
+            limits: Mutex::new(limits),
+            kill_switch: KillSwitch::default(),
+            positions: DashMap::new(),
+            open_orders: DashSet::new(),
+            recent_orders: Mutex::new(VecDeque::new()),
+            daily_pnl: Mutex::new((Utc::now().date_naive(), 0)),
+            reference_mid: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> RiskLimits {
+        *self.limits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replaces every limit at once; orders already accepted are unaffected.
+    pub fn set_limits(&self, limits: RiskLimits) {
+        *self.limits.lock().unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+
+    /// Latest cross-exchange mid, used for price bands and market order notionals.
+    pub fn update_reference_mid(&self, mid: u64) {
+        self.reference_mid.store(mid, Ordering::Relaxed);
+    }
+
+    pub fn position(&self, exchange: Exchange) -> i64 {
+        self.positions.get(&exchange).map(|p| *p).unwrap_or(0)
+    }
+
+    pub fn open_orders(&self) -> usize {
+        self.open_orders.len()
+    }
+
+    /// Realised PnL booked so far today (UTC).
+    pub fn daily_pnl(&self) -> i64 {
+        let mut daily = self.daily_pnl.lock().unwrap_or_else(|e| e.into_inner());
+        roll_day(&mut daily);
+        daily.1
+    }
+
+    pub fn record_realized_pnl(&self, pnl: i64) {
+        let mut daily = self.daily_pnl.lock().unwrap_or_else(|e| e.into_inner());
+        roll_day(&mut daily);
+        daily.1 += pnl;
+    }
+
+    /// Runs every pre-trade check. On success the order is counted towards the
+    /// rate limit and stays open until `on_order_done` or `on_order_closed`.
+    pub fn check_order(&self, order: &OrderRequest) -> Result<(), RiskViolation> {
+        if self.kill_switch.is_triggered() {
+            return Err(RiskViolation::KillSwitchActive {
+                reason: self.kill_switch.reason().unwrap_or_default(),
+            });
+        }
+
+        let mid = self.reference_mid.load(Ordering::Relaxed);
+        if mid == 0 {
+            return Err(RiskViolation::NoReferencePrice);
+        }
+        let limits = self.limits();
+
+        let price = order.limit_price.unwrap_or(mid);
+        if let Some(limit_price) = order.limit_price {
+            let band = mid * limits.price_band_bps / 10_000;
+            if limit_price.abs_diff(mid) > band {
+                return Err(RiskViolation::PriceBand {
+                    price: limit_price,
+                    mid,
+                    band_bps: limits.price_band_bps,
+                });
+            }
+        }
+
+        let notional = util::notional(order.quantity, price);
+        if notional > limits.max_order_notional {
+            return Err(RiskViolation::OrderNotional {
+                notional,
+                limit: limits.max_order_notional,
+            });
+        }
+
+        let current = self.position(order.exchange);
+        let projected = current + signed_quantity(order.side, order.quantity);
+        let reduces_position = projected.unsigned_abs() < current.unsigned_abs();
+        if !reduces_position {
+            if projected.unsigned_abs() > limits.max_position {
+                return Err(RiskViolation::Position {
+                    exchange: order.exchange,
+                    projected,
+                    limit: limits.max_position,
+                });
+            }
+
+            let daily_pnl = self.daily_pnl();
+            if daily_pnl < 0 && daily_pnl.unsigned_abs() >= limits.max_daily_loss {
+                return Err(RiskViolation::DailyLoss {
+                    loss: daily_pnl.unsigned_abs(),
+                    limit: limits.max_daily_loss,
+                });
+            }
+        }
+
+        if self.open_orders() >= limits.max_open_orders {
+            return Err(RiskViolation::OpenOrders {
+                limit: limits.max_open_orders,
+            });
+        }
+
+        let now = Instant::now();
+        let mut recent = self.recent_orders.lock().unwrap_or_else(|e| e.into_inner());
+        while recent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= Duration::from_secs(1))
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= limits.max_orders_per_second {
+            return Err(RiskViolation::OrderRate {
+                limit: limits.max_orders_per_second,
+            });
+        }
+        recent.push_back(now);
+        self.open_orders.insert(order.client_order_id);
+
+        Ok(())
+    }
+
+    /// Books an order's fill into the venue position. Immediate orders are closed
+    /// here; a resting remainder stays open until `on_order_closed`, which the
+    /// order manager calls once the venue reports it finished.
+    pub fn on_order_done(&self, order: &OrderRequest, fill: Option<&Fill>) {
+        if let Some(fill) = fill {
+            *self.positions.entry(order.exchange).or_insert(0) +=
+                signed_quantity(fill.side, fill.filled_quantity);
+        }
+
+        let rested = fill.is_some_and(|f| {
+            f.filled_quantity < f.requested_quantity
+                && order.limit_price.is_some()
+                && !order.time_in_force.is_immediate()
+        });
+        if !rested {
+            self.on_order_closed(order.client_order_id);
+        }
+    }
+
+    /// Releases an order's open slot once it is filled, cancelled or rejected.
+    /// Closing an order twice, or one that was never accepted, does nothing.
+    pub fn on_order_closed(&self, client_order_id: OrderId) {
+        self.open_orders.remove(&client_order_id);
+    }
+}
+
+fn signed_quantity(side: Side, quantity: u64) -> i64 {
+    match side {
+        Side::Buy => quantity as i64,
+        Side::Sell => -(quantity as i64),
+    }
+}
+
+fn roll_day(daily: &mut (NaiveDate, i64)) {
+    let today = Utc::now().date_naive();
+    if daily.0 != today {
+        *daily = (today, 0);
+    }
+}
+
+/// Gateway wrapper that runs the risk checks before every order it forwards.
+pub struct RiskCheckedGateway<G> {
+    inner: Arc<G>,
+    risk: Arc<RiskEngine>,
+}
+
+impl<G: ExecutionGateway> RiskCheckedGateway<G> {
+    pub fn new(inner: Arc<G>, risk: Arc<RiskEngine>) -> Self {
+        Self { inner, risk }
+    }
+
+    pub fn risk(&self) -> &Arc<RiskEngine> {
+        &self.risk
+    }
+
+    /// Triggers the kill switch and cancels everything still working.
+    pub async fn kill(&self, reason: impl Into<String>) -> anyhow::Result<usize> {
+        self.risk.kill_switch().trigger(reason);
+        self.cancel_all_orders().await
+    }
+}
+
+impl<G: ExecutionGateway> ExecutionGateway for RiskCheckedGateway<G> {
+    fn is_simulated(&self) -> bool {
+        self.inner.is_simulated()
+    }
+
+    async fn submit_order(&self, order: OrderRequest) -> anyhow::Result<Fill> {
+        if let Err(violation) = self.risk.check_order(&order) {
+            warn!("[Risk] {:?} order rejected: {}", order.exchange, violation);
+            return Err(violation.into());
+        }
+
+        let result = self.inner.submit_order(order).await;
+        self.risk.on_order_done(&order, result.as_ref().ok());
+        result
+    }
+
+    async fn cancel_all_orders(&self) -> anyhow::Result<usize> {
+        let cancelled = self.inner.cancel_all_orders().await?;
+        self.risk.open_orders.clear();
+        Ok(cancelled)
+    }
+}
+
+/// Triggers the kill switch when the process receives `SIGUSR1`.
+#[cfg(unix)]
+pub fn spawn_kill_signal_listener<G>(
+    gateway: Arc<RiskCheckedGateway<G>>,
+) -> std::io::Result<tokio::task::JoinHandle<()>>
+where
+    G: ExecutionGateway + 'static,
+{
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::user_defined1())?;
+    Ok(tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            match gateway.kill("SIGUSR1 received").await {
+                Ok(cancelled) => warn!("[Risk] Cancelled {} working orders", cancelled),
+                Err(e) => error!("[Risk] Failed to cancel working orders: {}", e),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use pricelevel::{OrderId, Side, TimeInForce};
+
+    use super::{RiskCheckedGateway, RiskEngine, RiskLimits, RiskViolation};
+    use crate::{
+        execution::{ExecutionGateway, Fill, OrderRequest, PaperGateway},
+        orderbook::{book::Exchange, MatchingEngine},
+        util::QUANTITY_SCALE,
+    };
+
+    fn limits() -> RiskLimits {
+        RiskLimits {
+            max_order_notional: 10_000,
+            max_position: 50,
+            max_open_orders: 2,
+            max_daily_loss: 500,
+            max_orders_per_second: 100,
+            price_band_bps: 500,
+        }
+    }
+
+    fn risk() -> RiskEngine {
+        let risk = RiskEngine::new(limits());
+        risk.update_reference_mid(100);
+        risk
+    }
+
+    #[test]
+    fn test_order_notional_and_price_band() {
+        let risk = risk();
+
+        // 101 whole units at 100 cents
+        let too_big = OrderRequest::ioc(Exchange::Binance, Side::Buy, 101 * QUANTITY_SCALE, 100);
+        assert!(matches!(
+            risk.check_order(&too_big),
+            Err(RiskViolation::OrderNotional {
+                notional: 10_100,
+                ..
+            })
+        ));
+
+        // 5% band around a mid of 100
+        let fat_finger = OrderRequest::ioc(Exchange::Binance, Side::Sell, 1, 94);
+        assert!(matches!(
+            risk.check_order(&fat_finger),
+            Err(RiskViolation::PriceBand { .. })
+        ));
+        assert!(risk
+            .check_order(&OrderRequest::ioc(Exchange::Binance, Side::Sell, 1, 95))
+            .is_ok());
+
+        risk.update_reference_mid(0);
+        assert_eq!(
+            risk.check_order(&OrderRequest::market(Exchange::Binance, Side::Buy, 1)),
+            Err(RiskViolation::NoReferencePrice)
+        );
+    }
+
+    #[test]
+    fn test_position_limit_allows_reducing_orders() {
+        let risk = risk();
+
+        let buy = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 50, 100);
+        risk.check_order(&buy).unwrap();
+        risk.on_order_done(
+            &buy,
+            Some(&Fill {
+                filled_quantity: 50,
+                ..Fill::empty(&buy)
+            }),
+        );
+        assert_eq!(risk.position(Exchange::Kraken), 50);
+
+        assert!(matches!(
+            risk.check_order(&OrderRequest::ioc(Exchange::Kraken, Side::Buy, 1, 100)),
+            Err(RiskViolation::Position { projected: 51, .. })
+        ));
+        // Other venues have their own limit
+        let other = OrderRequest::ioc(Exchange::Binance, Side::Buy, 1, 100);
+        assert!(risk.check_order(&other).is_ok());
+        risk.on_order_done(&other, None);
+
+        // Losses past the daily limit only block orders that add risk
+        risk.record_realized_pnl(-500);
+        assert!(matches!(
+            risk.check_order(&OrderRequest::ioc(Exchange::Coinbase, Side::Buy, 1, 100)),
+            Err(RiskViolation::DailyLoss { loss: 500, .. })
+        ));
+        assert!(risk
+            .check_order(&OrderRequest::market(Exchange::Kraken, Side::Sell, 10))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_open_orders_and_rate_limit() {
+        let risk = RiskEngine::new(RiskLimits {
+            max_orders_per_second: 3,
+            ..limits()
+        });
+        risk.update_reference_mid(100);
+        let order = || OrderRequest::ioc(Exchange::Binance, Side::Buy, 1, 100);
+        let (first, second) = (order(), order());
+
+        risk.check_order(&first).unwrap();
+        risk.check_order(&second).unwrap();
+        assert_eq!(
+            risk.check_order(&order()),
+            Err(RiskViolation::OpenOrders { limit: 2 })
+        );
+
+        risk.on_order_done(&first, None);
+        risk.on_order_done(&second, None);
+        // Closing again can't free a slot some other order holds
+        risk.on_order_closed(first.client_order_id);
+        assert_eq!(risk.open_orders(), 0);
+
+        risk.check_order(&order()).unwrap();
+        assert_eq!(
+            risk.check_order(&order()),
+            Err(RiskViolation::OrderRate { limit: 3 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kill_switch_cancels_working_orders() {
+        let paper = PaperGateway::new();
+        let engine = Arc::new(MatchingEngine::new(Exchange::Binance, "BTC/USD"));
+        engine
+            .add_to_limit_order(OrderId::default(), 101, 5, Side::Sell)
+            .unwrap();
+        paper.add_engine(Arc::clone(&engine));
+
+        let risk = Arc::new(risk());
+        let gateway = RiskCheckedGateway::new(Arc::new(paper), Arc::clone(&risk));
+
+        let resting = OrderRequest {
+            time_in_force: TimeInForce::Gtc,
+            ..OrderRequest::ioc(Exchange::Binance, Side::Buy, 3, 99)
+        };
+        gateway.submit_order(resting).await.unwrap();
+        assert_eq!(engine.order_count(), 2);
+        assert_eq!(risk.open_orders(), 1);
+
+        assert_eq!(gateway.kill("test").await.unwrap(), 1);
+        // Only our order is cancelled, seeded liquidity stays
+        assert_eq!(engine.order_count(), 1);
+        assert_eq!(risk.open_orders(), 0);
+
+        let rejected = gateway
+            .submit_order(OrderRequest::ioc(Exchange::Binance, Side::Buy, 1, 101))
+            .await;
+        assert!(rejected.is_err());
+
+        risk.kill_switch().reset();
+        assert!(gateway
+            .submit_order(OrderRequest::ioc(Exchange::Binance, Side::Buy, 1, 101))
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,529 @@
+//! # Smart Order Router
+//!
+//! Splits a parent order across venues using the consolidated book:
+//! - Levels from every venue are ranked by all-in price, i.e. including each
+//!   venue's taker fee, and taken best first up to the parent's limit
+//! - Each venue's share is capped by the balance held there and rounded down
+//!   to the venue's lot size; shares below its minimum size are dropped
+//! - Children go out as IOC orders at the worst price planned on their venue
+//! - Quantity that misses is re-routed to the remaining venues, net of what
+//!   earlier rounds already took from the book
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use futures_util::future::join_all;
+use pricelevel::Side;
+use tracing::{info, warn};
+
+use super::{ExecutionGateway, Fill, OrderRequest};
+use crate::{
+    inventory::InventoryTracker,
+    orderbook::book::{Exchange, OrderBook},
+    util,
+};
+
+/// Trading rules and costs for one venue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VenueRules {
+    pub taker_fee_bps: u64,
+    /// Smallest order the venue accepts
+    pub min_quantity: u64,
+    /// Order sizes must be a multiple of this
+    pub lot_size: u64,
+}
+
+impl Default for VenueRules {
+    fn default() -> Self {
+        Self {
+            taker_fee_bps: 10,
+            min_quantity: 1,
+            lot_size: 1,
+        }
+    }
+}
+
+impl VenueRules {
+    /// Price per unit including the taker fee (more for a buy, less for a
+    /// sell), scaled by 10_000 so fee differences of a fraction of a cent still rank.
+    fn all_in_price(&self, side: Side, price: u64) -> u64 {
+        match side {
+            Side::Buy => price * (10_000 + self.taker_fee_bps),
+            Side::Sell => price * 10_000u64.saturating_sub(self.taker_fee_bps),
+        }
+    }
+
+    fn fee(&self, notional: u64) -> u64 {
+        notional * self.taker_fee_bps / 10_000
+    }
+
+    /// Largest valid order size not above `quantity`, or 0 if below the minimum.
+    fn round(&self, quantity: u64) -> u64 {
+        let rounded = quantity - quantity % self.lot_size.max(1);
+        if rounded < self.min_quantity {
+            0
+        } else {
+            rounded
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouterConfig {
+    /// Venues the router may use; any venue missing here is never routed to
+    pub venues: HashMap<Exchange, VenueRules>,
+    /// How many times quantity that missed is routed again
+    pub max_reroutes: usize,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            venues: Exchange::ALL
+                .iter()
+                .map(|&exchange| (exchange, VenueRules::default()))
+                .collect(),
+            max_reroutes: 2,
+        }
+    }
+}
+
+/// An order to work across venues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParentOrder {
+    pub side: Side,
+    pub quantity: u64,
+    /// Worst price any child may trade at, in cents
+    pub limit_price: u64,
+}
+
+/// How a parent order would be split given the current book.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RoutePlan {
+    pub children: Vec<OrderRequest>,
+    /// Parent quantity no venue can take within the limit, balances and lot rules
+    pub unrouted_quantity: u64,
+    /// Expected notional of the children, before fees
+    pub estimated_notional: u64,
+    pub estimated_fees: u64,
+}
+
+/// What executing a parent order achieved.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RouteResult {
+    pub fills: Vec<Fill>,
+    pub filled_quantity: u64,
+    pub notional: u64,
+    pub fees: u64,
+    /// Routing rounds sent, 1 when nothing needed re-routing
+    pub rounds: usize,
+}
+
+impl RouteResult {
+    /// All-in average price per unit including fees, `None` if nothing filled.
+    pub fn all_in_average_price(&self, side: Side) -> Option<u64> {
+        let all_in = match side {
+            Side::Buy => self.notional + self.fees,
+            Side::Sell => self.notional.saturating_sub(self.fees),
+        };
+        util::unit_price(all_in, self.filled_quantity)
+    }
+}
+
+/// Planned liquidity taken from one venue, best price first.
+#[derive(Default)]
+struct VenueTakes {
+    takes: Vec<(u64, u64)>,
+}
+
+impl VenueTakes {
+    fn quantity(&self) -> u64 {
+        self.takes.iter().map(|(_, q)| q).sum()
+    }
+
+    /// Drops the worst-priced quantity until only `quantity` remains.
+    fn trim_to(&mut self, quantity: u64) {
+        let mut excess = self.quantity().saturating_sub(quantity);
+        while excess > 0 {
+            let Some((_, last)) = self.takes.last_mut() else {
+                break;
+            };
+            let cut = excess.min(*last);
+            *last -= cut;
+            excess -= cut;
+            if *last == 0 {
+                self.takes.pop();
+            }
+        }
+    }
+}
+
+pub struct SmartOrderRouter<G> {
+    gateway: Arc<G>,
+    book: Arc<OrderBook>,
+    config: RouterConfig,
+    inventory: Option<Arc<InventoryTracker>>,
+}
+
+impl<G: ExecutionGateway> SmartOrderRouter<G> {
+    pub fn new(gateway: Arc<G>, book: Arc<OrderBook>, config: RouterConfig) -> Self {
+        Self {
+            gateway,
+            book,
+            config,
+            inventory: None,
+        }
+    }
+
+    /// Caps each venue's share by the balances held there and applies fills to them.
+    pub fn with_inventory(mut self, inventory: Arc<InventoryTracker>) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    pub fn config(&self) -> &RouterConfig {
+        &self.config
+    }
+
+    /// Splits `parent` across venues against the current book.
+    pub fn plan(&self, parent: &ParentOrder) -> RoutePlan {
+        self.plan_excluding(parent, &HashSet::new(), &HashMap::new())
+    }
+
+    /// Plans around venues in `excluded`, ignoring the first `consumed`
+    /// quantity of each venue's depth because earlier rounds already took it.
+    fn plan_excluding(
+        &self,
+        parent: &ParentOrder,
+        excluded: &HashSet<Exchange>,
+        consumed: &HashMap<Exchange, u64>,
+    ) -> RoutePlan {
+        let mut excluded = excluded.clone();
+        loop {
+            let mut per_venue = self.allocate(parent, &excluded, consumed);
+
+            // Venues whose share rounds below their minimum are dropped and the
+            // allocation redone so the next-best liquidity can take their place
+            let too_small: Vec<Exchange> = per_venue
+                .iter()
+                .filter(|(exchange, takes)| {
+                    self.config.venues[*exchange].round(takes.quantity()) == 0
+                })
+                .map(|(exchange, _)| *exchange)
+                .collect();
+            if !too_small.is_empty() {
+                excluded.extend(too_small);
+                continue;
+            }
+
+            let mut plan = RoutePlan::default();
+            let mut venues: Vec<_> = per_venue.iter_mut().collect();
+            venues.sort_by_key(|(exchange, _)| Exchange::ALL.iter().position(|e| e == *exchange));
+            for (&exchange, takes) in venues {
+                let rules = self.config.venues[&exchange];
+                takes.trim_to(rules.round(takes.quantity()));
+                let Some(&(worst_price, _)) = takes.takes.last() else {
+                    continue;
+                };
+                let notional: u64 = takes.takes.iter().map(|&(p, q)| util::notional(q, p)).sum();
+                plan.estimated_notional += notional;
+                plan.estimated_fees += rules.fee(notional);
+                plan.children.push(OrderRequest::ioc(
+                    exchange,
+                    parent.side,
+                    takes.quantity(),
+                    worst_price,
+                ));
+            }
+            let routed: u64 = plan.children.iter().map(|c| c.quantity).sum();
+            plan.unrouted_quantity = parent.quantity.saturating_sub(routed);
+            return plan;
+        }
+    }
+
+    /// Greedy allocation over every eligible level, cheapest all-in price first.
+    fn allocate(
+        &self,
+        parent: &ParentOrder,
+        excluded: &HashSet<Exchange>,
+        consumed: &HashMap<Exchange, u64>,
+    ) -> HashMap<Exchange, VenueTakes> {
+        let within_limit = |price: u64| match parent.side {
+            Side::Buy => price <= parent.limit_price,
+            Side::Sell => price >= parent.limit_price,
+        };
+
+        // (all-in price, price, venue, quantity) for every level we may take
+        let mut already_taken = consumed.clone();
+        let mut levels: Vec<(u64, u64, Exchange, u64)> = Vec::new();
+        let ladder = self.book.consolidated_ladder(parent.side.opposite());
+        let ordered: Vec<_> = match parent.side {
+            Side::Buy => ladder.into_iter().collect(),
+            Side::Sell => ladder.into_iter().rev().collect(),
+        };
+        for (price, venues) in ordered.into_iter().filter(|(p, _)| within_limit(*p)) {
+            for (exchange, mut quantity) in venues {
+                let Some(rules) = self.config.venues.get(&exchange) else {
+                    continue;
+                };
+                if excluded.contains(&exchange) {
+                    continue;
+                }
+                // Skip depth our earlier children already took, best levels first
+                let taken = already_taken.entry(exchange).or_insert(0);
+                let skip = quantity.min(*taken);
+                *taken -= skip;
+                quantity -= skip;
+                if quantity > 0 {
+                    levels.push((
+                        rules.all_in_price(parent.side, price),
+                        price,
+                        exchange,
+                        quantity,
+                    ));
+                }
+            }
+        }
+        match parent.side {
+            Side::Buy => levels.sort_by_key(|(all_in, ..)| *all_in),
+            Side::Sell => levels.sort_by_key(|(all_in, ..)| std::cmp::Reverse(*all_in)),
+        }
+
+        let mut capacity: HashMap<Exchange, i64> = HashMap::new();
+        let mut per_venue: HashMap<Exchange, VenueTakes> = HashMap::new();
+        let mut remaining = parent.quantity;
+        for (all_in, price, exchange, available) in levels {
+            if remaining == 0 {
+                break;
+            }
+            let quantity = available.min(remaining).min(self.affordable(
+                exchange,
+                parent.side,
+                all_in,
+                &mut capacity,
+            ));
+            if quantity == 0 {
+                continue;
+            }
+            if let Some(left) = capacity.get_mut(&exchange) {
+                *left -= match parent.side {
+                    Side::Buy => (util::notional(quantity, all_in) / 10_000) as i64,
+                    Side::Sell => quantity as i64,
+                };
+            }
+            per_venue
+                .entry(exchange)
+                .or_default()
+                .takes
+                .push((price, quantity));
+            remaining -= quantity;
+        }
+        per_venue
+    }
+
+    /// How much the venue's remaining balance allows at `all_in` (scaled) per unit.
+    fn affordable(
+        &self,
+        exchange: Exchange,
+        side: Side,
+        all_in: u64,
+        capacity: &mut HashMap<Exchange, i64>,
+    ) -> u64 {
+        let Some(inventory) = &self.inventory else {
+            return u64::MAX;
+        };
+        let left = *capacity.entry(exchange).or_insert_with(|| match side {
+            Side::Buy => inventory.quote_balance(exchange),
+            Side::Sell => inventory.base_balance(exchange),
+        });
+        let left = left.max(0) as u64;
+        match side {
+            Side::Buy => util::quantity_for(left.saturating_mul(10_000), all_in),
+            Side::Sell => left,
+        }
+    }
+
+    /// Routes `parent`, sends the children concurrently and re-routes whatever
+    /// missed to the venues that haven't, until filled or out of rounds.
+    pub async fn execute(&self, parent: ParentOrder) -> RouteResult {
+        let mut result = RouteResult::default();
+        let mut missed_venues = HashSet::new();
+        let mut consumed: HashMap<Exchange, u64> = HashMap::new();
+
+        while result.rounds <= self.config.max_reroutes && result.filled_quantity < parent.quantity
+        {
+            let remaining = ParentOrder {
+                quantity: parent.quantity - result.filled_quantity,
+                ..parent
+            };
+            let plan = self.plan_excluding(&remaining, &missed_venues, &consumed);
+            if plan.children.is_empty() {
+                break;
+            }
+            result.rounds += 1;
+
+            let fills = join_all(plan.children.iter().map(|&child| self.submit(child))).await;
+            for (child, fill) in plan.children.iter().zip(fills) {
+                if fill.filled_quantity < child.quantity {
+                    missed_venues.insert(child.exchange);
+                }
+                *consumed.entry(child.exchange).or_insert(0) += fill.filled_quantity;
+                result.filled_quantity += fill.filled_quantity;
+                result.notional += fill.notional;
+                result.fees += self.config.venues[&child.exchange].fee(fill.notional);
+                result.fills.push(fill);
+            }
+        }
+
+        info!(
+            "[Router] {:?} {} filled {} of {} over {} round(s)",
+            parent.side, self.book.symbol, result.filled_quantity, parent.quantity, result.rounds
+        );
+        result
+    }
+
+    /// Sends one child straight to its venue, applying the fill to inventory.
+    /// Rejections come back as an empty fill.
+    pub async fn submit(&self, order: OrderRequest) -> Fill {
+        match self.gateway.submit_order(order).await {
+            Ok(fill) => {
+                if let Some(inventory) = &self.inventory {
+                    inventory.apply_fill(&fill);
+                }
+                fill
+            }
+            Err(e) => {
+                warn!("[Router] {:?} child rejected: {}", order.exchange, e);
+                Fill::empty(&order)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use pricelevel::{OrderId, Side};
+
+    use super::{ParentOrder, RouterConfig, SmartOrderRouter, VenueRules};
+    use crate::{
+        execution::PaperGateway,
+        inventory::InventoryTracker,
+        orderbook::{
+            book::{Exchange, OrderBook},
+            MatchingEngine,
+        },
+        util::QUANTITY_SCALE,
+    };
+
+    /// Binance offers 5 @ 100 with a high fee, Kraken 5 @ 100 cheaply and
+    /// Coinbase 10 @ 101, in whole units.
+    fn book() -> Arc<OrderBook> {
+        let book = OrderBook::new("BTC/USD".to_string());
+        book.set_exchange_price_level(100, Exchange::Binance, Side::Sell, 5 * QUANTITY_SCALE);
+        book.set_exchange_price_level(100, Exchange::Kraken, Side::Sell, 5 * QUANTITY_SCALE);
+        book.set_exchange_price_level(101, Exchange::Coinbase, Side::Sell, 10 * QUANTITY_SCALE);
+        Arc::new(book)
+    }
+
+    fn config() -> RouterConfig {
+        let mut config = RouterConfig::default();
+        config.venues.insert(
+            Exchange::Binance,
+            VenueRules {
+                taker_fee_bps: 200,
+                ..VenueRules::default()
+            },
+        );
+        config
+    }
+
+    fn parent(quantity: u64) -> ParentOrder {
+        ParentOrder {
+            side: Side::Buy,
+            quantity: quantity * QUANTITY_SCALE,
+            limit_price: 101,
+        }
+    }
+
+    #[test]
+    fn test_plan_prefers_lowest_all_in_cost() {
+        let router = SmartOrderRouter::new(Arc::new(PaperGateway::new()), book(), config());
+        let plan = router.plan(&parent(12));
+
+        // Binance at 100 + 2% is dearer than Coinbase at 101 + 0.1%
+        let split: Vec<_> = plan
+            .children
+            .iter()
+            .map(|c| (c.exchange, c.quantity / QUANTITY_SCALE, c.limit_price))
+            .collect();
+        assert_eq!(
+            split,
+            vec![
+                (Exchange::Coinbase, 7, Some(101)),
+                (Exchange::Kraken, 5, Some(100))
+            ]
+        );
+        assert_eq!(plan.unrouted_quantity, 0);
+        assert_eq!(plan.estimated_notional, 7 * 101 + 5 * 100);
+    }
+
+    #[test]
+    fn test_plan_respects_lots_and_balances() {
+        let mut config = config();
+        config.venues.insert(
+            Exchange::Coinbase,
+            VenueRules {
+                lot_size: 4 * QUANTITY_SCALE,
+                min_quantity: 4 * QUANTITY_SCALE,
+                ..VenueRules::default()
+            },
+        );
+        config.venues.insert(
+            Exchange::Kraken,
+            VenueRules {
+                lot_size: QUANTITY_SCALE,
+                ..VenueRules::default()
+            },
+        );
+        let inventory = Arc::new(InventoryTracker::new("BTC", "USD"));
+        inventory.set_balance(Exchange::Kraken, "USD", 301);
+        inventory.set_balance(Exchange::Coinbase, "USD", 10_000);
+        let router = SmartOrderRouter::new(Arc::new(PaperGateway::new()), book(), config)
+            .with_inventory(inventory);
+
+        // Kraken can only fund 3 whole lots; Coinbase's 9 rounds down to 8; Binance has no USD
+        let plan = router.plan(&parent(12));
+        let split: Vec<_> = plan
+            .children
+            .iter()
+            .map(|c| (c.exchange, c.quantity / QUANTITY_SCALE))
+            .collect();
+        assert_eq!(split, vec![(Exchange::Coinbase, 8), (Exchange::Kraken, 3)]);
+        assert_eq!(plan.unrouted_quantity, QUANTITY_SCALE);
+    }
+
+    #[tokio::test]
+    async fn test_missed_child_is_rerouted() {
+        // The book shows Kraken depth, but its engine has already been swept
+        let gateway = PaperGateway::new();
+        let coinbase = Arc::new(MatchingEngine::new(Exchange::Coinbase, "BTC/USD"));
+        coinbase
+            .add_to_limit_order(OrderId::default(), 101, 10 * QUANTITY_SCALE, Side::Sell)
+            .unwrap();
+        gateway.add_engine(coinbase);
+        gateway.add_engine(Arc::new(MatchingEngine::new(Exchange::Kraken, "BTC/USD")));
+
+        let router = SmartOrderRouter::new(Arc::new(gateway), book(), config());
+        let result = router.execute(parent(8)).await;
+
+        // Round one: 3 on Coinbase fill, 5 on Kraken miss; round two: 5 more on Coinbase
+        assert_eq!(result.rounds, 2);
+        assert_eq!(result.filled_quantity, 8 * QUANTITY_SCALE);
+        assert_eq!(result.notional, 8 * 101);
+        assert_eq!(result.all_in_average_price(Side::Buy), Some(101));
+    }
+}
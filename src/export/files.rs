@@ -0,0 +1,415 @@
+//! # Partitioned Files
+//!
+//! Export rows written Hive-style, one directory level per partition:
+//!
+//! `<dir>/parquet/<table>/date=2026-01-31/instrument=BTC-USDT/venue=kraken/part-140000.parquet`
+//!
+//! The BBO spans venues, so its files have no `venue=` level. The partition
+//! values live only in the path, where pandas and pyarrow pick them up as
+//! columns. CSV, when enabled, is written under `<dir>/csv` in the same
+//! layout, so neither tree holds files the other format's readers would
+//! trip over.
+//!
+//! A Parquet file is only readable once closed, so each file covers one
+//! roll interval of the day (an hour by default) and is closed shortly
+//! after it ends, or at shutdown. A file already on disk, e.g. from an
+//! earlier run in the same interval, is never overwritten; the new one
+//! gets a numbered suffix.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use arrow_array::{
+    ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use pricelevel::Side;
+use tracing::info;
+
+use super::{BestPrice, Row};
+use crate::orderbook::book::Exchange;
+
+/// Timezone of every timestamp column. An offset rather than "UTC", which
+/// the CSV writer can't format without a timezone database.
+const TIMEZONE: &str = "+00:00";
+/// How long after its interval ends a file stays open for rows still queued
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    Levels,
+    Bbo,
+    Trades,
+}
+
+impl Table {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Levels => "levels",
+            Self::Bbo => "bbo",
+            Self::Trades => "trades",
+        }
+    }
+
+    fn of(row: &Row) -> Self {
+        match row {
+            Row::Level(_) => Self::Levels,
+            Row::Bbo(_) => Self::Bbo,
+            Row::Trade(_) => Self::Trades,
+        }
+    }
+
+    pub fn schema(self) -> SchemaRef {
+        let time = |name| {
+            Field::new(
+                name,
+                DataType::Timestamp(TimeUnit::Microsecond, Some(TIMEZONE.into())),
+                name == "exchange_time",
+            )
+        };
+        let fields = match self {
+            Self::Levels => vec![
+                time("time"),
+                Field::new("side", DataType::Utf8, false),
+                Field::new("depth", DataType::UInt32, false),
+                Field::new("price", DataType::UInt64, false),
+                Field::new("quantity", DataType::UInt64, false),
+            ],
+            Self::Bbo => vec![
+                time("time"),
+                Field::new("bid", DataType::UInt64, true),
+                Field::new("bid_quantity", DataType::UInt64, true),
+                Field::new("bid_venue", DataType::Utf8, true),
+                Field::new("ask", DataType::UInt64, true),
+                Field::new("ask_quantity", DataType::UInt64, true),
+                Field::new("ask_venue", DataType::Utf8, true),
+            ],
+            Self::Trades => vec![
+                time("time"),
+                time("exchange_time"),
+                Field::new("trade_id", DataType::Utf8, false),
+                Field::new("price", DataType::UInt64, false),
+                Field::new("quantity", DataType::UInt64, false),
+                Field::new("aggressor", DataType::Utf8, false),
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+
+    /// The table's rows among `rows` as one batch.
+    fn batch(self, rows: &[&Row]) -> anyhow::Result<RecordBatch> {
+        let times = |time: &dyn Fn(&Row) -> Option<DateTime<Utc>>| -> ArrayRef {
+            let micros: Vec<_> = rows
+                .iter()
+                .map(|&row| time(row).map(|time| time.timestamp_micros()))
+                .collect();
+            Arc::new(TimestampMicrosecondArray::from(micros).with_timezone(TIMEZONE))
+        };
+        let columns: Vec<ArrayRef> = match self {
+            Self::Levels => {
+                let levels: Vec<_> = rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Level(level) => Some(level),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    times(&|row| Some(row.time())),
+                    Arc::new(StringArray::from_iter_values(levels.iter().map(|level| {
+                        match level.side {
+                            Side::Buy => "bid",
+                            Side::Sell => "ask",
+                        }
+                    }))),
+                    Arc::new(UInt32Array::from_iter_values(
+                        levels.iter().map(|level| level.depth),
+                    )),
+                    Arc::new(UInt64Array::from_iter_values(
+                        levels.iter().map(|level| level.price),
+                    )),
+                    Arc::new(UInt64Array::from_iter_values(
+                        levels.iter().map(|level| level.quantity),
+                    )),
+                ]
+            }
+            Self::Bbo => {
+                let bbos: Vec<_> = rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Bbo(bbo) => Some(bbo),
+                        _ => None,
+                    })
+                    .collect();
+                let mut columns = vec![times(&|row| Some(row.time()))];
+                for best in [
+                    bbos.iter().map(|bbo| bbo.bid).collect::<Vec<_>>(),
+                    bbos.iter().map(|bbo| bbo.ask).collect(),
+                ] {
+                    let field = |f: fn(&BestPrice) -> u64| {
+                        Arc::new(UInt64Array::from_iter(
+                            best.iter().map(|b| b.as_ref().map(f)),
+                        ))
+                    };
+                    columns.push(field(|best| best.price));
+                    columns.push(field(|best| best.quantity));
+                    columns.push(Arc::new(StringArray::from_iter(
+                        best.iter().map(|b| b.map(|best| venue_key(best.venue))),
+                    )));
+                }
+                columns
+            }
+            Self::Trades => {
+                let trades: Vec<_> = rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Trade(trade) => Some(trade),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    times(&|row| Some(row.time())),
+                    times(&|row| match row {
+                        Row::Trade(trade) => trade.exchange_time,
+                        _ => None,
+                    }),
+                    Arc::new(StringArray::from_iter_values(
+                        trades.iter().map(|trade| trade.trade_id.as_str()),
+                    )),
+                    Arc::new(UInt64Array::from_iter_values(
+                        trades.iter().map(|trade| trade.price),
+                    )),
+                    Arc::new(UInt64Array::from_iter_values(
+                        trades.iter().map(|trade| trade.quantity),
+                    )),
+                    Arc::new(StringArray::from_iter_values(trades.iter().map(|trade| {
+                        match trade.aggressor {
+                            Side::Buy => "buy",
+                            Side::Sell => "sell",
+                        }
+                    }))),
+                ]
+            }
+        };
+        RecordBatch::try_new(self.schema(), columns)
+            .with_context(|| format!("building a {} batch", self.name()))
+    }
+}
+
+/// One file's worth of rows: a table, day, instrument and venue, within one
+/// roll interval.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Partition {
+    table: Table,
+    date: NaiveDate,
+    instrument: Arc<str>,
+    venue: Option<Exchange>,
+    /// Start of the roll interval
+    starts_at: DateTime<Utc>,
+}
+
+impl Partition {
+    /// The partition's directory under one format's tree.
+    fn dir(&self, root: &Path) -> PathBuf {
+        let mut dir = root
+            .join(self.table.name())
+            .join(format!("date={}", self.date))
+            .join(format!("instrument={}", self.instrument.replace('/', "-")));
+        if let Some(venue) = self.venue {
+            dir = dir.join(format!("venue={}", venue_key(venue)));
+        }
+        dir
+    }
+}
+
+struct PartitionFile {
+    parquet: ArrowWriter<File>,
+    csv: Option<arrow_csv::Writer<File>>,
+    path: PathBuf,
+    /// The end of the roll interval, or midnight if sooner
+    ends_at: DateTime<Utc>,
+}
+
+/// The open files of every partition. Owned by the export thread.
+pub struct PartitionedFiles {
+    dir: PathBuf,
+    csv: bool,
+    roll_interval: Duration,
+    open: HashMap<Partition, PartitionFile>,
+}
+
+impl PartitionedFiles {
+    /// Parquet only, a file per hour, under `dir`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            csv: false,
+            roll_interval: Duration::from_secs(3_600),
+            open: HashMap::new(),
+        }
+    }
+
+    /// Also writes every row as CSV.
+    pub fn with_csv(mut self, csv: bool) -> Self {
+        self.csv = csv;
+        self
+    }
+
+    /// How much of the day each file covers.
+    pub fn with_roll_interval(mut self, interval: Duration) -> Self {
+        self.roll_interval = interval;
+        self
+    }
+
+    /// Appends the rows to their partitions' files, opening files as needed.
+    pub fn write(&mut self, rows: &[Row]) -> anyhow::Result<()> {
+        let mut partitions: HashMap<Partition, Vec<&Row>> = HashMap::new();
+        for row in rows {
+            partitions.entry(self.partition(row)).or_default().push(row);
+        }
+        for (partition, rows) in partitions {
+            let batch = partition.table.batch(&rows)?;
+            let file = match self.open.entry(partition) {
+                std::collections::hash_map::Entry::Occupied(open) => open.into_mut(),
+                std::collections::hash_map::Entry::Vacant(vacant) => {
+                    let file = Self::create(&self.dir, self.csv, self.roll_interval, vacant.key())?;
+                    vacant.insert(file)
+                }
+            };
+            file.parquet
+                .write(&batch)
+                .with_context(|| format!("writing {}", file.path.display()))?;
+            if let Some(csv) = &mut file.csv {
+                csv.write(&batch)
+                    .with_context(|| format!("writing the CSV of {}", file.path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the files whose interval ended a little before `now`.
+    pub fn close_expired(&mut self, now: DateTime<Utc>) -> anyhow::Result<()> {
+        let grace = chrono::Duration::from_std(CLOSE_GRACE).unwrap_or_default();
+        let expired: Vec<Partition> = self
+            .open
+            .iter()
+            .filter(|(_, file)| file.ends_at + grace <= now)
+            .map(|(partition, _)| partition.clone())
+            .collect();
+        self.close(expired)
+    }
+
+    pub fn close_all(&mut self) -> anyhow::Result<()> {
+        let open: Vec<Partition> = self.open.keys().cloned().collect();
+        self.close(open)
+    }
+
+    /// Closes every given file, returning the first error after trying all.
+    fn close(&mut self, partitions: Vec<Partition>) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for partition in partitions {
+            let Some(file) = self.open.remove(&partition) else {
+                continue;
+            };
+            let path = file.path;
+            let closed = file
+                .parquet
+                .close()
+                .with_context(|| format!("closing {}", path.display()));
+            match closed {
+                Ok(metadata) => info!(
+                    "[Export] Wrote {} rows to {}",
+                    metadata.num_rows,
+                    path.display()
+                ),
+                Err(e) => result = result.and(Err(e)),
+            }
+        }
+        result
+    }
+
+    fn partition(&self, row: &Row) -> Partition {
+        let time = row.time();
+        let date = time.date_naive();
+        let midnight = date.and_time(NaiveTime::MIN).and_utc();
+        let roll = self.roll_interval.as_secs().max(1) as i64;
+        let elapsed = (time - midnight).num_seconds();
+        Partition {
+            table: Table::of(row),
+            date,
+            instrument: Arc::clone(row.instrument()),
+            venue: row.venue(),
+            starts_at: midnight + chrono::Duration::seconds(elapsed / roll * roll),
+        }
+    }
+
+    fn create(
+        dir: &Path,
+        csv: bool,
+        roll_interval: Duration,
+        partition: &Partition,
+    ) -> anyhow::Result<PartitionFile> {
+        let parquet_dir = partition.dir(&dir.join("parquet"));
+        let csv_dir = csv.then(|| partition.dir(&dir.join("csv")));
+        for dir in std::iter::once(&parquet_dir).chain(&csv_dir) {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let stem = format!("part-{}", partition.starts_at.format("%H%M%S"));
+        let (path, csv_path) = (0..)
+            .map(|n| match n {
+                0 => stem.clone(),
+                n => format!("{stem}-{n}"),
+            })
+            .map(|name| {
+                (
+                    parquet_dir.join(format!("{name}.parquet")),
+                    csv_dir.as_ref().map(|dir| dir.join(format!("{name}.csv"))),
+                )
+            })
+            .find(|(path, csv_path)| {
+                !path.exists() && !csv_path.as_ref().is_some_and(|path| path.exists())
+            })
+            .expect("some suffix is free");
+
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let parquet = ArrowWriter::try_new(file, partition.table.schema(), Some(properties))
+            .with_context(|| format!("creating {}", path.display()))?;
+        let csv = match csv_path {
+            Some(csv_path) => {
+                let file = File::create(&csv_path)
+                    .with_context(|| format!("creating {}", csv_path.display()))?;
+                Some(arrow_csv::Writer::new(file))
+            }
+            None => None,
+        };
+        let roll = chrono::Duration::from_std(roll_interval).unwrap_or_default();
+        let next_midnight = (partition.date + chrono::Days::new(1))
+            .and_time(NaiveTime::MIN)
+            .and_utc();
+        Ok(PartitionFile {
+            parquet,
+            csv,
+            path,
+            ends_at: (partition.starts_at + roll).min(next_midnight),
+        })
+    }
+}
+
+fn venue_key(exchange: Exchange) -> &'static str {
+    match exchange {
+        Exchange::Binance => "binance",
+        Exchange::Coinbase => "coinbase",
+        Exchange::Kraken => "kraken",
+    }
+}
@@ -0,0 +1,380 @@
+//! # Research Export
+//!
+//! Book snapshots and the public trade tape as Parquet, and optionally CSV,
+//! for analysis in pandas:
+//! - `levels`: each venue's top N levels per side, sampled at an interval
+//! - `bbo`: the consolidated best bid and offer at the same samples, with the
+//!   venue quoting each and the size shown at that price across venues
+//! - `trades`: every public trade as it arrives
+//!
+//! `Snapshotter` samples the book on the aggregator's thread, at most once per
+//! interval on the first event after it has passed, and hands the rows to
+//! `ExportWriter`. Like `storage`, the files are written on a thread of their
+//! own and a full queue drops rows rather than blocking the book. Venues
+//! whose book is suppressed (rebuilding or quarantined) are left out of the
+//! samples. Prices are in cents and quantities in base units, as everywhere
+//! else; times are UTC microseconds.
+//!
+//! Files are partitioned by table, day, instrument and venue (see `files`),
+//! so `pandas.read_parquet("<dir>/parquet/levels")` loads a whole table with
+//! the partitions as columns.
+
+use std::{
+    cmp::Reverse,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use pricelevel::Side;
+
+use crate::{
+    api::MarketEvent,
+    orderbook::{
+        book::{Exchange, OrderBook},
+        ladder::PriceLadder,
+        Trade,
+    },
+    util::wall_clock,
+};
+
+pub mod files;
+pub mod writer;
+
+pub use files::PartitionedFiles;
+pub use writer::{Export, ExportWriter};
+
+/// One level of one venue's book at a sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelRow {
+    pub time: DateTime<Utc>,
+    pub instrument: Arc<str>,
+    pub venue: Exchange,
+    pub side: Side,
+    /// 0 is the venue's best price on the side
+    pub depth: u32,
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// The best price on one side of the consolidated book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BestPrice {
+    pub price: u64,
+    /// Summed over every venue at the price
+    pub quantity: u64,
+    /// The first venue at the price, in `Exchange::ALL` order
+    pub venue: Exchange,
+}
+
+/// The consolidated best bid and offer at a sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BboRow {
+    pub time: DateTime<Utc>,
+    pub instrument: Arc<str>,
+    pub bid: Option<BestPrice>,
+    pub ask: Option<BestPrice>,
+}
+
+/// One public trade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeRow {
+    /// When we received it
+    pub time: DateTime<Utc>,
+    /// When the venue says it happened, where the feed says
+    pub exchange_time: Option<DateTime<Utc>>,
+    pub instrument: Arc<str>,
+    pub venue: Exchange,
+    pub trade_id: String,
+    pub price: u64,
+    pub quantity: u64,
+    pub aggressor: Side,
+}
+
+impl TradeRow {
+    pub fn new(instrument: Arc<str>, trade: Trade) -> Self {
+        Self {
+            time: wall_clock(trade.received_at),
+            exchange_time: trade
+                .exchange_timestamp
+                .and_then(|ms| DateTime::from_timestamp_millis(ms as i64)),
+            instrument,
+            venue: trade.exchange,
+            trade_id: trade.trade_id,
+            price: trade.price,
+            quantity: trade.quantity,
+            aggressor: trade.aggressor,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    Level(LevelRow),
+    Bbo(BboRow),
+    Trade(TradeRow),
+}
+
+impl Row {
+    pub fn time(&self) -> DateTime<Utc> {
+        match self {
+            Self::Level(row) => row.time,
+            Self::Bbo(row) => row.time,
+            Self::Trade(row) => row.time,
+        }
+    }
+
+    pub fn instrument(&self) -> &Arc<str> {
+        match self {
+            Self::Level(row) => &row.instrument,
+            Self::Bbo(row) => &row.instrument,
+            Self::Trade(row) => &row.instrument,
+        }
+    }
+
+    /// The venue partition the row goes to; the BBO spans venues.
+    pub fn venue(&self) -> Option<Exchange> {
+        match self {
+            Self::Level(row) => Some(row.venue),
+            Self::Bbo(_) => None,
+            Self::Trade(row) => Some(row.venue),
+        }
+    }
+}
+
+/// Samples the book for export and forwards the trade tape.
+pub struct Snapshotter {
+    writer: ExportWriter,
+    instrument: Arc<str>,
+    interval: Duration,
+    depth: usize,
+    next_sample: Option<Instant>,
+}
+
+impl Snapshotter {
+    /// Samples the top 10 levels once a second unless configured otherwise.
+    pub fn new(instrument: &str, writer: ExportWriter) -> Self {
+        Self {
+            writer,
+            instrument: Arc::from(instrument),
+            interval: Duration::from_secs(1),
+            depth: 10,
+            next_sample: None,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Levels sampled per venue and side.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Exports the event's trade, if it carries one, and samples the book
+    /// if the interval has passed since the last sample. Call after the
+    /// event has been applied to `orderbook`.
+    pub fn on_event<L: PriceLadder>(&mut self, orderbook: &OrderBook<L>, event: &MarketEvent) {
+        if let Some(trade) = event.trade() {
+            let row = TradeRow::new(Arc::clone(&self.instrument), trade);
+            self.writer.write(vec![Row::Trade(row)]);
+        }
+        let at = event.received_at;
+        if self.next_sample.is_some_and(|next| at < next) {
+            return;
+        }
+        self.next_sample = Some(at + self.interval);
+        let rows = self.snapshot(orderbook, at);
+        if !rows.is_empty() {
+            self.writer.write(rows);
+        }
+    }
+
+    /// Every unsuppressed venue's top levels, best first, then the
+    /// consolidated BBO over them. Nothing if no venue has a book.
+    pub fn snapshot<L: PriceLadder>(&self, orderbook: &OrderBook<L>, at: Instant) -> Vec<Row> {
+        let time = wall_clock(at);
+        let mut rows = Vec::new();
+        let mut best = [(Side::Buy, None), (Side::Sell, None)];
+        for (side, best) in &mut best {
+            let mut venues: Vec<(Exchange, Vec<(u64, u64)>)> = Exchange::ALL
+                .into_iter()
+                .filter(|&venue| !orderbook.is_suppressed(venue))
+                .map(|venue| (venue, Vec::new()))
+                .collect();
+            orderbook
+                .price_levels(*side)
+                .for_each_level(&mut |exchange, price, quantity| {
+                    if let Some((_, levels)) = venues.iter_mut().find(|(v, _)| *v == exchange) {
+                        levels.push((price, quantity));
+                    }
+                });
+            for (venue, mut levels) in venues {
+                match side {
+                    Side::Buy => levels.sort_unstable_by_key(|&(price, _)| Reverse(price)),
+                    Side::Sell => levels.sort_unstable_by_key(|&(price, _)| price),
+                }
+                if let Some(&(price, quantity)) = levels.first() {
+                    *best = Some(consolidate(*side, *best, venue, price, quantity));
+                }
+                levels.truncate(self.depth);
+                rows.extend(
+                    levels
+                        .into_iter()
+                        .enumerate()
+                        .map(|(depth, (price, quantity))| {
+                            Row::Level(LevelRow {
+                                time,
+                                instrument: Arc::clone(&self.instrument),
+                                venue,
+                                side: *side,
+                                depth: depth as u32,
+                                price,
+                                quantity,
+                            })
+                        }),
+                );
+            }
+        }
+        if rows.is_empty() {
+            return rows;
+        }
+        let [(_, bid), (_, ask)] = best;
+        rows.push(Row::Bbo(BboRow {
+            time,
+            instrument: Arc::clone(&self.instrument),
+            bid,
+            ask,
+        }));
+        rows
+    }
+}
+
+/// The best price on `side` so far, after looking at one more venue's top
+/// level. Venues are visited in `Exchange::ALL` order.
+fn consolidate(
+    side: Side,
+    best: Option<BestPrice>,
+    venue: Exchange,
+    price: u64,
+    quantity: u64,
+) -> BestPrice {
+    let level = BestPrice {
+        price,
+        quantity,
+        venue,
+    };
+    match best {
+        None => level,
+        Some(best) if best.price == price => BestPrice {
+            quantity: best.quantity + quantity,
+            ..best
+        },
+        Some(best) => {
+            let better = match side {
+                Side::Buy => price > best.price,
+                Side::Sell => price < best.price,
+            };
+            if better {
+                level
+            } else {
+                best
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::{BestPrice, Export, PartitionedFiles, Row, Snapshotter};
+    use crate::{
+        api::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        orderbook::book::{Exchange, OrderBook},
+    };
+
+    fn book(exchange: Exchange, levels: Vec<Level>, at: Instant) -> MarketEvent {
+        MarketEvent {
+            levels,
+            ..MarketEvent::new(exchange, "BTC/USDT", MarketEventKind::Snapshot, at)
+        }
+    }
+
+    #[test]
+    fn test_snapshot_takes_top_levels_and_consolidated_bbo() {
+        let dir = std::env::temp_dir().join(format!("export-snapshot-{}", std::process::id()));
+        let export = Export::spawn(PartitionedFiles::new(&dir), 16).unwrap();
+        let snapshotter = Snapshotter::new("BTC/USDT", export.writer()).with_depth(2);
+        let orderbook = OrderBook::new("BTC/USDT".to_string());
+        let at = Instant::now();
+        for event in [
+            book(
+                Exchange::Binance,
+                vec![
+                    Level::new(Side::Buy, 99_900, 1_000),
+                    Level::new(Side::Buy, 100_000, 2_000),
+                    Level::new(Side::Buy, 99_800, 3_000),
+                    Level::new(Side::Sell, 100_100, 4_000),
+                ],
+                at,
+            ),
+            book(
+                Exchange::Kraken,
+                vec![
+                    Level::new(Side::Buy, 100_000, 500),
+                    Level::new(Side::Sell, 100_200, 600),
+                ],
+                at,
+            ),
+            book(
+                Exchange::Coinbase,
+                vec![Level::new(Side::Sell, 99_000, 700)],
+                at,
+            ),
+            // Coinbase's book is being rebuilt, so its crossed ask is ignored
+            MarketEvent::status(Exchange::Coinbase, "BTC/USDT", FeedStatus::Resyncing, at),
+        ] {
+            orderbook.apply_market_event(&event);
+        }
+
+        let rows = snapshotter.snapshot(&orderbook, at);
+        let levels: Vec<_> = rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Level(level) => Some((level.venue, level.side, level.depth, level.price)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            levels,
+            vec![
+                (Exchange::Binance, Side::Buy, 0, 100_000),
+                (Exchange::Binance, Side::Buy, 1, 99_900),
+                (Exchange::Kraken, Side::Buy, 0, 100_000),
+                (Exchange::Binance, Side::Sell, 0, 100_100),
+                (Exchange::Kraken, Side::Sell, 0, 100_200),
+            ]
+        );
+        let Some(Row::Bbo(bbo)) = rows.last() else {
+            panic!("expected the BBO last, got {rows:?}");
+        };
+        assert_eq!(
+            bbo.bid,
+            Some(BestPrice {
+                price: 100_000,
+                quantity: 2_500,
+                venue: Exchange::Binance
+            })
+        );
+        assert_eq!(bbo.ask.map(|ask| ask.venue), Some(Exchange::Binance));
+
+        export.close();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
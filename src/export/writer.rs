@@ -0,0 +1,157 @@
+//! # Export Writer
+//!
+//! Rows are written by a `Background` thread that owns the
+//! `PartitionedFiles`. Between batches, and at least once a second when
+//! idle, it closes each file whose roll interval is over.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::error;
+
+use super::{PartitionedFiles, Row};
+use crate::util::background::{Background, BackgroundWriter, BatchSink};
+
+/// Queues rows to the export thread. Clones share the thread and the queue.
+#[derive(Clone)]
+pub struct ExportWriter(BackgroundWriter<Vec<Row>>);
+
+/// Owns the export thread; `close` flushes the queue, closes every file and
+/// stops it.
+pub struct Export(Background<Vec<Row>>);
+
+impl Export {
+    /// Moves `files` to a new thread, queueing up to `capacity` writes (a
+    /// sample or a trade each).
+    pub fn spawn(files: PartitionedFiles, capacity: usize) -> std::io::Result<Self> {
+        Background::spawn("Export", FilesSink(files), capacity).map(Self)
+    }
+
+    pub fn writer(&self) -> ExportWriter {
+        ExportWriter(self.0.writer())
+    }
+
+    /// Waits for everything queued so far to be written and the files to be
+    /// closed, then stops the thread. Writers still held elsewhere drop
+    /// whatever they send after.
+    pub fn close(self) {
+        self.0.close();
+    }
+}
+
+impl ExportWriter {
+    pub fn write(&self, rows: Vec<Row>) {
+        self.0.send(rows);
+    }
+
+    /// Writes dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped()
+    }
+}
+
+/// The export thread's end.
+struct FilesSink(PartitionedFiles);
+
+impl BatchSink for FilesSink {
+    type Item = Vec<Row>;
+
+    const IDLE_POLL: Option<Duration> = Some(Duration::from_secs(1));
+
+    fn write(&mut self, batch: &[Vec<Row>]) {
+        let rows = batch.concat();
+        if let Err(e) = self.0.write(&rows) {
+            error!("[Export] Failed to write {} rows: {:#}", rows.len(), e);
+        }
+    }
+
+    fn tick(&mut self) {
+        if let Err(e) = self.0.close_expired(Utc::now()) {
+            error!("[Export] {:#}", e);
+        }
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.0.close_all() {
+            error!("[Export] {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::File, sync::Arc, time::Instant};
+
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use pricelevel::Side;
+
+    use super::Export;
+    use crate::{
+        export::{PartitionedFiles, Row, TradeRow},
+        orderbook::{book::Exchange, Trade},
+    };
+
+    #[test]
+    fn test_close_writes_readable_partitions() {
+        let dir = std::env::temp_dir().join(format!("export-writer-{}", std::process::id()));
+        let files = PartitionedFiles::new(&dir).with_csv(true);
+        let export = Export::spawn(files, 1_000).unwrap();
+        let writer = export.writer();
+        let instrument: Arc<str> = Arc::from("BTC/USDT");
+        for (id, exchange) in [Exchange::Binance, Exchange::Kraken, Exchange::Binance]
+            .into_iter()
+            .enumerate()
+        {
+            let trade = Trade {
+                exchange,
+                price: 100_000 + id as u64,
+                quantity: 1_000,
+                aggressor: Side::Buy,
+                trade_id: id.to_string(),
+                exchange_timestamp: None,
+                received_at: Instant::now(),
+            };
+            writer.write(vec![Row::Trade(TradeRow::new(
+                Arc::clone(&instrument),
+                trade,
+            ))]);
+        }
+        export.close();
+        assert_eq!(writer.dropped(), 0);
+
+        let venue = |format: &str, venue: &str| {
+            let [date] = &std::fs::read_dir(dir.join(format).join("trades"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>()[..]
+            else {
+                panic!("expected one day");
+            };
+            let dir = date
+                .join("instrument=BTC-USDT")
+                .join(format!("venue={venue}"));
+            let files: Vec<_> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            files
+        };
+        let [binance] = &venue("parquet", "binance")[..] else {
+            panic!("expected one Binance file");
+        };
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(binance).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        let [kraken_csv] = &venue("csv", "kraken")[..] else {
+            panic!("expected one Kraken CSV");
+        };
+        let csv = std::fs::read_to_string(kraken_csv).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(rows, 2);
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.starts_with("time,exchange_time,trade_id,price,quantity,aggressor"));
+    }
+}
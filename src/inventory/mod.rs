@@ -0,0 +1,350 @@
+//! # Inventory Module
+//!
+//! Cross-exchange arbitrage can only trade what is already sitting on each
+//! venue: buying needs quote currency on the buy venue and selling needs the
+//! base asset on the sell venue. This module provides:
+//! - Per-venue, per-asset balances updated from fills (simulated or real)
+//! - Opportunity sizing capped by the balances on both legs' venues
+//! - Periodic snapshots of how the base asset is spread across venues
+//! - A rebalancing planner that moves inventory back towards target allocations
+//!
+//! Base balances are in order quantity units and quote balances in cents,
+//! the same units as `Fill::notional`.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use pricelevel::Side;
+use tracing::info;
+
+use crate::{
+    execution::{ExecutionReport, Fill},
+    orderbook::book::{ArbitrageOpportunity, Exchange},
+    util,
+};
+
+pub mod rebalance;
+
+pub use rebalance::{RebalanceConfig, RebalancePlanner};
+
+/// How many snapshots `InventoryTracker` keeps for imbalance reporting.
+const SNAPSHOT_HISTORY: usize = 1024;
+
+/// Balances across all venues at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventorySnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub base: Vec<(Exchange, i64)>,
+    pub quote: Vec<(Exchange, i64)>,
+    /// Largest deviation of any venue's share of the base asset from an even
+    /// split, in basis points. 0 means perfectly balanced.
+    pub imbalance_bps: u64,
+}
+
+/// Balances for one instrument's base and quote assets on every venue.
+pub struct InventoryTracker {
+    pub base_asset: String,
+    pub quote_asset: String,
+    balances: DashMap<(Exchange, String), i64>,
+    history: Mutex<VecDeque<InventorySnapshot>>,
+}
+
+impl InventoryTracker {
+    pub fn new(base_asset: impl Into<String>, quote_asset: impl Into<String>) -> Self {
+        Self {
+            base_asset: base_asset.into(),
+            quote_asset: quote_asset.into(),
+            balances: DashMap::new(),
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Sets a balance outright, e.g. from a venue balance query at startup.
+    pub fn set_balance(&self, exchange: Exchange, asset: &str, amount: i64) {
+        self.balances.insert((exchange, asset.to_string()), amount);
+    }
+
+    pub fn balance(&self, exchange: Exchange, asset: &str) -> i64 {
+        self.balances
+            .get(&(exchange, asset.to_string()))
+            .map(|b| *b)
+            .unwrap_or(0)
+    }
+
+    pub fn base_balance(&self, exchange: Exchange) -> i64 {
+        self.balance(exchange, &self.base_asset)
+    }
+
+    pub fn quote_balance(&self, exchange: Exchange) -> i64 {
+        self.balance(exchange, &self.quote_asset)
+    }
+
+    /// Adjusts a balance by `delta`, e.g. for a deposit, withdrawal or fee.
+    pub fn adjust_balance(&self, exchange: Exchange, asset: &str, delta: i64) {
+        *self
+            .balances
+            .entry((exchange, asset.to_string()))
+            .or_insert(0) += delta;
+    }
+
+    /// Moves base and quote balances on the fill's venue.
+    pub fn apply_fill(&self, fill: &Fill) {
+        if fill.filled_quantity == 0 {
+            return;
+        }
+        self.apply_trade(
+            fill.exchange,
+            fill.side,
+            fill.filled_quantity,
+            fill.notional,
+        );
+    }
+
+    /// Moves balances for an execution reported on a venue's private stream.
+    /// Reports that aren't fills, or don't say which side traded, are ignored.
+    pub fn apply_report(&self, report: &ExecutionReport) {
+        let Some(side) = report.side.filter(|_| report.is_fill()) else {
+            return;
+        };
+        self.apply_trade(
+            report.exchange,
+            side,
+            report.last_quantity,
+            report.last_notional(),
+        );
+    }
+
+    fn apply_trade(&self, exchange: Exchange, side: Side, quantity: u64, notional: u64) {
+        let quantity = quantity as i64;
+        let notional = notional as i64;
+        let (base_delta, quote_delta) = match side {
+            Side::Buy => (quantity, -notional),
+            Side::Sell => (-quantity, notional),
+        };
+        self.adjust_balance(exchange, &self.base_asset, base_delta);
+        self.adjust_balance(exchange, &self.quote_asset, quote_delta);
+    }
+
+    /// Most that can be bought on `exchange` at `price` with the quote held there.
+    pub fn max_buy_quantity(&self, exchange: Exchange, price: u64) -> u64 {
+        util::quantity_for(self.quote_balance(exchange).max(0) as u64, price)
+    }
+
+    /// Most that can be sold on `exchange` with the base held there.
+    pub fn max_sell_quantity(&self, exchange: Exchange) -> u64 {
+        self.base_balance(exchange).max(0) as u64
+    }
+
+    /// Shrinks an opportunity to what both venues can fund, or `None` if either can't.
+    pub fn cap_opportunity(
+        &self,
+        opportunity: ArbitrageOpportunity,
+    ) -> Option<ArbitrageOpportunity> {
+        let quantity = opportunity
+            .quantity
+            .min(self.max_buy_quantity(opportunity.buy_exchange, opportunity.buy_price))
+            .min(self.max_sell_quantity(opportunity.sell_exchange));
+
+        (quantity > 0).then_some(ArbitrageOpportunity {
+            quantity,
+            ..opportunity
+        })
+    }
+
+    /// Captures current balances and imbalance and appends them to the history.
+    pub fn record_snapshot(&self) -> InventorySnapshot {
+        let base: Vec<(Exchange, i64)> = Exchange::ALL
+            .iter()
+            .map(|&exchange| (exchange, self.base_balance(exchange)))
+            .collect();
+        let quote = Exchange::ALL
+            .iter()
+            .map(|&exchange| (exchange, self.quote_balance(exchange)))
+            .collect();
+
+        let snapshot = InventorySnapshot {
+            taken_at: Utc::now(),
+            imbalance_bps: imbalance_bps(&base),
+            base,
+            quote,
+        };
+
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if history.len() == SNAPSHOT_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(snapshot.clone());
+        snapshot
+    }
+
+    /// Recorded snapshots, oldest first.
+    pub fn history(&self) -> Vec<InventorySnapshot> {
+        self.history
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Largest gap between any venue's share of the total and an even split, in bps.
+fn imbalance_bps(balances: &[(Exchange, i64)]) -> u64 {
+    let total: i64 = balances.iter().map(|(_, b)| (*b).max(0)).sum();
+    if total == 0 || balances.is_empty() {
+        return 0;
+    }
+    let even_share = 10_000 / balances.len() as u64;
+    balances
+        .iter()
+        .map(|(_, b)| ((*b).max(0) as u64 * 10_000 / total as u64).abs_diff(even_share))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Records a snapshot every `interval` and logs the imbalance.
+pub fn spawn_imbalance_reporter(
+    tracker: Arc<InventoryTracker>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let snapshot = tracker.record_snapshot();
+            info!(
+                "[Inventory] {} imbalance {}bps across {:?}",
+                tracker.base_asset, snapshot.imbalance_bps, snapshot.base
+            );
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::{OrderId, Side};
+
+    use super::InventoryTracker;
+    use crate::{
+        execution::{ExecType, ExecutionReport, Fill},
+        orderbook::book::{ArbitrageOpportunity, Exchange},
+        util::QUANTITY_SCALE,
+    };
+
+    fn tracker() -> InventoryTracker {
+        let tracker = InventoryTracker::new("BTC", "USD");
+        tracker.set_balance(Exchange::Kraken, "USD", 1_000);
+        tracker.set_balance(Exchange::Binance, "BTC", 4 * QUANTITY_SCALE as i64);
+        tracker
+    }
+
+    #[test]
+    fn test_apply_fill_moves_balances() {
+        let tracker = tracker();
+
+        tracker.apply_fill(&Fill {
+            client_order_id: OrderId::default(),
+            exchange: Exchange::Kraken,
+            side: Side::Buy,
+            requested_quantity: 5 * QUANTITY_SCALE,
+            filled_quantity: 3 * QUANTITY_SCALE,
+            notional: 300,
+        });
+        assert_eq!(
+            tracker.base_balance(Exchange::Kraken),
+            3 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(tracker.quote_balance(Exchange::Kraken), 700);
+
+        tracker.apply_fill(&Fill {
+            client_order_id: OrderId::default(),
+            exchange: Exchange::Binance,
+            side: Side::Sell,
+            requested_quantity: QUANTITY_SCALE,
+            filled_quantity: QUANTITY_SCALE,
+            notional: 105,
+        });
+        assert_eq!(
+            tracker.base_balance(Exchange::Binance),
+            3 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(tracker.quote_balance(Exchange::Binance), 105);
+    }
+
+    #[test]
+    fn test_apply_report_fill() {
+        let tracker = tracker();
+        let mut report = ExecutionReport::new(
+            Exchange::Kraken,
+            "OGTT3Y-C6I3P-XRI6HX",
+            ExecType::Trade,
+            Instant::now(),
+        );
+        report.side = Some(Side::Buy);
+        report.last_quantity = 2 * QUANTITY_SCALE;
+        report.last_price = 150;
+        tracker.apply_report(&report);
+        assert_eq!(
+            tracker.base_balance(Exchange::Kraken),
+            2 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(tracker.quote_balance(Exchange::Kraken), 700);
+
+        // Without a side there is nothing to apply
+        report.side = None;
+        tracker.apply_report(&report);
+        assert_eq!(tracker.quote_balance(Exchange::Kraken), 700);
+    }
+
+    #[test]
+    fn test_cap_opportunity_by_balances() {
+        let tracker = tracker();
+        let opportunity = ArbitrageOpportunity {
+            buy_exchange: Exchange::Kraken,
+            buy_price: 100,
+            sell_exchange: Exchange::Binance,
+            sell_price: 105,
+            quantity: 20 * QUANTITY_SCALE,
+        };
+
+        // 1_000 cents buys 10 BTC on Kraken, but only 4 BTC can be sold on Binance
+        assert_eq!(
+            tracker.max_buy_quantity(Exchange::Kraken, 100),
+            10 * QUANTITY_SCALE
+        );
+        assert_eq!(
+            tracker.cap_opportunity(opportunity).unwrap().quantity,
+            4 * QUANTITY_SCALE
+        );
+
+        // Nothing to sell in the other direction
+        let reverse = ArbitrageOpportunity {
+            buy_exchange: Exchange::Binance,
+            sell_exchange: Exchange::Kraken,
+            ..opportunity
+        };
+        assert_eq!(tracker.cap_opportunity(reverse), None);
+    }
+
+    #[test]
+    fn test_imbalance_history() {
+        let tracker = InventoryTracker::new("BTC", "USD");
+        for exchange in Exchange::ALL {
+            tracker.set_balance(exchange, "BTC", 10);
+        }
+        assert_eq!(tracker.record_snapshot().imbalance_bps, 0);
+
+        tracker.adjust_balance(Exchange::Coinbase, "BTC", -10);
+        let snapshot = tracker.record_snapshot();
+        // Coinbase holds nothing against an even split of 33.33%
+        assert_eq!(snapshot.imbalance_bps, 3_333);
+        assert_eq!(tracker.history().len(), 2);
+    }
+}
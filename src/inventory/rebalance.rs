@@ -0,0 +1,534 @@
+//! # Inventory Rebalancing
+//!
+//! Repeated one-direction arbitrage drains the base asset from one venue and
+//! quote currency from another. The `RebalancePlanner` compares each venue's
+//! balances against target allocations and proposes the cheaper of:
+//! - A withdrawal to the venue that is short, paying the route's fee and
+//!   waiting out its transfer delay
+//! - A pair of offsetting trades (sell where long, buy where short), paying
+//!   taker fees on both sides but settling immediately
+//!
+//! In paper mode the plan is applied to the `InventoryTracker`, with transfers
+//! held in flight until their delay has passed. Every cost is accumulated so it
+//! can be taken off the strategy's gross trading PnL.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use pricelevel::{OrderId, Side};
+use tracing::info;
+
+use super::InventoryTracker;
+use crate::{
+    execution::Fill,
+    orderbook::book::{Exchange, OrderBook},
+    util,
+};
+
+/// Fee and delay for withdrawing an asset from a venue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferCost {
+    /// Charged at the source on top of the amount sent, in the asset's units
+    pub withdrawal_fee: u64,
+    pub delay: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceMode {
+    /// Log proposed actions without touching balances
+    Propose,
+    /// Apply proposed actions to the tracked balances
+    Paper,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebalanceConfig {
+    pub mode: RebalanceMode,
+    /// Target share of each asset per venue in basis points, summing to 10_000
+    pub targets: Vec<(Exchange, u64)>,
+    /// How far a venue may drift from its target, in bps of the total, before rebalancing
+    pub tolerance_bps: u64,
+    /// Taker fee paid on each side of an offsetting trade
+    pub taker_fee_bps: u64,
+    /// Withdrawal routes by (source venue, asset); venues without a route can't send that asset
+    pub transfer_costs: HashMap<(Exchange, String), TransferCost>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebalanceAction {
+    Transfer {
+        asset: String,
+        from: Exchange,
+        to: Exchange,
+        amount: u64,
+        fee: u64,
+        delay: Duration,
+    },
+    /// Sell the base asset where there is too much and buy it where there is too little
+    OffsettingTrade {
+        sell_exchange: Exchange,
+        buy_exchange: Exchange,
+        quantity: u64,
+        price: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebalancePlan {
+    pub actions: Vec<RebalanceAction>,
+    /// Fees the plan will pay, in cents
+    pub estimated_cost: u64,
+}
+
+/// Strategy PnL with rebalancing costs taken off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PnlSummary {
+    pub gross_trading_pnl: i64,
+    pub rebalancing_cost: i64,
+    pub net_pnl: i64,
+}
+
+#[derive(Debug, Clone)]
+struct PendingTransfer {
+    asset: String,
+    to: Exchange,
+    amount: u64,
+    arrives_at: Instant,
+}
+
+pub struct RebalancePlanner {
+    config: RebalanceConfig,
+    inventory: Arc<InventoryTracker>,
+    in_flight: Mutex<Vec<PendingTransfer>>,
+    /// Total rebalancing fees paid in paper mode, in cents
+    total_cost: AtomicI64,
+}
+
+impl RebalancePlanner {
+    pub fn new(config: RebalanceConfig, inventory: Arc<InventoryTracker>) -> Self {
+        Self {
+            config,
+            inventory,
+            in_flight: Mutex::new(Vec::new()),
+            total_cost: AtomicI64::new(0),
+        }
+    }
+
+    /// Proposes actions bringing every venue within tolerance of its target,
+    /// valuing base asset fees at `mid` cents per unit.
+    pub fn plan(&self, mid: u64) -> RebalancePlan {
+        let mut projected = self.projected_balances();
+        let mut plan = RebalancePlan::default();
+        let base = self.inventory.base_asset.clone();
+        let quote = self.inventory.quote_asset.clone();
+
+        for (from, to, amount) in self.moves(&projected, &base) {
+            let transfer = self.transfer_cost(from, &base);
+            let value = util::notional(amount, mid);
+            let fee_per_side = value * self.config.taker_fee_bps / 10_000;
+            let trade_cost = fee_per_side * 2;
+            // Buying on the short venue needs quote there
+            let affordable = util::quantity_for(
+                projected_balance(&projected, to, &quote).max(0) as u64,
+                mid + mid * self.config.taker_fee_bps / 10_000,
+            );
+
+            let trade_is_cheaper =
+                transfer.is_none_or(|t| trade_cost < util::notional(t.withdrawal_fee, mid));
+            if trade_is_cheaper && affordable >= amount {
+                *projected.entry((from, base.clone())).or_insert(0) -= amount as i64;
+                *projected.entry((to, base.clone())).or_insert(0) += amount as i64;
+                *projected.entry((from, quote.clone())).or_insert(0) +=
+                    (value - fee_per_side) as i64;
+                *projected.entry((to, quote.clone())).or_insert(0) -= (value + fee_per_side) as i64;
+                plan.estimated_cost += trade_cost;
+                plan.actions.push(RebalanceAction::OffsettingTrade {
+                    sell_exchange: from,
+                    buy_exchange: to,
+                    quantity: amount,
+                    price: mid,
+                });
+            } else if let Some(transfer) = transfer {
+                *projected.entry((from, base.clone())).or_insert(0) -=
+                    (amount + transfer.withdrawal_fee) as i64;
+                *projected.entry((to, base.clone())).or_insert(0) += amount as i64;
+                plan.estimated_cost += util::notional(transfer.withdrawal_fee, mid);
+                plan.actions.push(RebalanceAction::Transfer {
+                    asset: base.clone(),
+                    from,
+                    to,
+                    amount,
+                    fee: transfer.withdrawal_fee,
+                    delay: transfer.delay,
+                });
+            }
+        }
+
+        for (from, to, amount) in self.moves(&projected, &quote) {
+            let Some(transfer) = self.transfer_cost(from, &quote) else {
+                continue;
+            };
+            plan.estimated_cost += transfer.withdrawal_fee;
+            plan.actions.push(RebalanceAction::Transfer {
+                asset: quote.clone(),
+                from,
+                to,
+                amount,
+                fee: transfer.withdrawal_fee,
+                delay: transfer.delay,
+            });
+        }
+
+        plan
+    }
+
+    /// Applies a plan to the tracked balances as if it had been executed at `now`.
+    pub fn simulate(&self, plan: &RebalancePlan, now: Instant) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        for action in &plan.actions {
+            match action {
+                RebalanceAction::Transfer {
+                    asset,
+                    from,
+                    to,
+                    amount,
+                    fee,
+                    delay,
+                } => {
+                    self.inventory
+                        .adjust_balance(*from, asset, -((amount + fee) as i64));
+                    in_flight.push(PendingTransfer {
+                        asset: asset.clone(),
+                        to: *to,
+                        amount: *amount,
+                        arrives_at: now + *delay,
+                    });
+                }
+                RebalanceAction::OffsettingTrade {
+                    sell_exchange,
+                    buy_exchange,
+                    quantity,
+                    price,
+                } => {
+                    let notional = util::notional(*quantity, *price);
+                    let fee = (notional * self.config.taker_fee_bps / 10_000) as i64;
+                    for (exchange, side) in
+                        [(*sell_exchange, Side::Sell), (*buy_exchange, Side::Buy)]
+                    {
+                        self.inventory.apply_fill(&Fill {
+                            client_order_id: OrderId::new_uuid(),
+                            exchange,
+                            side,
+                            requested_quantity: *quantity,
+                            filled_quantity: *quantity,
+                            notional,
+                        });
+                        self.inventory
+                            .adjust_balance(exchange, &self.inventory.quote_asset, -fee);
+                    }
+                }
+            }
+        }
+        self.total_cost
+            .fetch_add(plan.estimated_cost as i64, Ordering::AcqRel);
+    }
+
+    /// Credits transfers whose delay has passed by `now`, returning how many landed.
+    pub fn settle_transfers(&self, now: Instant) -> usize {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let before = in_flight.len();
+        in_flight.retain(|transfer| {
+            if transfer.arrives_at > now {
+                return true;
+            }
+            self.inventory
+                .adjust_balance(transfer.to, &transfer.asset, transfer.amount as i64);
+            false
+        });
+        before - in_flight.len()
+    }
+
+    /// Plans at `mid` and, in paper mode, applies the plan.
+    pub fn run_once(&self, mid: u64, now: Instant) -> RebalancePlan {
+        self.settle_transfers(now);
+        let plan = self.plan(mid);
+        for action in &plan.actions {
+            info!("[Rebalance] {:?} proposed: {:?}", self.config.mode, action);
+        }
+        if self.config.mode == RebalanceMode::Paper && !plan.actions.is_empty() {
+            self.simulate(&plan, now);
+        }
+        plan
+    }
+
+    /// Rebalancing fees paid so far, in cents.
+    pub fn total_cost(&self) -> i64 {
+        self.total_cost.load(Ordering::Acquire)
+    }
+
+    pub fn pnl_summary(&self, gross_trading_pnl: i64) -> PnlSummary {
+        let rebalancing_cost = self.total_cost();
+        PnlSummary {
+            gross_trading_pnl,
+            rebalancing_cost,
+            net_pnl: gross_trading_pnl - rebalancing_cost,
+        }
+    }
+
+    fn transfer_cost(&self, from: Exchange, asset: &str) -> Option<TransferCost> {
+        self.config
+            .transfer_costs
+            .get(&(from, asset.to_string()))
+            .copied()
+    }
+
+    /// Current balances plus anything already in flight towards each venue.
+    fn projected_balances(&self) -> HashMap<(Exchange, String), i64> {
+        let mut projected = HashMap::new();
+        for (exchange, _) in &self.config.targets {
+            for asset in [&self.inventory.base_asset, &self.inventory.quote_asset] {
+                projected.insert(
+                    (*exchange, asset.clone()),
+                    self.inventory.balance(*exchange, asset),
+                );
+            }
+        }
+        for transfer in self
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            *projected
+                .entry((transfer.to, transfer.asset.clone()))
+                .or_insert(0) += transfer.amount as i64;
+        }
+        projected
+    }
+
+    /// Pairs venues above target with venues below it, largest gaps first.
+    fn moves(
+        &self,
+        projected: &HashMap<(Exchange, String), i64>,
+        asset: &str,
+    ) -> Vec<(Exchange, Exchange, u64)> {
+        let total: i64 = self
+            .config
+            .targets
+            .iter()
+            .map(|(exchange, _)| projected_balance(projected, *exchange, asset).max(0))
+            .sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        let tolerance = total * self.config.tolerance_bps as i64 / 10_000;
+
+        let mut surplus = Vec::new();
+        let mut deficit = Vec::new();
+        for (exchange, target_bps) in &self.config.targets {
+            let target = total * *target_bps as i64 / 10_000;
+            let gap = projected_balance(projected, *exchange, asset) - target;
+            if gap > tolerance {
+                surplus.push((*exchange, gap));
+            } else if gap < -tolerance {
+                deficit.push((*exchange, -gap));
+            }
+        }
+        surplus.sort_by_key(|(_, gap)| std::cmp::Reverse(*gap));
+        deficit.sort_by_key(|(_, gap)| std::cmp::Reverse(*gap));
+
+        let mut moves = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < surplus.len() && j < deficit.len() {
+            let amount = surplus[i].1.min(deficit[j].1);
+            moves.push((surplus[i].0, deficit[j].0, amount as u64));
+            surplus[i].1 -= amount;
+            deficit[j].1 -= amount;
+            if surplus[i].1 == 0 {
+                i += 1;
+            }
+            if deficit[j].1 == 0 {
+                j += 1;
+            }
+        }
+        moves
+    }
+}
+
+fn projected_balance(
+    projected: &HashMap<(Exchange, String), i64>,
+    exchange: Exchange,
+    asset: &str,
+) -> i64 {
+    projected
+        .get(&(exchange, asset.to_string()))
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Runs the planner every `interval` at the current cross-exchange mid.
+pub fn spawn_rebalancer(
+    planner: Arc<RebalancePlanner>,
+    order_book: Arc<OrderBook>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Some(mid) = order_book.mid_all_exchanges() {
+                planner.run_once(mid, Instant::now());
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use super::{RebalanceAction, RebalanceConfig, RebalanceMode, RebalancePlanner, TransferCost};
+    use crate::{inventory::InventoryTracker, orderbook::book::Exchange, util::QUANTITY_SCALE};
+
+    /// After buying on Binance and selling on Kraken, Binance holds all the BTC
+    /// and Kraken most of the USD, against a 50/50 target. `btc_fee` is in
+    /// whole BTC.
+    fn planner(btc_fee: u64, mode: RebalanceMode) -> (Arc<InventoryTracker>, RebalancePlanner) {
+        let inventory = Arc::new(InventoryTracker::new("BTC", "USD"));
+        inventory.set_balance(Exchange::Binance, "BTC", 10 * QUANTITY_SCALE as i64);
+        inventory.set_balance(Exchange::Binance, "USD", 500);
+        inventory.set_balance(Exchange::Kraken, "USD", 1_500);
+
+        let mut transfer_costs = HashMap::new();
+        transfer_costs.insert(
+            (Exchange::Binance, "BTC".to_string()),
+            TransferCost {
+                withdrawal_fee: btc_fee * QUANTITY_SCALE,
+                delay: Duration::from_secs(600),
+            },
+        );
+        transfer_costs.insert(
+            (Exchange::Kraken, "USD".to_string()),
+            TransferCost {
+                withdrawal_fee: 5,
+                delay: Duration::from_secs(3600),
+            },
+        );
+
+        let config = RebalanceConfig {
+            mode,
+            targets: vec![(Exchange::Binance, 5_000), (Exchange::Kraken, 5_000)],
+            tolerance_bps: 1_000,
+            taker_fee_bps: 20,
+            transfer_costs,
+        };
+        (
+            Arc::clone(&inventory),
+            RebalancePlanner::new(config, inventory),
+        )
+    }
+
+    #[test]
+    fn test_plan_prefers_cheaper_transfer() {
+        let (_, planner) = planner(0, RebalanceMode::Propose);
+
+        let plan = planner.plan(100);
+        assert_eq!(
+            plan.actions,
+            vec![
+                RebalanceAction::Transfer {
+                    asset: "BTC".to_string(),
+                    from: Exchange::Binance,
+                    to: Exchange::Kraken,
+                    amount: 5 * QUANTITY_SCALE,
+                    fee: 0,
+                    delay: Duration::from_secs(600),
+                },
+                RebalanceAction::Transfer {
+                    asset: "USD".to_string(),
+                    from: Exchange::Kraken,
+                    to: Exchange::Binance,
+                    amount: 500,
+                    fee: 5,
+                    delay: Duration::from_secs(3600),
+                },
+            ]
+        );
+        assert_eq!(plan.estimated_cost, 5);
+    }
+
+    #[test]
+    fn test_plan_offsetting_trade_when_withdrawal_is_expensive() {
+        let (inventory, planner) = planner(1, RebalanceMode::Paper);
+
+        // Withdrawing costs 1 BTC (100 cents), trading 5 on both venues costs 2 cents
+        let plan = planner.run_once(100, Instant::now());
+        assert_eq!(
+            plan.actions,
+            vec![RebalanceAction::OffsettingTrade {
+                sell_exchange: Exchange::Binance,
+                buy_exchange: Exchange::Kraken,
+                quantity: 5 * QUANTITY_SCALE,
+                price: 100,
+            }]
+        );
+
+        // The trades fix both assets at once
+        assert_eq!(
+            inventory.base_balance(Exchange::Binance),
+            5 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(
+            inventory.base_balance(Exchange::Kraken),
+            5 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(inventory.quote_balance(Exchange::Binance), 999);
+        assert_eq!(inventory.quote_balance(Exchange::Kraken), 999);
+        assert_eq!(plan.estimated_cost, 2);
+        assert_eq!(planner.total_cost(), 2);
+        assert_eq!(planner.pnl_summary(50).net_pnl, 48);
+    }
+
+    #[test]
+    fn test_paper_transfers_land_after_delay() {
+        let (inventory, planner) = planner(0, RebalanceMode::Paper);
+        let start = Instant::now();
+
+        planner.run_once(100, start);
+        assert_eq!(
+            inventory.base_balance(Exchange::Binance),
+            5 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(inventory.base_balance(Exchange::Kraken), 0);
+
+        // In-flight transfers count towards the target, so nothing new is proposed
+        assert!(planner.plan(100).actions.is_empty());
+
+        assert_eq!(
+            planner.settle_transfers(start + Duration::from_secs(601)),
+            1
+        );
+        assert_eq!(
+            inventory.base_balance(Exchange::Kraken),
+            5 * QUANTITY_SCALE as i64
+        );
+        assert_eq!(inventory.quote_balance(Exchange::Binance), 500);
+
+        assert_eq!(
+            planner.settle_transfers(start + Duration::from_secs(3601)),
+            1
+        );
+        assert_eq!(inventory.quote_balance(Exchange::Binance), 1_000);
+        assert_eq!(inventory.quote_balance(Exchange::Kraken), 995);
+    }
+}
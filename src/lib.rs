@@ -0,0 +1,9 @@
+pub mod api;
+pub mod config;
+pub mod execution;
+pub mod export;
+pub mod inventory;
+pub mod orderbook;
+pub mod pipeline;
+pub mod storage;
+pub mod util;
@@ -1,133 +1,629 @@
-mod api;
-mod orderbook;
-mod util;
+use std::{
+    collections::HashMap,
+    fs::File,
+    future::Future,
+    io::LineWriter,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use api::{BinanceClient, CoinbaseClient, ExchangePrice, KrakenClient};
-use tracing::{info, Level};
-use tracing_subscriber;
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use futures_util::future::BoxFuture;
+use security_flamegraph_lowlatency::{
+    api::{
+        user_data::coinbase, BinanceClient, BinanceSigner, BinanceUserStream, CoinbaseClient,
+        CoinbaseSigner, CoinbaseUserStream, EventSink, KrakenClient, KrakenSigner,
+        KrakenUserStream, MarketDataBus, MarketEvent, PublicTradeClient,
+    },
+    config::{reload, Config, LadderKind, ParamStore, StrategyParams, VenueConfig},
+    execution::{orders, OrderManager, RiskEngine},
+    export::{Export, PartitionedFiles, Snapshotter},
+    inventory::{spawn_imbalance_reporter, InventoryTracker},
+    orderbook::{
+        book::{ArbitrageOpportunity, Exchange, OrderBook},
+        ladder::{ArrayLadder, PriceLadder, DEFAULT_WINDOW},
+        DataValidator,
+    },
+    pipeline::{
+        self, layout, Metrics, OpportunityEvent, OpportunityTracker, PipelineMode, Recorder,
+        Replay, RingPipeline,
+    },
+    storage::{Database, Storage, StorageWriter},
+};
+use tokio::{sync::mpsc, task::JoinSet};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-use crate::orderbook::book::OrderBook;
+/// Cross-venue BTC order book aggregator
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// TOML config file; the built-in defaults are used without one
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[tokio::main]
-async fn main() {
-    run("BTC/USDT".to_string()).await;
+#[derive(Subcommand)]
+enum Command {
+    /// Stream every enabled venue into the book (the default)
+    Run,
+    /// Stream like `run`, also writing every event to a recording
+    Record {
+        /// Recording to write, one JSON event per line
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Stop after this many seconds instead of at Ctrl-C
+        #[arg(long)]
+        duration: Option<u64>,
+    },
+    /// Feed a recording through the book at the pace it was recorded
+    Replay {
+        /// Recording written by `record`
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Playback speed; 0 replays as fast as possible
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Feed a recording through the book as fast as possible and summarise
+    /// how long opportunity windows lasted per venue pair
+    Backtest {
+        /// Recording written by `record`
+        #[arg(short, long)]
+        input: PathBuf,
+    },
 }
-async fn run(order_book_name: String) {
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match &cli.config {
+        Some(path) => Config::load(path),
+        None => Ok(Config::default()),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
     // Initialize tracing for tokio-console compatibility
     tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
+        .with_env_filter(EnvFilter::new(&config.log_level))
         .with_target(false)
         .init();
 
-    info!("Starting low-latency order book aggregator...");
-    info!("Monitoring BTC/USDT pair across multiple exchanges");
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<ExchangePrice>(1000);
-    let (tx_exchange, rx_exchange) = tokio::sync::mpsc::channel::<ExchangePrice>(1000);
+    let result = match cli.command.unwrap_or(Command::Run) {
+        Command::Run => live(config, cli.config, None, None),
+        Command::Record { output, duration } => Recorder::create(&output).and_then(|recorder| {
+            live(
+                config,
+                cli.config,
+                Some(recorder),
+                duration.map(Duration::from_secs),
+            )
+        }),
+        Command::Replay { input, speed } => replay(config, &input, speed),
+        Command::Backtest { input } => backtest(&config, &input),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // Spawn tasks for each exchange
-    let binance_tx = tx.clone();
-    let binance_handle = tokio::spawn(async move {
-        BinanceClient::new(binance_tx).listen_btc_usdt().await;
-    });
+fn map_book(config: &Config) -> OrderBook {
+    OrderBook::new(config.instrument().to_string())
+        .with_validator(DataValidator::new(config.thresholds.validation()))
+}
 
-    let kraken_tx = tx.clone();
-    let kraken_handle = tokio::spawn(async move {
-        KrakenClient::new(kraken_tx).listen_btc_usdt().await;
-    });
+fn array_book(config: &Config) -> OrderBook<ArrayLadder> {
+    OrderBook::with_ladders(
+        config.instrument().to_string(),
+        ArrayLadder::new(pricelevel::Side::Buy, 1, DEFAULT_WINDOW),
+        ArrayLadder::new(pricelevel::Side::Sell, 1, DEFAULT_WINDOW),
+    )
+    .with_validator(DataValidator::new(config.thresholds.validation()))
+}
 
-    let coinbase_handle = tokio::spawn(async move {
-        CoinbaseClient::new(tx).listen_btc_usdt().await;
-    });
+/// When each venue's book last changed, for `thresholds.max_quote_age_ms`.
+#[derive(Default)]
+struct QuoteClock {
+    updated_at: HashMap<Exchange, Instant>,
+}
 
-    /* let compare_price_handle = tokio::spawn(async move {
-    });
-        while let Some(price) = rx.recv().await {
-            info!(
-                "Received BTC/USDT price: {}, exchange timestamp: {:?}",
-                price.price(),
-                price.exchange_timestamp()
+impl QuoteClock {
+    fn update(&mut self, event: &MarketEvent) {
+        if event.kind.updates_book() {
+            self.updated_at.insert(event.exchange, event.received_at);
+        }
+    }
+
+    /// Age of the older of the opportunity's two quotes as of `now`.
+    fn age(&self, opportunity: &ArbitrageOpportunity, now: Instant) -> Duration {
+        [opportunity.buy_exchange, opportunity.sell_exchange]
+            .iter()
+            .map(|exchange| match self.updated_at.get(exchange) {
+                Some(updated_at) => now.saturating_duration_since(*updated_at),
+                None => Duration::MAX,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// The opportunity's net edge if it clears `params`: the minimum edge after
+/// fees, priced off quotes no older than the limit.
+fn qualifying_edge(
+    params: &StrategyParams,
+    quotes: &QuoteClock,
+    opportunity: &ArbitrageOpportunity,
+    now: Instant,
+) -> Option<i64> {
+    let fresh = !params.is_stale(quotes.age(opportunity, now));
+    (fresh && params.is_actionable(opportunity)).then(|| params.net_edge_bps(opportunity))
+}
+
+fn log_window(event: &OpportunityEvent) {
+    let window = event.opportunity();
+    let current = &window.current;
+    match event {
+        OpportunityEvent::Opened(_) => info!(
+            "[Arbitrage] #{} opened: buy {:?} at {}, sell {:?} at {}, {} bps net of fees, size {}",
+            window.id,
+            current.buy_exchange,
+            current.buy_price,
+            current.sell_exchange,
+            current.sell_price,
+            window.net_edge_bps,
+            current.quantity
+        ),
+        OpportunityEvent::Updated(_) => debug!(
+            "[Arbitrage] #{} updated: buy at {}, sell at {}, {} bps net of fees, size {}",
+            window.id, current.buy_price, current.sell_price, window.net_edge_bps, current.quantity
+        ),
+        OpportunityEvent::Closed(_) => info!(
+            "[Arbitrage] #{} closed after {:?}: peak {} bps net of fees, peak size {}, {} updates",
+            window.id,
+            window.duration(),
+            window.peak_edge_bps,
+            window.peak_quantity,
+            window.updates
+        ),
+    }
+}
+
+/// What happens to every event the pipeline delivers: the book applies it,
+/// windows of opportunities clearing the current parameters are tracked and
+/// reported as they open and close, and the event is recorded if recording.
+struct Aggregator<L: PriceLadder> {
+    orderbook: OrderBook<L>,
+    config: Config,
+    params: Arc<ParamStore>,
+    /// Kept on the cross-venue mid, which its price bands are measured from
+    risk: Option<Arc<RiskEngine>>,
+    quotes: QuoteClock,
+    /// Shared so windows still open at shutdown can be closed from outside
+    tracker: Arc<Mutex<OpportunityTracker>>,
+    metrics: Arc<Metrics>,
+    recorder: Option<Recorder<LineWriter<File>>>,
+    storage: Option<StorageWriter>,
+    export: Option<Snapshotter>,
+}
+
+impl<L: PriceLadder> Aggregator<L> {
+    fn new(orderbook: OrderBook<L>, config: Config, params: Arc<ParamStore>) -> Self {
+        Self {
+            orderbook,
+            config,
+            params,
+            risk: None,
+            quotes: QuoteClock::default(),
+            tracker: Arc::default(),
+            metrics: Arc::new(Metrics::default()),
+            recorder: None,
+            storage: None,
+            export: None,
+        }
+    }
+
+    fn handle(&mut self, event: &MarketEvent) {
+        self.metrics.record_event(event.exchange);
+        let opportunities = pipeline::process_event(&self.orderbook, event);
+        self.quotes.update(event);
+        if let Some(risk) = &self.risk {
+            let best_bid = self.orderbook.best_bid_all_exchanges();
+            if let (Some((bid, _)), Some((ask, _))) =
+                (best_bid, self.orderbook.best_ask_all_exchanges())
+            {
+                risk.update_reference_mid((bid + ask) / 2);
+            }
+        }
+        if let Some(export) = &mut self.export {
+            export.on_event(&self.orderbook, event);
+        }
+        let params = self.params.params();
+        let now = event.received_at;
+        for opportunity in &opportunities {
+            let actionable = qualifying_edge(&params, &self.quotes, opportunity, now).is_some();
+            self.metrics.record_opportunity(actionable);
+        }
+        let quotes = &self.quotes;
+        let windows = self
+            .tracker
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .on_event(&self.orderbook, event.exchange, now, |opportunity| {
+                qualifying_edge(&params, quotes, opportunity, now)
+            });
+        report_windows(&windows, self.storage.as_ref());
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(event) {
+                error!("[Record] Stopped recording: {}", e);
+                self.recorder = None;
+            }
+        }
+        info!("Aggregated {}", event);
+    }
+}
+
+/// Logs window events and stores the closed windows.
+fn report_windows(windows: &[OpportunityEvent], storage: Option<&StorageWriter>) {
+    for window in windows {
+        log_window(window);
+        if let (OpportunityEvent::Closed(window), Some(storage)) = (window, storage) {
+            storage.record_opportunity(*window);
+        }
+    }
+}
+
+/// Closes every window still open, e.g. when the feeds stop.
+fn close_windows(
+    tracker: &Mutex<OpportunityTracker>,
+    storage: Option<&StorageWriter>,
+    at: Instant,
+) {
+    let closed = tracker
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .close_all(at);
+    report_windows(&closed, storage);
+}
+
+/// The balances configured under `[inventory]`, if any.
+fn inventory_tracker(config: &Config) -> Option<Arc<InventoryTracker>> {
+    let settings = config.inventory.as_ref()?;
+    let (base, quote) = config.assets();
+    let tracker = InventoryTracker::new(base, quote);
+    for (exchange, balances) in &settings.balances {
+        for (asset, balance) in balances {
+            tracker.set_balance(*exchange, asset, *balance);
+        }
+    }
+    Some(Arc::new(tracker))
+}
+
+/// Streams the enabled venues until Ctrl-C or, if given, `duration` has passed.
+/// The strategy parameters and `[risk]` limits are reloaded from `path` on
+/// SIGHUP or through the admin endpoint. Venues with API credentials stream
+/// our own order updates once orders are placed. Opportunities are capped by
+/// the balances under `[inventory]`, closed opportunity windows written to the
+/// database configured under `[storage]`, and book samples and trades
+/// exported under `[export]`, if configured.
+fn live(
+    config: Config,
+    path: Option<PathBuf>,
+    recorder: Option<Recorder<LineWriter<File>>>,
+    duration: Option<Duration>,
+) -> anyhow::Result<()> {
+    let storage = match &config.storage.path {
+        Some(path) => {
+            info!("[Storage] Writing to {}", path.display());
+            let database = Database::open(path)?;
+            Some(Storage::spawn(database, config.storage.queue_capacity)?)
+        }
+        None => None,
+    };
+    let writer = storage.as_ref().map(Storage::writer);
+    let tracker = Arc::new(Mutex::new(OpportunityTracker::new()));
+    let export = match &config.export.dir {
+        Some(dir) => {
+            info!("[Export] Writing to {}", dir.display());
+            let files = PartitionedFiles::new(dir)
+                .with_csv(config.export.csv)
+                .with_roll_interval(Duration::from_secs(config.export.roll_minutes * 60));
+            Some(Export::spawn(files, config.export.queue_capacity)?)
+        }
+        None => None,
+    };
+    let snapshotter = || {
+        export.as_ref().map(|export| {
+            Snapshotter::new(config.instrument(), export.writer())
+                .with_interval(Duration::from_millis(config.export.interval_ms))
+                .with_depth(config.export.depth)
+        })
+    };
+    let inventory = inventory_tracker(&config);
+    let runtime = config.pipeline.cores.strategy_runtime()?;
+    runtime.block_on(async {
+        info!("Starting low-latency order book aggregator...");
+        info!(
+            "Monitoring {} across {:?}",
+            config.instrument(),
+            config.enabled_venues()
+        );
+        if let (Some(inventory), Some(settings)) = (&inventory, &config.inventory) {
+            spawn_imbalance_reporter(
+                Arc::clone(inventory),
+                Duration::from_secs(settings.report_secs),
             );
         }
-    }); */
-    let orderbook = OrderBook::new(order_book_name.to_string());
-    let aggregator_handle = tokio::spawn(async move {
-        while let Some(price) = rx.recv().await {
-            match price {
-                ExchangePrice::Binance {
-                    price,
-                    exchange_timestamp,
-                    received_at,
-                } => {
-                    orderbook.check_for_immediate_purchase(
-                        price,
-                        orderbook::book::Exchange::Binance,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
-                    orderbook.add_exchange_price_level(
-                        price,
-                        orderbook::book::Exchange::Binance,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
+
+        let metrics = Arc::new(Metrics::default());
+        if let Some(port) = config.metrics.port {
+            let addr = SocketAddr::new(config.metrics.bind.parse()?, port);
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                if let Err(e) = pipeline::metrics::serve(addr, metrics).await {
+                    error!("[Metrics] Failed to serve on {}: {}", addr, e);
                 }
-                ExchangePrice::Kraken {
-                    price,
-                    exchange_timestamp,
-                    received_at,
-                } => {
-                    orderbook.check_for_immediate_purchase(
-                        price,
-                        orderbook::book::Exchange::Kraken,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
-                    orderbook.add_exchange_price_level(
-                        price,
-                        orderbook::book::Exchange::Kraken,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
+            });
+        }
+
+        let risk = config.risk.map(|limits| Arc::new(RiskEngine::new(limits)));
+        let mut params = ParamStore::new(config.clone(), path);
+        if let Some(risk) = &risk {
+            params = params.with_risk_engine(Arc::clone(risk));
+        }
+        let params = Arc::new(params);
+        #[cfg(unix)]
+        reload::spawn_reload_signal_listener(Arc::clone(&params))?;
+        spawn_user_streams(&config, risk.clone(), inventory.clone())?;
+        if let Some(port) = config.admin.port {
+            let addr = SocketAddr::new(config.admin.bind.parse()?, port);
+            let params = Arc::clone(&params);
+            tokio::spawn(async move {
+                if let Err(e) = reload::serve_admin(addr, params).await {
+                    error!("[Params] Failed to serve on {}: {}", addr, e);
                 }
-                ExchangePrice::Coinbase {
-                    price,
-                    exchange_timestamp,
-                    received_at,
-                } => {
-                    orderbook.check_for_immediate_purchase(
-                        price,
-                        orderbook::book::Exchange::Coinbase,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
-                    orderbook.add_exchange_price_level(
-                        price,
-                        orderbook::book::Exchange::Coinbase,
-                        pricelevel::Side::Buy,
-                        unimplemented!(),
-                    );
+            });
+        }
+
+        let stop = async {
+            match duration {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => {
+                    tokio::signal::ctrl_c().await.ok();
                 }
             }
-            info!(
-                "Aggregated BTC/USDT price: {}, exchange timestamp: {:?}",
-                price,
-                price.exchange_timestamp()
-            );
-            tx_exchange.send(price).await.unwrap();
-            // Here you could implement more complex aggregation logic
+        };
+        let aggregate = async {
+            match config.pipeline.ladder {
+                LadderKind::Map => {
+                    let mut orderbook = map_book(&config);
+                    if let Some(inventory) = &inventory {
+                        orderbook = orderbook.with_inventory(Arc::clone(inventory));
+                    }
+                    start(Aggregator {
+                        metrics,
+                        recorder,
+                        tracker: Arc::clone(&tracker),
+                        risk,
+                        storage: writer.clone(),
+                        export: snapshotter(),
+                        ..Aggregator::new(orderbook, config.clone(), params)
+                    })
+                    .await
+                }
+                LadderKind::Array => {
+                    info!("Using array price ladders");
+                    let mut orderbook = array_book(&config);
+                    if let Some(inventory) = &inventory {
+                        orderbook = orderbook.with_inventory(Arc::clone(inventory));
+                    }
+                    start(Aggregator {
+                        metrics,
+                        recorder,
+                        tracker: Arc::clone(&tracker),
+                        risk,
+                        storage: writer.clone(),
+                        export: snapshotter(),
+                        ..Aggregator::new(orderbook, config.clone(), params)
+                    })
+                    .await
+                }
+            }
+        };
+        tokio::select! {
+            _ = aggregate => {}
+            _ = stop => info!("Stopping"),
+        }
+        anyhow::Ok(())
+    })?;
+    // The ring pipeline's book thread is joined from a blocking task, which
+    // would otherwise keep the runtime from shutting down
+    runtime.shutdown_background();
+    close_windows(&tracker, writer.as_ref(), Instant::now());
+    if let Some(storage) = storage {
+        storage.close();
+    }
+    if let Some(export) = export {
+        export.close();
+    }
+    Ok(())
+}
+
+/// Streams our own order updates from each enabled venue with API
+/// credentials into an order manager, which releases `risk`'s open-order
+/// slots as orders finish and applies fills to `inventory`. The streams
+/// connect once the manager has an order to follow.
+fn spawn_user_streams(
+    config: &Config,
+    risk: Option<Arc<RiskEngine>>,
+    inventory: Option<Arc<InventoryTracker>>,
+) -> anyhow::Result<()> {
+    let mut manager = OrderManager::new();
+    if let Some(risk) = risk {
+        manager = manager.with_risk_engine(risk);
+    }
+    if let Some(inventory) = inventory {
+        manager = manager.with_inventory(inventory);
+    }
+    let manager = Arc::new(manager);
+
+    let (tx, rx) = mpsc::channel(config.pipeline.channel_capacity);
+    let mut streams = Vec::new();
+    for exchange in config.enabled_venues() {
+        let Some(credentials) = config.credentials(exchange)? else {
+            continue;
+        };
+        let tx = tx.clone();
+        let stream: BoxFuture<'static, ()> = match exchange {
+            Exchange::Binance => {
+                let stream = BinanceUserStream::new(BinanceSigner::new(credentials), tx);
+                Box::pin(async move { stream.run().await })
+            }
+            Exchange::Kraken => {
+                let stream = KrakenUserStream::new(KrakenSigner::new(credentials), tx);
+                Box::pin(async move { stream.run().await })
+            }
+            Exchange::Coinbase => {
+                let product = coinbase::product_id(config.instrument());
+                let stream =
+                    CoinbaseUserStream::new(CoinbaseSigner::new(credentials)?, vec![product], tx);
+                Box::pin(async move { stream.run().await })
+            }
+        };
+        streams.push((exchange, stream));
+    }
+
+    if !streams.is_empty() {
+        orders::spawn_report_handler(Arc::clone(&manager), rx);
+        let waiting = Arc::clone(&manager);
+        tokio::spawn(async move {
+            waiting.wait_for_orders().await;
+            for (exchange, stream) in streams {
+                info!("[Orders] Streaming {:?} order updates", exchange);
+                tokio::spawn(stream);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn start<L: PriceLadder + 'static>(aggregator: Aggregator<L>) {
+    let pipeline = &aggregator.config.pipeline;
+    info!(
+        "Using {:?} pipeline with {:?}",
+        pipeline.mode, pipeline.cores
+    );
+    match pipeline.mode {
+        PipelineMode::Tokio => {
+            let layout = &pipeline.cores;
+            if !layout.venue_cores.is_empty() || layout.book_core.is_some() || layout.busy_poll {
+                warn!("Venue and book thread settings only apply to the ring pipeline");
+            }
+            run_tokio(aggregator).await
+        }
+        PipelineMode::Ring => run_ring(aggregator).await,
+    }
+}
+
+/// Streams one venue's depth into `tx` from its configured endpoints.
+async fn stream_depth<S: EventSink<MarketEvent>>(exchange: Exchange, venue: VenueConfig, tx: S) {
+    match exchange {
+        Exchange::Binance => {
+            let mut client = BinanceClient::new(tx);
+            if let Some(url) = venue.depth_url {
+                client = client.with_url(url);
+            }
+            if let Some(url) = venue.snapshot_url {
+                client = client.with_snapshot_url(url);
+            }
+            client.listen_btc_usdt().await
+        }
+        Exchange::Kraken => {
+            let mut client = KrakenClient::new(tx);
+            if let Some(url) = venue.depth_url {
+                client = client.with_url(url);
+            }
+            client.listen_btc_usdt().await
+        }
+        Exchange::Coinbase => {
+            let mut client = CoinbaseClient::new(tx);
+            if let Some(url) = venue.depth_url {
+                client = client.with_url(url);
+            }
+            client.listen_btc_usdt().await
+        }
+    }
+}
+
+/// Streams one venue's public trades into `tx` from its configured endpoint.
+fn stream_trades<S: EventSink<MarketEvent>>(
+    exchange: Exchange,
+    venue: VenueConfig,
+    tx: S,
+) -> impl Future<Output = ()> {
+    let mut client = PublicTradeClient::new(exchange, tx);
+    if let Some(url) = venue.trades_url {
+        client = client.with_url(url);
+    }
+    async move { client.run().await }
+}
+
+async fn run_tokio<L: PriceLadder + 'static>(mut aggregator: Aggregator<L>) {
+    let config = aggregator.config.clone();
+    let mut bus = config.enabled_venues().into_iter().fold(
+        MarketDataBus::new(config.pipeline.channel_capacity),
+        |bus, exchange| bus.with_policy(exchange, config.venue(exchange).backpressure),
+    );
+
+    // Depth and public trades for each venue; the trades feed the book's tape
+    let mut feeds = JoinSet::new();
+    for exchange in config.enabled_venues() {
+        let venue = config.venue(exchange);
+        let depth = stream_depth(exchange, venue.clone(), bus.sender(exchange));
+        feeds.spawn(async move {
+            depth.await;
+            exchange
+        });
+        tokio::spawn(stream_trades(exchange, venue, bus.sender(exchange)));
+    }
+
+    let aggregator_handle = tokio::spawn(async move {
+        let mut stats_interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            let event = tokio::select! {
+                event = bus.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = stats_interval.tick() => {
+                    for exchange in config.enabled_venues() {
+                        info!("[Feed] {:?} {:?}", exchange, bus.stats(exchange));
+                    }
+                    continue;
+                }
+            };
+            aggregator.handle(&event);
         }
     });
 
     // Wait for all tasks (they run indefinitely)
     tokio::select! {
-        _ = binance_handle => {
-            info!("Binance task ended");
-        }
-        _ = kraken_handle => {
-            info!("Kraken task ended");
-        }
-        _ = coinbase_handle => {
-            info!("Coinbase task ended");
+        Some(Ok(exchange)) = feeds.join_next() => {
+            info!("{:?} task ended", exchange);
         }
         _ = aggregator_handle => {
             info!("Aggregator task ended");
@@ -135,6 +631,189 @@ async fn run(order_book_name: String) {
     }
 }
 
+/// Each venue's feeds decode on the venue's I/O thread into SPSC rings; a
+/// dedicated thread drains the rings and builds the book.
+async fn run_ring<L: PriceLadder + 'static>(mut aggregator: Aggregator<L>) {
+    let config = aggregator.config.clone();
+    let layout = &config.pipeline.cores;
+    let mut rings =
+        RingPipeline::new(config.pipeline.channel_capacity).with_busy_poll(layout.busy_poll);
+
+    for exchange in config.enabled_venues() {
+        // Depth and trades each get a ring, both written from this thread
+        let depth_tx = rings.add_feed();
+        let trade_tx = rings.add_feed();
+        let venue = config.venue(exchange);
+        let io = pipeline::spawn_feed(
+            &format!("io-{exchange:?}").to_lowercase(),
+            layout.venue_core(exchange),
+            move || async move {
+                let depth = stream_depth(exchange, venue.clone(), depth_tx);
+                tokio::join!(depth, stream_trades(exchange, venue, trade_tx));
+            },
+        );
+        if let Err(e) = io {
+            error!("Failed to start {:?} I/O thread: {}", exchange, e);
+            return;
+        }
+    }
+
+    let book_core = layout.book_core;
+    let book = std::thread::Builder::new()
+        .name("book".to_string())
+        .spawn(move || {
+            if let Some(core) = book_core {
+                layout::pin_current(core);
+            }
+            rings.run(|event| aggregator.handle(&event))
+        });
+    match book {
+        // The feeds run indefinitely, and the book thread with them
+        Ok(book) => {
+            tokio::task::spawn_blocking(move || book.join()).await.ok();
+            info!("Book thread ended");
+        }
+        Err(e) => error!("Failed to start book thread: {}", e),
+    }
+}
+
+fn replay(config: Config, input: &Path, speed: f64) -> anyhow::Result<()> {
+    if !speed.is_finite() || speed < 0.0 {
+        return Err(anyhow!("--speed must be zero or positive, got {speed}"));
+    }
+    let params = Arc::new(ParamStore::new(config.clone(), None));
+    match config.pipeline.ladder {
+        LadderKind::Map => {
+            let orderbook = map_book(&config);
+            replay_into(Aggregator::new(orderbook, config, params), input, speed)
+        }
+        LadderKind::Array => {
+            let orderbook = array_book(&config);
+            replay_into(Aggregator::new(orderbook, config, params), input, speed)
+        }
+    }
+}
+
+fn replay_into<L: PriceLadder>(
+    mut aggregator: Aggregator<L>,
+    input: &Path,
+    speed: f64,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut last = started;
+    for entry in Replay::open(input)? {
+        let (offset, mut event) = entry?;
+        if speed > 0.0 {
+            let due = started + offset.div_f64(speed);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            event.received_at = Instant::now();
+        } else {
+            // Keeps quote ages as they were recorded
+            event.received_at = started + offset;
+        }
+        aggregator.handle(&event);
+        last = event.received_at;
+    }
+    close_windows(&aggregator.tracker, aggregator.storage.as_ref(), last);
+    for exchange in Exchange::ALL {
+        info!(
+            "[Replay] {:?}: {} events",
+            exchange,
+            aggregator.metrics.events(exchange)
+        );
+    }
+    Ok(())
+}
+
+fn backtest(config: &Config, input: &Path) -> anyhow::Result<()> {
+    match config.pipeline.ladder {
+        LadderKind::Map => backtest_on(map_book(config), config, input),
+        LadderKind::Array => backtest_on(array_book(config), config, input),
+    }
+}
+
+/// Opportunity windows for one direction between two venues.
+#[derive(Default)]
+struct PairSummary {
+    windows: u32,
+    total_duration: Duration,
+    max_duration: Duration,
+    total_peak_bps: i64,
+    max_peak_bps: i64,
+}
+
+fn backtest_on<L: PriceLadder>(
+    orderbook: OrderBook<L>,
+    config: &Config,
+    input: &Path,
+) -> anyhow::Result<()> {
+    let params = config.params();
+    let mut quotes = QuoteClock::default();
+    let mut tracker = OpportunityTracker::new();
+    let started = Instant::now();
+    let mut last = started;
+    let mut events = 0;
+    let mut detected = 0;
+    let mut closed = Vec::new();
+    for entry in Replay::open(input)? {
+        let (offset, mut event) = entry?;
+        // Quote ages and window durations as they were recorded
+        event.received_at = started + offset;
+        last = event.received_at;
+        events += 1;
+        detected += pipeline::process_event(&orderbook, &event).len();
+        quotes.update(&event);
+        let now = event.received_at;
+        closed.extend(
+            tracker
+                .on_event(&orderbook, event.exchange, now, |opportunity| {
+                    qualifying_edge(&params, &quotes, opportunity, now)
+                })
+                .into_iter()
+                .filter(|window| matches!(window, OpportunityEvent::Closed(_))),
+        );
+    }
+    // Windows still open when the recording ends last until then
+    closed.extend(tracker.close_all(last));
+
+    let mut pairs: HashMap<(Exchange, Exchange), PairSummary> = HashMap::new();
+    for window in &closed {
+        let window = window.opportunity();
+        let pair = pairs
+            .entry((window.buy_exchange(), window.sell_exchange()))
+            .or_default();
+        pair.windows += 1;
+        pair.total_duration += window.duration();
+        pair.max_duration = pair.max_duration.max(window.duration());
+        pair.total_peak_bps += window.peak_edge_bps;
+        pair.max_peak_bps = pair.max_peak_bps.max(window.peak_edge_bps);
+    }
+
+    println!(
+        "{events} events, {detected} detections, {} windows clearing {} bps net of fees",
+        closed.len(),
+        params.min_edge_bps
+    );
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    pairs.sort_by_key(|(_, pair)| std::cmp::Reverse(pair.windows));
+    for ((buy, sell), pair) in pairs {
+        println!(
+            "buy {:<8} sell {:<8} {:>6} windows  mean {:>10.3?}  max {:>10.3?}  \
+             mean peak {:>5} bps  max peak {:>5} bps",
+            format!("{buy:?}"),
+            format!("{sell:?}"),
+            pair.windows,
+            pair.total_duration / pair.windows,
+            pair.max_duration,
+            pair.total_peak_bps / pair.windows as i64,
+            pair.max_peak_bps
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
 
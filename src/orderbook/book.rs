@@ -2,7 +2,7 @@
 //!
 //! This module defines the core OrderBook data structure and its associated types.
 //! The OrderBook manages:
-//! - Concurrent bid and ask price levels using DashMap for lock-free operations
+//! - Bid and ask price levels per venue, stored in a `PriceLadder`
 //! - Order tracking and management with unique order IDs
 //! - Best bid/ask price calculation
 //! - Transaction ID generation for order matching
@@ -11,14 +11,22 @@
 //! The implementation uses concurrent data structures to support high-throughput
 //! order processing in a multi-threaded environment.
 
-use crossbeam_queue::SegQueue;
 use dashmap::DashMap;
-use pricelevel::{OrderId, PriceLevel, Side, UuidGenerator};
-use std::{
-    collections::BTreeMap,
-    sync::{atomic::AtomicU64, Arc},
+use pricelevel::{OrderId, Side};
+use serde::{Deserialize, Serialize};
+use std::sync::{atomic::AtomicU64, Arc};
+use tracing::{debug, trace};
+
+use super::{
+    ladder::{MapLadder, PriceLadder},
+    sync::BookState,
+    tape::{TradeTape, VolumeTracker},
+    validation::DataValidator,
+};
+use crate::{
+    api::event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+    inventory::InventoryTracker,
 };
-use uuid::Uuid;
 
 #[warn(clippy::too_many_lines)]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -30,49 +38,110 @@ pub enum FillType {
     Full(Vec<OrderId>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Exchange {
     Binance,
     Coinbase,
     Kraken,
 }
 
+impl Exchange {
+    pub const ALL: [Exchange; 3] = [Exchange::Binance, Exchange::Coinbase, Exchange::Kraken];
+}
+
+/// A cross-exchange mispricing: buy on one venue below where another venue bids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArbitrageOpportunity {
+    pub buy_exchange: Exchange,
+    /// Ask price seen on the buy venue when the opportunity was detected
+    pub buy_price: u64,
+    pub sell_exchange: Exchange,
+    /// Bid price seen on the sell venue when the opportunity was detected
+    pub sell_price: u64,
+    pub quantity: u64,
+}
+
+impl ArbitrageOpportunity {
+    /// Gross edge per unit in cents, before fees.
+    pub fn edge(&self) -> u64 {
+        self.sell_price.saturating_sub(self.buy_price)
+    }
+}
+
 /// The OrderBook manages a collection of price levels for both bid and ask sides.
 /// It supports adding, cancelling, and matching orders with lock-free operations where possible.
-pub struct OrderBook {
+/// Each side's levels live in a `PriceLadder`, the `DashMap`-backed `MapLadder` unless the
+/// book is built with `with_ladders`.
+pub struct OrderBook<L: PriceLadder = MapLadder> {
     /// The symbol or identifier for this order book
     pub symbol: String,
-    // BTreeMap keeps prices sorted (bids: highest first, asks: lowest first) and maps price → quantity.
-    pub exchange_bids_price_level: DashMap<(u64, Exchange), BTreeMap<u64, u64>>,
+    /// Every venue's bid levels, price → quantity
+    pub exchange_bids_price_level: L,
 
-    pub exchange_asks_price_level: DashMap<(u64, Exchange), BTreeMap<u64, u64>>,
+    pub exchange_asks_price_level: L,
 
     pub cached_best_bid: DashMap<Exchange, AtomicU64>,
 
     pub cached_best_ask: DashMap<Exchange, AtomicU64>,
 
-    /// Best bid across all exchanges. Returns None if no data available.
-    /// The tuple contains (exchange, price), where price of 0 means no data.
-    pub best_bid_all_exchanges: (Exchange, AtomicU64),
+    /// Balances used to cap opportunity size, if inventory is being tracked
+    pub inventory: Option<Arc<InventoryTracker>>,
+
+    /// Recent public trades and per-venue trade statistics
+    pub trades: TradeTape,
+
+    /// Volume observed for execution algos, if being tracked
+    pub volumes: Option<Arc<VolumeTracker>>,
+
+    /// Sync state of each venue fed through market events
+    pub sync_states: DashMap<Exchange, BookState>,
 
-    /// Best ask across all exchanges. Returns None if no data available.
-    /// The tuple contains (exchange, price), where price of 0 means no data.
-    pub best_ask_all_exchanges: (Exchange, AtomicU64),
+    /// Sanity checks on market events, quarantining venues sending bad data
+    pub validator: DataValidator,
 }
 
 impl OrderBook {
     pub fn new(symbol: String) -> Self {
+        Self::with_ladders(symbol, MapLadder::new(Side::Buy), MapLadder::new(Side::Sell))
+    }
+}
+
+impl<L: PriceLadder> OrderBook<L> {
+    /// A book keeping its bid and ask levels in the given ladders.
+    pub fn with_ladders(symbol: String, bids: L, asks: L) -> Self {
         Self {
             symbol,
-            exchange_bids_price_level: DashMap::new(),
-            exchange_asks_price_level: DashMap::new(),
+            exchange_bids_price_level: bids,
+            exchange_asks_price_level: asks,
             cached_best_bid: DashMap::new(),
             cached_best_ask: DashMap::new(),
-            best_bid_all_exchanges: (Exchange::Binance, AtomicU64::new(0)),
-            best_ask_all_exchanges: (Exchange::Binance, AtomicU64::new(0)),
+            inventory: None,
+            trades: TradeTape::new(),
+            volumes: None,
+            sync_states: DashMap::new(),
+            validator: DataValidator::default(),
         }
     }
 
+    /// Caps detected opportunities by the balances held on each venue.
+    pub fn with_inventory(mut self, inventory: Arc<InventoryTracker>) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Feeds every recorded trade's size into `volumes`, e.g. for VWAP.
+    pub fn with_volume_tracker(mut self, volumes: Arc<VolumeTracker>) -> Self {
+        self.volumes = Some(volumes);
+        self
+    }
+
+    /// Replaces the default market-data checks, e.g. to change thresholds or receive alerts.
+    pub fn with_validator(mut self, validator: DataValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
     pub fn best_bid(&self, exchange: Exchange) -> Option<u64> {
         let best_bid = self.cached_best_bid.get(&exchange)?;
 
@@ -85,88 +154,252 @@ impl OrderBook {
         Some(best_ask.load(std::sync::atomic::Ordering::Relaxed))
     }
 
-    /// Returns the best bid price across all exchanges, or None if no data is available.
-    /// A price of 0 is treated as "no data" since it's invalid for trading.
+    /// Returns the best bid price across all exchanges that are not suppressed,
+    /// or None if none has a bid.
     pub fn best_bid_all_exchanges(&self) -> Option<(u64, Exchange)> {
-        let price = self
-            .best_bid_all_exchanges
-            .1
-            .load(std::sync::atomic::Ordering::Relaxed);
-        if price == 0 {
-            None
-        } else {
-            Some((price, self.best_bid_all_exchanges.0))
-        }
+        self.best_across(Side::Buy, None)
     }
 
-    /// Returns the best ask price across all exchanges, or None if no data is available.
-    /// A price of 0 is treated as "no data" since it's invalid for trading.
+    /// Returns the best ask price across all exchanges that are not suppressed,
+    /// or None if none has an ask.
     pub fn best_ask_all_exchanges(&self) -> Option<(u64, Exchange)> {
-        let price = self
-            .best_ask_all_exchanges
-            .1
-            .load(std::sync::atomic::Ordering::Relaxed);
-        if price == 0 {
-            None
-        } else {
-            Some((price, self.best_ask_all_exchanges.0))
-        }
+        self.best_across(Side::Sell, None)
+    }
+
+    /// Highest cached bid or lowest cached ask over the venues that are not
+    /// suppressed, leaving out `except`. Ties go to the venue first in
+    /// `Exchange::ALL`. A price of 0 is treated as "no data".
+    fn best_across(&self, side: Side, except: Option<Exchange>) -> Option<(u64, Exchange)> {
+        let cache = match side {
+            Side::Buy => &self.cached_best_bid,
+            Side::Sell => &self.cached_best_ask,
+        };
+        Exchange::ALL
+            .into_iter()
+            .filter(|&exchange| Some(exchange) != except && !self.is_suppressed(exchange))
+            .filter_map(|exchange| {
+                let price = cache
+                    .get(&exchange)?
+                    .load(std::sync::atomic::Ordering::Relaxed);
+                (price > 0).then_some((price, exchange))
+            })
+            .reduce(|best, next| match side {
+                Side::Buy if next.0 > best.0 => next,
+                Side::Sell if next.0 < best.0 => next,
+                _ => best,
+            })
     }
 
+    /// Mid of the best bid and ask across all exchanges, or None until both sides have data.
+    pub fn mid_all_exchanges(&self) -> Option<u64> {
+        let (bid, _) = self.best_bid_all_exchanges()?;
+        let (ask, _) = self.best_ask_all_exchanges()?;
+        Some((bid + ask) / 2)
+    }
+
+    /// Checks an incoming price against the best opposite price on the other
+    /// venues that are not suppressed.
+    /// A bid above another venue's best ask (or an ask below another venue's best bid)
+    /// is returned as an opportunity to buy on the cheap venue and sell on the rich one.
     pub fn check_for_immediate_purchase(
         &self,
         price: u64,
         exchange: Exchange,
         side: Side,
         quantity: u64,
-    ) {
+    ) -> Option<ArbitrageOpportunity> {
+        if self.is_suppressed(exchange) {
+            return None;
+        }
         match side {
             Side::Buy => {
-                let val = self.best_ask_all_exchanges();
+                let val = self.best_across(Side::Sell, Some(exchange));
                 if let Some(best_ask_exchange) = val {
-                    if best_ask_exchange.1 != exchange && best_ask_exchange.0 < price {
-                        println!("Best ask: {:?} from exchange: {:?}, is better higher than our bid: {:?}, from exchange: {:?}",
+                    if best_ask_exchange.0 < price {
+                        trace!("Best ask: {:?} from exchange: {:?}, is better higher than our bid: {:?}, from exchange: {:?}",
                             best_ask_exchange.0, best_ask_exchange.1, exchange, price);
+                        return self.size_opportunity(ArbitrageOpportunity {
+                            buy_exchange: best_ask_exchange.1,
+                            buy_price: best_ask_exchange.0,
+                            sell_exchange: exchange,
+                            sell_price: price,
+                            quantity,
+                        });
                     }
                 }
             }
             Side::Sell => {
-                let val = self.best_bid_all_exchanges();
+                let val = self.best_across(Side::Buy, Some(exchange));
                 if let Some(best_bid_exchange) = val {
-                    if best_bid_exchange.1 != exchange && best_bid_exchange.0 > price {}
-                    println!("Best bid: {:?} from exchange: {:?}, is better lower than our ask: {:?}, from exchange: {:?}",
+                    if best_bid_exchange.0 > price {
+                        trace!("Best bid: {:?} from exchange: {:?}, is better lower than our ask: {:?}, from exchange: {:?}",
                             best_bid_exchange.0, best_bid_exchange.1, exchange, price);
+                        return self.size_opportunity(ArbitrageOpportunity {
+                            buy_exchange: exchange,
+                            buy_price: price,
+                            sell_exchange: best_bid_exchange.1,
+                            sell_price: best_bid_exchange.0,
+                            quantity,
+                        });
+                    }
                 }
             }
         }
+        None
     }
 
-    pub fn add_exchange_price_level(
+    /// Whether `buy`'s best ask is below `sell`'s best bid right now, sized to
+    /// the smaller of the two top levels. Unlike `check_for_immediate_purchase`
+    /// this names both venues, so one pair can be watched until it closes.
+    pub fn check_pair(&self, buy: Exchange, sell: Exchange) -> Option<ArbitrageOpportunity> {
+        if buy == sell || self.is_suppressed(buy) || self.is_suppressed(sell) {
+            return None;
+        }
+        let ask = self.best_ask(buy).filter(|&ask| ask > 0)?;
+        let bid = self.best_bid(sell).filter(|&bid| bid > 0)?;
+        if ask >= bid {
+            return None;
+        }
+        let quantity = self
+            .exchange_asks_price_level
+            .quantity(buy, ask)
+            .min(self.exchange_bids_price_level.quantity(sell, bid));
+        self.size_opportunity(ArbitrageOpportunity {
+            buy_exchange: buy,
+            buy_price: ask,
+            sell_exchange: sell,
+            sell_price: bid,
+            quantity,
+        })
+    }
+
+    /// Limits an opportunity to what the tracked balances can fund on both venues.
+    fn size_opportunity(&self, opportunity: ArbitrageOpportunity) -> Option<ArbitrageOpportunity> {
+        let Some(inventory) = &self.inventory else {
+            return Some(opportunity);
+        };
+        let sized = inventory.cap_opportunity(opportunity);
+        if sized.is_none() {
+            debug!(
+                "Skipping {:?} -> {:?}: no balance to fund it",
+                opportunity.buy_exchange, opportunity.sell_exchange
+            );
+        }
+        sized
+    }
+
+    /// Replaces the quantity a venue shows at `price`, as depth snapshots and
+    /// updates report it. A quantity of 0 removes the level.
+    pub fn set_exchange_price_level(
         &self,
         price: u64,
         exchange: Exchange,
         side: Side,
         quantity: u64,
     ) {
-        match side {
-            Side::Buy => {
-                let key = (price, exchange);
-                let mut price_level = self
-                    .exchange_bids_price_level
-                    .entry(key)
-                    .or_insert_with(BTreeMap::new);
-                let entry = price_level.entry(price).or_insert(0);
-                *entry += quantity;
+        self.price_levels(side).set(exchange, price, quantity);
+    }
+
+    /// Applies a normalized market-data event from one venue. Snapshots
+    /// replace the venue's book and make it live, level updates and BBOs
+    /// overwrite what they carry while it is live, trades go to the tape, and
+    /// status changes move the venue's sync state. Events are validated
+    /// before and after applying; a failure quarantines the venue. A level
+    /// update rejected before applying also takes the venue out of sync, as
+    /// its book is wrong without it.
+    pub fn apply_market_event(&self, event: &MarketEvent) {
+        let exchange = event.exchange;
+        if let Some(issue) = self.validator.screen(event) {
+            self.validator.quarantine(exchange, issue);
+            if event.kind == MarketEventKind::LevelUpdate && !self.awaiting_snapshot(exchange) {
+                self.apply_feed_status(exchange, FeedStatus::Resyncing);
             }
-            Side::Sell => {
-                let key = (price, exchange);
-                let mut price_level = self
-                    .exchange_asks_price_level
-                    .entry(key)
-                    .or_insert_with(BTreeMap::new);
-                let entry = price_level.entry(price).or_insert(0);
-                *entry += quantity;
+            return;
+        }
+        match &event.kind {
+            MarketEventKind::Snapshot => {
+                self.clear_exchange(exchange);
+                self.set_levels(exchange, &event.levels);
+                self.set_book_state(exchange, BookState::Live);
+            }
+            MarketEventKind::LevelUpdate | MarketEventKind::Bbo
+                if self.awaiting_snapshot(exchange) =>
+            {
+                debug!("Dropping {:?} update while its book is not live", exchange);
+                return;
+            }
+            MarketEventKind::LevelUpdate => self.set_levels(exchange, &event.levels),
+            MarketEventKind::Bbo => {
+                // Only the top of book is known, so leave the depth alone
+                if let Some(bid) = event.best_bid() {
+                    Self::store_best(&self.cached_best_bid, exchange, Some(bid));
+                }
+                if let Some(ask) = event.best_ask() {
+                    Self::store_best(&self.cached_best_ask, exchange, Some(ask));
+                }
             }
+            MarketEventKind::Trade { .. } => {
+                if let Some(trade) = event.trade() {
+                    self.record_trade(trade);
+                }
+            }
+            MarketEventKind::Status(status) => self.apply_feed_status(exchange, *status),
+        }
+        if let Some(issue) = self.check_applied_event(event) {
+            self.validator.quarantine(exchange, issue);
+        }
+    }
+
+    /// Removes every level a venue shows, on both sides.
+    pub fn clear_exchange(&self, exchange: Exchange) {
+        self.exchange_bids_price_level.clear(exchange);
+        self.exchange_asks_price_level.clear(exchange);
+        self.cached_best_bid.remove(&exchange);
+        self.cached_best_ask.remove(&exchange);
+    }
+
+    fn set_levels(&self, exchange: Exchange, levels: &[Level]) {
+        for level in levels {
+            self.set_exchange_price_level(level.price, exchange, level.side, level.quantity);
+        }
+        self.refresh_best_prices(exchange);
+    }
+
+    /// Recomputes a venue's cached best bid and ask from its levels.
+    fn refresh_best_prices(&self, exchange: Exchange) {
+        let bid = self.exchange_bids_price_level.best(exchange);
+        let ask = self.exchange_asks_price_level.best(exchange);
+        Self::store_best(&self.cached_best_bid, exchange, bid);
+        Self::store_best(&self.cached_best_ask, exchange, ask);
+    }
+
+    fn store_best(cache: &DashMap<Exchange, AtomicU64>, exchange: Exchange, price: Option<u64>) {
+        match price {
+            Some(price) => cache
+                .entry(exchange)
+                .or_insert_with(|| AtomicU64::new(0))
+                .store(price, std::sync::atomic::Ordering::Relaxed),
+            None => {
+                cache.remove(&exchange);
+            }
+        }
+    }
+
+    pub fn add_exchange_price_level(
+        &self,
+        price: u64,
+        exchange: Exchange,
+        side: Side,
+        quantity: u64,
+    ) {
+        self.price_levels(side).add(exchange, price, quantity);
+    }
+
+    /// The ladder holding `side`'s levels.
+    pub fn price_levels(&self, side: Side) -> &L {
+        match side {
+            Side::Buy => &self.exchange_bids_price_level,
+            Side::Sell => &self.exchange_asks_price_level,
         }
     }
 }
@@ -178,18 +411,23 @@ mod test {
     use pricelevel::Side;
     use tokio::sync::mpsc::channel;
 
-    use crate::orderbook::book::{ExchangePriceList, OrderBook};
+    use crate::{
+        api::event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        inventory::InventoryTracker,
+        orderbook::book::{Exchange, OrderBook},
+        util::QUANTITY_SCALE,
+    };
 
     #[test]
     fn test_add_exchange_price_level_different_exchanges() {
         let order_book = OrderBook::new("BTC/USD".to_string());
 
         // Add same price to different exchanges - should be separate
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Binance, Side::Buy, 10);
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Coinbase, Side::Buy, 20);
+        order_book.add_exchange_price_level(50000, Exchange::Binance, Side::Buy, 10);
+        order_book.add_exchange_price_level(50000, Exchange::Coinbase, Side::Buy, 20);
 
-        let binance_key = (50000, ExchangePriceList::Binance);
-        let coinbase_key = (50000, ExchangePriceList::Coinbase);
+        let binance_key = (50000, Exchange::Binance);
+        let coinbase_key = (50000, Exchange::Coinbase);
 
         assert!(order_book
             .exchange_bids_price_level
@@ -216,9 +454,9 @@ mod test {
         let order_book = OrderBook::new("BTC/USD".to_string());
 
         // Add bid for Binance
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Binance, Side::Buy, 10);
+        order_book.add_exchange_price_level(50000, Exchange::Binance, Side::Buy, 10);
 
-        let key = (50000, ExchangePriceList::Binance);
+        let key = (50000, Exchange::Binance);
         assert!(order_book.exchange_bids_price_level.contains_key(&key));
 
         let price_level = order_book.exchange_bids_price_level.get(&key).unwrap();
@@ -230,9 +468,9 @@ mod test {
         let order_book = OrderBook::new("BTC/USD".to_string());
 
         // Add ask for Coinbase
-        order_book.add_exchange_price_level(50100, ExchangePriceList::Coinbase, Side::Sell, 5);
+        order_book.add_exchange_price_level(50100, Exchange::Coinbase, Side::Sell, 5);
 
-        let key = (50100, ExchangePriceList::Coinbase);
+        let key = (50100, Exchange::Coinbase);
         assert!(order_book.exchange_asks_price_level.contains_key(&key));
 
         let price_level = order_book.exchange_asks_price_level.get(&key).unwrap();
@@ -244,11 +482,11 @@ mod test {
         let order_book = OrderBook::new("BTC/USD".to_string());
 
         // Add same price level multiple times - quantities should accumulate
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Binance, Side::Buy, 10);
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Binance, Side::Buy, 5);
-        order_book.add_exchange_price_level(50000, ExchangePriceList::Binance, Side::Buy, 3);
+        order_book.add_exchange_price_level(50000, Exchange::Binance, Side::Buy, 10);
+        order_book.add_exchange_price_level(50000, Exchange::Binance, Side::Buy, 5);
+        order_book.add_exchange_price_level(50000, Exchange::Binance, Side::Buy, 3);
 
-        let key = (50000, ExchangePriceList::Binance);
+        let key = (50000, Exchange::Binance);
         let price_level = order_book.exchange_bids_price_level.get(&key).unwrap();
         assert_eq!(price_level.get(&50000), Some(&18)); // 10 + 5 + 3
     }
@@ -261,26 +499,119 @@ mod test {
         let (tx, mut rx) = channel::<u64>(1);
 
         let task = tokio::spawn(async move {
-            book_1.add_exchange_price_level(2000, ExchangePriceList::Binance, Side::Sell, 13);
-
-            let key = (2000, ExchangePriceList::Binance);
-            let price_level = book_1.exchange_asks_price_level.get(&key).unwrap();
-            let quantity = price_level.get(&2000).unwrap();
+            book_1.add_exchange_price_level(2000, Exchange::Binance, Side::Sell, 13);
 
+            // Give the second task time to add its quantity, and don't hold the
+            // DashMap guard across the await or the writer can never get in
             tokio::time::sleep(Duration::from_secs(1)).await;
 
-            tx.send(*quantity).await.unwrap();
+            let key = (2000, Exchange::Binance);
+            let quantity = *book_1
+                .exchange_asks_price_level
+                .get(&key)
+                .unwrap()
+                .get(&2000)
+                .unwrap();
+
+            tx.send(quantity).await.unwrap();
         });
 
         let task_2 = tokio::spawn(async move {
-            book_2.add_exchange_price_level(2000, ExchangePriceList::Binance, Side::Sell, 13);
+            book_2.add_exchange_price_level(2000, Exchange::Binance, Side::Sell, 13);
         });
 
-        tokio::join!(task, task_2);
+        let (result, result_2) = tokio::join!(task, task_2);
+        result.unwrap();
+        result_2.unwrap();
 
         while let Some(val) = rx.recv().await {
             // Quantities should accumulate: 13 + 13 = 26
             assert_eq!(val, 26);
         }
     }
+
+    #[test]
+    fn test_opportunity_capped_by_inventory() {
+        let inventory = Arc::new(InventoryTracker::new("BTC", "USD"));
+        inventory.set_balance(Exchange::Binance, "USD", 100_000);
+        inventory.set_balance(Exchange::Kraken, "BTC", 20 * QUANTITY_SCALE as i64);
+        let order_book = OrderBook::new("BTC/USD".to_string()).with_inventory(inventory);
+
+        // Binance offers at 10_000 while Kraken bids 10_010
+        let snapshot = |exchange: Exchange, bid: u64, ask: u64| MarketEvent {
+            levels: vec![
+                Level::new(Side::Buy, bid, 20 * QUANTITY_SCALE),
+                Level::new(Side::Sell, ask, 20 * QUANTITY_SCALE),
+            ],
+            ..MarketEvent::new(
+                exchange,
+                "BTC/USD",
+                MarketEventKind::Snapshot,
+                std::time::Instant::now(),
+            )
+        };
+        order_book.apply_market_event(&snapshot(Exchange::Binance, 9_990, 10_000));
+        order_book.apply_market_event(&snapshot(Exchange::Kraken, 10_010, 10_020));
+        assert_eq!(
+            order_book.best_ask_all_exchanges(),
+            Some((10_000, Exchange::Binance))
+        );
+
+        let opportunity = order_book
+            .check_for_immediate_purchase(
+                10_010,
+                Exchange::Kraken,
+                Side::Buy,
+                15 * QUANTITY_SCALE,
+            )
+            .unwrap();
+        assert_eq!(opportunity.buy_exchange, Exchange::Binance);
+        assert_eq!(opportunity.sell_exchange, Exchange::Kraken);
+        // 100_000 cents on Binance only buys 10 BTC at 10_000
+        assert_eq!(opportunity.quantity, 10 * QUANTITY_SCALE);
+    }
+
+    #[test]
+    fn test_apply_market_events() {
+        let order_book = OrderBook::new("BTC/USD".to_string());
+        let event = |kind: MarketEventKind, levels: Vec<Level>| MarketEvent {
+            levels,
+            ..MarketEvent::new(Exchange::Kraken, "BTC/USD", kind, std::time::Instant::now())
+        };
+
+        order_book.apply_market_event(&event(
+            MarketEventKind::Snapshot,
+            vec![
+                Level::new(Side::Buy, 99, 4),
+                Level::new(Side::Buy, 98, 6),
+                Level::new(Side::Sell, 101, 3),
+            ],
+        ));
+        assert_eq!(order_book.best_bid(Exchange::Kraken), Some(99));
+        assert_eq!(order_book.best_ask(Exchange::Kraken), Some(101));
+
+        // Removing the top bid exposes the next level
+        order_book.apply_market_event(&event(
+            MarketEventKind::LevelUpdate,
+            vec![Level::new(Side::Buy, 99, 0), Level::new(Side::Sell, 100, 2)],
+        ));
+        assert_eq!(order_book.best_bid(Exchange::Kraken), Some(98));
+        assert_eq!(order_book.best_ask(Exchange::Kraken), Some(100));
+
+        // A new snapshot replaces the venue's book rather than merging into it
+        order_book.apply_market_event(&event(
+            MarketEventKind::Snapshot,
+            vec![Level::new(Side::Sell, 105, 1)],
+        ));
+        assert_eq!(order_book.best_bid(Exchange::Kraken), None);
+        assert_eq!(order_book.best_ask(Exchange::Kraken), Some(105));
+        assert_eq!(order_book.exchange_asks_price_level.len(), 1);
+
+        order_book.apply_market_event(&event(
+            MarketEventKind::Status(FeedStatus::Disconnected),
+            Vec::new(),
+        ));
+        assert_eq!(order_book.best_ask(Exchange::Kraken), None);
+        assert!(order_book.exchange_asks_price_level.is_empty());
+    }
 }
@@ -0,0 +1,214 @@
+//! # Consolidated Depth
+//!
+//! Views of the order book beyond top of book:
+//! - A consolidated ladder per side merging every venue's levels, price → [(venue, qty)]
+//! - Depth walks that sweep the consolidated (or a single venue's) book for a size
+//! - `cost_to_buy`, `proceeds_to_sell` and `vwap_for_size` built on those walks
+//!
+//! Walks are what a marketable order of that size would pay if the book didn't
+//! move, so they are the basis for both routing and realistic arbitrage sizing.
+
+use std::collections::BTreeMap;
+
+use pricelevel::Side;
+
+use super::{
+    book::{Exchange, OrderBook},
+    ladder::PriceLadder,
+};
+use crate::util;
+
+/// Levels on one side of the consolidated book, keyed by price. Each price
+/// lists the venues quoting it and how much each shows.
+pub type ConsolidatedLadder = BTreeMap<u64, Vec<(Exchange, u64)>>;
+
+/// Result of sweeping one side of the book for a target size.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DepthWalk {
+    /// How much of the requested size the visible depth covers
+    pub filled_quantity: u64,
+    /// Cents for the levels consumed
+    pub notional: u64,
+    /// Quantity taken from each venue, in the order venues were first touched
+    pub allocations: Vec<(Exchange, u64)>,
+    /// Last (worst) price touched, 0 if nothing was taken
+    pub worst_price: u64,
+}
+
+impl DepthWalk {
+    pub fn is_complete(&self, quantity: u64) -> bool {
+        self.filled_quantity >= quantity
+    }
+
+    /// Volume-weighted price over what was filled, `None` if nothing was.
+    pub fn average_price(&self) -> Option<u64> {
+        util::unit_price(self.notional, self.filled_quantity)
+    }
+
+    fn take(&mut self, exchange: Exchange, price: u64, quantity: u64) {
+        self.filled_quantity += quantity;
+        self.notional += util::notional(quantity, price);
+        self.worst_price = price;
+        match self.allocations.iter_mut().find(|(e, _)| *e == exchange) {
+            Some((_, allocated)) => *allocated += quantity,
+            None => self.allocations.push((exchange, quantity)),
+        }
+    }
+}
+
+impl<L: PriceLadder> OrderBook<L> {
+    /// All venues' levels on `side` merged by price. Venues at the same price
+    /// are listed in `Exchange::ALL` order; empty levels and suppressed venues
+    /// are left out.
+    pub fn consolidated_ladder(&self, side: Side) -> ConsolidatedLadder {
+        let mut ladder = ConsolidatedLadder::new();
+        let suppressed: Vec<Exchange> = Exchange::ALL
+            .into_iter()
+            .filter(|&exchange| self.is_suppressed(exchange))
+            .collect();
+        self.price_levels(side)
+            .for_each_level(&mut |exchange, price, quantity| {
+                if !suppressed.contains(&exchange) {
+                    ladder.entry(price).or_default().push((exchange, quantity));
+                }
+            });
+        for venues in ladder.values_mut() {
+            venues.sort_by_key(|(exchange, _)| Exchange::ALL.iter().position(|e| e == exchange));
+        }
+        ladder
+    }
+
+    /// Sweeps the side an order on `side` would take from (asks for a buy,
+    /// bids for a sell), best price first, until `quantity` is covered or the
+    /// book runs out. `venue` restricts the walk to one exchange's depth.
+    pub fn walk_depth(&self, side: Side, quantity: u64, venue: Option<Exchange>) -> DepthWalk {
+        let mut levels: Vec<_> = self
+            .consolidated_ladder(side.opposite())
+            .into_iter()
+            .collect();
+        if side == Side::Sell {
+            levels.reverse();
+        }
+
+        let mut walk = DepthWalk::default();
+        for (price, venues) in levels {
+            for (exchange, available) in venues {
+                if venue.is_some_and(|v| v != exchange) {
+                    continue;
+                }
+                let remaining = quantity - walk.filled_quantity;
+                if remaining == 0 {
+                    return walk;
+                }
+                walk.take(exchange, price, available.min(remaining));
+            }
+        }
+        walk
+    }
+
+    /// Cents needed to buy `quantity` by sweeping the asks, or `None` if the
+    /// visible depth is too thin.
+    pub fn cost_to_buy(&self, quantity: u64, venue: Option<Exchange>) -> Option<u64> {
+        let walk = self.walk_depth(Side::Buy, quantity, venue);
+        walk.is_complete(quantity).then_some(walk.notional)
+    }
+
+    /// Cents received for selling `quantity` into the bids, or `None` if the
+    /// visible depth is too thin.
+    pub fn proceeds_to_sell(&self, quantity: u64, venue: Option<Exchange>) -> Option<u64> {
+        let walk = self.walk_depth(Side::Sell, quantity, venue);
+        walk.is_complete(quantity).then_some(walk.notional)
+    }
+
+    /// Average price an order on `side` for `quantity` would execute at, or
+    /// `None` if the visible depth is too thin.
+    pub fn vwap_for_size(&self, side: Side, quantity: u64, venue: Option<Exchange>) -> Option<u64> {
+        let walk = self.walk_depth(side, quantity, venue);
+        if walk.is_complete(quantity) {
+            walk.average_price()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pricelevel::Side;
+
+    use crate::{
+        orderbook::book::{Exchange, OrderBook},
+        util::QUANTITY_SCALE,
+    };
+
+    const BTC: u64 = QUANTITY_SCALE;
+
+    fn book() -> OrderBook {
+        let book = OrderBook::new("BTC/USD".to_string());
+        book.set_exchange_price_level(100, Exchange::Binance, Side::Sell, 5 * BTC);
+        book.set_exchange_price_level(100, Exchange::Kraken, Side::Sell, 3 * BTC);
+        book.set_exchange_price_level(101, Exchange::Coinbase, Side::Sell, 10 * BTC);
+        book.set_exchange_price_level(99, Exchange::Kraken, Side::Buy, 4 * BTC);
+        book.set_exchange_price_level(98, Exchange::Binance, Side::Buy, 6 * BTC);
+        book
+    }
+
+    #[test]
+    fn test_consolidated_ladder_merges_venues() {
+        let book = book();
+        let asks = book.consolidated_ladder(Side::Sell);
+        assert_eq!(
+            asks.get(&100),
+            Some(&vec![
+                (Exchange::Binance, 5 * BTC),
+                (Exchange::Kraken, 3 * BTC)
+            ])
+        );
+        assert_eq!(asks.get(&101), Some(&vec![(Exchange::Coinbase, 10 * BTC)]));
+
+        // Clearing a level removes it from the ladder
+        book.set_exchange_price_level(100, Exchange::Kraken, Side::Sell, 0);
+        assert_eq!(
+            book.consolidated_ladder(Side::Sell).get(&100),
+            Some(&vec![(Exchange::Binance, 5 * BTC)])
+        );
+    }
+
+    #[test]
+    fn test_walk_consolidated_depth() {
+        let book = book();
+
+        // 8 at 100 across two venues, then 2 at 101
+        let walk = book.walk_depth(Side::Buy, 10 * BTC, None);
+        assert_eq!(walk.notional, 8 * 100 + 2 * 101);
+        assert_eq!(walk.worst_price, 101);
+        assert_eq!(
+            walk.allocations,
+            vec![
+                (Exchange::Binance, 5 * BTC),
+                (Exchange::Kraken, 3 * BTC),
+                (Exchange::Coinbase, 2 * BTC)
+            ]
+        );
+        assert_eq!(book.cost_to_buy(10 * BTC, None), Some(1_002));
+        assert_eq!(book.vwap_for_size(Side::Buy, 10 * BTC, None), Some(100));
+
+        // Bids are walked from the highest price down
+        assert_eq!(book.proceeds_to_sell(6 * BTC, None), Some(4 * 99 + 2 * 98));
+        assert_eq!(book.proceeds_to_sell(11 * BTC, None), None);
+    }
+
+    #[test]
+    fn test_walk_single_venue_depth() {
+        let book = book();
+        assert_eq!(book.cost_to_buy(3 * BTC, Some(Exchange::Kraken)), Some(300));
+        assert_eq!(book.cost_to_buy(4 * BTC, Some(Exchange::Kraken)), None);
+        assert_eq!(
+            book.vwap_for_size(Side::Buy, 10 * BTC, Some(Exchange::Coinbase)),
+            Some(101)
+        );
+        let partial = book.walk_depth(Side::Sell, 10 * BTC, Some(Exchange::Binance));
+        assert_eq!(partial.filled_quantity, 6 * BTC);
+        assert!(!partial.is_complete(10 * BTC));
+    }
+}
@@ -0,0 +1,132 @@
+//! # Simulated Matching Engine
+//!
+//! This module defines a per-venue matching engine used for paper trading and
+//! execution testing. Each `MatchingEngine` models a single exchange's book:
+//! - Resting limit orders queued per price level in arrival order
+//! - Price-time priority matching with partial fills
+//! - An order index for cancels and amends via `OrderModification`
+//! - Transaction ID generation and last traded timestamp tracking
+//!
+//! The order entry operations themselves live in `modifications.rs`.
+
+use pricelevel::{OrderId, Side, UuidGenerator};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+use uuid::Uuid;
+
+use super::book::Exchange;
+
+/// A limit order resting in the simulated book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestingOrder {
+    pub id: OrderId,
+    pub price: u64,
+    /// Remaining (unfilled) quantity
+    pub quantity: u64,
+    pub side: Side,
+    /// Time the order joined its price level, in milliseconds
+    pub timestamp: u64,
+}
+
+/// Price levels for one side of the book. Each level is a FIFO queue so the
+/// oldest order at a price is always matched first.
+pub(crate) type Ladder = BTreeMap<u64, VecDeque<RestingOrder>>;
+
+#[derive(Default)]
+pub(crate) struct EngineState {
+    pub(crate) bids: Ladder,
+    pub(crate) asks: Ladder,
+    /// Order id → (side, price) so cancels and amends can find the level directly
+    pub(crate) orders: HashMap<OrderId, (Side, u64)>,
+}
+
+impl EngineState {
+    pub(crate) fn ladder_mut(&mut self, side: Side) -> &mut Ladder {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
+
+/// Simulated matching engine for a single venue and instrument.
+pub struct MatchingEngine {
+    /// The venue this engine simulates
+    pub exchange: Exchange,
+    /// The symbol or identifier for this book
+    pub symbol: String,
+
+    pub(crate) state: Mutex<EngineState>,
+
+    pub(crate) transaction_id_generator: UuidGenerator,
+
+    /// Timestamp of the last execution in milliseconds, 0 if nothing has traded
+    pub(crate) last_traded_at: AtomicU64,
+}
+
+impl MatchingEngine {
+    pub fn new(exchange: Exchange, symbol: impl Into<String>) -> Self {
+        Self {
+            exchange,
+            symbol: symbol.into(),
+            state: Mutex::new(EngineState::default()),
+            transaction_id_generator: UuidGenerator::new(Uuid::new_v4()),
+            last_traded_at: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, EngineState> {
+        // A panic while holding the lock cannot leave the ladders half-updated
+        // in a way later calls can't cope with, so recover from poisoning.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn best_bid(&self) -> Option<u64> {
+        self.lock().bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<u64> {
+        self.lock().asks.keys().next().copied()
+    }
+
+    /// Total resting quantity at `price` on `side`, 0 if the level is empty.
+    pub fn quantity_at(&self, side: Side, price: u64) -> u64 {
+        self.lock()
+            .ladder_mut(side)
+            .get(&price)
+            .map(|level| level.iter().map(|o| o.quantity).sum())
+            .unwrap_or(0)
+    }
+
+    /// Number of price levels with resting orders on `side`.
+    pub fn level_count(&self, side: Side) -> usize {
+        self.lock().ladder_mut(side).len()
+    }
+
+    /// Number of resting orders across both sides.
+    pub fn order_count(&self) -> usize {
+        self.lock().orders.len()
+    }
+
+    /// Returns the resting order with `order_id`, if it is still working.
+    pub fn order(&self, order_id: OrderId) -> Option<RestingOrder> {
+        let mut state = self.lock();
+        let (side, price) = *state.orders.get(&order_id)?;
+        state
+            .ladder_mut(side)
+            .get(&price)?
+            .iter()
+            .find(|o| o.id == order_id)
+            .copied()
+    }
+
+    /// Timestamp of the last execution in milliseconds, 0 if nothing has traded.
+    pub fn last_traded_at(&self) -> u64 {
+        self.last_traded_at.load(Ordering::Acquire)
+    }
+}
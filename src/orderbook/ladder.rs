@@ -0,0 +1,435 @@
+//! # Price Ladders
+//!
+//! Storage for one side of the book: the quantity each venue shows at each
+//! price. `OrderBook` is generic over the `PriceLadder` it uses:
+//! - `MapLadder`: a `DashMap` keyed by (price, venue), the original layout.
+//!   Simple and concurrent, but every update hashes and takes a shard lock,
+//!   and finding a venue's best price scans the whole side
+//! - `ArrayLadder`: per venue, a tick-indexed ring of quantities centred on
+//!   the touch, with a `BTreeMap` fallback for levels outside the window. An
+//!   update is an index into a contiguous array and the best price is cached,
+//!   so the hot path never hashes or chases pointers
+//!
+//! `benches/price_ladder.rs` compares the two.
+
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    sync::{Mutex, MutexGuard},
+};
+
+use dashmap::DashMap;
+use pricelevel::Side;
+
+use super::book::Exchange;
+
+/// Ticks an `ArrayLadder` holds in its window by default: ±20.48 at a one
+/// cent tick, which covers the levels that move with BTC's top of book.
+pub const DEFAULT_WINDOW: usize = 4096;
+
+/// One side of the book across every venue.
+pub trait PriceLadder: Send + Sync {
+    /// Replaces the quantity `exchange` shows at `price`; 0 removes the level.
+    fn set(&self, exchange: Exchange, price: u64, quantity: u64);
+
+    /// Adds to the quantity `exchange` shows at `price`.
+    fn add(&self, exchange: Exchange, price: u64, quantity: u64);
+
+    fn quantity(&self, exchange: Exchange, price: u64) -> u64;
+
+    /// The venue's best price on this side: its highest bid or lowest ask.
+    fn best(&self, exchange: Exchange) -> Option<u64>;
+
+    /// Removes every level the venue shows.
+    fn clear(&self, exchange: Exchange);
+
+    /// Visits every non-empty level as (venue, price, quantity), in no
+    /// particular order.
+    fn for_each_level(&self, f: &mut dyn FnMut(Exchange, u64, u64));
+}
+
+/// Whether `price` is better than `other` for a resting order on `side`.
+fn is_better(side: Side, price: u64, other: u64) -> bool {
+    match side {
+        Side::Buy => price > other,
+        Side::Sell => price < other,
+    }
+}
+
+/// The original ladder: price and venue map to that venue's levels at the
+/// price. Derefs to the map so it can be inspected directly.
+pub struct MapLadder {
+    side: Side,
+    levels: DashMap<(u64, Exchange), BTreeMap<u64, u64>>,
+}
+
+impl MapLadder {
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            levels: DashMap::new(),
+        }
+    }
+}
+
+impl Deref for MapLadder {
+    type Target = DashMap<(u64, Exchange), BTreeMap<u64, u64>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.levels
+    }
+}
+
+impl PriceLadder for MapLadder {
+    fn set(&self, exchange: Exchange, price: u64, quantity: u64) {
+        let key = (price, exchange);
+        if quantity == 0 {
+            self.levels.remove(&key);
+        } else {
+            self.levels.insert(key, BTreeMap::from([(price, quantity)]));
+        }
+    }
+
+    fn add(&self, exchange: Exchange, price: u64, quantity: u64) {
+        let mut level = self.levels.entry((price, exchange)).or_default();
+        *level.entry(price).or_insert(0) += quantity;
+    }
+
+    fn quantity(&self, exchange: Exchange, price: u64) -> u64 {
+        self.levels
+            .get(&(price, exchange))
+            .and_then(|level| level.get(&price).copied())
+            .unwrap_or(0)
+    }
+
+    fn best(&self, exchange: Exchange) -> Option<u64> {
+        let shown = self
+            .levels
+            .iter()
+            .filter(|entry| entry.key().1 == exchange && entry.value().values().any(|&q| q > 0))
+            .map(|entry| entry.key().0);
+        match self.side {
+            Side::Buy => shown.max(),
+            Side::Sell => shown.min(),
+        }
+    }
+
+    fn clear(&self, exchange: Exchange) {
+        self.levels.retain(|(_, e), _| *e != exchange);
+    }
+
+    fn for_each_level(&self, f: &mut dyn FnMut(Exchange, u64, u64)) {
+        for entry in self.levels.iter() {
+            for (&price, &quantity) in entry.value().iter().filter(|(_, &q)| q > 0) {
+                f(entry.key().1, price, quantity);
+            }
+        }
+    }
+}
+
+/// A tick-indexed ladder per venue. Each venue's window of `window` ticks is
+/// a ring: tick `t` lives in slot `t % window`, so recentring only moves the
+/// levels that enter or leave it. The window is centred on the venue's best
+/// price when its first level arrives and again whenever the best price moves
+/// outside it. Levels outside the window, or off the tick grid, are kept in a
+/// sorted fallback map.
+pub struct ArrayLadder {
+    venues: [Mutex<TickWindow>; Exchange::ALL.len()],
+}
+
+impl ArrayLadder {
+    /// A ladder for `side` with a `tick`-cent grid and `window` ticks per venue.
+    pub fn new(side: Side, tick: u64, window: usize) -> Self {
+        assert!(tick > 0 && window > 0, "tick and window must be non-zero");
+        Self {
+            venues: std::array::from_fn(|_| Mutex::new(TickWindow::new(side, tick, window))),
+        }
+    }
+
+    fn venue(&self, exchange: Exchange) -> MutexGuard<'_, TickWindow> {
+        let index = match exchange {
+            Exchange::Binance => 0,
+            Exchange::Coinbase => 1,
+            Exchange::Kraken => 2,
+        };
+        self.venues[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PriceLadder for ArrayLadder {
+    fn set(&self, exchange: Exchange, price: u64, quantity: u64) {
+        self.venue(exchange).set(price, quantity);
+    }
+
+    fn add(&self, exchange: Exchange, price: u64, quantity: u64) {
+        let mut venue = self.venue(exchange);
+        let total = venue.get(price) + quantity;
+        venue.set(price, total);
+    }
+
+    fn quantity(&self, exchange: Exchange, price: u64) -> u64 {
+        self.venue(exchange).get(price)
+    }
+
+    fn best(&self, exchange: Exchange) -> Option<u64> {
+        self.venue(exchange).best
+    }
+
+    fn clear(&self, exchange: Exchange) {
+        self.venue(exchange).clear();
+    }
+
+    fn for_each_level(&self, f: &mut dyn FnMut(Exchange, u64, u64)) {
+        for exchange in Exchange::ALL {
+            self.venue(exchange)
+                .for_each(&mut |price, quantity| f(exchange, price, quantity));
+        }
+    }
+}
+
+/// One venue's levels on one side.
+struct TickWindow {
+    side: Side,
+    tick: u64,
+    slots: Vec<u64>,
+    /// First tick the window covers, None until the venue shows a level
+    low: Option<u64>,
+    far: BTreeMap<u64, u64>,
+    best: Option<u64>,
+}
+
+impl TickWindow {
+    fn new(side: Side, tick: u64, window: usize) -> Self {
+        Self {
+            side,
+            tick,
+            slots: vec![0; window],
+            low: None,
+            far: BTreeMap::new(),
+            best: None,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.slots.len() as u64
+    }
+
+    /// Slot holding `price`, if it is on the grid and inside the window.
+    fn slot(&self, price: u64) -> Option<usize> {
+        let low = self.low?;
+        let tick = price / self.tick;
+        (price.is_multiple_of(self.tick) && tick >= low && tick < low + self.len())
+            .then(|| (tick % self.len()) as usize)
+    }
+
+    fn get(&self, price: u64) -> u64 {
+        match self.slot(price) {
+            Some(slot) => self.slots[slot],
+            None => self.far.get(&price).copied().unwrap_or(0),
+        }
+    }
+
+    fn set(&mut self, price: u64, quantity: u64) {
+        if self.low.is_none() && quantity > 0 {
+            self.recentre(price);
+        }
+        match self.slot(price) {
+            Some(slot) => self.slots[slot] = quantity,
+            None if quantity == 0 => {
+                self.far.remove(&price);
+            }
+            None => {
+                self.far.insert(price, quantity);
+            }
+        }
+
+        if quantity > 0 {
+            if self
+                .best
+                .is_none_or(|best| is_better(self.side, price, best))
+            {
+                self.best = Some(price);
+            }
+        } else if self.best == Some(price) {
+            self.best = self.next_best(price);
+        }
+        // Keep the window on the touch as the market moves
+        if let Some(best) = self.best {
+            if self.slot(best).is_none() && best.is_multiple_of(self.tick) {
+                self.recentre(best);
+            }
+        }
+    }
+
+    /// Best price strictly worse than `from`, after the level at `from` was
+    /// removed.
+    fn next_best(&self, from: u64) -> Option<u64> {
+        let far = match self.side {
+            Side::Buy => self.far.range(..from).next_back(),
+            Side::Sell => self.far.range(from + 1..).next(),
+        }
+        .map(|(&price, _)| price);
+        match (self.scan_window(from), far) {
+            (Some(window), Some(far)) if is_better(self.side, far, window) => Some(far),
+            (window, far) => window.or(far),
+        }
+    }
+
+    /// Nearest non-empty slot worse than `from`, walking away from the touch.
+    fn scan_window(&self, from: u64) -> Option<u64> {
+        let low = self.low?;
+        let high = low + self.len() - 1;
+        let non_empty = |tick: &u64| self.slots[(tick % self.len()) as usize] > 0;
+        let tick = match self.side {
+            Side::Buy => {
+                let start = from.checked_sub(1)? / self.tick;
+                (low..=start.min(high)).rev().find(non_empty)
+            }
+            Side::Sell => (low.max(from / self.tick + 1)..=high).find(non_empty),
+        };
+        tick.map(|tick| tick * self.tick)
+    }
+
+    /// Moves the window to centre on `price`. Levels leaving it go to the
+    /// fallback map and levels the new window covers come out of it.
+    fn recentre(&mut self, price: u64) {
+        let new_low = (price / self.tick).saturating_sub(self.len() / 2);
+        if self.low == Some(new_low) {
+            return;
+        }
+        if let Some(low) = self.low {
+            for tick in low..low + self.len() {
+                let slot = (tick % self.len()) as usize;
+                let quantity = std::mem::take(&mut self.slots[slot]);
+                if quantity > 0 {
+                    self.far.insert(tick * self.tick, quantity);
+                }
+            }
+        }
+        self.low = Some(new_low);
+
+        let covered = new_low * self.tick..(new_low + self.len()) * self.tick;
+        let entering: Vec<u64> = self
+            .far
+            .range(covered)
+            .map(|(&price, _)| price)
+            .filter(|price| price.is_multiple_of(self.tick))
+            .collect();
+        for price in entering {
+            if let (Some(quantity), Some(slot)) = (self.far.remove(&price), self.slot(price)) {
+                self.slots[slot] = quantity;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.slots.fill(0);
+        self.far.clear();
+        self.low = None;
+        self.best = None;
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(u64, u64)) {
+        if let Some(low) = self.low {
+            for tick in low..low + self.len() {
+                let quantity = self.slots[(tick % self.len()) as usize];
+                if quantity > 0 {
+                    f(tick * self.tick, quantity);
+                }
+            }
+        }
+        for (&price, &quantity) in &self.far {
+            f(price, quantity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pricelevel::Side;
+
+    use super::{ArrayLadder, MapLadder, PriceLadder};
+    use crate::orderbook::book::Exchange;
+
+    fn levels(ladder: &dyn PriceLadder, exchange: Exchange) -> Vec<(u64, u64)> {
+        let mut levels = Vec::new();
+        ladder.for_each_level(&mut |e, price, quantity| {
+            if e == exchange {
+                levels.push((price, quantity));
+            }
+        });
+        levels.sort_unstable();
+        levels
+    }
+
+    #[test]
+    fn test_ladders_agree() {
+        // A small window so updates regularly land outside it
+        let ladders: [(Box<dyn PriceLadder>, Box<dyn PriceLadder>); 2] = [
+            (
+                Box::new(MapLadder::new(Side::Buy)),
+                Box::new(ArrayLadder::new(Side::Buy, 1, 8)),
+            ),
+            (
+                Box::new(MapLadder::new(Side::Sell)),
+                Box::new(ArrayLadder::new(Side::Sell, 1, 8)),
+            ),
+        ];
+        for (map, array) in &ladders {
+            let mut seed = 7u64;
+            for _ in 0..2_000 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let exchange = Exchange::ALL[(seed >> 60) as usize % 3];
+                let price = 1_000 + (seed >> 33) % 40;
+                let quantity = (seed >> 20) % 4;
+                map.set(exchange, price, quantity);
+                array.set(exchange, price, quantity);
+                assert_eq!(map.best(exchange), array.best(exchange));
+                assert_eq!(
+                    map.quantity(exchange, price),
+                    array.quantity(exchange, price)
+                );
+            }
+            for exchange in Exchange::ALL {
+                assert_eq!(
+                    levels(map.as_ref(), exchange),
+                    levels(array.as_ref(), exchange)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_array_ladder_follows_the_touch() {
+        let bids = ArrayLadder::new(Side::Buy, 10, 4);
+        bids.set(Exchange::Kraken, 1_000, 1);
+        bids.set(Exchange::Kraken, 990, 2);
+        // Deep level and an off-grid price go to the fallback map
+        bids.set(Exchange::Kraken, 500, 3);
+        bids.set(Exchange::Kraken, 995, 4);
+        assert_eq!(bids.best(Exchange::Kraken), Some(1_000));
+
+        // The market rallies past the window, which recentres on the new touch
+        bids.set(Exchange::Kraken, 1_200, 5);
+        bids.add(Exchange::Kraken, 1_200, 1);
+        assert_eq!(bids.best(Exchange::Kraken), Some(1_200));
+        assert_eq!(bids.quantity(Exchange::Kraken, 1_200), 6);
+        assert_eq!(bids.quantity(Exchange::Kraken, 990), 2);
+
+        // Pulling levels walks the best down through the window and the fallback
+        bids.set(Exchange::Kraken, 1_200, 0);
+        assert_eq!(bids.best(Exchange::Kraken), Some(1_000));
+        bids.set(Exchange::Kraken, 1_000, 0);
+        assert_eq!(bids.best(Exchange::Kraken), Some(995));
+        bids.set(Exchange::Kraken, 995, 0);
+        bids.set(Exchange::Kraken, 990, 0);
+        assert_eq!(bids.best(Exchange::Kraken), Some(500));
+        assert_eq!(bids.best(Exchange::Binance), None);
+
+        bids.clear(Exchange::Kraken);
+        assert_eq!(bids.best(Exchange::Kraken), None);
+        assert_eq!(levels(&bids, Exchange::Kraken), Vec::new());
+    }
+}
@@ -4,7 +4,11 @@
 //! It includes:
 //! - Order book data structures and management
 //! - Order matching and execution logic
-//! - Price level management for bids and asks
+//! - Price level management for bids and asks, behind the `PriceLadder` trait
+//! - Consolidated multi-venue depth and depth-weighted pricing
+//! - The public trade tape and per-venue trade statistics
+//! - Per-venue book sync state, suppressing venues whose feed lost sync
+//! - Market-data sanity checks, quarantining venues that send bad data
 //! - Order modification and cancellation operations
 //! - Error handling for order book operations
 //!
@@ -16,9 +20,23 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use ::pricelevel::MatchResult;
 
 pub mod book;
+pub mod depth;
+pub mod engine;
+pub mod ladder;
 mod modifications;
+pub mod sync;
+pub mod tape;
+pub mod validation;
 
+pub use depth::{ConsolidatedLadder, DepthWalk};
+pub use engine::MatchingEngine;
+pub use ladder::{ArrayLadder, MapLadder, PriceLadder};
 pub use modifications::OrderModification;
+pub use sync::BookState;
+pub use tape::{Trade, TradeStats, TradeTape, VolumeTracker};
+pub use validation::{DataAlert, DataIssue, DataValidator, ValidationConfig};
+
+use book::FillType;
 
 #[derive(Debug)]
 pub enum FillResponse {
@@ -27,6 +45,29 @@ pub enum FillResponse {
     Error(anyhow::Error),
 }
 
+impl FillResponse {
+    /// The maker orders this fill completed, `None` for errors.
+    pub fn fill_type(&self) -> Option<FillType> {
+        match self {
+            FillResponse::Fill(result) => Some(FillType::Full(result.filled_order_ids.clone())),
+            FillResponse::PartialFill(result) => {
+                Some(FillType::Partial(result.filled_order_ids.clone()))
+            }
+            FillResponse::Error(_) => None,
+        }
+    }
+}
+
+impl From<anyhow::Result<MatchResult>> for FillResponse {
+    fn from(result: anyhow::Result<MatchResult>) -> Self {
+        match result {
+            Ok(result) if result.is_complete => FillResponse::Fill(result),
+            Ok(result) => FillResponse::PartialFill(result),
+            Err(e) => FillResponse::Error(e),
+        }
+    }
+}
+
 pub fn current_time_millis() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -34,13 +75,13 @@ pub fn current_time_millis() -> u64 {
         .as_millis() as u64
 }
 
-/* #[cfg(test)]
+#[cfg(test)]
 mod test {
-    use super::book::OrderBook;
+    use super::{book::Exchange, engine::MatchingEngine, FillResponse};
 
     #[test]
     fn test_partial_fill() {
-        let order_book = OrderBook::new("BTCUSD".to_string());
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTCUSD");
 
         let order_id = pricelevel::OrderId::default();
 
@@ -50,8 +91,29 @@ mod test {
             .add_to_limit_order(order_id, price, quantity, pricelevel::Side::Buy)
             .unwrap();
 
-        let _ = order_book
-            .submit_market_order(pricelevel::OrderId::default(), 5, pricelevel::Side::Sell)
-            .unwrap();
+        let response = FillResponse::from(order_book.submit_market_order(
+            pricelevel::OrderId::default(),
+            5,
+            pricelevel::Side::Sell,
+        ));
+        assert!(matches!(response, FillResponse::Fill(_)));
+        assert_eq!(
+            order_book.order(order_id).map(|o| o.quantity),
+            Some(5),
+            "Resting order should be partially filled"
+        );
+
+        let response = FillResponse::from(order_book.submit_market_order(
+            pricelevel::OrderId::default(),
+            8,
+            pricelevel::Side::Sell,
+        ));
+        match response {
+            FillResponse::PartialFill(result) => {
+                assert_eq!(result.executed_quantity(), 5);
+                assert_eq!(result.filled_order_ids, vec![order_id]);
+            }
+            other => panic!("expected partial fill, got {other:?}"),
+        }
     }
-} */
+}
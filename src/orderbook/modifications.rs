@@ -11,17 +11,18 @@
 //! The operations are designed for high-performance concurrent access and
 //! maintain order book integrity while processing orders in real-time.
 
-use crossbeam_queue::SegQueue;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::atomic::Ordering};
 use uuid::Uuid;
 
-use anyhow::{anyhow, Context, Result};
-use pricelevel::{OrderId, OrderType, OrderUpdate, PriceLevel, Side};
-use std::sync::atomic::Ordering;
-use tracing::{info, trace};
+use anyhow::{anyhow, bail, Result};
+use pricelevel::{MatchResult, OrderId, Side, TimeInForce, Transaction};
+use tracing::trace;
 
-use super::book::OrderBook;
+use super::{
+    current_time_millis,
+    engine::{EngineState, Ladder, MatchingEngine, RestingOrder},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OrderModification {
@@ -43,121 +44,480 @@ pub enum OrderModification {
     },
 }
 
-/* #[cfg(test)]
+impl OrderModification {
+    /// The engine order id this modification applies to
+    pub fn order_id(&self) -> OrderId {
+        let uuid = match self {
+            OrderModification::UpdatePrice { order_id, .. }
+            | OrderModification::UpdateQuantity { order_id, .. }
+            | OrderModification::UpdatePriceAndQuantity { order_id, .. }
+            | OrderModification::Cancel { order_id } => *order_id,
+        };
+        OrderId::from_uuid(uuid)
+    }
+}
+
+impl MatchingEngine {
+    /// Adds a good-till-cancel limit order. Any part of the order that crosses the
+    /// opposite side is executed immediately and the remainder rests on the book.
+    pub fn add_to_limit_order(
+        &self,
+        order_id: OrderId,
+        price: u64,
+        quantity: u64,
+        side: Side,
+    ) -> Result<OrderId> {
+        self.submit_limit_order(order_id, price, quantity, side, TimeInForce::Gtc)?;
+        Ok(order_id)
+    }
+
+    /// Submits a limit order with the given time in force.
+    ///
+    /// IOC orders execute what they can and drop the remainder, FOK orders only
+    /// execute if the full quantity is available at or better than `price`.
+    pub fn submit_limit_order(
+        &self,
+        order_id: OrderId,
+        price: u64,
+        quantity: u64,
+        side: Side,
+        time_in_force: TimeInForce,
+    ) -> Result<MatchResult> {
+        if price == 0 {
+            bail!("limit price must be non-zero");
+        }
+        if quantity == 0 {
+            bail!("order quantity must be non-zero");
+        }
+
+        let mut state = self.lock();
+        if state.orders.contains_key(&order_id) {
+            bail!("duplicate order id {order_id}");
+        }
+        if time_in_force == TimeInForce::Fok
+            && available_quantity(&state, side, Some(price), quantity) < quantity
+        {
+            return Ok(MatchResult::new(order_id, quantity));
+        }
+
+        Ok(self.enter_order(&mut state, order_id, price, quantity, side, time_in_force))
+    }
+
+    /// Submits a market order, sweeping the opposite side until `quantity` is
+    /// filled or the book runs out. Unfilled quantity is not rested.
+    pub fn submit_market_order(
+        &self,
+        order_id: OrderId,
+        quantity: u64,
+        side: Side,
+    ) -> Result<MatchResult> {
+        if quantity == 0 {
+            bail!("order quantity must be non-zero");
+        }
+
+        let mut state = self.lock();
+        if state.orders.contains_key(&order_id) {
+            bail!("duplicate order id {order_id}");
+        }
+
+        Ok(self.match_incoming(&mut state, order_id, quantity, side, None))
+    }
+
+    /// Cancels a resting order by id, returning what was left of it.
+    pub fn cancel_order(&self, order_id: OrderId) -> Result<RestingOrder> {
+        let order = remove_order(&mut self.lock(), order_id)
+            .ok_or_else(|| anyhow!("order {order_id} not found"))?;
+        trace!("[{:?}] cancelled {}", self.exchange, order_id);
+        Ok(order)
+    }
+
+    /// Applies a cancel or amend to a resting order.
+    ///
+    /// Quantity reductions keep the order's queue position. Quantity increases and
+    /// price changes re-queue it at the back of its (new) level, and a price change
+    /// that crosses the spread trades immediately; the resulting match is returned.
+    pub fn update_order(&self, modification: OrderModification) -> Result<Option<MatchResult>> {
+        let order_id = modification.order_id();
+        let mut state = self.lock();
+        let &(side, price) = state
+            .orders
+            .get(&order_id)
+            .ok_or_else(|| anyhow!("order {order_id} not found"))?;
+
+        let (new_price, new_quantity) = match modification {
+            OrderModification::Cancel { .. } => {
+                remove_order(&mut state, order_id);
+                trace!("[{:?}] cancelled {}", self.exchange, order_id);
+                return Ok(None);
+            }
+            OrderModification::UpdatePrice { new_price, .. } => (new_price, None),
+            OrderModification::UpdateQuantity { new_quantity, .. } => (price, Some(new_quantity)),
+            OrderModification::UpdatePriceAndQuantity {
+                new_price,
+                new_quantity,
+                ..
+            } => (new_price, Some(new_quantity)),
+        };
+        if new_price == 0 {
+            bail!("limit price must be non-zero");
+        }
+        if new_quantity == Some(0) {
+            bail!("order quantity must be non-zero, cancel the order instead");
+        }
+
+        if new_price == price {
+            let level = state
+                .ladder_mut(side)
+                .get_mut(&price)
+                .ok_or_else(|| anyhow!("order {order_id} missing from level {price}"))?;
+            let position = level
+                .iter()
+                .position(|o| o.id == order_id)
+                .ok_or_else(|| anyhow!("order {order_id} missing from level {price}"))?;
+            let new_quantity = new_quantity.unwrap_or(level[position].quantity);
+
+            if new_quantity <= level[position].quantity {
+                level[position].quantity = new_quantity;
+            } else if let Some(mut order) = level.remove(position) {
+                order.quantity = new_quantity;
+                order.timestamp = current_time_millis();
+                level.push_back(order);
+            }
+            return Ok(None);
+        }
+
+        let order = remove_order(&mut state, order_id)
+            .ok_or_else(|| anyhow!("order {order_id} missing from level {price}"))?;
+        let quantity = new_quantity.unwrap_or(order.quantity);
+        Ok(Some(self.enter_order(
+            &mut state,
+            order_id,
+            new_price,
+            quantity,
+            side,
+            TimeInForce::Gtc,
+        )))
+    }
+
+    /// Matches a limit order and rests any remainder unless it is immediate-only.
+    fn enter_order(
+        &self,
+        state: &mut EngineState,
+        order_id: OrderId,
+        price: u64,
+        quantity: u64,
+        side: Side,
+        time_in_force: TimeInForce,
+    ) -> MatchResult {
+        let result = self.match_incoming(state, order_id, quantity, side, Some(price));
+
+        if result.remaining_quantity > 0 && !time_in_force.is_immediate() {
+            state
+                .ladder_mut(side)
+                .entry(price)
+                .or_default()
+                .push_back(RestingOrder {
+                    id: order_id,
+                    price,
+                    quantity: result.remaining_quantity,
+                    side,
+                    timestamp: current_time_millis(),
+                });
+            state.orders.insert(order_id, (side, price));
+        }
+
+        result
+    }
+
+    /// Walks the opposite side best level first, oldest order first within a level,
+    /// until the incoming quantity is filled or the next level is through `limit`.
+    fn match_incoming(
+        &self,
+        state: &mut EngineState,
+        order_id: OrderId,
+        quantity: u64,
+        side: Side,
+        limit: Option<u64>,
+    ) -> MatchResult {
+        let mut result = MatchResult::new(order_id, quantity);
+        let mut remaining = quantity;
+        let mut filled = Vec::new();
+
+        while remaining > 0 {
+            let ladder = state.ladder_mut(side.opposite());
+            let entry = match side {
+                Side::Buy => ladder.first_entry(),
+                Side::Sell => ladder.last_entry(),
+            };
+            let Some(mut entry) = entry else {
+                break;
+            };
+            let level_price = *entry.key();
+            if limit.is_some_and(|limit| !crosses(side, limit, level_price)) {
+                break;
+            }
+
+            let level = entry.get_mut();
+            while remaining > 0 {
+                let Some(maker) = level.front_mut() else {
+                    break;
+                };
+                let fill = remaining.min(maker.quantity);
+                maker.quantity -= fill;
+                remaining -= fill;
+                result.add_transaction(Transaction::new(
+                    self.transaction_id_generator.next(),
+                    order_id,
+                    maker.id,
+                    level_price,
+                    fill,
+                    side,
+                ));
+                if maker.quantity == 0 {
+                    filled.push(maker.id);
+                    level.pop_front();
+                }
+            }
+            if level.is_empty() {
+                entry.remove();
+            }
+        }
+
+        for id in filled {
+            state.orders.remove(&id);
+            result.add_filled_order_id(id);
+        }
+        if !result.transactions.is_empty() {
+            self.last_traded_at
+                .store(current_time_millis(), Ordering::Release);
+            trace!(
+                "[{:?}] {} {} executed {} of {}",
+                self.exchange,
+                side,
+                order_id,
+                result.executed_quantity(),
+                quantity
+            );
+        }
+
+        result
+    }
+}
+
+/// Whether an incoming order on `side` limited at `limit` can trade at `level_price`.
+fn crosses(side: Side, limit: u64, level_price: u64) -> bool {
+    match side {
+        Side::Buy => level_price <= limit,
+        Side::Sell => level_price >= limit,
+    }
+}
+
+/// Opposite-side quantity available at or better than `limit`, counted up to `needed`.
+fn available_quantity(state: &EngineState, side: Side, limit: Option<u64>, needed: u64) -> u64 {
+    let levels: Box<dyn Iterator<Item = (&u64, &VecDeque<RestingOrder>)>> = match side {
+        Side::Buy => Box::new(state.asks.iter()),
+        Side::Sell => Box::new(state.bids.iter().rev()),
+    };
+    let mut available = 0;
+    for (price, level) in levels {
+        if limit.is_some_and(|limit| !crosses(side, limit, *price)) || available >= needed {
+            break;
+        }
+        available += level.iter().map(|o| o.quantity).sum::<u64>();
+    }
+    available
+}
+
+/// Removes an order from its level and the index, dropping the level if it empties.
+fn remove_order(state: &mut EngineState, order_id: OrderId) -> Option<RestingOrder> {
+    let (side, price) = state.orders.remove(&order_id)?;
+    let ladder: &mut Ladder = state.ladder_mut(side);
+    let level = ladder.get_mut(&price)?;
+    let position = level.iter().position(|o| o.id == order_id)?;
+    let order = level.remove(position);
+    if level.is_empty() {
+        ladder.remove(&price);
+    }
+    order
+}
+
+#[cfg(test)]
 mod test {
-    use pricelevel::OrderId;
+    use pricelevel::{OrderId, Side, TimeInForce};
+    use uuid::Uuid;
 
-    use crate::orderbook::book::OrderBook;
+    use crate::orderbook::{book::Exchange, engine::MatchingEngine, OrderModification};
 
     #[test]
     fn test_multiple_price_levels_market_order() {
-        let order_book = OrderBook::new("BTC/USD".to_string());
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTC/USD");
 
         // Add multiple price levels
         order_book
-            .add_to_limit_order(OrderId::default(), 100, 1, pricelevel::Side::Buy)
+            .add_to_limit_order(OrderId::default(), 100, 1, Side::Buy)
             .unwrap();
         order_book
-            .add_to_limit_order(OrderId::default(), 105, 1, pricelevel::Side::Buy)
+            .add_to_limit_order(OrderId::default(), 105, 1, Side::Buy)
             .unwrap();
         order_book
-            .add_to_limit_order(OrderId::default(), 110, 1, pricelevel::Side::Buy)
+            .add_to_limit_order(OrderId::default(), 110, 1, Side::Buy)
             .unwrap();
 
-        // Submit a market order that matches the best bid
+        // Submit a market order that sweeps the two best bids
         let match_result = order_book
-            .submit_market_order(OrderId::default(), 2, pricelevel::Side::Sell)
+            .submit_market_order(OrderId::default(), 2, Side::Sell)
             .unwrap();
 
-        assert_eq!(order_book.orders.len(), 1);
+        assert!(match_result.is_complete);
+        assert_eq!(match_result.executed_value(), 110 + 105);
+        assert_eq!(match_result.filled_order_ids.len(), 2);
+        assert_eq!(order_book.order_count(), 1);
+        assert_eq!(order_book.best_bid(), Some(100));
     }
+
     #[test]
     fn test_update_order() {
-        let order_book = OrderBook::new("BTC/USD".to_string());
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTC/USD");
 
-        // Add a limit order and get its ID
-        let order_id = order_book
-            .add_to_limit_order(OrderId::default(), 100, 1, pricelevel::Side::Buy)
+        // Add a limit order and keep its ID
+        let order_id = Uuid::new_v4();
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(order_id), 100, 1, Side::Buy)
             .unwrap();
 
         // Update the order's price
         order_book
-            .update_order(
-                pricelevel::OrderUpdate::UpdatePrice {
-                    order_id,
-                    new_price: 105,
-                },
+            .update_order(OrderModification::UpdatePrice {
                 order_id,
-            )
+                new_price: 105,
+            })
             .unwrap();
 
         // Verify the order was updated
-        assert!(
-            order_book.bids.get(&105).is_some(),
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 105),
+            1,
             "Order should be updated to new price"
         );
+        assert_eq!(order_book.quantity_at(Side::Buy, 100), 0);
 
         order_book
-            .update_order(
-                pricelevel::OrderUpdate::UpdateQuantity {
-                    order_id,
-                    new_quantity: 5,
-                },
+            .update_order(OrderModification::UpdateQuantity {
                 order_id,
-            )
+                new_quantity: 5,
+            })
             .unwrap();
 
-        let price_level = order_book.bids.get(&105);
-
-        assert!(
-            price_level.is_some_and(|x| x.value().total_quantity() == 5),
-            "Order should be updated to new price"
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 105),
+            5,
+            "Order quantity should be updated"
         );
 
         order_book
-            .update_order(
-                pricelevel::OrderUpdate::UpdatePriceAndQuantity {
-                    order_id,
-                    new_price: 200,
-                    new_quantity: 25,
-                },
+            .update_order(OrderModification::UpdatePriceAndQuantity {
                 order_id,
-            )
+                new_price: 200,
+                new_quantity: 25,
+            })
             .unwrap();
 
-        assert!(
-            order_book
-                .bids
-                .get(&200)
-                .is_some_and(|x| x.value().total_quantity() == 25),
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 200),
+            25,
             "Order quantity and price should be updated"
         );
 
         order_book
-            .update_order(pricelevel::OrderUpdate::Cancel { order_id }, order_id)
+            .update_order(OrderModification::Cancel { order_id })
             .unwrap();
 
-        assert!(
-            order_book.bids.get(&200).is_none(),
+        assert_eq!(
+            order_book.level_count(Side::Buy),
+            0,
             "Order should be removed after cancel update"
         );
 
-        assert!(
-            order_book.bids.is_empty(),
+        assert_eq!(
+            order_book.order_count(),
+            0,
             "Order should be removed from orders map"
         );
     }
 
+    #[test]
+    fn test_quantity_reduction_keeps_priority() {
+        let order_book = MatchingEngine::new(Exchange::Kraken, "BTC/USD");
+
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(first), 100, 5, Side::Sell)
+            .unwrap();
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(second), 100, 5, Side::Sell)
+            .unwrap();
+
+        order_book
+            .update_order(OrderModification::UpdateQuantity {
+                order_id: first,
+                new_quantity: 2,
+            })
+            .unwrap();
+
+        let match_result = order_book
+            .submit_market_order(OrderId::default(), 2, Side::Buy)
+            .unwrap();
+        assert_eq!(
+            match_result.filled_order_ids,
+            vec![OrderId::from_uuid(first)]
+        );
+
+        // Increasing quantity sends the order to the back of the queue
+        order_book
+            .update_order(OrderModification::UpdateQuantity {
+                order_id: second,
+                new_quantity: 8,
+            })
+            .unwrap();
+        assert_eq!(order_book.quantity_at(Side::Sell, 100), 8);
+    }
+
+    #[test]
+    fn test_amend_through_spread_executes() {
+        let order_book = MatchingEngine::new(Exchange::Coinbase, "BTC/USD");
+
+        order_book
+            .add_to_limit_order(OrderId::default(), 110, 3, Side::Sell)
+            .unwrap();
+        let order_id = Uuid::new_v4();
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(order_id), 100, 5, Side::Buy)
+            .unwrap();
+
+        let match_result = order_book
+            .update_order(OrderModification::UpdatePrice {
+                order_id,
+                new_price: 110,
+            })
+            .unwrap()
+            .expect("crossing amend should match");
+
+        assert_eq!(match_result.executed_quantity(), 3);
+        assert_eq!(order_book.quantity_at(Side::Buy, 110), 2);
+        assert_eq!(order_book.best_ask(), None);
+    }
+
     #[test]
     fn test_last_trade_execution_tracking() {
-        let order_book = OrderBook::new("BTC/USD".to_string());
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTC/USD");
 
         // Initially, last_traded_at should be 0
         assert_eq!(order_book.last_traded_at(), 0);
 
         // Add a limit order (buy)
         let _order_id_1 = order_book
-            .add_to_limit_order(OrderId::default(), 100, 5, pricelevel::Side::Buy)
+            .add_to_limit_order(OrderId::default(), 100, 5, Side::Buy)
             .unwrap();
 
         // No trade yet, last_traded_at should still be 0
@@ -165,7 +525,7 @@ mod test {
 
         // Add a matching limit order (sell) that should trigger a trade
         let _order_id_2 = order_book
-            .add_to_limit_order(OrderId::default(), 100, 3, pricelevel::Side::Sell)
+            .add_to_limit_order(OrderId::default(), 100, 3, Side::Sell)
             .unwrap();
 
         // Now we should have a trade execution timestamp
@@ -178,7 +538,7 @@ mod test {
         // Add another matching order to verify timestamp updates
         std::thread::sleep(std::time::Duration::from_millis(1)); // Small delay to ensure different timestamp
         let _order_id_3 = order_book
-            .add_to_limit_order(OrderId::default(), 100, 2, pricelevel::Side::Sell)
+            .add_to_limit_order(OrderId::default(), 100, 2, Side::Sell)
             .unwrap();
 
         let new_last_trade_time = order_book.last_traded_at();
@@ -189,13 +549,13 @@ mod test {
 
         // Add more liquidity for market order test
         let _order_id_4 = order_book
-            .add_to_limit_order(OrderId::default(), 99, 5, pricelevel::Side::Sell)
+            .add_to_limit_order(OrderId::default(), 99, 5, Side::Sell)
             .unwrap();
 
         // Test market order execution
         std::thread::sleep(std::time::Duration::from_millis(1));
         let _market_result = order_book
-            .submit_market_order(OrderId::default(), 1, pricelevel::Side::Buy)
+            .submit_market_order(OrderId::default(), 1, Side::Buy)
             .unwrap();
 
         let market_trade_time = order_book.last_traded_at();
@@ -207,57 +567,88 @@ mod test {
 
     #[test]
     fn test_order_book_cancel_order() {
-        let order_book = OrderBook::new("BTC/USD".to_string());
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTC/USD");
 
-        // Add a limit order and get its ID
-        let order_id_1 = order_book
-            .add_to_limit_order(OrderId::default(), 100, 1, pricelevel::Side::Buy)
+        // Add limit orders and keep their IDs
+        let order_id_1 = Uuid::new_v4();
+        let order_id_2 = Uuid::new_v4();
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(order_id_1), 100, 1, Side::Buy)
             .unwrap();
-        let order_id_2 = order_book
-            .add_to_limit_order(OrderId::default(), 105, 1, pricelevel::Side::Buy)
+        order_book
+            .add_to_limit_order(OrderId::from_uuid(order_id_2), 105, 1, Side::Buy)
             .unwrap();
 
-        assert!(
-            order_book.bids.contains_key(&100),
-            "Order should contain key 100 in bids"
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 100),
+            1,
+            "Order should rest at 100 in bids"
         );
 
         // Cancel the order
         order_book
-            .update_order(
-                pricelevel::OrderUpdate::Cancel {
-                    order_id: order_id_1,
-                },
-                order_id_1,
-            )
+            .update_order(OrderModification::Cancel {
+                order_id: order_id_1,
+            })
             .unwrap();
 
-        assert!(
-            !order_book.bids.contains_key(&100),
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 100),
+            0,
             "Order should be removed from bids"
         );
 
-        assert!(
-            order_book.bids.contains_key(&105),
-            "Order should contain key 105 in bids"
+        assert_eq!(
+            order_book.quantity_at(Side::Buy, 105),
+            1,
+            "Order should rest at 105 in bids"
         );
         order_book
-            .update_order(
-                pricelevel::OrderUpdate::Cancel {
-                    order_id: order_id_2,
-                },
-                order_id_2,
-            )
+            .update_order(OrderModification::Cancel {
+                order_id: order_id_2,
+            })
             .unwrap();
 
-        assert!(
-            order_book.bids.is_empty(),
+        assert_eq!(
+            order_book.level_count(Side::Buy),
+            0,
             "Bids should be empty after cancellation"
         );
 
-        assert!(
-            order_book.orders.is_empty(),
+        assert_eq!(
+            order_book.order_count(),
+            0,
             "Order should be removed from orders map"
         );
+
+        // Cancelling twice is an error
+        assert!(order_book
+            .update_order(OrderModification::Cancel {
+                order_id: order_id_2,
+            })
+            .is_err());
     }
-} */
+
+    #[test]
+    fn test_immediate_time_in_force() {
+        let order_book = MatchingEngine::new(Exchange::Binance, "BTC/USD");
+        order_book
+            .add_to_limit_order(OrderId::default(), 100, 4, Side::Sell)
+            .unwrap();
+
+        // FOK for more than is available does nothing
+        let fok = order_book
+            .submit_limit_order(OrderId::default(), 100, 5, Side::Buy, TimeInForce::Fok)
+            .unwrap();
+        assert_eq!(fok.executed_quantity(), 0);
+        assert_eq!(order_book.quantity_at(Side::Sell, 100), 4);
+
+        // IOC fills what it can and never rests
+        let ioc = order_book
+            .submit_limit_order(OrderId::default(), 100, 5, Side::Buy, TimeInForce::Ioc)
+            .unwrap();
+        assert_eq!(ioc.executed_quantity(), 4);
+        assert_eq!(ioc.remaining_quantity, 1);
+        assert_eq!(order_book.order_count(), 0);
+    }
+}
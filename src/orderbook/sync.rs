@@ -0,0 +1,190 @@
+//! # Book Sync State
+//!
+//! Per-venue state machine for books built from market-data events:
+//! - `Syncing`: subscribed and waiting for the first snapshot
+//! - `Live`: a snapshot was applied and every update since arrived in order
+//! - `OutOfSync`: the feed dropped or the adapter saw a gap; the venue's
+//!   levels were discarded and it waits for a fresh snapshot
+//!
+//! Level updates and BBOs are ignored unless the venue is live, and a venue
+//! that is syncing or out of sync is left out of arbitrage checks and
+//! consolidated depth, as is one quarantined by validation. Venues fed
+//! directly through `set_exchange_price_level` (paper trading, tests) have no
+//! sync state.
+
+use tracing::{info, warn};
+
+use super::{
+    book::{Exchange, OrderBook},
+    ladder::PriceLadder,
+};
+use crate::api::event::FeedStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookState {
+    Syncing,
+    Live,
+    OutOfSync,
+}
+
+impl<L: PriceLadder> OrderBook<L> {
+    /// Sync state of a venue's book, None if it was never fed market events.
+    pub fn book_state(&self, exchange: Exchange) -> Option<BookState> {
+        self.sync_states.get(&exchange).map(|state| *state)
+    }
+
+    /// Whether the venue's book is being rebuilt and must wait for a snapshot.
+    pub fn awaiting_snapshot(&self, exchange: Exchange) -> bool {
+        self.book_state(exchange)
+            .is_some_and(|state| state != BookState::Live)
+    }
+
+    /// Whether the venue must not be traded on: its book is being rebuilt or
+    /// its data is quarantined.
+    pub fn is_suppressed(&self, exchange: Exchange) -> bool {
+        self.awaiting_snapshot(exchange) || self.validator.is_quarantined(exchange)
+    }
+
+    pub(super) fn set_book_state(&self, exchange: Exchange, state: BookState) {
+        let previous = self.sync_states.insert(exchange, state);
+        if previous != Some(state) {
+            info!("[Sync] {:?} book {:?} -> {:?}", exchange, previous, state);
+        }
+    }
+
+    /// Moves a venue's book through the state machine on a feed status change.
+    pub(super) fn apply_feed_status(&self, exchange: Exchange, status: FeedStatus) {
+        match status {
+            FeedStatus::Subscribed => self.set_book_state(exchange, BookState::Syncing),
+            FeedStatus::Disconnected | FeedStatus::Resyncing => {
+                if status == FeedStatus::Resyncing {
+                    warn!("[Sync] {:?} feed lost sync, discarding its book", exchange);
+                }
+                self.clear_exchange(exchange);
+                self.validator.reset(exchange);
+                self.set_book_state(exchange, BookState::OutOfSync);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use pricelevel::Side;
+
+    use super::BookState;
+    use crate::{
+        api::event::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        orderbook::book::{Exchange, OrderBook},
+    };
+
+    fn event(kind: MarketEventKind, levels: Vec<Level>) -> MarketEvent {
+        venue_event(Exchange::Binance, kind, levels)
+    }
+
+    fn venue_event(exchange: Exchange, kind: MarketEventKind, levels: Vec<Level>) -> MarketEvent {
+        MarketEvent {
+            levels,
+            ..MarketEvent::new(exchange, "BTC/USDT", kind, Instant::now())
+        }
+    }
+
+    #[test]
+    fn test_book_state_machine() {
+        let book = OrderBook::new("BTC/USDT".to_string());
+        assert_eq!(book.book_state(Exchange::Binance), None);
+        assert!(!book.is_suppressed(Exchange::Binance));
+
+        book.apply_market_event(&event(
+            MarketEventKind::Status(FeedStatus::Subscribed),
+            Vec::new(),
+        ));
+        assert_eq!(book.book_state(Exchange::Binance), Some(BookState::Syncing));
+
+        // Updates before the snapshot can't be placed and are dropped
+        book.apply_market_event(&event(
+            MarketEventKind::LevelUpdate,
+            vec![Level::new(Side::Buy, 99, 1)],
+        ));
+        assert_eq!(book.best_bid(Exchange::Binance), None);
+        assert!(book.is_suppressed(Exchange::Binance));
+
+        book.apply_market_event(&event(
+            MarketEventKind::Snapshot,
+            vec![Level::new(Side::Buy, 100, 1)],
+        ));
+        assert_eq!(book.book_state(Exchange::Binance), Some(BookState::Live));
+        assert_eq!(book.best_bid(Exchange::Binance), Some(100));
+
+        book.apply_market_event(&event(
+            MarketEventKind::Status(FeedStatus::Resyncing),
+            Vec::new(),
+        ));
+        assert_eq!(
+            book.book_state(Exchange::Binance),
+            Some(BookState::OutOfSync)
+        );
+        assert_eq!(book.best_bid(Exchange::Binance), None);
+    }
+
+    #[test]
+    fn test_suppressed_venue_skipped_for_arbitrage() {
+        let book = OrderBook::new("BTC/USDT".to_string());
+        book.apply_market_event(&event(
+            MarketEventKind::Snapshot,
+            vec![
+                Level::new(Side::Buy, 9_999, 1),
+                Level::new(Side::Sell, 10_000, 1),
+            ],
+        ));
+        book.apply_market_event(&venue_event(
+            Exchange::Kraken,
+            MarketEventKind::Snapshot,
+            vec![
+                Level::new(Side::Buy, 10_001, 1),
+                Level::new(Side::Sell, 10_002, 1),
+            ],
+        ));
+        assert_eq!(
+            book.best_bid_all_exchanges(),
+            Some((10_001, Exchange::Kraken))
+        );
+        assert!(book
+            .check_for_immediate_purchase(10_001, Exchange::Kraken, Side::Buy, 1)
+            .is_some());
+
+        // Binance losing sync takes its ask out of the cross-venue best
+        book.apply_market_event(&event(
+            MarketEventKind::Status(FeedStatus::Resyncing),
+            Vec::new(),
+        ));
+        assert_eq!(
+            book.best_ask_all_exchanges(),
+            Some((10_002, Exchange::Kraken))
+        );
+        assert!(book
+            .check_for_immediate_purchase(10_001, Exchange::Kraken, Side::Buy, 1)
+            .is_none());
+
+        // Back live, but now the bidding venue itself drops
+        book.apply_market_event(&event(
+            MarketEventKind::Snapshot,
+            vec![
+                Level::new(Side::Buy, 9_999, 1),
+                Level::new(Side::Sell, 10_000, 1),
+            ],
+        ));
+
+        book.apply_market_event(&MarketEvent::status(
+            Exchange::Kraken,
+            "BTC/USD",
+            FeedStatus::Disconnected,
+            Instant::now(),
+        ));
+        assert!(book
+            .check_for_immediate_purchase(10_001, Exchange::Kraken, Side::Buy, 1)
+            .is_none());
+    }
+}
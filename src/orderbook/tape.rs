@@ -0,0 +1,262 @@
+//! # Trade Tape
+//!
+//! Public trades from every venue, normalized into `Trade`:
+//! - A rolling in-memory tape of the most recent trades per venue
+//! - Running per-venue statistics: trade count, volume split by aggressor
+//!   side, notional and the last trade
+//! - Optionally, a `VolumeTracker` of volume since it was last taken
+//!
+//! Sizes are in order quantity units and prices in cents, so notional uses
+//! the same scaling as `ExecutionReport::last_notional`.
+
+use std::{collections::VecDeque, sync::Mutex, time::Instant};
+
+use dashmap::DashMap;
+use pricelevel::Side;
+
+use super::{
+    book::{Exchange, OrderBook},
+    ladder::PriceLadder,
+};
+use crate::util;
+
+/// How many recent trades the tape keeps per venue.
+const TAPE_CAPACITY: usize = 1_000;
+
+/// One public trade as printed by a venue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trade {
+    pub exchange: Exchange,
+    pub price: u64,
+    pub quantity: u64,
+    /// Side of the order that took liquidity
+    pub aggressor: Side,
+    pub trade_id: String,
+    pub exchange_timestamp: Option<u64>,
+    pub received_at: Instant,
+}
+
+impl Trade {
+    pub fn notional(&self) -> u64 {
+        util::notional(self.quantity, self.price)
+    }
+}
+
+/// Running totals for one venue's trades.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TradeStats {
+    pub trade_count: u64,
+    /// Volume where the buyer was the aggressor
+    pub buy_volume: u64,
+    /// Volume where the seller was the aggressor
+    pub sell_volume: u64,
+    pub notional: u64,
+    pub last_trade: Option<Trade>,
+}
+
+impl TradeStats {
+    pub fn volume(&self) -> u64 {
+        self.buy_volume + self.sell_volume
+    }
+
+    /// Volume-weighted average trade price, `None` before the first trade.
+    pub fn vwap(&self) -> Option<u64> {
+        util::unit_price(self.notional, self.volume())
+    }
+
+    fn record(&mut self, trade: &Trade) {
+        self.trade_count += 1;
+        match trade.aggressor {
+            Side::Buy => self.buy_volume += trade.quantity,
+            Side::Sell => self.sell_volume += trade.quantity,
+        }
+        self.notional += trade.notional();
+        self.last_trade = Some(trade.clone());
+    }
+}
+
+/// Traded volume observed per venue since it was last taken. A book built
+/// `with_volume_tracker` adds every trade it records; VWAP takes it once per
+/// slice.
+#[derive(Default)]
+pub struct VolumeTracker {
+    volumes: DashMap<Exchange, u64>,
+}
+
+impl VolumeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, exchange: Exchange, quantity: u64) {
+        *self.volumes.entry(exchange).or_insert(0) += quantity;
+    }
+
+    /// Volume per venue since the previous call, resetting the counters.
+    pub fn take(&self) -> Vec<(Exchange, u64)> {
+        Exchange::ALL
+            .iter()
+            .filter_map(|&exchange| {
+                let volume = self
+                    .volumes
+                    .get_mut(&exchange)
+                    .map(|mut v| std::mem::take(&mut *v))?;
+                (volume > 0).then_some((exchange, volume))
+            })
+            .collect()
+    }
+}
+
+/// Recent trades and running statistics for every venue.
+#[derive(Default)]
+pub struct TradeTape {
+    recent: DashMap<Exchange, Mutex<VecDeque<Trade>>>,
+    stats: DashMap<Exchange, TradeStats>,
+}
+
+impl TradeTape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, trade: Trade) {
+        self.stats.entry(trade.exchange).or_default().record(&trade);
+
+        let tape = self.recent.entry(trade.exchange).or_default();
+        let mut tape = tape.lock().unwrap_or_else(|e| e.into_inner());
+        if tape.len() == TAPE_CAPACITY {
+            tape.pop_front();
+        }
+        tape.push_back(trade);
+    }
+
+    /// Up to `limit` most recent trades on `exchange`, newest first.
+    pub fn recent(&self, exchange: Exchange, limit: usize) -> Vec<Trade> {
+        let Some(tape) = self.recent.get(&exchange) else {
+            return Vec::new();
+        };
+        let tape = tape.lock().unwrap_or_else(|e| e.into_inner());
+        tape.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Volume on `exchange` among the taped trades received at or after `since`.
+    pub fn volume_since(&self, exchange: Exchange, since: Instant) -> u64 {
+        let Some(tape) = self.recent.get(&exchange) else {
+            return 0;
+        };
+        let tape = tape.lock().unwrap_or_else(|e| e.into_inner());
+        tape.iter()
+            .rev()
+            .take_while(|t| t.received_at >= since)
+            .map(|t| t.quantity)
+            .sum()
+    }
+
+    pub fn stats(&self, exchange: Exchange) -> TradeStats {
+        self.stats
+            .get(&exchange)
+            .map(|s| s.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl<L: PriceLadder> OrderBook<L> {
+    /// Adds a public trade to the tape and the venue's statistics, and to
+    /// the observed volume if tracked.
+    pub fn record_trade(&self, trade: Trade) {
+        if let Some(volumes) = &self.volumes {
+            volumes.record(trade.exchange, trade.quantity);
+        }
+        self.trades.record(trade);
+    }
+
+    pub fn trade_stats(&self, exchange: Exchange) -> TradeStats {
+        self.trades.stats(exchange)
+    }
+
+    pub fn last_trade(&self, exchange: Exchange) -> Option<Trade> {
+        self.trades.stats(exchange).last_trade
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use pricelevel::Side;
+
+    use super::{Trade, VolumeTracker};
+    use crate::{
+        api::event::{Level, MarketEvent, MarketEventKind},
+        orderbook::book::{Exchange, OrderBook},
+    };
+
+    fn trade(id: &str, price: u64, quantity: u64, aggressor: Side, received_at: Instant) -> Trade {
+        Trade {
+            exchange: Exchange::Kraken,
+            price,
+            quantity,
+            aggressor,
+            trade_id: id.to_string(),
+            exchange_timestamp: None,
+            received_at,
+        }
+    }
+
+    #[test]
+    fn test_trade_stats_and_tape() {
+        let book = OrderBook::new("BTC/USD".to_string());
+        let start = Instant::now();
+        book.record_trade(trade("1", 10_000, 100_000_000, Side::Buy, start));
+        book.record_trade(trade(
+            "2",
+            10_300,
+            50_000_000,
+            Side::Sell,
+            start + Duration::from_millis(5),
+        ));
+
+        let stats = book.trade_stats(Exchange::Kraken);
+        assert_eq!(stats.trade_count, 2);
+        assert_eq!(stats.buy_volume, 100_000_000);
+        assert_eq!(stats.sell_volume, 50_000_000);
+        assert_eq!(stats.notional, 10_000 + 5_150);
+        assert_eq!(stats.vwap(), Some(10_100));
+        assert_eq!(book.last_trade(Exchange::Kraken).unwrap().trade_id, "2");
+        assert_eq!(book.trade_stats(Exchange::Binance).volume(), 0);
+
+        let recent = book.trades.recent(Exchange::Kraken, 5);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].trade_id, "2");
+        assert_eq!(
+            book.trades
+                .volume_since(Exchange::Kraken, start + Duration::from_millis(1)),
+            50_000_000
+        );
+    }
+
+    #[test]
+    fn test_trade_events_feed_the_volume_tracker() {
+        let volumes = Arc::new(VolumeTracker::new());
+        let book = OrderBook::new("BTC/USD".to_string()).with_volume_tracker(Arc::clone(&volumes));
+        let trade = MarketEvent {
+            levels: vec![Level::new(Side::Sell, 10_000, 1_000)],
+            ..MarketEvent::new(
+                Exchange::Kraken,
+                "BTC/USD",
+                MarketEventKind::Trade {
+                    trade_id: "1".to_string(),
+                },
+                Instant::now(),
+            )
+        };
+        book.apply_market_event(&trade);
+        book.apply_market_event(&trade);
+
+        assert_eq!(volumes.take(), vec![(Exchange::Kraken, 2_000)]);
+        assert!(volumes.take().is_empty());
+    }
+}
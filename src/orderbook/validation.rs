@@ -0,0 +1,430 @@
+//! # Market Data Validation
+//!
+//! Sanity checks on every venue's data before it can drive trading:
+//! - Events with a zero price, or a zero size where one can't mean a removal
+//! - Exchange timestamps going backwards within one stream
+//! - The venue's own book crossed (bid above ask) or locked (bid equal to ask)
+//! - The venue's mid or trades outside a band around the other venues' mid
+//! - The venue's mid jumping absurdly far in a single event
+//!
+//! A venue that fails a check is quarantined: it stays out of arbitrage and
+//! consolidated depth until it has gone `quarantine` without another issue,
+//! and an alert is raised. Its book keeps updating so it can heal, unless a
+//! level update was rejected: the book is wrong without it, so the venue is
+//! marked out of sync and waits for a fresh snapshot.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{
+    book::{Exchange, OrderBook},
+    ladder::PriceLadder,
+};
+use crate::api::event::{MarketEvent, MarketEventKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// Furthest a venue's mid or trade may be from the other venues' mid, in basis points
+    pub price_band_bps: u64,
+    /// Largest move of a venue's own mid in one event, in basis points
+    pub max_jump_bps: u64,
+    /// How long a venue stays quarantined after its last issue
+    pub quarantine: Duration,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            price_band_bps: 200,
+            max_jump_bps: 200,
+            quarantine: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataIssue {
+    ZeroPrice,
+    ZeroSize { price: u64 },
+    TimestampRegression { previous: u64, current: u64 },
+    CrossedBook { bid: u64, ask: u64 },
+    LockedBook { price: u64 },
+    OutsideBand { price: u64, mid: u64, band_bps: u64 },
+    PriceJump { from: u64, to: u64 },
+}
+
+impl fmt::Display for DataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataIssue::ZeroPrice => write!(f, "level with zero price"),
+            DataIssue::ZeroSize { price } => write!(f, "zero size at {}", price),
+            DataIssue::TimestampRegression { previous, current } => write!(
+                f,
+                "timestamp went backwards from {}ms to {}ms",
+                previous, current
+            ),
+            DataIssue::CrossedBook { bid, ask } => {
+                write!(f, "book crossed: bid {} above ask {}", bid, ask)
+            }
+            DataIssue::LockedBook { price } => write!(f, "book locked at {}", price),
+            DataIssue::OutsideBand {
+                price,
+                mid,
+                band_bps,
+            } => write!(
+                f,
+                "price {} is more than {}bps from consolidated mid {}",
+                price, band_bps, mid
+            ),
+            DataIssue::PriceJump { from, to } => write!(f, "mid jumped from {} to {}", from, to),
+        }
+    }
+}
+
+/// A failed check on one venue's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataAlert {
+    pub exchange: Exchange,
+    pub issue: DataIssue,
+    pub detected_at: Instant,
+}
+
+/// Per-venue validation state: last timestamps and mids seen, and quarantines.
+pub struct DataValidator {
+    config: ValidationConfig,
+    /// Last exchange timestamp per venue and event kind, since each kind may
+    /// arrive on its own stream
+    last_timestamps: DashMap<(Exchange, &'static str), u64>,
+    last_mids: DashMap<Exchange, u64>,
+    quarantined_until: DashMap<Exchange, Instant>,
+    alerts: Option<mpsc::Sender<DataAlert>>,
+}
+
+impl Default for DataValidator {
+    fn default() -> Self {
+        Self::new(ValidationConfig::default())
+    }
+}
+
+impl DataValidator {
+    pub fn new(config: ValidationConfig) -> Self {
+        Self {
+            config,
+            last_timestamps: DashMap::new(),
+            last_mids: DashMap::new(),
+            quarantined_until: DashMap::new(),
+            alerts: None,
+        }
+    }
+
+    /// Sends every detected issue to `alerts` as well as logging it. Alerts
+    /// are dropped rather than blocking the feed if the receiver falls behind.
+    pub fn with_alerts(mut self, alerts: mpsc::Sender<DataAlert>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    pub fn config(&self) -> ValidationConfig {
+        self.config
+    }
+
+    pub fn is_quarantined(&self, exchange: Exchange) -> bool {
+        self.quarantined_until
+            .get(&exchange)
+            .is_some_and(|until| Instant::now() < *until)
+    }
+
+    /// Quarantines the venue (or extends its quarantine) and raises an alert.
+    pub fn quarantine(&self, exchange: Exchange, issue: DataIssue) {
+        warn!("[Validation] Quarantining {:?}: {}", exchange, issue);
+        let detected_at = Instant::now();
+        self.quarantined_until
+            .insert(exchange, detected_at + self.config.quarantine);
+        if let Some(alerts) = &self.alerts {
+            let alert = DataAlert {
+                exchange,
+                issue,
+                detected_at,
+            };
+            if alerts.try_send(alert).is_err() {
+                warn!("[Validation] Alert receiver full or closed, dropping alert");
+            }
+        }
+    }
+
+    /// Checks an event on its own, before it is applied. Events that fail
+    /// here are rejected.
+    pub fn screen(&self, event: &MarketEvent) -> Option<DataIssue> {
+        // A zero size only makes sense as a removal in a level update
+        let removals_allowed = event.kind == MarketEventKind::LevelUpdate;
+        for level in &event.levels {
+            if level.price == 0 {
+                return Some(DataIssue::ZeroPrice);
+            }
+            if level.quantity == 0 && !removals_allowed {
+                return Some(DataIssue::ZeroSize { price: level.price });
+            }
+        }
+
+        let current = event.exchange_timestamp?;
+        let key = (event.exchange, event.kind.name());
+        let previous = self.last_timestamps.insert(key, current);
+        match previous {
+            Some(previous) if current < previous => {
+                // Keep the newest timestamp as the watermark
+                self.last_timestamps.insert(key, previous);
+                Some(DataIssue::TimestampRegression { previous, current })
+            }
+            _ => None,
+        }
+    }
+
+    /// Forgets a venue's history once its feed restarts.
+    pub(super) fn reset(&self, exchange: Exchange) {
+        self.last_timestamps.retain(|(e, _), _| *e != exchange);
+        self.last_mids.remove(&exchange);
+    }
+
+    fn outside_band(&self, price: u64, mid: u64) -> Option<DataIssue> {
+        let band_bps = self.config.price_band_bps;
+        (deviation_bps(price, mid) > band_bps).then_some(DataIssue::OutsideBand {
+            price,
+            mid,
+            band_bps,
+        })
+    }
+}
+
+fn deviation_bps(price: u64, reference: u64) -> u64 {
+    if reference == 0 {
+        return 0;
+    }
+    price.abs_diff(reference) * 10_000 / reference
+}
+
+impl<L: PriceLadder> OrderBook<L> {
+    /// Mid of the other venues' top of book, skipping suppressed venues and
+    /// those without both sides.
+    pub fn reference_mid(&self, exclude: Exchange) -> Option<u64> {
+        let mids: Vec<u64> = Exchange::ALL
+            .into_iter()
+            .filter(|&e| e != exclude && !self.is_suppressed(e))
+            .filter_map(|e| Some((self.best_bid(e)? + self.best_ask(e)?) / 2))
+            .collect();
+        (!mids.is_empty()).then(|| mids.iter().sum::<u64>() / mids.len() as u64)
+    }
+
+    /// Runs the checks that need the book after `event` was applied.
+    pub(super) fn check_applied_event(&self, event: &MarketEvent) -> Option<DataIssue> {
+        let exchange = event.exchange;
+        if let Some(trade) = event.trade() {
+            let mid = self.reference_mid(exchange)?;
+            return self.validator.outside_band(trade.price, mid);
+        }
+        if !event.kind.updates_book() {
+            return None;
+        }
+
+        let (bid, ask) = (self.best_bid(exchange)?, self.best_ask(exchange)?);
+        if bid > ask {
+            return Some(DataIssue::CrossedBook { bid, ask });
+        }
+        if bid == ask {
+            return Some(DataIssue::LockedBook { price: bid });
+        }
+        let mid = (bid + ask) / 2;
+        let previous = self.validator.last_mids.insert(exchange, mid);
+        if let Some(from) = previous {
+            if deviation_bps(mid, from) > self.validator.config.max_jump_bps {
+                return Some(DataIssue::PriceJump { from, to: mid });
+            }
+        }
+        let reference = self.reference_mid(exchange)?;
+        self.validator.outside_band(mid, reference)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use pricelevel::Side;
+    use tokio::sync::mpsc;
+
+    use super::{DataIssue, DataValidator, ValidationConfig};
+    use crate::{
+        api::event::{Level, MarketEvent, MarketEventKind},
+        orderbook::{
+            book::{Exchange, OrderBook},
+            BookState,
+        },
+    };
+
+    fn event(exchange: Exchange, kind: MarketEventKind, levels: Vec<Level>) -> MarketEvent {
+        MarketEvent {
+            levels,
+            ..MarketEvent::new(exchange, "BTC/USD", kind, Instant::now())
+        }
+    }
+
+    fn snapshot(exchange: Exchange, bid: u64, ask: u64) -> MarketEvent {
+        event(
+            exchange,
+            MarketEventKind::Snapshot,
+            vec![
+                Level::new(Side::Buy, bid, 1),
+                Level::new(Side::Sell, ask, 1),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_screen_rejects_bad_events() {
+        let validator = DataValidator::default();
+        let zero_size = event(
+            Exchange::Kraken,
+            MarketEventKind::Bbo,
+            vec![Level::new(Side::Buy, 100, 0)],
+        );
+        assert_eq!(
+            validator.screen(&zero_size),
+            Some(DataIssue::ZeroSize { price: 100 })
+        );
+        // In a level update a zero size removes the level
+        let removal = MarketEvent {
+            kind: MarketEventKind::LevelUpdate,
+            ..zero_size
+        };
+        assert_eq!(validator.screen(&removal), None);
+
+        let mut stamped = snapshot(Exchange::Kraken, 100, 101);
+        stamped.exchange_timestamp = Some(2_000);
+        assert_eq!(validator.screen(&stamped), None);
+        stamped.exchange_timestamp = Some(1_500);
+        assert_eq!(
+            validator.screen(&stamped),
+            Some(DataIssue::TimestampRegression {
+                previous: 2_000,
+                current: 1_500
+            })
+        );
+        // Other event kinds keep their own watermark
+        let mut trade = event(
+            Exchange::Kraken,
+            MarketEventKind::Trade {
+                trade_id: "1".to_string(),
+            },
+            vec![Level::new(Side::Buy, 100, 1)],
+        );
+        trade.exchange_timestamp = Some(1_800);
+        assert_eq!(validator.screen(&trade), None);
+    }
+
+    #[tokio::test]
+    async fn test_crossed_book_quarantines_and_alerts() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let book = OrderBook::new("BTC/USD".to_string())
+            .with_validator(DataValidator::default().with_alerts(tx));
+
+        book.apply_market_event(&snapshot(Exchange::Coinbase, 10_100, 10_000));
+        assert!(book.is_suppressed(Exchange::Coinbase));
+        let alert = rx.recv().await.unwrap();
+        assert_eq!(alert.exchange, Exchange::Coinbase);
+        assert_eq!(
+            alert.issue,
+            DataIssue::CrossedBook {
+                bid: 10_100,
+                ask: 10_000
+            }
+        );
+        // The book itself is kept so it can heal
+        assert_eq!(book.best_bid(Exchange::Coinbase), Some(10_100));
+
+        book.apply_market_event(&snapshot(Exchange::Binance, 10_000, 10_000));
+        assert_eq!(
+            rx.recv().await.unwrap().issue,
+            DataIssue::LockedBook { price: 10_000 }
+        );
+    }
+
+    #[test]
+    fn test_band_and_jump_checks() {
+        let book = OrderBook::new("BTC/USD".to_string()).with_validator(DataValidator::new(
+            ValidationConfig {
+                price_band_bps: 100,
+                max_jump_bps: 50,
+                quarantine: Duration::from_millis(20),
+            },
+        ));
+        book.apply_market_event(&snapshot(Exchange::Binance, 9_999, 10_001));
+        book.apply_market_event(&snapshot(Exchange::Kraken, 10_009, 10_011));
+        assert!(!book.is_suppressed(Exchange::Kraken));
+
+        // 3% away from Binance's mid
+        book.apply_market_event(&snapshot(Exchange::Coinbase, 10_299, 10_301));
+        assert!(book.is_suppressed(Exchange::Coinbase));
+        assert!(book
+            .check_for_immediate_purchase(10_299, Exchange::Coinbase, Side::Buy, 1)
+            .is_none());
+        // Nor do its prices count as the best bid against the other venues
+        assert_eq!(
+            book.best_bid_all_exchanges(),
+            Some((10_009, Exchange::Kraken))
+        );
+        assert!(book
+            .check_for_immediate_purchase(10_011, Exchange::Kraken, Side::Sell, 1)
+            .is_none());
+
+        // Kraken's mid moving 1% in one update is an absurd jump
+        book.apply_market_event(&snapshot(Exchange::Kraken, 10_109, 10_111));
+        assert!(book.is_suppressed(Exchange::Kraken));
+
+        // Quarantine lifts once the venue has been clean for a while
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!book.is_suppressed(Exchange::Kraken));
+    }
+
+    #[test]
+    fn test_rejected_diff_forces_a_snapshot() {
+        let book = OrderBook::new("BTC/USD".to_string()).with_validator(DataValidator::new(
+            ValidationConfig {
+                quarantine: Duration::ZERO,
+                ..ValidationConfig::default()
+            },
+        ));
+        book.apply_market_event(&snapshot(Exchange::Kraken, 10_000, 10_001));
+        assert_eq!(book.book_state(Exchange::Kraken), Some(BookState::Live));
+
+        // Dropping the diff would leave 10_000 in the book
+        book.apply_market_event(&event(
+            Exchange::Kraken,
+            MarketEventKind::LevelUpdate,
+            vec![
+                Level::new(Side::Buy, 10_000, 0),
+                Level::new(Side::Buy, 0, 1),
+            ],
+        ));
+        assert_eq!(
+            book.book_state(Exchange::Kraken),
+            Some(BookState::OutOfSync)
+        );
+        assert_eq!(book.best_bid(Exchange::Kraken), None);
+
+        // Later diffs can't be placed until a snapshot arrives
+        book.apply_market_event(&event(
+            Exchange::Kraken,
+            MarketEventKind::LevelUpdate,
+            vec![Level::new(Side::Buy, 9_999, 1)],
+        ));
+        assert!(book.is_suppressed(Exchange::Kraken));
+        book.apply_market_event(&snapshot(Exchange::Kraken, 9_999, 10_001));
+        assert!(!book.is_suppressed(Exchange::Kraken));
+        assert_eq!(book.best_bid(Exchange::Kraken), Some(9_999));
+    }
+}
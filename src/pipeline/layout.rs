@@ -0,0 +1,169 @@
+//! # Thread Layout
+//!
+//! Which core each stage of the ring pipeline runs on:
+//! - one I/O thread per venue, running that venue's depth and trade feeds
+//! - the book thread, which can busy-poll its rings instead of backing off
+//! - the strategy runtime, the tokio runtime for everything else (execution,
+//!   private streams, logging, metrics)
+//!
+//! Written as `binance=2,kraken=3,coinbase=4,book=1,strategy=5-7,busy-poll`.
+//! A stage without a core is left to the OS scheduler. Pinning the latency
+//! sensitive stages away from the strategy cores keeps them off the cores
+//! that log and collect metrics.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use core_affinity::CoreId;
+use tokio::runtime::Runtime;
+use tracing::warn;
+
+use crate::orderbook::book::Exchange;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadLayout {
+    pub venue_cores: HashMap<Exchange, usize>,
+    pub book_core: Option<usize>,
+    /// Cores shared by the strategy runtime's workers, one worker per core
+    pub strategy_cores: Vec<usize>,
+    /// Spin on the rings while idle rather than backing off
+    pub busy_poll: bool,
+}
+
+impl ThreadLayout {
+    pub fn venue_core(&self, exchange: Exchange) -> Option<usize> {
+        self.venue_cores.get(&exchange).copied()
+    }
+
+    /// Checks every core exists and that no core is given to two stages.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let available: Vec<usize> = core_affinity::get_core_ids()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|core| core.id)
+            .collect();
+        let mut owners: HashMap<usize, String> = HashMap::new();
+        let stages = self
+            .venue_cores
+            .iter()
+            .map(|(exchange, core)| (format!("{exchange:?}"), *core))
+            .chain(self.book_core.map(|core| ("book".to_string(), core)))
+            .chain(
+                self.strategy_cores
+                    .iter()
+                    .map(|core| ("strategy".to_string(), *core)),
+            );
+        for (stage, core) in stages {
+            if !available.contains(&core) {
+                return Err(anyhow!("{stage} core {core} does not exist"));
+            }
+            if let Some(owner) = owners.insert(core, stage.clone()) {
+                return Err(anyhow!("core {core} given to both {owner} and {stage}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the strategy runtime, its workers pinned round-robin to the
+    /// strategy cores. Without strategy cores it is tokio's default runtime.
+    pub fn strategy_runtime(&self) -> std::io::Result<Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().thread_name("strategy");
+        if !self.strategy_cores.is_empty() {
+            let cores = Arc::new(self.strategy_cores.clone());
+            let next = AtomicUsize::new(0);
+            builder
+                .worker_threads(cores.len())
+                .on_thread_start(move || {
+                    let core = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+                    pin_current(core);
+                });
+        }
+        builder.build()
+    }
+}
+
+/// Pins the calling thread to `core`, returning whether the OS allowed it.
+pub fn pin_current(core: usize) -> bool {
+    let pinned = core_affinity::set_for_current(CoreId { id: core });
+    if !pinned {
+        warn!("[Pipeline] Could not pin thread to core {}", core);
+    }
+    pinned
+}
+
+impl FromStr for ThreadLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let mut layout = ThreadLayout::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry == "busy-poll" {
+                layout.busy_poll = true;
+                continue;
+            }
+            let (stage, cores) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected stage=core, got {entry}"))?;
+            let core = |value: &str| {
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("invalid core {value} for {stage}"))
+            };
+            match stage.trim() {
+                "book" => layout.book_core = Some(core(cores)?),
+                "strategy" => {
+                    layout.strategy_cores = match cores.split_once('-') {
+                        Some((first, last)) => (core(first)?..=core(last)?).collect(),
+                        None => vec![core(cores)?],
+                    }
+                }
+                venue => {
+                    let exchange = Exchange::ALL
+                        .into_iter()
+                        .find(|e| format!("{e:?}").eq_ignore_ascii_case(venue))
+                        .ok_or_else(|| anyhow!("unknown stage {venue}"))?;
+                    layout.venue_cores.insert(exchange, core(cores)?);
+                }
+            }
+        }
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ThreadLayout;
+    use crate::orderbook::book::Exchange;
+
+    #[test]
+    fn test_parse_thread_layout() {
+        let layout: ThreadLayout = "binance=2, kraken=3,book=1,strategy=5-7,busy-poll"
+            .parse()
+            .unwrap();
+        assert_eq!(layout.venue_core(Exchange::Binance), Some(2));
+        assert_eq!(layout.venue_core(Exchange::Kraken), Some(3));
+        assert_eq!(layout.venue_core(Exchange::Coinbase), None);
+        assert_eq!(layout.book_core, Some(1));
+        assert_eq!(layout.strategy_cores, vec![5, 6, 7]);
+        assert!(layout.busy_poll);
+
+        assert!("bitmex=1".parse::<ThreadLayout>().is_err());
+        assert!("book=one".parse::<ThreadLayout>().is_err());
+        assert!("book".parse::<ThreadLayout>().is_err());
+
+        // Two stages on one core defeats the point of pinning
+        let shared: ThreadLayout = "book=0,binance=0".parse().unwrap();
+        assert!(shared.validate().is_err());
+        let missing: ThreadLayout = "book=100000".parse().unwrap();
+        assert!(missing.validate().is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! # Metrics
+//!
+//! Counters the aggregator bumps as events go through `process_event`,
+//! served in the Prometheus text format by `serve`:
+//! - `aggregator_events_total{venue}`: events applied to the book
+//! - `aggregator_opportunities_total`: opportunities the book detected
+//! - `aggregator_actionable_opportunities_total`: those clearing the minimum
+//!   edge after fees
+
+use std::{
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{info, warn};
+
+use crate::orderbook::book::Exchange;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Indexed like `Exchange::ALL`
+    events: [AtomicU64; 3],
+    opportunities: AtomicU64,
+    actionable: AtomicU64,
+}
+
+fn venue_index(exchange: Exchange) -> usize {
+    Exchange::ALL
+        .iter()
+        .position(|&e| e == exchange)
+        .unwrap_or_default()
+}
+
+impl Metrics {
+    pub fn record_event(&self, exchange: Exchange) {
+        self.events[venue_index(exchange)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_opportunity(&self, actionable: bool) {
+        self.opportunities.fetch_add(1, Ordering::Relaxed);
+        if actionable {
+            self.actionable.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn events(&self, exchange: Exchange) -> u64 {
+        self.events[venue_index(exchange)].load(Ordering::Relaxed)
+    }
+
+    /// The counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# TYPE aggregator_events_total counter\n");
+        for exchange in Exchange::ALL {
+            let venue = format!("{exchange:?}").to_lowercase();
+            let _ = writeln!(
+                out,
+                "aggregator_events_total{{venue=\"{venue}\"}} {}",
+                self.events(exchange)
+            );
+        }
+        for (name, counter) in [
+            ("aggregator_opportunities_total", &self.opportunities),
+            (
+                "aggregator_actionable_opportunities_total",
+                &self.actionable,
+            ),
+        ] {
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+/// Answers every HTTP request on `addr` with the rendered metrics, whatever
+/// the path. Only returns if the listener can't be bound.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("[Metrics] Serving on http://{}/metrics", addr);
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("[Metrics] Accept failed: {}", e);
+                continue;
+            }
+        };
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            // The request itself doesn't matter, only that one arrived
+            let mut request = [0u8; 1024];
+            if stream.read(&mut request).await.is_err() {
+                return;
+            }
+            let body = metrics.render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.ok();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::Metrics;
+    use crate::orderbook::book::Exchange;
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_event(Exchange::Kraken);
+        metrics.record_event(Exchange::Kraken);
+        metrics.record_opportunity(true);
+        metrics.record_opportunity(false);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(super::serve(addr, Arc::clone(&metrics)));
+
+        let mut stream = loop {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("aggregator_events_total{venue=\"kraken\"} 2"));
+        assert!(response.contains("aggregator_events_total{venue=\"binance\"} 0"));
+        assert!(response.contains("aggregator_opportunities_total 2"));
+        assert!(response.contains("aggregator_actionable_opportunities_total 1"));
+    }
+}
@@ -0,0 +1,236 @@
+//! # Pipeline Module
+//!
+//! How decoded market events travel from the venue feeds to the book. Two
+//! modes are selectable at startup:
+//! - `Tokio`: feeds run as tasks on the shared runtime and send through the
+//!   `MarketDataBus`, drained by an aggregator task
+//! - `Ring`: every feed runs on its venue's I/O thread with a single-threaded
+//!   runtime and pushes into a preallocated SPSC ring; one dedicated thread
+//!   drains the rings and builds the book
+//!
+//! A `ThreadLayout` pins the ring pipeline's threads and the strategy runtime
+//! to cores. Both modes end in `process_event`; `benches/pipeline_latency.rs`
+//! compares their end-to-end latency. Events can also be written to a
+//! recording and replayed through `process_event` later, and `Metrics`
+//! counts what went through. `OpportunityTracker` turns the opportunities
+//! found on every tick into windows with a lifetime.
+
+use std::{future::Future, str::FromStr, thread, time::Duration};
+
+use anyhow::anyhow;
+use pricelevel::Side;
+use tracing::error;
+
+use crate::{
+    api::MarketEvent,
+    orderbook::{
+        book::{ArbitrageOpportunity, OrderBook},
+        ladder::PriceLadder,
+    },
+};
+
+pub mod layout;
+pub mod metrics;
+pub mod opportunities;
+pub mod recording;
+pub mod ring;
+
+pub use layout::ThreadLayout;
+pub use metrics::Metrics;
+pub use opportunities::{OpportunityEvent, OpportunityTracker, TrackedOpportunity};
+pub use recording::{Recorder, Replay};
+pub use ring::{Consumer, Producer};
+
+/// Empty polls the book thread spins through before it backs off.
+const SPIN_LIMIT: u32 = 1_000;
+/// How long the book thread sleeps between polls once backed off
+const IDLE_SLEEP: Duration = Duration::from_micros(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PipelineMode {
+    #[default]
+    Tokio,
+    Ring,
+}
+
+impl FromStr for PipelineMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> anyhow::Result<Self> {
+        match mode {
+            "tokio" => Ok(PipelineMode::Tokio),
+            "ring" | "spsc" => Ok(PipelineMode::Ring),
+            other => Err(anyhow!(
+                "unknown pipeline mode {other}, expected tokio or ring"
+            )),
+        }
+    }
+}
+
+/// Applies one event to the book and, if it moved the venue's book, checks
+/// the venue's new top of book against the other venues, returning the
+/// opportunities found.
+pub fn process_event<L: PriceLadder>(
+    orderbook: &OrderBook<L>,
+    event: &MarketEvent,
+) -> Vec<ArbitrageOpportunity> {
+    orderbook.apply_market_event(event);
+    if !event.kind.updates_book() {
+        return Vec::new();
+    }
+    let bid = orderbook
+        .best_bid(event.exchange)
+        .and_then(|bid| orderbook.check_for_immediate_purchase(bid, event.exchange, Side::Buy, 0));
+    let ask = orderbook
+        .best_ask(event.exchange)
+        .and_then(|ask| orderbook.check_for_immediate_purchase(ask, event.exchange, Side::Sell, 0));
+    bid.into_iter().chain(ask).collect()
+}
+
+/// The book-building end of the ring pipeline: one SPSC ring per feed.
+pub struct RingPipeline {
+    capacity: usize,
+    feeds: Vec<Consumer<MarketEvent>>,
+    busy_poll: bool,
+}
+
+impl RingPipeline {
+    /// A pipeline whose rings each hold up to `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            feeds: Vec::new(),
+            busy_poll: false,
+        }
+    }
+
+    /// Never back off while idle: the book thread keeps its core spinning so
+    /// the next event is picked up within a poll.
+    pub fn with_busy_poll(mut self, busy_poll: bool) -> Self {
+        self.busy_poll = busy_poll;
+        self
+    }
+
+    /// Adds a ring for one more feed, returning the end its decoder pushes into.
+    pub fn add_feed(&mut self) -> Producer<MarketEvent> {
+        let (producer, consumer) = ring::channel(self.capacity);
+        self.feeds.push(consumer);
+        producer
+    }
+
+    /// Drains the rings on the calling thread, handing each event to `handle`,
+    /// until every feed is gone and its ring is empty. Rings are polled
+    /// round-robin, one event each per pass, so a busy feed can't starve the
+    /// others. When idle the thread spins for a while and then sleeps between
+    /// polls, unless busy-polling.
+    pub fn run(mut self, mut handle: impl FnMut(MarketEvent)) {
+        let mut idle = 0;
+        while !self.feeds.is_empty() {
+            let mut busy = false;
+            for feed in &self.feeds {
+                if let Some(event) = feed.pop() {
+                    handle(event);
+                    busy = true;
+                }
+            }
+            if busy {
+                idle = 0;
+                continue;
+            }
+            // Disconnected first: a producer that pushed and then dropped is
+            // seen as non-empty
+            self.feeds
+                .retain(|feed| !(feed.is_disconnected() && feed.is_empty()));
+            idle += 1;
+            if self.busy_poll || idle < SPIN_LIMIT {
+                std::hint::spin_loop();
+            } else {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+    }
+}
+
+/// Runs a feed on a dedicated named thread with its own single-threaded
+/// runtime, so its websocket reads and decoding never share a core's
+/// scheduler with other venues. The thread is pinned to `core` if given.
+pub fn spawn_feed<F, Fut>(
+    name: &str,
+    core: Option<usize>,
+    feed: F,
+) -> std::io::Result<thread::JoinHandle<()>>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()>,
+{
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            if let Some(core) = core {
+                layout::pin_current(core);
+            }
+            match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime.block_on(feed()),
+                Err(e) => error!("[Pipeline] Failed to start feed runtime: {}", e),
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::{PipelineMode, RingPipeline};
+    use crate::{
+        api::{EventSink, MarketEvent, MarketEventKind},
+        orderbook::book::Exchange,
+    };
+
+    #[test]
+    fn test_ring_pipeline_drains_every_feed() {
+        let mut pipeline = RingPipeline::new(4);
+        let feeds: Vec<_> = Exchange::ALL
+            .into_iter()
+            .map(|exchange| {
+                let producer = pipeline.add_feed();
+                super::spawn_feed(&format!("feed-{exchange:?}"), None, move || async move {
+                    for sequence in 0..100 {
+                        let event = MarketEvent {
+                            sequence: Some(sequence),
+                            ..MarketEvent::new(
+                                exchange,
+                                "BTC/USDT",
+                                MarketEventKind::LevelUpdate,
+                                Instant::now(),
+                            )
+                        };
+                        producer.deliver(event).await.unwrap();
+                    }
+                })
+                .unwrap()
+            })
+            .collect();
+
+        // Returns once every feed thread has finished and its ring is drained
+        let mut received = Vec::new();
+        pipeline.run(|event| received.push((event.exchange, event.sequence)));
+        for feed in feeds {
+            feed.join().unwrap();
+        }
+
+        assert_eq!(received.len(), 300);
+        for exchange in Exchange::ALL {
+            let sequences: Vec<_> = received
+                .iter()
+                .filter(|(venue, _)| *venue == exchange)
+                .map(|(_, sequence)| sequence.unwrap())
+                .collect();
+            assert_eq!(sequences, (0..100).collect::<Vec<_>>());
+        }
+        assert_eq!("ring".parse::<PipelineMode>().unwrap(), PipelineMode::Ring);
+        assert!("fast".parse::<PipelineMode>().is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! # Opportunity Lifecycle
+//!
+//! A mispricing usually outlasts many book updates, and `process_event`
+//! reports it again on each one. `OpportunityTracker` follows it instead as
+//! a single window per venue pair and direction (buy on one venue, sell on
+//! the other):
+//! - `Opened` when the pair first qualifies
+//! - `Updated` when its prices, size or net edge change while open
+//! - `Closed` when it stops qualifying, with how long it lasted, its peak net
+//!   edge and its peak size
+//!
+//! After every event from a venue, each pair the venue is part of is checked
+//! against the book again, so a window closes on the update that removes it.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::orderbook::{
+    book::{ArbitrageOpportunity, Exchange, OrderBook},
+    ladder::PriceLadder,
+};
+
+/// One window during which a venue pair stayed mispriced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackedOpportunity {
+    /// Unique per tracker, in the order windows opened
+    pub id: u64,
+    pub opened_at: Instant,
+    /// The last time the window still qualified
+    pub last_seen: Instant,
+    pub closed_at: Option<Instant>,
+    /// Prices and size as last seen
+    pub current: ArbitrageOpportunity,
+    /// Net edge as last seen, in basis points
+    pub net_edge_bps: i64,
+    pub peak_edge_bps: i64,
+    pub peak_quantity: u64,
+    /// How many times the prices, size or edge changed while open
+    pub updates: u64,
+}
+
+impl TrackedOpportunity {
+    pub fn buy_exchange(&self) -> Exchange {
+        self.current.buy_exchange
+    }
+
+    pub fn sell_exchange(&self) -> Exchange {
+        self.current.sell_exchange
+    }
+
+    /// From opening to closing, or to the last time it qualified if still open.
+    pub fn duration(&self) -> Duration {
+        self.closed_at
+            .unwrap_or(self.last_seen)
+            .saturating_duration_since(self.opened_at)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpportunityEvent {
+    Opened(TrackedOpportunity),
+    Updated(TrackedOpportunity),
+    Closed(TrackedOpportunity),
+}
+
+impl OpportunityEvent {
+    pub fn opportunity(&self) -> &TrackedOpportunity {
+        match self {
+            Self::Opened(tracked) | Self::Updated(tracked) | Self::Closed(tracked) => tracked,
+        }
+    }
+}
+
+/// The open window of every venue pair and direction.
+#[derive(Debug, Default)]
+pub struct OpportunityTracker {
+    open: HashMap<(Exchange, Exchange), TrackedOpportunity>,
+    next_id: u64,
+}
+
+impl OpportunityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks both directions between `venue` and every other venue after
+    /// one of `venue`'s events, received `at`. `evaluate` returns the net
+    /// edge of an opportunity that qualifies (clears the minimum edge, isn't
+    /// priced off a stale quote) and None for one that doesn't.
+    pub fn on_event<L: PriceLadder>(
+        &mut self,
+        orderbook: &OrderBook<L>,
+        venue: Exchange,
+        at: Instant,
+        mut evaluate: impl FnMut(&ArbitrageOpportunity) -> Option<i64>,
+    ) -> Vec<OpportunityEvent> {
+        let mut events = Vec::new();
+        for other in Exchange::ALL.into_iter().filter(|&other| other != venue) {
+            for (buy, sell) in [(venue, other), (other, venue)] {
+                let qualifying = orderbook.check_pair(buy, sell).and_then(|opportunity| {
+                    evaluate(&opportunity).map(|net_edge_bps| (opportunity, net_edge_bps))
+                });
+                let event = match qualifying {
+                    Some((opportunity, net_edge_bps)) => {
+                        self.observe(opportunity, net_edge_bps, at)
+                    }
+                    None => self.close(buy, sell, at),
+                };
+                events.extend(event);
+            }
+        }
+        events
+    }
+
+    /// Opens a window for the opportunity's pair, or updates the open one.
+    /// Returns nothing if the open window is unchanged.
+    pub fn observe(
+        &mut self,
+        opportunity: ArbitrageOpportunity,
+        net_edge_bps: i64,
+        at: Instant,
+    ) -> Option<OpportunityEvent> {
+        let key = (opportunity.buy_exchange, opportunity.sell_exchange);
+        match self.open.get_mut(&key) {
+            Some(tracked) => {
+                tracked.last_seen = at;
+                if tracked.current == opportunity && tracked.net_edge_bps == net_edge_bps {
+                    return None;
+                }
+                tracked.current = opportunity;
+                tracked.net_edge_bps = net_edge_bps;
+                tracked.peak_edge_bps = tracked.peak_edge_bps.max(net_edge_bps);
+                tracked.peak_quantity = tracked.peak_quantity.max(opportunity.quantity);
+                tracked.updates += 1;
+                Some(OpportunityEvent::Updated(*tracked))
+            }
+            None => {
+                self.next_id += 1;
+                let tracked = TrackedOpportunity {
+                    id: self.next_id,
+                    opened_at: at,
+                    last_seen: at,
+                    closed_at: None,
+                    current: opportunity,
+                    net_edge_bps,
+                    peak_edge_bps: net_edge_bps,
+                    peak_quantity: opportunity.quantity,
+                    updates: 0,
+                };
+                self.open.insert(key, tracked);
+                Some(OpportunityEvent::Opened(tracked))
+            }
+        }
+    }
+
+    /// Closes the pair's window, if open.
+    pub fn close(
+        &mut self,
+        buy: Exchange,
+        sell: Exchange,
+        at: Instant,
+    ) -> Option<OpportunityEvent> {
+        let mut tracked = self.open.remove(&(buy, sell))?;
+        tracked.closed_at = Some(at);
+        Some(OpportunityEvent::Closed(tracked))
+    }
+
+    /// Closes every open window, e.g. when the feeds stop.
+    pub fn close_all(&mut self, at: Instant) -> Vec<OpportunityEvent> {
+        let mut open: Vec<_> = self.open.keys().copied().collect();
+        open.sort_by_key(|key| self.open[key].id);
+        open.into_iter()
+            .filter_map(|(buy, sell)| self.close(buy, sell, at))
+            .collect()
+    }
+
+    pub fn open(&self) -> impl Iterator<Item = &TrackedOpportunity> {
+        self.open.values()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use pricelevel::Side;
+
+    use super::{OpportunityEvent, OpportunityTracker};
+    use crate::{
+        api::{Level, MarketEvent, MarketEventKind},
+        orderbook::book::{ArbitrageOpportunity, Exchange, OrderBook},
+    };
+
+    fn quote(exchange: Exchange, bid: u64, ask: u64, at: Instant) -> MarketEvent {
+        MarketEvent {
+            levels: vec![
+                Level::new(Side::Buy, bid, 2_000),
+                Level::new(Side::Sell, ask, 3_000),
+            ],
+            ..MarketEvent::new(exchange, "BTC/USDT", MarketEventKind::Snapshot, at)
+        }
+    }
+
+    /// Net edge is the gross edge in bps, with no fees or minimum.
+    fn gross_bps(opportunity: &ArbitrageOpportunity) -> Option<i64> {
+        Some((opportunity.edge() * 10_000 / opportunity.buy_price) as i64)
+    }
+
+    #[test]
+    fn test_window_opens_updates_and_closes() {
+        let orderbook = OrderBook::new("BTC/USDT".to_string());
+        let mut tracker = OpportunityTracker::new();
+        let start = Instant::now();
+        let mut feed = |event: MarketEvent| {
+            orderbook.apply_market_event(&event);
+            tracker.on_event(&orderbook, event.exchange, event.received_at, gross_bps)
+        };
+
+        assert!(feed(quote(Exchange::Kraken, 99_900, 100_000, start)).is_empty());
+        // Binance bids 20 bps over Kraken's ask
+        let opened = feed(quote(
+            Exchange::Binance,
+            100_200,
+            100_300,
+            start + Duration::from_millis(1),
+        ));
+        let [OpportunityEvent::Opened(window)] = opened[..] else {
+            panic!("expected one opened window, got {opened:?}");
+        };
+        assert_eq!(window.buy_exchange(), Exchange::Kraken);
+        assert_eq!(window.sell_exchange(), Exchange::Binance);
+        assert_eq!(window.net_edge_bps, 20);
+        // The smaller of Kraken's ask size and Binance's bid size
+        assert_eq!(window.current.quantity, 2_000);
+
+        // The same quotes again change nothing, however often they arrive
+        for ms in 2..10 {
+            let at = start + Duration::from_millis(ms);
+            assert!(feed(quote(Exchange::Binance, 100_200, 100_300, at)).is_empty());
+        }
+
+        let widened = feed(quote(
+            Exchange::Binance,
+            100_400,
+            100_500,
+            start + Duration::from_millis(20),
+        ));
+        let [OpportunityEvent::Updated(window)] = widened[..] else {
+            panic!("expected one update, got {widened:?}");
+        };
+        assert_eq!(window.net_edge_bps, 40);
+        assert_eq!(window.updates, 1);
+
+        // Kraken's ask moving up through Binance's bid closes it
+        let closed = feed(quote(
+            Exchange::Kraken,
+            100_400,
+            100_450,
+            start + Duration::from_millis(50),
+        ));
+        let [OpportunityEvent::Closed(window)] = closed[..] else {
+            panic!("expected one closed window, got {closed:?}");
+        };
+        assert_eq!(window.peak_edge_bps, 40);
+        assert_eq!(window.duration(), Duration::from_millis(49));
+        assert_eq!(tracker.open().count(), 0);
+    }
+
+    #[test]
+    fn test_windows_are_per_pair_and_direction() {
+        let orderbook = OrderBook::new("BTC/USDT".to_string());
+        let mut tracker = OpportunityTracker::new();
+        let at = Instant::now();
+        for event in [
+            quote(Exchange::Kraken, 99_900, 100_000, at),
+            quote(Exchange::Coinbase, 99_950, 100_050, at),
+            quote(Exchange::Binance, 100_200, 100_300, at),
+        ] {
+            orderbook.apply_market_event(&event);
+            tracker.on_event(&orderbook, event.exchange, at, gross_bps);
+        }
+        // Binance's bid is over both other asks: two windows, not one per tick
+        let mut open: Vec<_> = tracker
+            .open()
+            .map(|window| (window.buy_exchange(), window.sell_exchange()))
+            .collect();
+        open.sort_by_key(|(buy, _)| format!("{buy:?}"));
+        assert_eq!(
+            open,
+            vec![
+                (Exchange::Coinbase, Exchange::Binance),
+                (Exchange::Kraken, Exchange::Binance)
+            ]
+        );
+
+        // A window the caller no longer accepts closes like a vanished one
+        let closed = tracker.on_event(&orderbook, Exchange::Binance, at, |_| None);
+        assert_eq!(closed.len(), 2);
+        assert!(tracker.close_all(at).is_empty());
+    }
+}
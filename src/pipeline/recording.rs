@@ -0,0 +1,204 @@
+//! # Recordings
+//!
+//! Market events written one JSON object per line, so a session captured
+//! live can be replayed through the book later. Each line holds the event and
+//! its offset from the start of the recording; `received_at` can't be
+//! carried over, so replayed events are stamped when they are read.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Lines, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{Level, MarketEvent, MarketEventKind},
+    orderbook::book::Exchange,
+};
+
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent {
+    /// Microseconds since the recording started
+    offset_us: u64,
+    exchange: Exchange,
+    instrument: String,
+    kind: MarketEventKind,
+    sequence: Option<u64>,
+    exchange_timestamp: Option<u64>,
+    levels: Vec<Level>,
+}
+
+/// Writes events to a recording.
+pub struct Recorder<W: Write> {
+    writer: W,
+    started: Instant,
+}
+
+impl Recorder<LineWriter<File>> {
+    /// Creates (or truncates) the file at `path`. Every event is flushed as
+    /// it is written, so a recording cut short by a crash stays readable.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("creating recording {}", path.display()))?;
+        Ok(Self::new(LineWriter::new(file)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, event: &MarketEvent) -> anyhow::Result<()> {
+        let recorded = RecordedEvent {
+            offset_us: event
+                .received_at
+                .saturating_duration_since(self.started)
+                .as_micros() as u64,
+            exchange: event.exchange,
+            instrument: event.instrument.clone(),
+            kind: event.kind.clone(),
+            sequence: event.sequence,
+            exchange_timestamp: event.exchange_timestamp,
+            levels: event.levels.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &recorded)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a recording back as each event with its offset from the start.
+pub struct Replay<R: BufRead> {
+    lines: Lines<R>,
+    line: usize,
+}
+
+impl Replay<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("opening recording {}", path.display()))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> Replay<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Replay<R> {
+    type Item = anyhow::Result<(Duration, MarketEvent)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let text = match self.lines.next()? {
+            Ok(text) => text,
+            Err(e) => return Some(Err(e.into())),
+        };
+        self.line += 1;
+        let recorded: RecordedEvent = match serde_json::from_str(&text) {
+            Ok(recorded) => recorded,
+            Err(e) => return Some(Err(anyhow!("line {}: {e}", self.line))),
+        };
+        let event = MarketEvent {
+            sequence: recorded.sequence,
+            exchange_timestamp: recorded.exchange_timestamp,
+            levels: recorded.levels,
+            ..MarketEvent::new(
+                recorded.exchange,
+                &recorded.instrument,
+                recorded.kind,
+                Instant::now(),
+            )
+        };
+        Some(Ok((Duration::from_micros(recorded.offset_us), event)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use pricelevel::Side;
+
+    use super::{Recorder, Replay};
+    use crate::{
+        api::{FeedStatus, Level, MarketEvent, MarketEventKind},
+        orderbook::book::Exchange,
+    };
+
+    #[test]
+    fn test_recording_round_trip() {
+        let mut recorder = Recorder::new(Vec::new());
+        let update = MarketEvent {
+            sequence: Some(42),
+            exchange_timestamp: Some(1_718_000_000_100),
+            levels: vec![
+                Level::new(Side::Buy, 6_499_999, 25_000_000),
+                Level::new(Side::Sell, 6_500_001, 0),
+            ],
+            ..MarketEvent::new(
+                Exchange::Binance,
+                "BTC/USDT",
+                MarketEventKind::LevelUpdate,
+                Instant::now() + Duration::from_millis(5),
+            )
+        };
+        let trade = MarketEvent {
+            levels: vec![Level::new(Side::Sell, 6_500_000, 1_000)],
+            ..MarketEvent::new(
+                Exchange::Kraken,
+                "BTC/USD",
+                MarketEventKind::Trade {
+                    trade_id: "t-1".to_string(),
+                },
+                Instant::now(),
+            )
+        };
+        let status = MarketEvent::status(
+            Exchange::Coinbase,
+            "BTC/USD",
+            FeedStatus::Resyncing,
+            Instant::now(),
+        );
+        for event in [&update, &trade, &status] {
+            recorder.record(event).unwrap();
+        }
+
+        let bytes = recorder.into_inner();
+        let replayed: Vec<_> = Replay::new(bytes.as_slice())
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert_eq!(replayed.len(), 3);
+        assert!(replayed[0].0 >= Duration::from_millis(5));
+        for ((_, replayed), original) in replayed.iter().zip([&update, &trade, &status]) {
+            assert_eq!(replayed.exchange, original.exchange);
+            assert_eq!(replayed.instrument, original.instrument);
+            assert_eq!(replayed.kind, original.kind);
+            assert_eq!(replayed.sequence, original.sequence);
+            assert_eq!(replayed.exchange_timestamp, original.exchange_timestamp);
+            assert_eq!(replayed.levels, original.levels);
+        }
+
+        let mut corrupt = Replay::new("{\"offset_us\":1}\n".as_bytes());
+        let error = corrupt.next().unwrap().unwrap_err();
+        assert!(error.to_string().starts_with("line 1"));
+    }
+}
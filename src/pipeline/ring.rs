@@ -0,0 +1,158 @@
+//! # SPSC Ring
+//!
+//! A bounded ring carrying events from one feed thread to the book thread.
+//! Slots are preallocated by crossbeam's lock-free `ArrayQueue`, so pushing
+//! and popping never allocate or take a lock. `Producer` and `Consumer` can't
+//! be cloned, which keeps each ring to a single writer and a single reader.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::anyhow;
+use crossbeam_queue::ArrayQueue;
+
+use crate::api::EventSink;
+
+struct Ring<T> {
+    slots: ArrayQueue<T>,
+    producer_alive: AtomicBool,
+    consumer_alive: AtomicBool,
+}
+
+/// A ring holding up to `capacity` events, split into its two ends.
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let ring = Arc::new(Ring {
+        slots: ArrayQueue::new(capacity),
+        producer_alive: AtomicBool::new(true),
+        consumer_alive: AtomicBool::new(true),
+    });
+    (
+        Producer {
+            ring: Arc::clone(&ring),
+        },
+        Consumer { ring },
+    )
+}
+
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> Producer<T> {
+    /// Pushes without waiting, handing the value back if the ring is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        self.ring.slots.push(value)
+    }
+
+    /// Whether the consumer has been dropped.
+    pub fn is_disconnected(&self) -> bool {
+        !self.ring.consumer_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.ring.producer_alive.store(false, Ordering::Release);
+    }
+}
+
+/// A full ring stalls the feed until the book thread catches up. While it
+/// waits the feed yields its core, in case the book thread shares it, and
+/// lets the feed's other tasks (pings, snapshot fetches) run.
+impl<T: Send> EventSink<T> for Producer<T> {
+    async fn deliver(&self, event: T) -> anyhow::Result<()> {
+        let mut event = event;
+        while let Err(rejected) = self.push(event) {
+            if self.is_disconnected() {
+                return Err(anyhow!("ring consumer dropped"));
+            }
+            event = rejected;
+            std::thread::yield_now();
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    }
+}
+
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> Consumer<T> {
+    pub fn pop(&self) -> Option<T> {
+        self.ring.slots.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.slots.is_empty()
+    }
+
+    /// Whether the producer has been dropped. Events it pushed before that
+    /// can still be popped.
+    pub fn is_disconnected(&self) -> bool {
+        !self.ring.producer_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.ring.consumer_alive.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::channel;
+
+    #[test]
+    fn test_ring_is_bounded_and_ordered() {
+        let (producer, consumer) = channel(2);
+        assert!(producer.push(1).is_ok());
+        assert!(producer.push(2).is_ok());
+        assert_eq!(producer.push(3), Err(3));
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert!(producer.push(3).is_ok());
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+
+        producer.push(4).unwrap();
+        drop(producer);
+        assert!(consumer.is_disconnected());
+        assert_eq!(consumer.pop(), Some(4));
+    }
+
+    #[test]
+    fn test_ring_across_threads() {
+        let (producer, consumer) = channel(16);
+        let feed = std::thread::spawn(move || {
+            for i in 0..10_000u32 {
+                let mut value = i;
+                while let Err(rejected) = producer.push(value) {
+                    value = rejected;
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 10_000 {
+            match consumer.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        feed.join().unwrap();
+        assert!(consumer.is_disconnected());
+    }
+}
@@ -0,0 +1,534 @@
+//! # Storage
+//!
+//! A local SQLite database of what the aggregator saw and did, so sessions
+//! can be analysed with SQL instead of from the logs:
+//! - `opportunities`: every closed opportunity window
+//! - `trades`: every executed opportunity with its outcome, realised PnL and
+//!   slippage, flagged if simulated
+//! - `orders` and `fills`: each order a trade sent and what it filled
+//!
+//! Writes go through `StorageWriter`, which hands records to a thread of
+//! their own and never blocks the caller. `Database::daily_summary`
+//! aggregates one day by venue pair.
+//!
+//! `open` creates the schema or upgrades it with whichever `MIGRATIONS` it
+//! hasn't applied yet, tracked in SQLite's `user_version`. Times are UTC
+//! RFC 3339 text, so a day's rows share a prefix; prices are in cents and
+//! quantities in base units, as everywhere else.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use chrono::{NaiveDate, SecondsFormat};
+use pricelevel::Side;
+use rusqlite::{params, Connection, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    execution::TradeRecord, orderbook::book::Exchange, pipeline::TrackedOpportunity,
+    util::wall_clock,
+};
+
+pub mod writer;
+
+pub use writer::{Record, Storage, StorageWriter};
+
+/// Each entry upgrades the schema by one version. Applied migrations must
+/// never change; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: opportunity windows, trades, and the trades' orders and fills
+    "CREATE TABLE opportunities (
+        id INTEGER PRIMARY KEY,
+        session TEXT NOT NULL,
+        window_id INTEGER NOT NULL,
+        buy_exchange TEXT NOT NULL,
+        sell_exchange TEXT NOT NULL,
+        opened_at TEXT NOT NULL,
+        closed_at TEXT NOT NULL,
+        duration_us INTEGER NOT NULL,
+        buy_price INTEGER NOT NULL,
+        sell_price INTEGER NOT NULL,
+        net_edge_bps INTEGER NOT NULL,
+        peak_edge_bps INTEGER NOT NULL,
+        peak_quantity INTEGER NOT NULL,
+        updates INTEGER NOT NULL,
+        UNIQUE (session, window_id)
+    );
+    CREATE INDEX opportunities_opened_at ON opportunities (opened_at);
+
+    CREATE TABLE trades (
+        trade_id TEXT PRIMARY KEY,
+        simulated INTEGER NOT NULL,
+        buy_exchange TEXT NOT NULL,
+        sell_exchange TEXT NOT NULL,
+        buy_price INTEGER NOT NULL,
+        sell_price INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        outcome TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        duration_us INTEGER NOT NULL,
+        bought_quantity INTEGER NOT NULL,
+        sold_quantity INTEGER NOT NULL,
+        realized_pnl INTEGER NOT NULL,
+        buy_slippage INTEGER NOT NULL,
+        sell_slippage INTEGER NOT NULL,
+        net_position INTEGER NOT NULL
+    );
+    CREATE INDEX trades_started_at ON trades (started_at);
+
+    CREATE TABLE orders (
+        client_order_id TEXT PRIMARY KEY,
+        trade_id TEXT NOT NULL REFERENCES trades (trade_id),
+        exchange TEXT NOT NULL,
+        side TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        limit_price INTEGER,
+        time_in_force TEXT NOT NULL
+    );
+    CREATE INDEX orders_trade_id ON orders (trade_id);
+
+    CREATE TABLE fills (
+        id INTEGER PRIMARY KEY,
+        client_order_id TEXT NOT NULL REFERENCES orders (client_order_id),
+        exchange TEXT NOT NULL,
+        side TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        notional INTEGER NOT NULL,
+        average_price INTEGER NOT NULL
+    );
+    CREATE INDEX fills_client_order_id ON fills (client_order_id);",
+];
+
+/// One day of one venue pair, buying on `buy_exchange` and selling on
+/// `sell_exchange`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairSummary {
+    pub buy_exchange: Exchange,
+    pub sell_exchange: Exchange,
+    /// Opportunity windows opened that day
+    pub windows: u64,
+    pub mean_duration: Duration,
+    pub max_duration: Duration,
+    pub max_peak_edge_bps: i64,
+    /// Trades started that day, simulated ones included
+    pub trades: u64,
+    pub simulated_trades: u64,
+    pub bought_quantity: u64,
+    pub realized_pnl: i64,
+}
+
+impl PairSummary {
+    fn new(buy_exchange: Exchange, sell_exchange: Exchange) -> Self {
+        Self {
+            buy_exchange,
+            sell_exchange,
+            windows: 0,
+            mean_duration: Duration::ZERO,
+            max_duration: Duration::ZERO,
+            max_peak_edge_bps: 0,
+            trades: 0,
+            simulated_trades: 0,
+            bought_quantity: 0,
+            realized_pnl: 0,
+        }
+    }
+}
+
+pub struct Database {
+    connection: Connection,
+    /// Identifies this process's opportunity windows, whose ids restart
+    /// with every tracker
+    session: String,
+}
+
+impl Database {
+    /// Opens or creates the database at `path` and brings its schema up to date.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .with_context(|| format!("opening database {}", path.display()))?;
+        // Readers (a notebook, the sqlite3 shell) don't block the writer
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::with_connection(connection)
+            .with_context(|| format!("migrating database {}", path.display()))
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> anyhow::Result<Self> {
+        let mut database = Self {
+            connection,
+            session: Uuid::new_v4().to_string(),
+        };
+        database.migrate()?;
+        Ok(database)
+    }
+
+    /// The number of migrations applied.
+    pub fn schema_version(&self) -> anyhow::Result<usize> {
+        let version: i64 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    fn migrate(&mut self) -> anyhow::Result<()> {
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            return Err(anyhow!(
+                "schema version {version} is newer than this build's {}",
+                MIGRATIONS.len()
+            ));
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = self.connection.transaction()?;
+            transaction
+                .execute_batch(migration)
+                .with_context(|| format!("migration {}", i + 1))?;
+            transaction.pragma_update(None, "user_version", i + 1)?;
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Writes `records` in a single transaction.
+    pub fn write(&mut self, records: &[Record]) -> anyhow::Result<()> {
+        let transaction = self.connection.transaction()?;
+        for record in records {
+            match record {
+                Record::Opportunity(window) => {
+                    insert_opportunity(&transaction, &self.session, window)?
+                }
+                Record::Trade { record, simulated } => {
+                    insert_trade(&transaction, record, *simulated)?
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Every venue pair with an opportunity window or trade on `day` (UTC).
+    pub fn daily_summary(&self, day: NaiveDate) -> anyhow::Result<Vec<PairSummary>> {
+        let from = day.to_string();
+        let to = day
+            .succ_opt()
+            .ok_or_else(|| anyhow!("no day after {day}"))?
+            .to_string();
+        let mut pairs = BTreeMap::new();
+
+        let mut windows = self.connection.prepare(
+            "SELECT buy_exchange, sell_exchange, COUNT(*), AVG(duration_us), MAX(duration_us),
+                    MAX(peak_edge_bps)
+             FROM opportunities WHERE opened_at >= ?1 AND opened_at < ?2
+             GROUP BY buy_exchange, sell_exchange",
+        )?;
+        let mut rows = windows.query(params![from, to])?;
+        while let Some(row) = rows.next()? {
+            let pair = pair_summary(&mut pairs, row.get(0)?, row.get(1)?)?;
+            pair.windows = row.get(2)?;
+            pair.mean_duration = Duration::from_micros(row.get::<_, f64>(3)? as u64);
+            pair.max_duration = Duration::from_micros(row.get(4)?);
+            pair.max_peak_edge_bps = row.get(5)?;
+        }
+
+        let mut trades = self.connection.prepare(
+            "SELECT buy_exchange, sell_exchange, COUNT(*), SUM(simulated), SUM(bought_quantity),
+                    SUM(realized_pnl)
+             FROM trades WHERE started_at >= ?1 AND started_at < ?2
+             GROUP BY buy_exchange, sell_exchange",
+        )?;
+        let mut rows = trades.query(params![from, to])?;
+        while let Some(row) = rows.next()? {
+            let pair = pair_summary(&mut pairs, row.get(0)?, row.get(1)?)?;
+            pair.trades = row.get(2)?;
+            pair.simulated_trades = row.get(3)?;
+            pair.bought_quantity = row.get(4)?;
+            pair.realized_pnl = row.get(5)?;
+        }
+        Ok(pairs.into_values().collect())
+    }
+
+    /// Every UTC day with an opportunity window or trade, oldest first.
+    pub fn days(&self) -> anyhow::Result<Vec<NaiveDate>> {
+        let mut statement = self.connection.prepare(
+            "SELECT substr(opened_at, 1, 10) FROM opportunities
+             UNION SELECT substr(started_at, 1, 10) FROM trades
+             ORDER BY 1",
+        )?;
+        let days = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|day| Ok(day?.parse::<NaiveDate>()?))
+            .collect();
+        days
+    }
+}
+
+/// The pair's entry in a summary being built, keyed as stored.
+fn pair_summary(
+    pairs: &mut BTreeMap<(String, String), PairSummary>,
+    buy: String,
+    sell: String,
+) -> anyhow::Result<&mut PairSummary> {
+    let new = PairSummary::new(parse_exchange(&buy)?, parse_exchange(&sell)?);
+    Ok(pairs.entry((buy, sell)).or_insert(new))
+}
+
+fn insert_opportunity(
+    transaction: &Transaction<'_>,
+    session: &str,
+    window: &TrackedOpportunity,
+) -> anyhow::Result<()> {
+    let closed_at = window.closed_at.unwrap_or(window.last_seen);
+    transaction.execute(
+        "INSERT INTO opportunities (session, window_id, buy_exchange, sell_exchange, opened_at,
+             closed_at, duration_us, buy_price, sell_price, net_edge_bps, peak_edge_bps,
+             peak_quantity, updates)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            session,
+            window.id,
+            exchange_key(window.buy_exchange()),
+            exchange_key(window.sell_exchange()),
+            timestamp(window.opened_at),
+            timestamp(closed_at),
+            window.duration().as_micros() as u64,
+            window.current.buy_price,
+            window.current.sell_price,
+            window.net_edge_bps,
+            window.peak_edge_bps,
+            window.peak_quantity,
+            window.updates,
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_trade(
+    transaction: &Transaction<'_>,
+    record: &TradeRecord,
+    simulated: bool,
+) -> anyhow::Result<()> {
+    let trade_id = record.trade_id.to_string();
+    let opportunity = &record.opportunity;
+    transaction.execute(
+        "INSERT INTO trades (trade_id, simulated, buy_exchange, sell_exchange, buy_price,
+             sell_price, quantity, outcome, started_at, duration_us, bought_quantity,
+             sold_quantity, realized_pnl, buy_slippage, sell_slippage, net_position)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        params![
+            trade_id,
+            simulated,
+            exchange_key(opportunity.buy_exchange),
+            exchange_key(opportunity.sell_exchange),
+            opportunity.buy_price,
+            opportunity.sell_price,
+            opportunity.quantity,
+            format!("{:?}", record.outcome).to_lowercase(),
+            timestamp(record.started_at),
+            record.duration().as_micros() as u64,
+            record.bought_quantity(),
+            record.sold_quantity(),
+            record.realized_pnl(),
+            record.buy_slippage(),
+            record.sell_slippage(),
+            record.net_position(),
+        ],
+    )?;
+    for order in &record.orders {
+        transaction.execute(
+            "INSERT INTO orders (client_order_id, trade_id, exchange, side, quantity,
+                 limit_price, time_in_force)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                order.client_order_id.to_string(),
+                trade_id,
+                exchange_key(order.exchange),
+                side_key(order.side),
+                order.quantity,
+                order.limit_price,
+                format!("{:?}", order.time_in_force).to_lowercase(),
+            ],
+        )?;
+    }
+    for fill in record.fills.iter().filter(|fill| fill.filled_quantity > 0) {
+        transaction.execute(
+            "INSERT INTO fills (client_order_id, exchange, side, quantity, notional,
+                 average_price)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                fill.client_order_id.to_string(),
+                exchange_key(fill.exchange),
+                side_key(fill.side),
+                fill.filled_quantity,
+                fill.notional,
+                fill.average_price(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn timestamp(at: Instant) -> String {
+    wall_clock(at).to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn exchange_key(exchange: Exchange) -> String {
+    format!("{exchange:?}").to_lowercase()
+}
+
+fn parse_exchange(key: &str) -> anyhow::Result<Exchange> {
+    Exchange::ALL
+        .into_iter()
+        .find(|&exchange| exchange_key(exchange) == key)
+        .ok_or_else(|| anyhow!("unknown exchange {key:?} in database"))
+}
+
+fn side_key(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+    use pricelevel::Side;
+    use uuid::Uuid;
+
+    use super::{Database, Record, MIGRATIONS};
+    use crate::{
+        execution::{coordinator::TradeOutcome, Fill, OrderRequest, TradeRecord},
+        orderbook::book::{ArbitrageOpportunity, Exchange},
+        pipeline::TrackedOpportunity,
+        util::{self, QUANTITY_SCALE},
+    };
+
+    fn opportunity() -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            buy_exchange: Exchange::Kraken,
+            buy_price: 100_000,
+            sell_exchange: Exchange::Binance,
+            sell_price: 100_200,
+            quantity: 5 * QUANTITY_SCALE,
+        }
+    }
+
+    pub(super) fn window(id: u64, lasted: Duration, peak_edge_bps: i64) -> TrackedOpportunity {
+        let closed_at = Instant::now();
+        TrackedOpportunity {
+            id,
+            opened_at: closed_at - lasted,
+            last_seen: closed_at,
+            closed_at: Some(closed_at),
+            current: opportunity(),
+            net_edge_bps: 10,
+            peak_edge_bps,
+            peak_quantity: 5 * QUANTITY_SCALE,
+            updates: 3,
+        }
+    }
+
+    /// Bought 5, sold 4, so 1 is left exposed.
+    pub(super) fn trade() -> TradeRecord {
+        let buy = OrderRequest::ioc(Exchange::Kraken, Side::Buy, 5 * QUANTITY_SCALE, 100_000);
+        let sell = OrderRequest::ioc(Exchange::Binance, Side::Sell, 5 * QUANTITY_SCALE, 100_200);
+        let fill = |order: &OrderRequest, quantity: u64, price: u64| Fill {
+            filled_quantity: quantity * QUANTITY_SCALE,
+            notional: util::notional(quantity * QUANTITY_SCALE, price),
+            ..Fill::empty(order)
+        };
+        TradeRecord {
+            trade_id: Uuid::new_v4(),
+            opportunity: opportunity(),
+            orders: vec![buy, sell],
+            fills: vec![fill(&buy, 5, 100_000), fill(&sell, 4, 100_200)],
+            outcome: TradeOutcome::Exposed,
+            started_at: Instant::now(),
+            completed_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_migrations_apply_once() {
+        let path = std::env::temp_dir().join(format!("storage-{}.db", std::process::id()));
+        let database = Database::open(&path).unwrap();
+        assert_eq!(database.schema_version().unwrap(), MIGRATIONS.len());
+        drop(database);
+        // Reopening finds nothing left to apply
+        let database = Database::open(&path).unwrap();
+        assert_eq!(database.schema_version().unwrap(), MIGRATIONS.len());
+
+        database
+            .connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(database);
+        let error = Database::open(&path).err().unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{suffix}", path.display())).ok();
+        }
+        assert!(format!("{error:#}").contains("newer than this build"));
+    }
+
+    #[test]
+    fn test_daily_summary_by_pair() {
+        let mut database = Database::open_in_memory().unwrap();
+        database
+            .write(&[
+                Record::Opportunity(window(1, Duration::from_millis(100), 12)),
+                Record::Opportunity(window(2, Duration::from_millis(300), 20)),
+                Record::Trade {
+                    record: trade(),
+                    simulated: true,
+                },
+            ])
+            .unwrap();
+
+        let orders: i64 = database
+            .connection
+            .query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))
+            .unwrap();
+        let sold: i64 = database
+            .connection
+            .query_row(
+                "SELECT quantity FROM fills WHERE side = 'sell'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!((orders, sold), (2, 4 * QUANTITY_SCALE as i64));
+
+        let today = Utc::now().date_naive();
+        assert_eq!(database.days().unwrap(), vec![today]);
+        let summary = database.daily_summary(today).unwrap();
+        assert_eq!(summary.len(), 1);
+        let pair = &summary[0];
+        assert_eq!(
+            (pair.buy_exchange, pair.sell_exchange),
+            (Exchange::Kraken, Exchange::Binance)
+        );
+        assert_eq!(pair.windows, 2);
+        assert_eq!(pair.mean_duration, Duration::from_millis(200));
+        assert_eq!(pair.max_duration, Duration::from_millis(300));
+        assert_eq!(pair.max_peak_edge_bps, 20);
+        assert_eq!((pair.trades, pair.simulated_trades), (1, 1));
+        assert_eq!(pair.bought_quantity, 5 * QUANTITY_SCALE);
+        // 4 matched at a 200 cent edge
+        assert_eq!(pair.realized_pnl, 800);
+
+        assert!(database
+            .daily_summary(today.succ_opt().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+}
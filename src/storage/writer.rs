@@ -0,0 +1,166 @@
+//! # Storage Writer
+//!
+//! Records are written by a `Background` thread that owns the `Database`,
+//! each batch that queued up in one transaction.
+
+use tracing::error;
+
+use super::Database;
+use crate::{
+    execution::TradeRecord,
+    pipeline::TrackedOpportunity,
+    util::background::{Background, BackgroundWriter, BatchSink},
+};
+
+#[derive(Debug, Clone)]
+pub enum Record {
+    /// A closed opportunity window
+    Opportunity(TrackedOpportunity),
+    /// A resolved trade with its orders and fills
+    Trade {
+        record: TradeRecord,
+        simulated: bool,
+    },
+}
+
+/// Queues records to the storage thread. Clones share the thread and the
+/// queue.
+#[derive(Clone)]
+pub struct StorageWriter(BackgroundWriter<Record>);
+
+/// Owns the storage thread; `close` flushes the queue and stops it.
+pub struct Storage(Background<Record>);
+
+impl Storage {
+    /// Moves `database` to a new thread, queueing up to `capacity` records.
+    pub fn spawn(database: Database, capacity: usize) -> std::io::Result<Self> {
+        Background::spawn("Storage", DatabaseSink(database), capacity).map(Self)
+    }
+
+    pub fn writer(&self) -> StorageWriter {
+        StorageWriter(self.0.writer())
+    }
+
+    /// Waits for everything queued so far to be written, then stops the
+    /// thread. Writers still held elsewhere drop whatever they send after.
+    pub fn close(self) {
+        self.0.close();
+    }
+}
+
+impl StorageWriter {
+    pub fn record_opportunity(&self, window: TrackedOpportunity) {
+        self.0.send(Record::Opportunity(window));
+    }
+
+    pub fn record_trade(&self, record: &TradeRecord, simulated: bool) {
+        self.0.send(Record::Trade {
+            record: record.clone(),
+            simulated,
+        });
+    }
+
+    /// Records dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped()
+    }
+}
+
+/// The storage thread's end.
+struct DatabaseSink(Database);
+
+impl BatchSink for DatabaseSink {
+    type Item = Record;
+
+    fn write(&mut self, batch: &[Record]) {
+        write_batch(&mut self.0, batch);
+    }
+}
+
+/// Writes the batch in one transaction. One bad record, e.g. a duplicate
+/// trade, rolls back the lot, so a failed batch is retried record by record
+/// and only the records that fail on their own are lost.
+fn write_batch(database: &mut Database, batch: &[Record]) {
+    if database.write(batch).is_ok() {
+        return;
+    }
+    for record in batch {
+        if let Err(e) = database.write(std::slice::from_ref(record)) {
+            error!("[Storage] Failed to write {:?}: {:#}", record, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+
+    use super::{write_batch, Storage};
+    use crate::{
+        orderbook::book::{ArbitrageOpportunity, Exchange},
+        pipeline::TrackedOpportunity,
+        storage::{test, Database, Record},
+    };
+
+    #[test]
+    fn test_close_flushes_the_queue() {
+        let path = std::env::temp_dir().join(format!("storage-writer-{}.db", std::process::id()));
+        let storage = Storage::spawn(Database::open(&path).unwrap(), 1_000).unwrap();
+        let writer = storage.writer();
+        let at = Instant::now();
+        for id in 1..=100 {
+            writer.record_opportunity(TrackedOpportunity {
+                id,
+                opened_at: at,
+                last_seen: at,
+                closed_at: Some(at),
+                current: ArbitrageOpportunity {
+                    buy_exchange: Exchange::Coinbase,
+                    buy_price: 100_000,
+                    sell_exchange: Exchange::Kraken,
+                    sell_price: 100_100,
+                    quantity: 1,
+                },
+                net_edge_bps: 10,
+                peak_edge_bps: 10,
+                peak_quantity: 1,
+                updates: 0,
+            });
+        }
+        storage.close();
+        assert_eq!(writer.dropped(), 0);
+
+        let database = Database::open(&path).unwrap();
+        let day = database.days().unwrap()[0];
+        let summary = database.daily_summary(day).unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{suffix}", path.display())).ok();
+        }
+        assert_eq!(summary[0].windows, 100);
+    }
+
+    #[test]
+    fn test_failed_record_keeps_the_rest_of_its_batch() {
+        let mut database = Database::open_in_memory().unwrap();
+        let trade = test::trade();
+        database
+            .write(&[Record::Trade {
+                record: trade.clone(),
+                simulated: true,
+            }])
+            .unwrap();
+
+        // The same trade again fails on its primary key
+        let window = |id| Record::Opportunity(test::window(id, Duration::from_millis(1), 10));
+        let duplicate = Record::Trade {
+            record: trade,
+            simulated: true,
+        };
+        write_batch(&mut database, &[window(1), duplicate, window(2)]);
+
+        let summary = database.daily_summary(Utc::now().date_naive()).unwrap();
+        assert_eq!((summary[0].windows, summary[0].trades), (2, 1));
+    }
+}
@@ -0,0 +1,195 @@
+//! # Background Writer
+//!
+//! Keeps slow I/O off the hot path: items are queued to a dedicated thread
+//! that owns a `BatchSink` and hands it whatever has queued up at once. A
+//! full queue drops the item rather than blocking the caller, and the drops
+//! are counted. `storage` and `export` are both built on it.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use tracing::warn;
+
+/// Most items handed to the sink at once
+const MAX_BATCH: usize = 1_000;
+
+/// What the background thread does with the items queued to it.
+pub trait BatchSink: Send + 'static {
+    type Item: Send + 'static;
+
+    /// How long the thread waits for items before calling `tick` anyway;
+    /// None waits for as long as it takes.
+    const IDLE_POLL: Option<Duration> = None;
+
+    /// Writes everything that queued up since the last call, in order.
+    fn write(&mut self, batch: &[Self::Item]);
+
+    /// Called after every batch, and when idle for `IDLE_POLL`.
+    fn tick(&mut self) {}
+
+    /// Called once, after the last batch.
+    fn close(&mut self) {}
+}
+
+enum Message<T> {
+    Item(T),
+    Close,
+}
+
+/// The sending end of a background thread. Clones share the thread and the
+/// queue.
+pub struct BackgroundWriter<T> {
+    tx: SyncSender<Message<T>>,
+    dropped: Arc<AtomicU64>,
+    name: &'static str,
+}
+
+impl<T> Clone for BackgroundWriter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            dropped: Arc::clone(&self.dropped),
+            name: self.name,
+        }
+    }
+}
+
+impl<T> BackgroundWriter<T> {
+    /// Queues `item`, or drops it if the queue is full.
+    pub fn send(&self, item: T) {
+        match self.tx.try_send(Message::Item(item)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped.is_multiple_of(1_000) {
+                    warn!("[{}] Queue full, {} dropped", self.name, dropped);
+                }
+            }
+            // The sink has been closed; anything sent after is dropped
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Items dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Owns the background thread; `close` flushes the queue and stops it.
+pub struct Background<T> {
+    writer: BackgroundWriter<T>,
+    thread: JoinHandle<()>,
+}
+
+impl<T: Send + 'static> Background<T> {
+    /// Moves `sink` to a new thread, queueing up to `capacity` items. `name`
+    /// prefixes its warnings; the thread is named after it in lower case.
+    pub fn spawn<S: BatchSink<Item = T>>(
+        name: &'static str,
+        sink: S,
+        capacity: usize,
+    ) -> std::io::Result<Self> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let thread = thread::Builder::new()
+            .name(name.to_lowercase())
+            .spawn(move || run(sink, rx))?;
+        Ok(Self {
+            writer: BackgroundWriter {
+                tx,
+                dropped: Arc::new(AtomicU64::new(0)),
+                name,
+            },
+            thread,
+        })
+    }
+
+    pub fn writer(&self) -> BackgroundWriter<T> {
+        self.writer.clone()
+    }
+
+    /// Waits for everything queued so far to be written and the sink to be
+    /// closed, then stops the thread. Writers still held elsewhere drop
+    /// whatever they send after.
+    pub fn close(self) {
+        if self.writer.tx.send(Message::Close).is_ok() {
+            self.thread.join().ok();
+        }
+    }
+}
+
+fn run<S: BatchSink>(mut sink: S, rx: Receiver<Message<S::Item>>) {
+    let mut batch = Vec::new();
+    let mut closing = false;
+    while !closing {
+        let first = match S::IDLE_POLL {
+            Some(poll) => match rx.recv_timeout(poll) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+        };
+        for message in first.into_iter().chain(rx.try_iter().take(MAX_BATCH - 1)) {
+            match message {
+                Message::Item(item) => batch.push(item),
+                Message::Close => {
+                    closing = true;
+                    break;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            sink.write(&batch);
+            batch.clear();
+        }
+        sink.tick();
+    }
+    sink.close();
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Background, BatchSink};
+
+    struct Collect(Arc<Mutex<Vec<u32>>>);
+
+    impl BatchSink for Collect {
+        type Item = u32;
+
+        fn write(&mut self, batch: &[u32]) {
+            self.0.lock().unwrap().extend(batch);
+        }
+    }
+
+    #[test]
+    fn test_close_flushes_the_queue_and_full_queue_drops() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        // Holding the lock stalls the thread in `write`, so the queue fills
+        let stall = written.lock().unwrap();
+        let background = Background::spawn("Test", Collect(Arc::clone(&written)), 2).unwrap();
+        let writer = background.writer();
+        for item in 0..100 {
+            writer.send(item);
+        }
+        drop(stall);
+        background.close();
+
+        let written = written.lock().unwrap();
+        assert_eq!(written.len() as u64 + writer.dropped(), 100);
+        assert!(writer.dropped() > 0);
+        assert!(written.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+pub mod background;
+
+/// Fast decimal string to cents (u64) parser for low-latency applications
+/// Avoids f64 parsing overhead and floating-point arithmetic
+/// 
+/// Examples:
+/// - "95245.75" -> 9524575 (cents)
+/// - "100.00" -> 10000 (cents)
+/// - "50.5" -> 5050 (cents)
+/// - "100" -> 10000 (cents, assumes .00)
+pub fn parse_price_cents(s: &str) -> Option<u64> {
+    // Find decimal point
+    match s.find('.') {
+        Some(dot_pos) => {
+            // Parse integer part (before decimal)
+            let integer_part = s[..dot_pos].parse::<u64>().ok()?;
+            
+            // Parse fractional part (after decimal)
+            let fractional_str = &s[dot_pos + 1..];
+            
+            // Handle up to 2 decimal places (cents)
+            let fractional = match fractional_str.len() {
+                0 => 0,
+                1 => fractional_str.parse::<u64>().ok()? * 10,
+                2 => fractional_str.parse::<u64>().ok()?,
+                _ => {
+                    // More than 2 decimal places - truncate to 2
+                    fractional_str[..2].parse::<u64>().ok()?
+                }
+            };
+            
+            // Combine: integer_part * 100 + fractional
+            Some(integer_part * 100 + fractional)
+        }
+        None => {
+            // No decimal point - treat as whole dollars
+            s.parse::<u64>().ok().map(|v| v * 100)
+        }
+    }
+}
+
+/// Decimal places in one order quantity unit for live venues (1e-8 of the base asset)
+pub const QUANTITY_DECIMALS: u32 = 8;
+
+/// Order quantity units in one whole unit of the base asset
+pub const QUANTITY_SCALE: u64 = 10u64.pow(QUANTITY_DECIMALS);
+
+/// Cents for `quantity` order units at `price` cents per whole unit.
+pub fn notional(quantity: u64, price: u64) -> u64 {
+    (quantity as u128 * price as u128 / QUANTITY_SCALE as u128) as u64
+}
+
+/// Order units that `notional` cents buys at `price`, 0 if the price is 0.
+pub fn quantity_for(notional: u64, price: u64) -> u64 {
+    if price == 0 {
+        return 0;
+    }
+    (notional as u128 * QUANTITY_SCALE as u128 / price as u128).min(u64::MAX as u128) as u64
+}
+
+/// Cents per whole unit paid for `quantity` order units, `None` if there are none.
+pub fn unit_price(notional: u64, quantity: u64) -> Option<u64> {
+    (quantity > 0).then(|| quantity_for(notional, quantity))
+}
+
+/// Venue quantity string to order units (1e-8 of the base asset)
+///
+/// Examples:
+/// - "1.25" -> 125000000
+/// - "0.00000001" -> 1
+/// - "3" -> 300000000
+pub fn parse_quantity_units(s: &str) -> Option<u64> {
+    let (integer_str, fractional_str) = s.split_once('.').unwrap_or((s, ""));
+    let integer_part = if integer_str.is_empty() { 0 } else { integer_str.parse::<u64>().ok()? };
+
+    // Pad or truncate to exactly QUANTITY_DECIMALS digits
+    let digits = QUANTITY_DECIMALS as usize;
+    let fractional = if fractional_str.is_empty() {
+        0
+    } else {
+        let truncated = fractional_str.get(..fractional_str.len().min(digits))?;
+        truncated.parse::<u64>().ok()? * 10u64.pow((digits - truncated.len()) as u32)
+    };
+
+    integer_part
+        .checked_mul(10u64.pow(QUANTITY_DECIMALS))?
+        .checked_add(fractional)
+}
+
+/// An `Instant` as wall-clock time, by how far it is from now.
+pub fn wall_clock(at: Instant) -> DateTime<Utc> {
+    let (now, utc) = (Instant::now(), Utc::now());
+    let offset = |duration: Duration| chrono::Duration::from_std(duration).unwrap_or_default();
+    match now.checked_duration_since(at) {
+        Some(ago) => utc - offset(ago),
+        None => utc + offset(at.duration_since(now)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_price_cents() {
+        assert_eq!(parse_price_cents("95245.75"), Some(9524575));
+        assert_eq!(parse_price_cents("100.00"), Some(10000));
+        assert_eq!(parse_price_cents("50.5"), Some(5050));
+        assert_eq!(parse_price_cents("0.01"), Some(1));
+        assert_eq!(parse_price_cents("95245.75000000"), Some(9524575)); // Truncates extra decimals
+        assert_eq!(parse_price_cents("100"), Some(10000)); // No decimal point - assumes .00
+        assert_eq!(parse_price_cents("0"), Some(0));
+    }
+
+    #[test]
+    fn test_parse_quantity_units() {
+        assert_eq!(parse_quantity_units("1.25"), Some(125_000_000));
+        assert_eq!(parse_quantity_units("0.00000001"), Some(1));
+        assert_eq!(parse_quantity_units("1000000000.00000000"), Some(100_000_000_000_000_000));
+        assert_eq!(parse_quantity_units("0.123456789"), Some(12_345_678)); // Truncates extra decimals
+        assert_eq!(parse_quantity_units("3"), Some(300_000_000));
+        assert_eq!(parse_quantity_units("abc"), None);
+    }
+}